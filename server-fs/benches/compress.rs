@@ -0,0 +1,98 @@
+// compares the codecs on a typical extent mix, any file paths given are added to it
+//
+//     cargo bench --bench compress -- [path...]
+use server_fs::compress::compress::{Compress, CompressType};
+use server_fs::compress::{huffman, lz4, snappy, zstd};
+use std::time::Instant;
+
+const ROUNDS: usize = 8;
+
+fn text_log() -> Vec<u8> {
+    let mut data = String::new();
+    for i in 0..4096u32 {
+        data += &format!("2026-10-17 12:{:02}:{:02} INFO kv: flushed memtable {} into level {} ({} entries)\n", i / 60 % 60, i % 60, i, i % 4, i * 7 % 1000);
+    }
+    data.into_bytes()
+}
+
+fn json() -> Vec<u8> {
+    let mut data = String::from("[");
+    for i in 0..2048u32 {
+        data += &format!("{{\"ino\":{},\"name\":\"file-{}.txt\",\"size\":{},\"mode\":\"0644\",\"nlink\":1}},", i, i, i * 4099 % 65536);
+    }
+    data += "{}]";
+    data.into_bytes()
+}
+
+fn source_code() -> Vec<u8> {
+    include_str!("../src/kv/kv_manager.rs").as_bytes().to_vec()
+}
+
+fn binary() -> Vec<u8> {
+    std::env::current_exe().and_then(std::fs::read).unwrap_or_default().into_iter().take(1 << 20).collect()
+}
+
+fn random() -> Vec<u8> {
+    let mut state = 0x2545f4914f6cdd1du64;
+    (0..1 << 18).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    }).collect()
+}
+
+fn codecs() -> Vec<(&'static str, Box<dyn Compress>)> {
+    vec![
+        ("huffman", Box::new(huffman::HuffmanCodec::new())),
+        ("snappy", Box::new(snappy::Snappy::new())),
+        ("lz4", Box::new(lz4::Lz4::new())),
+        ("zstd", Box::new(zstd::Zstd::new(zstd::ZstdLevel::Fastest))),
+    ]
+}
+
+fn main() {
+    let mut samples = vec![
+        ("log".to_string(), text_log()),
+        ("json".to_string(), json()),
+        ("source".to_string(), source_code()),
+        ("binary".to_string(), binary()),
+        ("random".to_string(), random()),
+        ("zeros".to_string(), vec![0; 1 << 18]),
+    ];
+    for path in std::env::args().skip(1).filter(|arg| !arg.starts_with("--")) {
+        match std::fs::read(&path) {
+            Ok(data) => samples.push((path, data)),
+            Err(e) => eprintln!("skip {}: {}", path, e),
+        }
+    }
+    println!("{:<24} {:<8} {:>10} {:>10} {:>8} {:>12} {:>12}", "sample", "codec", "raw", "stored", "ratio", "encode MB/s", "decode MB/s");
+    for (name, data) in samples.iter() {
+        if data.is_empty() {
+            continue;
+        }
+        for (codec_name, mut codec) in codecs() {
+            // huffman codes chars and only ever sees ascii extents
+            if codec.compress_type() == CompressType::Huffman && !data.is_ascii() {
+                continue;
+            }
+            let mut encoded = vec![];
+            let start = Instant::now();
+            for _ in 0..ROUNDS {
+                encoded = codec.encode(data);
+            }
+            let encode_time = start.elapsed().as_secs_f64() / ROUNDS as f64;
+            let mut decoded = vec![];
+            let start = Instant::now();
+            for _ in 0..ROUNDS {
+                decoded = codec.decode(&encoded).unwrap();
+            }
+            let decode_time = start.elapsed().as_secs_f64() / ROUNDS as f64;
+            assert_eq!(&decoded[..data.len()], &data[..], "{} does not round trip {}", codec_name, name);
+            let mb = data.len() as f64 / (1024.0 * 1024.0);
+            println!("{:<24} {:<8} {:>10} {:>10} {:>7.1}% {:>12.1} {:>12.1}",
+                name, codec_name, data.len(), encoded.len(), encoded.len() as f64 * 100.0 / data.len() as f64,
+                mb / encode_time, mb / decode_time);
+        }
+    }
+}
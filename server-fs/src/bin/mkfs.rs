@@ -0,0 +1,82 @@
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use server_fs::mkfs;
+use server_fs::kv::component::super_block;
+
+const USAGE: &str = "usage: mkfs.wondfs <image> [--blocks N] [--kv N] [--main N] [--reserved N]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    }
+    let mut path = None;
+    let mut block_num = super_block::DEFAULT_BLOCK_NUM;
+    let mut kv_block_num = None;
+    let mut main_area_block_num = None;
+    let mut reserved_block_num = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--blocks" => block_num = parse_num(arg, iter.next()),
+            "--kv" => kv_block_num = Some(parse_num(arg, iter.next())),
+            "--main" => main_area_block_num = Some(parse_num(arg, iter.next())),
+            "--reserved" => reserved_block_num = Some(parse_num(arg, iter.next())),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ => {
+                if path.is_some() || arg.starts_with("--") {
+                    eprintln!("mkfs.wondfs: unexpected argument {}", arg);
+                    eprintln!("{}", USAGE);
+                    process::exit(1);
+                }
+                path = Some(PathBuf::from(arg));
+            },
+        }
+    }
+    if path.is_none() {
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    }
+    let path = path.unwrap();
+    let mut options = match mkfs::MkfsOptions::new(block_num) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("mkfs.wondfs: {}: {}", path.display(), err);
+            process::exit(1);
+        },
+    };
+    if let Some(num) = kv_block_num {
+        options.kv_block_num = num;
+    }
+    if let Some(num) = reserved_block_num {
+        options.reserved_block_num = num;
+    }
+    match main_area_block_num {
+        Some(num) => options.main_area_block_num = num,
+        None => {
+            let used = options.kv_block_num.saturating_add(options.reserved_block_num).saturating_add(4);
+            options.main_area_block_num = super_block::SuperStat::fit_main_area(block_num.saturating_sub(used));
+        },
+    }
+    if let Err(err) = mkfs::mkfs(&path, &options) {
+        eprintln!("mkfs.wondfs: {}: {}", path.display(), err);
+        process::exit(1);
+    }
+    println!("mkfs.wondfs: formatted {} with {} blocks (kv {}, main {}, reserved {})",
+        path.display(), options.block_num, options.kv_block_num, options.main_area_block_num, options.reserved_block_num);
+}
+
+fn parse_num(flag: &str, value: Option<&String>) -> u32 {
+    match value.and_then(|v| v.parse().ok()) {
+        Some(num) => num,
+        None => {
+            eprintln!("mkfs.wondfs: {} expects a number", flag);
+            process::exit(1);
+        },
+    }
+}
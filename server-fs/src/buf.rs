@@ -1,5 +1,6 @@
 extern crate alloc;
 use crate::tl::tl;
+use crate::error::WondResult;
 use alloc::sync::Arc;
 use std::collections::BTreeMap;
 
@@ -21,27 +22,28 @@ impl BufCache {
 }
 
 impl BufCache {
-    pub fn read(&mut self, _: u8, address: u32) -> [u8; 4096] {
+    pub fn read(&mut self, _: u8, address: u32) -> WondResult<[u8; 4096]> {
         let data = self.table.get(&address);
         if data.is_some() {
-            return *data.unwrap();
+            return Ok(*data.unwrap());
         }
-        let data = self.translation_layer.read(address);
+        let data = self.translation_layer.read(address)?;
         self.table.insert(address, data);
-        data
+        Ok(data)
     }
 
-    pub fn read_advanced(&mut self, _: u8, address: u32, buf: &mut[u8]) {
+    pub fn read_advanced(&mut self, _: u8, address: u32, buf: &mut[u8]) -> WondResult<()> {
         let data = self.table.get(&address);
         if data.is_some() {
             let data = data.unwrap();
             buf.copy_from_slice(&data[..buf.len()]);
-            return;
+            return Ok(());
         }
-        self.translation_layer.read_advanced(address, buf);
+        self.translation_layer.read_advanced(address, buf)?;
         let mut data = [0; 4096];
         data[..buf.len()].copy_from_slice(buf);
         self.table.insert(address, data);
+        Ok(())
     }
 
     pub fn write(&mut self, _: u8, address: u32, data: &[u8; 4096]) {
@@ -49,13 +51,13 @@ impl BufCache {
         self.translation_layer.write(address, data);
     }
 
-    pub fn erase(&mut self, _: u8, block_no: u32) {
+    pub fn erase(&mut self, _: u8, block_no: u32) -> WondResult<()> {
         let start_address = block_no * 128;
         let end_address = (block_no + 1) * 128;
         for address in start_address..end_address {
             self.table.remove(&address);
         }
-        self.translation_layer.erase(block_no);
+        self.translation_layer.erase(block_no)
     }
 }
 
@@ -66,16 +68,16 @@ mod tests {
     #[test]
     fn basics() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let mut cache = BufCache::new(Arc::new(tl));
         let data = [1; 4096];        
         cache.write(0, 100, &data);
-        assert_eq!(cache.read(0, 100), [1; 4096]);
+        assert_eq!(cache.read(0, 100).unwrap(), [1; 4096]);
         cache.write(0, 100, &data);
-        let data = cache.read(0, 100);
+        let data = cache.read(0, 100).unwrap();
         assert_eq!(data, [1; 4096]);
-        cache.erase(0, 0);
-        let data = cache.read(0, 100);
+        cache.erase(0, 0).unwrap();
+        let data = cache.read(0, 100).unwrap();
         assert_eq!(data, [0; 4096]);
     }
 }
\ No newline at end of file
@@ -1,67 +1,94 @@
 use crate::inode::inode;
+use crate::error::{WondError, WondResult};
+use crate::fs::consts::MAX_FNAME_LEN;
 
-pub fn dir_lookup(inode: &inode::Inode, name: String) -> Option<(u32, usize)> {
+pub fn dir_lookup(inode: &inode::Inode, name: String) -> WondResult<Option<(u64, usize)>> {
     if inode.stat.read().file_type != inode::InodeFileType::Directory {
-        return None;
+        return Ok(None);
     }
     let mut buf = vec![];
-    if inode.read_all(&mut buf) == 0 {
-        return None;
+    if inode.read_all(&mut buf)? == 0 {
+        return Ok(None);
     }
-    let iter = DirectoryParser::new(&buf);
+    let iter = DirectoryParser::new(&buf, inode.kv.get_ino_size())?;
     for (i, entry) in iter.enumerate() {
+        let entry = entry?;
         if entry.ino == 0 {
             continue;
         }
         if entry.file_name == name {
-            return Some((entry.ino, i));
+            return Ok(Some((entry.ino, i)));
         }
     }
-    None
+    Ok(None)
 }
 
-pub fn dir_link(inode: &inode::Inode, ino: u32, name: String) {
-    if dir_lookup(&inode, name.clone()).is_some() {
-        return;
+pub fn dir_link(inode: &inode::Inode, ino: u64, name: String) -> WondResult<()> {
+    if dir_lookup(&inode, name.clone())?.is_some() {
+        return Ok(());
     }
-    let index = inode.stat.read().size / 259;
+    let ino_size = inode.kv.get_ino_size();
+    let per_size = entry_size(ino_size) as u64;
+    let index = inode.stat.read().size / per_size;
     let entry = DirectoryInodeEntry {
         file_name: name,
         ino,
     };
-    let buf = DirectoryParser::encode(&entry).unwrap();
+    let buf = DirectoryParser::encode(&entry, ino_size)?;
     // println!("{:?}", buf);
-    inode.write(index as usize * 259, 259, &buf)
+    inode.write(index * per_size, per_size as usize, &buf)
 }
 
-pub fn dir_unlink(inode: &inode::Inode, ino: u32, name: String) {
-    if !dir_lookup(&inode, name.clone()).is_some() {
-        return;
+pub fn dir_replace(inode: &inode::Inode, ino: u64, name: String) -> WondResult<()> {
+    let entry = dir_lookup(inode, name.clone())?;
+    if entry.is_none() {
+        return Ok(());
+    }
+    let index = entry.unwrap().1;
+    let entry = DirectoryInodeEntry {
+        file_name: name,
+        ino,
+    };
+    let ino_size = inode.kv.get_ino_size();
+    let per_size = entry_size(ino_size);
+    let buf = DirectoryParser::encode(&entry, ino_size)?;
+    inode.write((index * per_size) as u64, per_size, &buf)
+}
+
+pub fn dir_unlink(inode: &inode::Inode, ino: u64, name: String) -> WondResult<()> {
+    if !dir_lookup(&inode, name.clone())?.is_some() {
+        return Ok(());
     }
     let mut buf = vec![];
-    if inode.read_all(&mut buf) == 0 {
-        return;
+    if inode.read_all(&mut buf)? == 0 {
+        return Ok(());
     }
-    let iter = DirectoryParser::new(&buf);
+    let iter = DirectoryParser::new(&buf, inode.kv.get_ino_size())?;
     let mut index = 0;
     let per_size = iter.per_size;
     let len = iter.len;
     for entry in iter {
+        let entry = entry?;
         if entry.ino == ino && entry.file_name == name {
             break;
         }
         index += 1;
     }
     if index == len {
-        return;
+        return Ok(());
     }
-    inode.truncate(index * per_size, per_size)
+    inode.truncate((index * per_size) as u64, per_size as u64)
 }
 
 #[derive(PartialEq, Debug)]
 pub struct DirectoryInodeEntry {
     pub file_name: String,
-    pub ino: u32,
+    pub ino: u64,
+}
+
+// an entry is the inode number, as wide as the image keeps them, and the name padded to 255 bytes
+pub fn entry_size(ino_size: u32) -> usize {
+    ino_size as usize + MAX_FNAME_LEN
 }
 
 pub struct DirectoryParser {
@@ -69,53 +96,51 @@ pub struct DirectoryParser {
     pub data: Vec<u8>,
     pub len: usize,
     pub per_size: usize,
+    pub ino_size: u32,
 }
 
 impl DirectoryParser {
-    pub fn new(data: &Vec<u8>) -> DirectoryParser {
+    pub fn new(data: &[u8], ino_size: u32) -> WondResult<DirectoryParser> {
         // println!("{:?}", data);
-        if data.len() % 259 != 0 {
-            panic!("DirectoryParser: new not matched size");
+        if !data.len().is_multiple_of(entry_size(ino_size)) {
+            return Err(WondError::Corrupted("directory size is not a multiple of an entry"));
         }
-        DirectoryParser {
+        Ok(DirectoryParser {
             count: 0,
-            data: data.clone(),
+            data: data.to_vec(),
             len: data.len(),
-            per_size: 259,
-        }
+            per_size: entry_size(ino_size),
+            ino_size,
+        })
     }
     
-    pub fn decode(buf: &Vec<u8>) -> Option<DirectoryInodeEntry> {
-        if buf.len() != 259 {
-            panic!("DirectoryParser: decode not matched size");
+    pub fn decode(buf: &[u8], ino_size: u32) -> WondResult<DirectoryInodeEntry> {
+        let ino_size = ino_size as usize;
+        if buf.len() != entry_size(ino_size as u32) {
+            return Err(WondError::Corrupted("directory entry has not matched size"));
         }
-        let mut ino = 0;
-        ino += (buf[0] as u32) << 24;
-        ino += (buf[1] as u32) << 16;
-        ino += (buf[2] as u32) << 8;
-        ino += buf[3] as u32;
+        let ino = buf[..ino_size].iter().fold(0, |ino, byte| ino << 8 | *byte as u64);
         let mut len = 0;
-        for byte in buf[4..259].iter() {
+        for byte in buf[ino_size..].iter() {
             if *byte != 0 {
                 len += 1;
             }
         }
         if len == 0 {
-            panic!("Directory: decode not available name");
+            return Err(WondError::Corrupted("directory entry has an empty name"));
         }
-        Some(DirectoryInodeEntry {
+        let file_name = std::str::from_utf8(&buf[ino_size..ino_size+len as usize]).map_err(|_| WondError::Corrupted("directory entry name is not utf-8"))?;
+        Ok(DirectoryInodeEntry {
             ino,
-            file_name: std::str::from_utf8(&buf[4..4+len as usize]).unwrap().to_string(),
+            file_name: file_name.to_string(),
         })
     }
     
-    pub fn encode(entry: &DirectoryInodeEntry) -> Option<Vec<u8>> {
-        let mut res = vec![];
-        let ino = entry.ino;
-        res.push((ino >> 24) as u8);
-        res.push((ino >> 16) as u8);
-        res.push((ino >> 8) as u8);
-        res.push(ino as u8);
+    pub fn encode(entry: &DirectoryInodeEntry, ino_size: u32) -> WondResult<Vec<u8>> {
+        if entry.file_name.len() > MAX_FNAME_LEN {
+            return Err(WondError::NameTooLong);
+        }
+        let mut res = entry.ino.to_be_bytes()[8 - ino_size as usize..].to_vec();
         let mut name = entry.file_name.clone().into_bytes();
         for _ in name.len()..255 {
             name.push(0);
@@ -123,19 +148,49 @@ impl DirectoryParser {
         for i in 0..255 {
             res.push(name[i]);
         }
-        Some(res)
+        Ok(res)
     }
 }
 
 impl Iterator for DirectoryParser {
-    type Item = DirectoryInodeEntry;
+    type Item = WondResult<DirectoryInodeEntry>;
     fn next(&mut self) -> Option<Self::Item> {
         if self.count < self.len {
-            let entry = DirectoryParser::decode(&self.data[self.count..self.count+259].to_vec()).unwrap();
-            self.count += 259;
+            let entry = DirectoryParser::decode(&self.data[self.count..self.count+self.per_size], self.ino_size);
+            self.count += self.per_size;
             Some(entry)
         } else {
             None
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codec() {
+        let entry = DirectoryInodeEntry {
+            file_name: "a".repeat(MAX_FNAME_LEN),
+            ino: 42,
+        };
+        let buf = DirectoryParser::encode(&entry, 4).unwrap();
+        assert_eq!(buf.len(), 259);
+        assert_eq!(DirectoryParser::decode(&buf, 4), Ok(entry));
+        let entry = DirectoryInodeEntry {
+            file_name: "b".to_string(),
+            ino: 1 << 40,
+        };
+        let buf = DirectoryParser::encode(&entry, 8).unwrap();
+        assert_eq!(buf.len(), 263);
+        assert_eq!(DirectoryParser::decode(&buf, 8), Ok(entry));
+        let entry = DirectoryInodeEntry {
+            file_name: "a".repeat(MAX_FNAME_LEN + 1),
+            ino: 42,
+        };
+        assert_eq!(DirectoryParser::encode(&entry, 8), Err(WondError::NameTooLong));
+        assert!(DirectoryParser::decode(&vec![0; 263], 8).is_err());
+        assert!(DirectoryParser::new(&vec![0; 259], 8).is_err());
+    }
 }
\ No newline at end of file
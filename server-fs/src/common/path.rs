@@ -4,6 +4,7 @@ use alloc::sync::Arc;
 use crate::inode::inode;
 use crate::inode::inode_manager;
 use crate::common::directory;
+use crate::error::WondResult;
 
 pub fn skip_elem(path: String) -> Option<(String, String)> {
     let path = path.as_str();
@@ -36,19 +37,22 @@ pub fn skip_elem(path: String) -> Option<(String, String)> {
     Some((path[index..].to_string(), path[temp_index..temp_index+len].to_string()))
 }
 
-pub fn name_x(i_manager: Arc<RwLock<inode_manager::InodeManager>>, path: String, name: &mut String, name_i_parent: bool) -> Option<inode_manager::InodeLink> {
+pub fn name_x(i_manager: Arc<RwLock<inode_manager::InodeManager>>, path: String, name: &mut String, name_i_parent: bool) -> WondResult<Option<inode_manager::InodeLink>> {
     let path = &mut path.clone();
     let mut ip;
     let mut next;
     if path.len() == 0 {
-        return None;
+        return Ok(None);
     }
     // if path[0..1] == '/'.to_string() {
     //     ip = i_manager.write().i_get(1).unwrap();
     // } else {
     //     return None;
     // }
-    ip = i_manager.write().i_get(1).unwrap();
+    ip = match i_manager.write().i_get(1)? {
+        Some(ip) => ip,
+        None => return Ok(None),
+    };
     loop {
         let res = skip_elem(path.clone());
         if res.is_none() {
@@ -57,29 +61,32 @@ pub fn name_x(i_manager: Arc<RwLock<inode_manager::InodeManager>>, path: String,
         }
         (*path, *name) = res.unwrap();
         if ip.stat.read().file_type != inode::InodeFileType::Directory {
-            return None;
+            return Ok(None);
         }
         if name_i_parent && path == "" {
-            return Some(ip);
+            return Ok(Some(ip));
         }
-        let res = directory::dir_lookup(&ip, name.clone());
+        let res = directory::dir_lookup(&ip, name.clone())?;
         if res.is_none() {
-            return None;
+            return Ok(None);
         }
-        next = i_manager.write().i_get(res.unwrap().0).unwrap();
+        next = match i_manager.write().i_get(res.unwrap().0)? {
+            Some(next) => next,
+            None => return Ok(None),
+        };
         ip = next;
     }
     if name_i_parent {
-        return None;
+        return Ok(None);
     }
-    return Some(ip);
+    return Ok(Some(ip));
 }
 
-pub fn name_i(i_manager: Arc<RwLock<inode_manager::InodeManager>>, path: String) -> Option<inode_manager::InodeLink> {
+pub fn name_i(i_manager: Arc<RwLock<inode_manager::InodeManager>>, path: String) -> WondResult<Option<inode_manager::InodeLink>> {
     let mut name = "".to_string();
     name_x(i_manager, path, &mut name, false)
 }
 
-pub fn name_i_parent(i_manager: Arc<RwLock<inode_manager::InodeManager>>, path: String, name: &mut String) -> Option<inode_manager::InodeLink> {
+pub fn name_i_parent(i_manager: Arc<RwLock<inode_manager::InodeManager>>, path: String, name: &mut String) -> WondResult<Option<inode_manager::InodeLink>> {
     name_x(i_manager, path, name, true)
 }
\ No newline at end of file
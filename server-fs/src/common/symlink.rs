@@ -1,8 +1,9 @@
 use crate::inode::inode;
+use crate::error::WondResult;
 
-pub fn write_symlink(inode: &inode::Inode, path: String) {
+pub fn write_symlink(inode: &inode::Inode, path: String) -> WondResult<()> {
     if inode.stat.read().size != 0 {
-        return;
+        return Ok(());
     }
     // let mut data = vec![0; 4];
     // data[0] = (ino >> 24) as u8;
@@ -10,7 +11,7 @@ pub fn write_symlink(inode: &inode::Inode, path: String) {
     // data[2] = (ino >> 8) as u8;
     // data[3] = ino as u8;
     let data = path.as_bytes().to_vec();
-    inode.write(0, data.len(), &data);
+    inode.write(0, data.len(), &data)
 }
 
 // pub fn read_symlink(inode: &inode::Inode) -> Option<u32> {
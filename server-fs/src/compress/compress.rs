@@ -1,135 +1,189 @@
 use crate::compress::huffman;
 use crate::compress::snappy;
+use crate::compress::lz4;
+use crate::compress::zstd;
+use crate::error::{WondError, WondResult};
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum CompressType {
     Huffman,
     Snappy,
+    Lz4,
+    Zstd,
     None,
 }
 
+// the tag stored with every extent, existing values must never change meaning
 impl From<CompressType> for u8 {
     fn from(kind: CompressType) -> Self {
         match kind {
             CompressType::None => 0,
             CompressType::Snappy => 1,
             CompressType::Huffman => 2,
+            CompressType::Lz4 => 3,
+            CompressType::Zstd => 4,
         }
     }
 }
 
-impl From<u8> for CompressType {
-    fn from(kind: u8) -> Self {
+// a tag written by a newer build is refused instead of being read as garbage
+impl TryFrom<u8> for CompressType {
+    type Error = WondError;
+
+    fn try_from(kind: u8) -> WondResult<Self> {
         match kind {
-            0 => CompressType::None,
-            1 => CompressType::Snappy,
-            2 => CompressType::Huffman,
-            _ => panic!(),
+            0 => Ok(CompressType::None),
+            1 => Ok(CompressType::Snappy),
+            2 => Ok(CompressType::Huffman),
+            3 => Ok(CompressType::Lz4),
+            4 => Ok(CompressType::Zstd),
+            _ => Err(WondError::Unsupported("unknown compression tag")),
         }
     }
 }
 
+// how hard a mount tries to shrink extents, fast sticks to lz4 and snappy and best also tries
+// zstd and huffman
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CompressPolicy {
+    Off,
+    Fast,
+    Best,
+}
+
+impl CompressPolicy {
+    pub fn parse(policy: &str) -> Option<CompressPolicy> {
+        match policy {
+            "off" => Some(CompressPolicy::Off),
+            "fast" => Some(CompressPolicy::Fast),
+            "best" => Some(CompressPolicy::Best),
+            _ => None,
+        }
+    }
+}
+
+// bytes handed to encode against bytes it put on flash, counted since mount
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompressStat {
+    pub extents: u64,
+    pub compressed_extents: u64,
+    pub raw_bytes: u64,
+    pub stored_bytes: u64,
+}
+
+impl CompressStat {
+    pub fn saved_bytes(&self) -> u64 {
+        self.raw_bytes.saturating_sub(self.stored_bytes)
+    }
+}
+
 pub trait Compress {
-    fn decode(&mut self, bytes: &[u8]) -> Vec<u8>;
+    fn compress_type(&self) -> CompressType;
+    // compressed size in percent of the input, averaged over the recent extents
+    fn coefficient(&self) -> u8;
+    fn decode(&mut self, bytes: &[u8]) -> WondResult<Vec<u8>>;
     fn encode(&mut self, bytes: &[u8]) -> Vec<u8>;
 }
 
 pub struct CompressManager {
     pub effect_huffman: u8,
     pub effect_snappy: u8,
-    pub huffman: huffman::HuffmanCodec,
-    pub snappy: snappy::Snappy,
+    pub codecs: Vec<Box<dyn Compress + Send + Sync>>,
+    pub policy: CompressPolicy,
+    pub stat: CompressStat,
+}
+
+impl Default for CompressManager {
+    fn default() -> Self {
+        CompressManager::new()
+    }
 }
 
 impl CompressManager {
     pub fn new() -> Self {
-        Self {
+        let mut manager = Self {
             effect_huffman: 0,
             effect_snappy: 0,
-            huffman: huffman::HuffmanCodec::new(),
-            snappy: snappy::Snappy::new(),
-        }
+            codecs: vec![],
+            policy: CompressPolicy::Fast,
+            stat: CompressStat::default(),
+        };
+        manager.register(Box::new(huffman::HuffmanCodec::new()));
+        manager.register(Box::new(snappy::Snappy::new()));
+        manager.register(Box::new(lz4::Lz4::new()));
+        manager.register(Box::new(zstd::Zstd::new(zstd::ZstdLevel::Fastest)));
+        manager
     }
 
+    // a codec registered again replaces the earlier one with its tag
+    pub fn register(&mut self, codec: Box<dyn Compress + Send + Sync>) {
+        let compress_type = codec.compress_type();
+        self.codecs.retain(|codec| codec.compress_type() != compress_type);
+        self.codecs.push(codec);
+    }
+
+    pub fn get_codec(&mut self, compress_type: CompressType) -> Option<&mut Box<dyn Compress + Send + Sync>> {
+        self.codecs.iter_mut().find(|codec| codec.compress_type() == compress_type)
+    }
+
+    // tries codecs best score first until one saves enough, an extent nothing helps is stored as is
     pub fn encode(&mut self, bytes: &[u8]) -> (Vec<u8>, u8) {
-        // let mut result = vec![];
-        // let mut compress_type = CompressType::None;
-        // let mut flag = false;
-        // let mut used_algorithm = vec![];
-        // while !flag {
-        //     compress_type = self.choose_compress_type_except(&used_algorithm);
-        //     match compress_type {
-        //         CompressType::Huffman => {
-        //             result = self.huffman.encode(bytes);
-        //         },
-        //         CompressType::Snappy => {
-        //             result = self.snappy.encode(bytes);
-        //         },
-        //         CompressType::None => {
-        //             result = bytes.to_owned();
-        //         },
-        //     }
-        //     used_algorithm.push(compress_type);
-        //     flag = CompressManager::judge_encode_effect(bytes, &result, compress_type);
-        // }
-        // let result = self.snappy.encode(bytes);
-        // (result, CompressType::Snappy.into())
-        (bytes.to_vec(), CompressType::None.into())
+        let mut result = vec![];
+        let mut compress_type = CompressType::None;
+        let mut flag = false;
+        let mut used_algorithm = vec![];
+        while !flag {
+            compress_type = self.choose_compress_type_except(bytes, &used_algorithm);
+            result = self.encode_with_type(bytes, compress_type);
+            used_algorithm.push(compress_type);
+            flag = CompressManager::judge_encode_effect(bytes, &result, compress_type);
+        }
+        self.stat.extents += 1;
+        self.stat.raw_bytes += bytes.len() as u64;
+        self.stat.stored_bytes += result.len() as u64;
+        if compress_type != CompressType::None {
+            self.stat.compressed_extents += 1;
+        }
+        (result, compress_type.into())
     }
 
     pub fn encode_with_type(&mut self, bytes: &[u8], compress_type: CompressType) -> Vec<u8> {
-        match compress_type {
-            CompressType::Huffman => {
-                self.huffman.encode(bytes)
-            },
-            CompressType::Snappy => {
-                self.snappy.encode(bytes)
-            },
-            CompressType::None => {
-                bytes.to_owned()
-            },
+        match self.get_codec(compress_type) {
+            Some(codec) => codec.encode(bytes),
+            None => bytes.to_owned(),
         }
     }
 
-    pub fn decode(&mut self, bytes: &[u8], compress_type: u8) -> Vec<u8> {
-        // let compress_type = compress_type.into();
-        // match compress_type {
-        //     CompressType::Huffman => {
-        //         self.huffman.decode(bytes)
-        //     },
-        //     CompressType::Snappy => {
-        //         self.snappy.decode(bytes)
-        //     },
-        //     CompressType::None => {
-        //         bytes.to_owned()
-        //     },
-        // }
-        // self.snappy.decode(bytes)
-        bytes.to_vec()
-    }
-
-    pub fn choose_compress_type_except(&self, except: &Vec<CompressType>) -> CompressType {
-        if !except.contains(&CompressType::Huffman) {
-            return CompressType::Huffman;
+    // huffman pads its last byte with bits that may decode to extra characters, callers cut
+    // the result down to the extent length
+    pub fn decode(&mut self, bytes: &[u8], compress_type: u8) -> WondResult<Vec<u8>> {
+        let compress_type = CompressType::try_from(compress_type)?;
+        if compress_type == CompressType::None {
+            return Ok(bytes.to_owned());
         }
-        if !except.contains(&CompressType::Snappy) {
-            return CompressType::Snappy;
+        match self.get_codec(compress_type) {
+            Some(codec) => codec.decode(bytes),
+            None => Err(WondError::Unsupported("compression codec is not registered")),
         }
-        CompressType::None
+    }
+
+    // huffman codes chars, so it only sees ascii extents where a char is a byte, and an extent
+    // within one page has no page to save
+    pub fn choose_compress_type_except(&self, bytes: &[u8], except: &[CompressType]) -> CompressType {
+        let mut candidates = match self.policy {
+            _ if bytes.len() <= 4096 => vec![],
+            CompressPolicy::Off => vec![],
+            CompressPolicy::Fast => vec![CompressType::Lz4, CompressType::Snappy],
+            CompressPolicy::Best if bytes.is_ascii() => vec![CompressType::Zstd, CompressType::Huffman, CompressType::Snappy, CompressType::Lz4],
+            CompressPolicy::Best => vec![CompressType::Zstd, CompressType::Snappy, CompressType::Lz4],
+        };
+        candidates.retain(|compress_type| !except.contains(compress_type) && self.codecs.iter().any(|codec| codec.compress_type() == *compress_type));
+        candidates.sort_by_key(|compress_type| self.get_compress_type_score(*compress_type));
+        candidates.first().cloned().unwrap_or(CompressType::None)
     }
 
     pub fn get_compress_type_score(&self, compress_type: CompressType) -> u8 {
-        match compress_type {
-            CompressType::Huffman => {
-                self.huffman.coefficient
-            },
-            CompressType::Snappy => {
-                self.snappy.coefficient
-            },
-            CompressType::None => {
-                1
-            },
-        }
+        self.codecs.iter().find(|codec| codec.compress_type() == compress_type).map_or(1, |codec| codec.coefficient())
     }
 
     pub fn judge_encode_effect(o_data: &[u8], data: &Vec<u8>, compress_type: CompressType) -> bool {
@@ -139,7 +193,7 @@ impl CompressManager {
         if o_data.len() / 4096 == data.len() / 4096 {
             return false;
         }
-        let coeffi = o_data.len() * 100 / data.len();
+        let coeffi = data.len() * 100 / o_data.len();
         if coeffi > 90 {
             return false;
         }
@@ -156,6 +210,63 @@ mod test {
         let data = "fsfjlahuhdwnf.v.sljp;jdqdsjdfhalkshdlhliqjdna,dnlawjdla.jdj.lskd.wnkak".as_bytes();
         let mut manager = CompressManager::new();
         let ret = manager.encode(&data);
-        manager.decode(&ret.0, ret.1);
+        assert_eq!(manager.decode(&ret.0, ret.1).unwrap(), data);
+    }
+
+    #[test]
+    fn policy() {
+        let data = "wondfs keeps its data on flash, ".repeat(1024).into_bytes();
+        let mut manager = CompressManager::new();
+        // an extent smaller than a page never saves one
+        assert_eq!(manager.encode(&data[..1000]).1, u8::from(CompressType::None));
+        let (encoded, compress_type) = manager.encode(&data);
+        assert_eq!(compress_type, u8::from(CompressType::Lz4));
+        assert_eq!(manager.decode(&encoded, compress_type).unwrap(), data);
+        // lz4 has earned the best score, so best keeps using it
+        manager.policy = CompressPolicy::Best;
+        assert_eq!(manager.encode(&data).1, u8::from(CompressType::Lz4));
+        manager.policy = CompressPolicy::Off;
+        assert_eq!(manager.encode(&data).1, u8::from(CompressType::None));
+        assert_eq!(manager.stat.extents, 4);
+        assert_eq!(manager.stat.compressed_extents, 2);
+        assert!(manager.stat.saved_bytes() > data.len() as u64);
+        let mut manager = CompressManager::new();
+        manager.policy = CompressPolicy::Best;
+        let (encoded, compress_type) = manager.encode(&data);
+        assert_eq!(compress_type, u8::from(CompressType::Zstd));
+        assert_eq!(manager.decode(&encoded, compress_type).unwrap(), data);
+        // binary extents are out of huffman's reach
+        let mut manager = CompressManager::new();
+        manager.policy = CompressPolicy::Best;
+        let binary: Vec<u8> = (0..16384u32).map(|i| (i % 7 * 40) as u8).collect();
+        assert_eq!(manager.choose_compress_type_except(&data, &[CompressType::Zstd]), CompressType::Huffman);
+        assert_eq!(manager.choose_compress_type_except(&binary, &[CompressType::Zstd]), CompressType::Snappy);
+        assert_eq!(CompressPolicy::parse("best"), Some(CompressPolicy::Best));
+        assert_eq!(CompressPolicy::parse("zstd"), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn tags() {
+        let data = "every codec reads back what it wrote, ".repeat(512).into_bytes();
+        let mut manager = CompressManager::new();
+        for compress_type in [CompressType::Huffman, CompressType::Snappy, CompressType::Lz4, CompressType::Zstd, CompressType::None] {
+            let tag = u8::from(compress_type);
+            assert_eq!(CompressType::try_from(tag), Ok(compress_type));
+            let encoded = manager.encode_with_type(&data, compress_type);
+            assert_eq!(manager.decode(&encoded, tag).unwrap()[..data.len()], data[..]);
+        }
+        assert_eq!(manager.decode(&data, 9), Err(WondError::Unsupported("unknown compression tag")));
+        assert!(manager.decode(&data, u8::from(CompressType::Lz4)).is_err());
+    }
+
+    #[test]
+    fn remount() {
+        // an extent written by one mount reads back with the codecs of the next
+        let data = "the next mount builds its codecs from scratch, ".repeat(256).into_bytes();
+        for compress_type in [CompressType::Huffman, CompressType::Snappy, CompressType::Lz4, CompressType::Zstd] {
+            let encoded = CompressManager::new().encode_with_type(&data, compress_type);
+            let decoded = CompressManager::new().decode(&encoded, compress_type.into()).unwrap();
+            assert_eq!(decoded[..data.len()], data[..]);
+        }
+    }
+}
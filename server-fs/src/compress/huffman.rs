@@ -1,6 +1,7 @@
 use crate::compress::compress;
+use crate::error::WondResult;
 use std::cell::RefCell;
-use std::collections::{HashMap, hash_map::Iter};
+use std::collections::{BTreeMap, HashMap, btree_map::Iter};
 use std::fmt::Display;
 use std::rc::Rc;
 use std::str;
@@ -17,6 +18,12 @@ pub struct HuffmanTree {
     pub right: Option<RefHuffmanTree>,
 }
 
+impl Default for HuffmanTree {
+    fn default() -> Self {
+        HuffmanTree::new()
+    }
+}
+
 impl HuffmanTree {
     pub fn new() -> Self {
         Self {
@@ -70,13 +77,14 @@ impl HuffmanTree {
     }
 }
 
+// ordered by char, so ties in weight build the same tree and codes on every mount
 pub struct CharWeightMap {
-    pub inner: HashMap<char, Weight>
+    pub inner: BTreeMap<char, Weight>
 }
 
 impl CharWeightMap {
     pub fn build() -> Self {
-        let mut map = HashMap::new();
+        let mut map = BTreeMap::new();
         for c in 0..=255 as u8 {
             map.insert(c as char, 1);
         }
@@ -94,6 +102,10 @@ impl CharWeightMap {
         self.inner.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
     pub fn iter(&self) -> Iter<char, Weight> {
         self.inner.iter()  
     }
@@ -151,6 +163,12 @@ pub struct HuffmanCodec {
     pub decode_map: DecodeConfig,
 }
 
+impl Default for HuffmanCodec {
+    fn default() -> Self {
+        HuffmanCodec::new()
+    }
+}
+
 impl HuffmanCodec {
     pub fn new() -> Self {
         let weight_map = CharWeightMap::build();
@@ -175,7 +193,15 @@ impl HuffmanCodec {
 }
 
 impl compress::Compress for HuffmanCodec {
-    fn decode(&mut self, bytes: &[u8]) -> Vec<u8> {
+    fn compress_type(&self) -> compress::CompressType {
+        compress::CompressType::Huffman
+    }
+
+    fn coefficient(&self) -> u8 {
+        self.coefficient
+    }
+
+    fn decode(&mut self, bytes: &[u8]) -> WondResult<Vec<u8>> {
         let source = bytes.to_vec();
         let start_time = SystemTime::now();
         let mut result = String::with_capacity(self.decode_map.capacity);
@@ -200,7 +226,7 @@ impl compress::Compress for HuffmanCodec {
         let end_time = SystemTime::now();
         let duration = end_time.duration_since(start_time).ok().unwrap();
         self.modify_efficiency(duration.as_micros());
-        result.into_bytes()
+        Ok(result.into_bytes())
     }
 
     fn encode(&mut self, bytes: &[u8]) -> Vec<u8> {        
@@ -281,6 +307,14 @@ mod test {
         let data = "fsfjlahuhdwnf.v.sljp;jdqdsjdfhalkshdlhliqjfsfjlahuhdwnf.v.sljp;jdqdsjdfhalkshdlhliqjdna,dnlawjdla.jdj.lskd.wnkakadmbDmabdmadahqbdkfsfsknasnwnkdnsnsckwkcwjlkrjflqwjclamlqwdjwlfdjlamflcmljwijrlqflkmlkmlam;c;wk;rk;qkf;,l.e,s;lad;lca;skc;lkasc;k;wk;ekr;qkw;fk;qk;aclks;lck;kwe;qlkf;lwekf;lqk;kca/kcq/;kf;/wq;er/;wemc;kasd/vjlerhgnkv,bsfnqlnfknjk,env,nq,nfwqnf.wmlmvavqljwlejl   jdlj    llk jcljljhajsjqbwd bdkcdashlcahlcb,kbd,    n,kew   kdkqwn,cknc ,k,qnwn qbd,k   bx, mbmasbcmbambmdbamcbamscmnavfkjfhkqwhecquhakcbkwb,ek,fbqwfqwbfnqefkqfqewfqwfqvaddna,dnlawjdla.jdj.lskd.wnkakadmbDmabdmadahqbdkfsfsknasnwnkdnsnsckwkcwjlkrjflqwjclamlqwdjwlfdjlamflcmljwijrlqflkmlkmlam;c;wk;rk;qkf;,l.e,s;lad;lca;skc;lkasc;k;wk;ekr;qkw;fk;qk;aclks;lck;kwe;qlkf;lwekf;lqk;kca/kcq/;kf;/wq;er/;wemc;kasd/vjlerhgnkv,bsfnqlnfknjk,env,nq,nfwqnf.wmlmvavqljwlejl   jdlj    llk jcljljhajsjqbwd bdkcdashlcahlcb,kbd,    n,kew   kdkqwn,cknc ,k,qnwn qbd,k   bx, mbmasbcmbambmdbamcbamscmnavfkjfhkqwhecquhakcbkwb,ek,fbqwfqwbfnqefkqfqewfqwfqvadvavafsfjlahuhdwnf.v.sljp;jdqdsjdfhalkshdlhliqjdna,dnlawjdla.jdj.lskd.wnkakadmbDmabdmadahqbdkfsfsknasnwnkdnsnsckwkcwjlkrjflqwjclamlqwdjwlfdjlamflcmljwijrlqflkmlkmlam;c;wk;rk;qkf;,l.e,s;lad;lca;skc;lkasc;k;wk;ekr;qkw;fk;qk;aclks;lck;kwe;qlkf;lwekf;lqk;kca/kcq/;kf;/wq;er/;wemc;kasd/vjlerhgnkv,bsfnqlnfknjk,env,nq,nfwqnf.wmlmvavqljwlejl   jdlj    llk jcljljhajsjqbwd bdkcdashlcahlcb,kbd,    n,kew   kdkqwn,cknc ,k,qnwn qbd,k   bx, mbmasbcmbambmdbamcbamscmnavfkjfhkqwhecquhakcbkwb,ek,fbqwfqwbfnqefkqfqewfqwfqvadfsfjlahuhdwnf.v.sljp;jdqdsjdfhalkshdlhliqjdna,dnlawjdla.jdj.lskd.wnkakadmbDmabdmadahqbdkfsfsknasnwnkdnsnsckwkcwjlkrjflqwjclamlqwdjwlfdjlamflcmljwijrlqflkmlkmlam;c;wk;rk;qkf;,l.e,s;lad;lca;skc;lkasc;k;wk;ekr;qkw;fk;qk;aclks;lck;kwe;qlkf;lwekf;lqk;kca/kcq/;kf;/wq;er/;wemc;kasd/vjlerhgnkv,bsfnqlnfknjk,env,nq,nfwqnf.wmlmvavqljwlejl   jdlj    llk jcljljhajsjqbwd bdkcdashlcahlcb,kbd,    n,kew   kdkqwn,cknc ,k,qnwn qbd,k   bx, mbmasbcmbambmdbamcbamscmnavfkjfhkqwhecquhakcbkwb,ek,fbqwfqwbfnqefkqfqewfqwfqvadfsfjlahuhdwnf.v.sljp;jdqdsjdfhalkshdlhliqjdna,dnlawjdla.jdj.lskd.wnkakadmbDmabdmadahqbdkfsfsknasnwnkdnsnsckwkcwjlkrjflqwjclamlqwdjwlfdjlamflcmljwijrlqflkmlkmlam;c;wk;rk;qkf;,l.e,s;lad;lca;skc;lkasc;k;wk;ekr;qkw;fk;qk;aclks;lck;kwe;qlkf;lwekf;lqk;kca/kcq/;kf;/wq;er/;wemc;kasd/vjlerhgnkv,bsfnqlnfknjk,env,nq,nfwqnf.wmlmvavqljwlejl   jdlj    llk jcljljhajsjqbwd bdkcdashlcahlcb,kbd,    n,kew   kdkqwn,cknc ,k,qnwn qbd,k   bx, mbmasbcmbambmdbamcbamscmnavfkjfhkqwhecquhakcbkwb,ek,fbqwfqwbfnqefkqfqewfqwfqvadfqfqv".as_bytes();
         let mut compress = HuffmanCodec::new();
         let compressed = compress.encode(&data);
-        compress.decode(&compressed);
+        compress.decode(&compressed).unwrap();
+    }
+
+    #[test]
+    fn stable_codes() {
+        let codec = HuffmanCodec::new();
+        for _ in 0..8 {
+            assert_eq!(HuffmanCodec::new().bit_map.inner, codec.bit_map.inner);
+        }
     }
 }
\ No newline at end of file
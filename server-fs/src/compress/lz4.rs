@@ -0,0 +1,77 @@
+use crate::compress::compress;
+use crate::error::{WondError, WondResult};
+use std::time::SystemTime;
+
+// lz4 block format with the raw length in front, faster than snappy on both ends
+pub struct Lz4 {
+    pub coefficient: u8,
+    pub efficiency: u128,
+}
+
+impl Default for Lz4 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lz4 {
+    pub fn new() -> Self {
+        Self {
+            coefficient: 1,
+            efficiency: 0,
+        }
+    }
+
+    pub fn modify_coefficient(&mut self, coeffi: u8) {
+        self.coefficient = (6 * coeffi as u16 / 10 + 4 * self.coefficient as u16 / 10) as u8;
+    }
+
+    pub fn modify_efficiency(&mut self, effi: u128) {
+        self.efficiency = 6 * effi / 10 + 4 * self.efficiency / 10;
+    }
+}
+
+impl compress::Compress for Lz4 {
+    fn compress_type(&self) -> compress::CompressType {
+        compress::CompressType::Lz4
+    }
+
+    fn coefficient(&self) -> u8 {
+        self.coefficient
+    }
+
+    fn encode(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let start_time = SystemTime::now();
+        let res = lz4_flex::block::compress_prepend_size(bytes);
+        let end_time = SystemTime::now();
+        let duration = end_time.duration_since(start_time).ok().unwrap();
+        self.modify_efficiency(duration.as_micros());
+        self.modify_coefficient((res.len() * 100 / bytes.len()).min(255) as u8);
+        res
+    }
+
+    fn decode(&mut self, bytes: &[u8]) -> WondResult<Vec<u8>> {
+        let start_time = SystemTime::now();
+        let buf = lz4_flex::block::decompress_size_prepended(bytes).map_err(|_| WondError::Corrupted("lz4 block does not decode"))?;
+        let end_time = SystemTime::now();
+        let duration = end_time.duration_since(start_time).ok().unwrap();
+        self.modify_efficiency(duration.as_micros());
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compress::compress::Compress;
+    use super::*;
+
+    #[test]
+    fn basics() {
+        let data = "lz4 finds the same matches snappy does, ".repeat(256).into_bytes();
+        let mut compress = Lz4::new();
+        let compressed = compress.encode(&data);
+        assert!(compressed.len() < data.len() / 10);
+        assert_eq!(compress.decode(&compressed).unwrap(), data);
+        assert!(compress.decode(&compressed[..compressed.len() / 2]).is_err());
+    }
+}
@@ -1,3 +1,5 @@
 pub mod snappy;
 pub mod huffman;
-pub mod compress;
\ No newline at end of file
+pub mod lz4;
+pub mod zstd;
+pub mod compress;
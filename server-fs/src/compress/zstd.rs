@@ -0,0 +1,93 @@
+use crate::compress::compress;
+use crate::error::{WondError, WondResult};
+use std::time::SystemTime;
+
+// the levels the pure rust encoder implements, anything it writes any zstd decoder reads
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ZstdLevel {
+    Uncompressed,
+    Fastest,
+}
+
+// zstd frames, slower than lz4 but with an entropy stage that shrinks text much further
+pub struct Zstd {
+    pub coefficient: u8,
+    pub efficiency: u128,
+    pub level: ZstdLevel,
+}
+
+impl Zstd {
+    pub fn new(level: ZstdLevel) -> Self {
+        Self {
+            coefficient: 1,
+            efficiency: 0,
+            level,
+        }
+    }
+
+    pub fn modify_coefficient(&mut self, coeffi: u8) {
+        self.coefficient = (6 * coeffi as u16 / 10 + 4 * self.coefficient as u16 / 10) as u8;
+    }
+
+    pub fn modify_efficiency(&mut self, effi: u128) {
+        self.efficiency = 6 * effi / 10 + 4 * self.efficiency / 10;
+    }
+}
+
+impl compress::Compress for Zstd {
+    fn compress_type(&self) -> compress::CompressType {
+        compress::CompressType::Zstd
+    }
+
+    fn coefficient(&self) -> u8 {
+        self.coefficient
+    }
+
+    fn encode(&mut self, bytes: &[u8]) -> Vec<u8> {
+        use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+        let level = match self.level {
+            ZstdLevel::Uncompressed => CompressionLevel::Uncompressed,
+            ZstdLevel::Fastest => CompressionLevel::Fastest,
+        };
+        let start_time = SystemTime::now();
+        let res = compress_to_vec(bytes, level);
+        let end_time = SystemTime::now();
+        let duration = end_time.duration_since(start_time).ok().unwrap();
+        self.modify_efficiency(duration.as_micros());
+        self.modify_coefficient((res.len() * 100 / bytes.len()).min(255) as u8);
+        res
+    }
+
+    fn decode(&mut self, bytes: &[u8]) -> WondResult<Vec<u8>> {
+        use ruzstd::decoding::StreamingDecoder;
+        use std::io::Read;
+        let start_time = SystemTime::now();
+        let mut buf = vec![];
+        let mut source = bytes;
+        let mut decoder = StreamingDecoder::new(&mut source).map_err(|_| WondError::Corrupted("zstd frame header does not decode"))?;
+        decoder.read_to_end(&mut buf).map_err(|_| WondError::Corrupted("zstd frame does not decode"))?;
+        let end_time = SystemTime::now();
+        let duration = end_time.duration_since(start_time).ok().unwrap();
+        self.modify_efficiency(duration.as_micros());
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compress::compress::Compress;
+    use super::*;
+
+    #[test]
+    fn basics() {
+        let data = "zstd adds entropy coding on top of the matches, ".repeat(256).into_bytes();
+        let mut compress = Zstd::new(ZstdLevel::Fastest);
+        let compressed = compress.encode(&data);
+        assert!(compressed.len() < data.len() / 10);
+        assert_eq!(compress.decode(&compressed).unwrap(), data);
+        assert!(compress.decode(&data[..64]).is_err());
+        let mut compress = Zstd::new(ZstdLevel::Uncompressed);
+        let stored = compress.encode(&data);
+        assert_eq!(compress.decode(&stored).unwrap(), data);
+    }
+}
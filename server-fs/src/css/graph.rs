@@ -2,10 +2,12 @@ use std::collections::HashMap;
 use std::collections::BinaryHeap;
 use std::collections::HashSet;
 use crate::kv::kv::KV;
-use rkyv::ser::{Serializer, serializers::AllocSerializer};
+use crate::error::WondResult;
+use crate::util::archive::{self, Versioned};
 use rkyv::{Archive, Deserialize, Serialize};
 
 #[derive(Archive, Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[archive(check_bytes)]
 pub struct Node {
     node_id: usize,
     node_ino: Vec<u32>,
@@ -13,7 +15,13 @@ pub struct Node {
     edges: Vec<Edge>,
 }
 
+impl Versioned for Node {
+    const KIND: u8 = archive::KIND_CSS_NODE;
+    const VERSION: u8 = 1;
+}
+
 #[derive(Archive, Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[archive(check_bytes)]
 pub struct Edge {
     end_node_id: usize,
     weight: usize,
@@ -28,7 +36,7 @@ pub struct Graph {
 }
 
 impl Graph {
-    pub fn add_node(&mut self, node_ino: u32, node_hash: Vec<bool>) {
+    pub fn add_node(&mut self, node_ino: u32, node_hash: Vec<bool>) -> WondResult<()> {
         let ret = self.contains_node_hash(&node_hash);
         if ret.is_some() {
             let index = ret.unwrap();
@@ -36,23 +44,23 @@ impl Graph {
                 self.ino_map.insert(node_ino, self.nodes[index].node_id);
                 self.nodes[index].node_ino.push(node_ino);
             }
-            return;
+            return Ok(());
         }
         self.curr_max_node_id += 1;
-        self.kv.set_extra_value("max_node_id".to_string(), &Graph::encode_max_node_id(self.curr_max_node_id));
+        self.kv.set_extra_value("max_node_id".to_string(), &Graph::encode_max_node_id(self.curr_max_node_id))?;
         let node_id = self.curr_max_node_id;
-        self.insert_node(node_id, node_ino, node_hash);
+        self.insert_node(node_id, node_ino, node_hash)
     }
 
-    pub fn delete_node_by_ino(&mut self, node_ino: u32) {
+    pub fn delete_node_by_ino(&mut self, node_ino: u32) -> WondResult<()> {
         let ret = self.ino_map.get(&node_ino);
         if ret.is_none() {
-            return;
+            return Ok(());
         }
         let node_id = *ret.unwrap();
         let index = *self.map.get(&node_id).unwrap();
         if self.nodes[index].node_ino.len() == 1 {
-            self.delete_node(index);
+            self.delete_node(index)?;
         } else {
             for i in 0..self.nodes[index].node_ino.len() {
                 if self.nodes[index].node_ino[i] == node_ino {
@@ -61,21 +69,22 @@ impl Graph {
                 }
             }
             let data = Graph::encode_node(&self.nodes[index]);
-            self.kv.set_extra_value(format!("e:node:{}", self.nodes[index].node_id), &data);
+            self.kv.set_extra_value(format!("e:node:{}", self.nodes[index].node_id), &data)?;
         }
+        Ok(())
     }
 
-    pub fn delete_node_by_hash(&mut self, node_hash: Vec<bool>) {
+    pub fn delete_node_by_hash(&mut self, node_hash: Vec<bool>) -> WondResult<()> {
         let ret = self.contains_node_hash(&node_hash);
         if ret.is_none() {
-            return;
+            return Ok(());
         }
-        self.delete_node(ret.unwrap());
+        self.delete_node(ret.unwrap())
     }
 
-    pub fn edit_node(&mut self, node_ino: u32, new_node_hash: Vec<bool>) {
-        self.delete_node_by_ino(node_ino);
-        self.add_node(node_ino, new_node_hash);
+    pub fn edit_node(&mut self, node_ino: u32, new_node_hash: Vec<bool>) -> WondResult<()> {
+        self.delete_node_by_ino(node_ino)?;
+        self.add_node(node_ino, new_node_hash)
     }
 
     pub fn query_node_by_ino(&mut self, node_ino: u32, radii: usize) -> Option<Vec<u32>> {
@@ -129,22 +138,23 @@ impl Graph {
         }
     }
 
-    pub fn build(&mut self) {
-        let ret = self.kv.get_extra_value("max_node_id".to_string());
+    pub fn build(&mut self) -> WondResult<()> {
+        let ret = self.kv.get_extra_value("max_node_id".to_string())?;
         if ret.is_none() {
             self.curr_max_node_id = 0;
         } else {
             self.curr_max_node_id = Graph::decode_max_node_id(ret.unwrap());
         }
         for id in 0..self.curr_max_node_id {
-            let data = self.kv.get_extra_value(format!("e:node:{}", id));
+            let data = self.kv.get_extra_value(format!("e:node:{}", id))?;
             if data.is_some() {
-                let node = Graph::decode_node(data.unwrap());
+                let node = Graph::decode_node(data.unwrap())?;
                 for node_ino in node.node_ino {
-                    self.insert_node(node.node_id, node_ino, node.node_hash.clone());
+                    self.insert_node(node.node_id, node_ino, node.node_hash.clone())?;
                 }
             }
         }
+        Ok(())
     }
 
     pub fn contains_node_hash(&self, node_hash: &Vec<bool>) -> Option<usize> {
@@ -156,7 +166,7 @@ impl Graph {
         None
     }
 
-    pub fn insert_node(&mut self, node_id: usize, node_ino: u32, node_hash: Vec<bool>) {
+    pub fn insert_node(&mut self, node_id: usize, node_ino: u32, node_hash: Vec<bool>) -> WondResult<()> {
         let ret = self.contains_node_hash(&node_hash);
         if ret.is_some() {
             let index = ret.unwrap();
@@ -164,7 +174,7 @@ impl Graph {
                 self.ino_map.insert(node_ino, self.nodes[index].node_id);
                 self.nodes[index].node_ino.push(node_ino);
             }
-            return;
+            return Ok(());
         }
         let mut node = Node {
             node_id,
@@ -221,10 +231,10 @@ impl Graph {
         self.ino_map.insert(node.node_ino[0], node.node_id);
         self.nodes.push(node.clone());
         let data = Graph::encode_node(&node);
-        self.kv.set_extra_value(format!("e:node:{}", node.node_id), &data);
+        self.kv.set_extra_value(format!("e:node:{}", node.node_id), &data)
     }
 
-    pub fn delete_node(&mut self, index: usize) {
+    pub fn delete_node(&mut self, index: usize) -> WondResult<()> {
         let node = self.nodes.remove(index);
         self.map.remove(&node.node_id);
         for ino in node.node_ino {
@@ -245,7 +255,7 @@ impl Graph {
                 }
             }
         }
-        self.kv.deleete_extra_value(format!("e:node:{}", node.node_id));
+        self.kv.deleete_extra_value(format!("e:node:{}", node.node_id))
     }
 
     pub fn query_node(&mut self, index: usize, radii: usize) -> Option<Vec<usize>> {
@@ -311,13 +321,10 @@ impl Graph {
     }
 
     pub fn encode_node(node: &Node) -> Vec<u8> {
-        let mut serializer = AllocSerializer::<0>::default();
-        serializer.serialize_value(node).unwrap();
-        serializer.into_serializer().into_inner().to_vec()
+        archive::encode(node)
     }
 
-    pub fn decode_node(data: Vec<u8>) -> Node {
-        let archived = unsafe { rkyv::archived_root::<Node>(&data) };
-        archived.deserialize(&mut rkyv::Infallible).ok().unwrap()
+    pub fn decode_node(data: Vec<u8>) -> WondResult<Node> {
+        archive::decode(&data)
     }
 }
\ No newline at end of file
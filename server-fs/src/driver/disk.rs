@@ -1,6 +1,9 @@
+use std::io;
+use std::env;
+use std::path::Path;
 use std::fs::{File, OpenOptions};
 use std::os::unix::prelude::FileExt;
-use std::env;
+use crate::error::{WondError, WondResult};
 
 pub struct Disk {
     pub file: File,
@@ -9,82 +12,80 @@ pub struct Disk {
 }
 
 impl Disk {
-    pub fn new() -> Disk {
-        let current_path = env::current_dir().ok().unwrap();
-        let file_path = current_path.join("test.img");
-        let ret = OpenOptions::new().read(true).write(true).open(file_path);
-        if ret.is_err() {
-            panic!();
-        }
-        let f = ret.ok().unwrap();
-        let size = f.metadata().unwrap().len();
+    pub fn new() -> WondResult<Disk> {
+        let file_path = env::current_dir()?.join("test.img");
+        Ok(Disk::open(&file_path)?)
+    }
+
+    pub fn open(path: &Path) -> io::Result<Disk> {
+        let f = OpenOptions::new().read(true).write(true).open(path)?;
+        let size = f.metadata()?.len();
         let block_num = size / (128 * 4096);
         let size = size / 4096;
-        Disk {
+        Ok(Disk {
             size: size as u32,
             block_num: block_num as u32,
             file: f,
-        }
+        })
+    }
+
+    pub fn create(path: &Path, block_num: u32) -> io::Result<Disk> {
+        let f = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        f.set_len(block_num as u64 * 128 * 4096)?;
+        Ok(Disk {
+            size: block_num * 128,
+            block_num,
+            file: f,
+        })
     }
 }
 
 impl Disk {
-    pub fn disk_read(&self, address: u32) -> [u8; 4096] {
-        if address > self.size - 1 {
-            panic!("FakeDisk: read at too big address");
+    pub fn disk_read(&self, address: u32) -> WondResult<[u8; 4096]> {
+        if address >= self.size {
+            return Err(WondError::Device("read at too big address"));
         }
-        let offset = address * 4096;
+        let offset = address as u64 * 4096;
         let mut buf = [0; 4096];
-        let ret = self.file.read_at(&mut buf, offset as u64);
-        if ret.is_err() {
-            panic!();
-        }
-        buf
+        self.file.read_exact_at(&mut buf, offset)?;
+        Ok(buf)
     }
 
-    pub fn disk_read_advanced(&self, address: u32, buf: &mut[u8]) {
-        if address > self.size - 1 {
-            panic!("FakeDisk: read at too big address");
-        }
-        let offset = address * 4096;
-        let ret = self.file.read_at(buf, offset as u64);
-        if ret.is_err() {
-            panic!();
+    pub fn disk_read_advanced(&self, address: u32, buf: &mut[u8]) -> WondResult<()> {
+        if address >= self.size {
+            return Err(WondError::Device("read at too big address"));
         }
+        let offset = address as u64 * 4096;
+        self.file.read_exact_at(buf, offset)?;
+        Ok(())
     }
 
-    pub fn disk_block_read(&self, block_no: u32, buf: &mut[u8]) {
-        if block_no > self.block_num - 1 {
-            panic!("FakeDisk: read at too big block_no");
-        }
-        let offset = block_no * 128 * 4096;
-        let ret = self.file.read_at(buf, offset as u64);
-        if ret.is_err() {
-            panic!();
+    pub fn disk_block_read(&self, block_no: u32, buf: &mut[u8]) -> WondResult<()> {
+        if block_no >= self.block_num {
+            return Err(WondError::Device("read at too big block_no"));
         }
+        let offset = block_no as u64 * 128 * 4096;
+        self.file.read_exact_at(buf, offset)?;
+        Ok(())
     }
     
-    pub fn disk_write(&mut self, address: u32, data: &[u8; 4096]) {
-        if address > self.size - 1 {
-            panic!("FakeDisk: write at too big address");
-        }
-        let offset = address * 4096;
-        let ret = self.file.write_at(data, offset as u64);
-        if ret.is_err() {
-            panic!();
+    pub fn disk_write(&mut self, address: u32, data: &[u8; 4096]) -> WondResult<()> {
+        if address >= self.size {
+            return Err(WondError::Device("write at too big address"));
         }
+        let offset = address as u64 * 4096;
+        self.file.write_all_at(data, offset)?;
+        Ok(())
     }
 
-    pub fn disk_erase(&mut self, block_no: u32) {
-        if block_no > self.block_num - 1 {
-            panic!("FakeDisk: erase at too big block number");
+    pub fn disk_erase(&mut self, block_no: u32) -> WondResult<()> {
+        if block_no >= self.block_num {
+            return Err(WondError::Device("erase at too big block number"));
         }
-        let offset = block_no * 128 * 4096;
+        let offset = block_no as u64 * 128 * 4096;
         let data = [0; 4096 * 128];
-        let ret = self.file.write_at(&data, offset as u64);
-        if ret.is_err() {
-            panic!();
-        }
+        self.file.write_all_at(&data, offset)?;
+        Ok(())
     }
 }
 
@@ -1,5 +1,6 @@
 use crate::driver::disk;
 use crate::driver::fake_disk;
+use crate::error::WondResult;
 
 pub struct DiskManager {
     pub is_virtual: bool,
@@ -8,81 +9,93 @@ pub struct DiskManager {
 }
 
 impl DiskManager {
-    pub fn new(is_virtual: bool) -> DiskManager {
-        let fake_disk;
-        let disk;
+    pub fn new(is_virtual: bool) -> WondResult<DiskManager> {
         if is_virtual {
-            let block_num = 1224;
-            fake_disk = Some(fake_disk::FakeDisk::new(block_num * 128));
-            disk = None;
+            Ok(DiskManager::virtual_disk())
         } else {
-            fake_disk = None;
-            disk = Some(disk::Disk::new());
+            Ok(DiskManager::with_disk(disk::Disk::new()?))
         }
+    }
+
+    pub fn virtual_disk() -> DiskManager {
+        let block_num = 1224;
+        DiskManager::with_fake_disk(fake_disk::FakeDisk::new(block_num * 128))
+    }
+
+    pub fn with_fake_disk(fake_disk: fake_disk::FakeDisk) -> DiskManager {
+        DiskManager {
+            is_virtual: true,
+            fake_disk: Some(fake_disk),
+            disk: None,
+        }
+    }
+
+    pub fn with_disk(disk: disk::Disk) -> DiskManager {
         DiskManager {
-            is_virtual,
-            fake_disk,
-            disk,
+            is_virtual: false,
+            fake_disk: None,
+            disk: Some(disk),
         }
     }
 }
 
 impl DiskManager {
-    pub fn disk_read(&self, address: u32) -> [u8; 4096] {
+    pub fn disk_read(&self, address: u32) -> WondResult<[u8; 4096]> {
         if self.is_virtual {
             return self.fake_disk.as_ref().unwrap().fake_disk_read(address);
         }
-        return self.disk.as_ref().unwrap().disk_read(address);
+        self.disk.as_ref().unwrap().disk_read(address)
     }
 
-    pub fn disk_read_advanced(&self, address: u32, buf: &mut[u8]) {
+    pub fn disk_read_advanced(&self, address: u32, buf: &mut[u8]) -> WondResult<()> {
         if self.is_virtual {
-            self.fake_disk.as_ref().unwrap().fake_disk_read_advanced(address, buf);
-            return;
+            return self.fake_disk.as_ref().unwrap().fake_disk_read_advanced(address, buf);
         }
-        self.disk.as_ref().unwrap().disk_read_advanced(address, buf);
+        self.disk.as_ref().unwrap().disk_read_advanced(address, buf)
     }
 
-    pub fn disk_block_read(&self, block_no: u32, buf: &mut[u8]) {
+    pub fn disk_block_read(&self, block_no: u32, buf: &mut[u8]) -> WondResult<()> {
         if self.is_virtual {
-            self.fake_disk.as_ref().unwrap().fake_disk_block_read_advanced(block_no, buf);
-            return;
+            return self.fake_disk.as_ref().unwrap().fake_disk_block_read_advanced(block_no, buf);
         }
-        self.disk.as_ref().unwrap().disk_block_read(block_no, buf);
+        self.disk.as_ref().unwrap().disk_block_read(block_no, buf)
     }
 
-    pub fn disk_write(&mut self, address: u32, data: &[u8; 4096]) {
+    pub fn disk_write(&mut self, address: u32, data: &[u8; 4096]) -> WondResult<()> {
         if self.is_virtual {
             return self.fake_disk.as_mut().unwrap().fake_disk_write(address, data);
         }
-        self.disk.as_mut().unwrap().disk_write(address, data);
+        self.disk.as_mut().unwrap().disk_write(address, data)
     }
 
-    pub fn disk_erase(&mut self, block_no: u32) {
+    pub fn disk_erase(&mut self, block_no: u32) -> WondResult<()> {
         if self.is_virtual {
             return self.fake_disk.as_mut().unwrap().fake_disk_erase(block_no);
         }
-        self.disk.as_mut().unwrap().disk_erase(block_no);
+        self.disk.as_mut().unwrap().disk_erase(block_no)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::error::WondError;
 
     #[test]
     fn basics() {
-        let mut manager = DiskManager::new(true);
+        let mut manager = DiskManager::new(true).unwrap();
         let data = [1; 4096];
-        manager.disk_write(100, &data);
-        let data = manager.disk_read(100);
+        manager.disk_write(100, &data).unwrap();
+        let data = manager.disk_read(100).unwrap();
         assert_eq!(data, [1; 4096]);
         let data =[2; 4096];
-        manager.disk_write(256, &data);
-        let data = manager.disk_read(256);
+        manager.disk_write(256, &data).unwrap();
+        let data = manager.disk_read(256).unwrap();
         assert_eq!(data, [2; 4096]);
-        manager.disk_erase(2);
-        let data = manager.disk_read(1);
+        assert_eq!(manager.disk_write(256, &data), Err(WondError::Device("write at not clean address")));
+        manager.disk_erase(2).unwrap();
+        let data = manager.disk_read(1).unwrap();
         assert_eq!(data, [0; 4096]);
+        assert!(manager.disk_read(1224 * 128).is_err());
     }
 }
\ No newline at end of file
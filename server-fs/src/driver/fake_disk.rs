@@ -1,14 +1,53 @@
 use crate::kv::component::super_block::*;
-use rkyv::ser::{Serializer, serializers::AllocSerializer};
+use crate::error::{WondError, WondResult};
 
+// how the simulated flash ages, the default is a perfect medium that never wears out
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlashModel {
+    // erases a block takes before it wears out and refuses the next one, 0 is unlimited
+    pub erase_limit: u32,
+    // flipped bits expected in a page programmed on a block at the end of its life, a
+    // block with half its erases used gets a quarter of them
+    pub bit_flips: f64,
+    // chance of a program or erase failing and retiring its block, grows with wear like bit_flips
+    pub program_fail_rate: f64,
+    pub erase_fail_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for FlashModel {
+    fn default() -> FlashModel {
+        FlashModel {
+            erase_limit: 0,
+            bit_flips: 0.0,
+            program_fail_rate: 0.0,
+            erase_fail_rate: 0.0,
+            seed: 0x2545f4914f6cdd1d,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct FakeDisk {
     pub size: u32,
     pub block_num: u32,
     pub data: Vec<[u8; 4096]>,
+    // erases each block has taken, for checking how evenly wear is spread
+    pub erase_count: Vec<u32>,
+    pub model: FlashModel,
+    // blocks that failed a program or erase, they still read but take no more writes
+    pub bad_block: Vec<bool>,
+    // a page is programmed once and then only an erase of its block clears it
+    pub programmed: Vec<bool>,
+    rng: u64,
 }
 
 impl FakeDisk {
     pub fn new(size: u32) -> FakeDisk {
+        FakeDisk::with_model(size, FlashModel::default())
+    }
+
+    pub fn with_model(size: u32, model: FlashModel) -> FakeDisk {
         let mut data = vec![];
         if size % 128 != 0 {
             panic!("FakeDisk: not available size")
@@ -17,87 +56,203 @@ impl FakeDisk {
             data.push([0; 4096]);
         }
         let block_num = size / 128;
-        let kv_ratio = 0.15;
-        let main_ratio = 0.6;
-        let super_stat = SuperStat {
-            magic_code: MAGICNUMBER,
-            block_num: block_num as u32,
-            super_block_num: 1,
-            bit_block_num: 2,
-            pit_block_num: 2,
-            journal_block_num: 1,
-            kv_block_num: (block_num as f64 * kv_ratio) as u32,
-            main_area_block_num:(block_num as f64 * main_ratio) as u32,
-            reserved_block_num: block_num as u32 - 6 - (block_num as f64 * kv_ratio) as u32 - (block_num as f64 * main_ratio) as u32,
-            page_size: 4096,
-            page_num_per_block: 128,
-        };
-        let mut serializer = AllocSerializer::<0>::default();
-        serializer.serialize_value(&super_stat).unwrap();
-        let mut stat_data = serializer.into_serializer().into_inner().to_vec();
-        let len = stat_data.len();
-        let mut stat_len: [u8; 4]  = [0; 4];
-        stat_len[0] = (len >> 24) as u8;
-        stat_len[1] = (len >> 16) as u8;
-        stat_len[2] = (len >> 8) as u8;
-        stat_len[3] = len as u8;
-        data[0][0..4].copy_from_slice(&stat_len);
-        data[0][4..4+len].copy_from_slice(&stat_data);
+        let super_stat = SuperStat::default_geometry(block_num).expect("FakeDisk: image too small");
+        data[0] = super_stat.encode();
+        let mut programmed = vec![false; size as usize];
+        programmed[0] = true;
         FakeDisk {
             size,
             data,
             block_num,
+            erase_count: vec![0; block_num as usize],
+            bad_block: vec![false; block_num as usize],
+            programmed,
+            rng: model.seed.max(1),
+            model,
         }
     }
+
+    pub fn bad_block_num(&self) -> u32 {
+        self.bad_block.iter().filter(|bad| **bad).count() as u32
+    }
 }
 
 impl FakeDisk {
-    pub fn fake_disk_read(&self, address: u32) -> [u8; 4096] {
-        if address > self.size - 1 {
-            panic!("FakeDisk: read at too big address");
+    pub fn fake_disk_read(&self, address: u32) -> WondResult<[u8; 4096]> {
+        if address >= self.size {
+            return Err(WondError::Device("read at too big address"));
         }
-        self.data[address as usize]
+        Ok(self.data[address as usize])
     }
 
-    pub fn fake_disk_read_advanced(&self, address: u32, buf: &mut[u8]) {
-        if address > self.size - 1 {
-            panic!("FakeDisk: read at too big address");
+    pub fn fake_disk_read_advanced(&self, address: u32, buf: &mut[u8]) -> WondResult<()> {
+        if address >= self.size {
+            return Err(WondError::Device("read at too big address"));
         }
         buf.copy_from_slice(&self.data[address as usize][..buf.len()]);
+        Ok(())
     }
 
-    pub fn fake_disk_block_read_advanced(&self, block_no: u32, buf: &mut[u8]) {
+    pub fn fake_disk_block_read_advanced(&self, block_no: u32, buf: &mut[u8]) -> WondResult<()> {
         std::thread::sleep(std::time::Duration::from_micros(50));
-        if block_no > self.block_num - 1 {
-            panic!("FakeDisk: read at too big block_no");
+        if block_no >= self.block_num {
+            return Err(WondError::Device("read at too big block_no"));
         }
         let start_index = 128 * block_no;
         for i in 0..128 {
             buf[i*4096..(i+1)*4096].copy_from_slice(&self.data[start_index as usize+i]);
         }
+        Ok(())
     }
-    
-    pub fn fake_disk_write(&mut self, address: u32, data: &[u8; 4096]) {
+
+    pub fn fake_disk_write(&mut self, address: u32, data: &[u8; 4096]) -> WondResult<()> {
         std::thread::sleep(std::time::Duration::from_micros(50));
-        if address > self.size - 1 {
-            panic!("FakeDisk: write at too big address");
+        if address >= self.size {
+            return Err(WondError::Device("write at too big address"));
+        }
+        if self.programmed[address as usize] {
+            return Err(WondError::Device("write at not clean address"));
+        }
+        let block_no = (address / 128) as usize;
+        if self.bad_block[block_no] {
+            return Err(WondError::BadBlock("program on a retired block"));
         }
-        let o_data = self.data[address as usize];
-        if o_data != [0; 4096] {
-            panic!("FakeDisk: write at not clean address");
+        self.programmed[address as usize] = true;
+        let wear = self.wear(block_no);
+        if self.chance(self.model.program_fail_rate * wear) {
+            self.bad_block[block_no] = true;
+            return Err(WondError::BadBlock("program failed"));
         }
-        self.data[address as usize] = *data;
+        let mut page = *data;
+        let flips = self.model.bit_flips * wear * wear;
+        let mut count = flips as u32;
+        if self.chance(flips.fract()) {
+            count += 1;
+        }
+        for _ in 0..count {
+            let bit = (self.next_random() % (4096 * 8)) as usize;
+            page[bit / 8] ^= 1 << (bit % 8);
+        }
+        self.data[address as usize] = page;
+        Ok(())
     }
 
-    pub fn fake_disk_erase(&mut self, block_no: u32) {
+    pub fn fake_disk_erase(&mut self, block_no: u32) -> WondResult<()> {
         std::thread::sleep(std::time::Duration::from_micros(50));
-        if block_no > self.block_num - 1 {
-            panic!("FakeDisk: erase at too big block number");
+        if block_no >= self.block_num {
+            return Err(WondError::Device("erase at too big block number"));
+        }
+        let index = block_no as usize;
+        if self.bad_block[index] {
+            return Err(WondError::BadBlock("erase of a retired block"));
+        }
+        if self.model.erase_limit != 0 && self.erase_count[index] >= self.model.erase_limit {
+            self.bad_block[index] = true;
+            return Err(WondError::BadBlock("block wore out"));
+        }
+        if self.chance(self.model.erase_fail_rate * self.wear(index)) {
+            self.bad_block[index] = true;
+            return Err(WondError::BadBlock("erase failed"));
         }
         let start_index = block_no * 128;
         let end_index = (block_no + 1) * 128;
         for index in start_index..end_index {
             self.data[index as usize] = [0; 4096];
+            self.programmed[index as usize] = false;
         }
+        self.erase_count[index] += 1;
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl FakeDisk {
+    // share of its erases a block has used up, an unlimited block never wears
+    fn wear(&self, block_no: usize) -> f64 {
+        if self.model.erase_limit == 0 {
+            return 0.0;
+        }
+        self.erase_count[block_no] as f64 / self.model.erase_limit as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        ((self.next_random() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endurance() {
+        let model = FlashModel {
+            erase_limit: 3,
+            ..FlashModel::default()
+        };
+        let mut disk = FakeDisk::with_model(16 * 128, model);
+        // a page written with zeros is programmed all the same
+        disk.fake_disk_write(130, &[0; 4096]).unwrap();
+        assert_eq!(disk.fake_disk_write(130, &[1; 4096]), Err(WondError::Device("write at not clean address")));
+        for _ in 0..3 {
+            disk.fake_disk_erase(1).unwrap();
+            disk.fake_disk_write(130, &[1; 4096]).unwrap();
+        }
+        assert_eq!(disk.fake_disk_erase(1), Err(WondError::BadBlock("block wore out")));
+        assert_eq!(disk.bad_block_num(), 1);
+        // a worn block still reads back, it only refuses to change
+        assert_eq!(disk.fake_disk_read(130).unwrap(), [1; 4096]);
+        assert_eq!(disk.fake_disk_write(131, &[1; 4096]), Err(WondError::BadBlock("program on a retired block")));
+        disk.fake_disk_write(260, &[2; 4096]).unwrap();
+    }
+
+    #[test]
+    fn failures() {
+        let model = FlashModel {
+            erase_limit: 100,
+            bit_flips: 4.0,
+            program_fail_rate: 1.0,
+            erase_fail_rate: 0.0,
+            seed: 7,
+        };
+        let mut disk = FakeDisk::with_model(16 * 128, model);
+        // a fresh block neither flips bits nor fails
+        disk.fake_disk_write(128, &[3; 4096]).unwrap();
+        assert_eq!(disk.fake_disk_read(128).unwrap(), [3; 4096]);
+        disk.erase_count[2] = 100;
+        assert_eq!(disk.fake_disk_write(256, &[3; 4096]), Err(WondError::BadBlock("program failed")));
+        assert!(disk.bad_block[2]);
+        disk.model.program_fail_rate = 0.0;
+        disk.erase_count[3] = 100;
+        disk.fake_disk_write(384, &[3; 4096]).unwrap();
+        let flipped: u32 = disk.fake_disk_read(384).unwrap().iter().map(|byte| (byte ^ 3).count_ones()).sum();
+        assert!(flipped > 0 && flipped <= 4);
+    }
+
+    #[test]
+    fn unlimited() {
+        let model = FlashModel {
+            bit_flips: 4.0,
+            program_fail_rate: 1.0,
+            erase_fail_rate: 1.0,
+            ..FlashModel::default()
+        };
+        let mut disk = FakeDisk::with_model(16 * 128, model);
+        // without an erase limit the rates that scale with wear never apply
+        for _ in 0..10 {
+            disk.fake_disk_write(128, &[3; 4096]).unwrap();
+            assert_eq!(disk.fake_disk_read(128).unwrap(), [3; 4096]);
+            disk.fake_disk_erase(1).unwrap();
+        }
+        assert_eq!(disk.bad_block_num(), 0);
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WondError {
+    // the host image file failed underneath us
+    Io(io::ErrorKind),
+    // the device refused the request, an address out of range or a write to an unerased page
+    Device(&'static str),
+    // a program or erase failed on the medium, the block it hit has to be retired
+    BadBlock(&'static str),
+    // on-disk structures that do not decode or fail their checksum
+    Corrupted(&'static str),
+    NoSpace,
+    NoMemory,
+    NameTooLong,
+    NotFound,
+    Unsupported(&'static str),
+}
+
+pub type WondResult<T> = Result<T, WondError>;
+
+impl WondError {
+    pub fn errno(&self) -> libc::c_int {
+        match self {
+            WondError::Io(_) => libc::EIO,
+            WondError::Device(_) => libc::EIO,
+            WondError::BadBlock(_) => libc::EIO,
+            WondError::Corrupted(_) => libc::EIO,
+            WondError::NoSpace => libc::ENOSPC,
+            WondError::NoMemory => libc::ENOMEM,
+            WondError::NameTooLong => libc::ENAMETOOLONG,
+            WondError::NotFound => libc::ENOENT,
+            WondError::Unsupported(_) => libc::EOPNOTSUPP,
+        }
+    }
+}
+
+impl fmt::Display for WondError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WondError::Io(kind) => write!(f, "io error: {:?}", kind),
+            WondError::Device(msg) => write!(f, "device error: {}", msg),
+            WondError::BadBlock(msg) => write!(f, "bad block: {}", msg),
+            WondError::Corrupted(msg) => write!(f, "corrupted: {}", msg),
+            WondError::NoSpace => write!(f, "no space left"),
+            WondError::NoMemory => write!(f, "out of cache slots"),
+            WondError::NameTooLong => write!(f, "name too long"),
+            WondError::NotFound => write!(f, "not found"),
+            WondError::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for WondError {}
+
+impl From<io::Error> for WondError {
+    fn from(err: io::Error) -> Self {
+        WondError::Io(err.kind())
+    }
+}
+
+impl From<WondError> for io::Error {
+    fn from(err: WondError) -> Self {
+        match err {
+            WondError::Io(kind) => io::Error::from(kind),
+            err => io::Error::from_raw_os_error(err.errno()),
+        }
+    }
+}
+
+// lets fs code returning a bare errno use ? on lower layers
+impl From<WondError> for libc::c_int {
+    fn from(err: WondError) -> Self {
+        err.errno()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errno() {
+        assert_eq!(WondError::NoSpace.errno(), libc::ENOSPC);
+        assert_eq!(WondError::Corrupted("x").errno(), libc::EIO);
+        let err: libc::c_int = WondError::NameTooLong.into();
+        assert_eq!(err, libc::ENAMETOOLONG);
+        let err: io::Error = WondError::NoMemory.into();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOMEM));
+        let err: WondError = io::Error::from(io::ErrorKind::UnexpectedEof).into();
+        assert_eq!(err, WondError::Io(io::ErrorKind::UnexpectedEof));
+    }
+}
@@ -1,6 +1,7 @@
 pub const PAGESIZE: usize = 1usize << PAGESIZE_LOG2;
 pub const PAGESIZE_LOG2: u8 = 12;
 pub const MAGIC: u32 = 0xab58b14c;
-pub const MAX_FNAME_LEN: usize = 10;
-pub const MAX_FILE_SIZE: usize = 0xffffffff;
-pub const ROOT_INO: u32 = 2;
+pub const MAX_FNAME_LEN: usize = 255;
+// the largest offset lseek and the kernel can express
+pub const MAX_FILE_SIZE: u64 = i64::MAX as u64;
+pub const ROOT_INO: u64 = 2;
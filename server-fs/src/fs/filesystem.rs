@@ -1,65 +1,561 @@
 extern crate alloc;
+use std::io;
 use std::sync::atomic::AtomicU64;
 use spin::RwLock;
 use alloc::sync::Arc;
 use std::thread;
+use crate::mkfs;
+use crate::error::{WondError, WondResult};
 use crate::kv::kv::KV;
+use crate::kv::component::super_block;
 use crate::tl::tl::TranslationLayer;
+use crate::driver::disk;
+use crate::driver::fake_disk;
+use crate::driver::disk_manager;
 use crate::inode::inode_manager::InodeManager;
 use crate::inode::inode;
 use crate::common::directory;
+use crate::common::directory::DirectoryParser;
+use super::fuse_helper::{atime_needs_update, seek_data_hole, time_now};
+use super::options::{AtimePolicy, MountConfig};
 
 pub struct WondFS {
     pub is_virtual: bool,
+    pub read_only: bool,
+    pub atime: AtimePolicy,
     pub kv: Arc<KV>,
     pub inode_manager: Option<Arc<RwLock<InodeManager>>>,
     pub tl: Arc<TranslationLayer>,
     pub next_file_handle: AtomicU64,
+    // run once the kernel has the filesystem mounted and the root is in place
+    pub on_init: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl Default for WondFS {
+    fn default() -> Self {
+        WondFS::new()
+    }
 }
 
 impl WondFS {
     pub fn new() -> Self {
         let mut tl = TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
+        WondFS::with_translation_layer(tl).unwrap()
+    }
+
+    pub fn mount(config: &MountConfig) -> io::Result<Self> {
+        let disk_manager;
+        let stat;
+        match config.image.as_ref() {
+            Some(path) => {
+                let disk = disk::Disk::open(path)?;
+                let block_num = disk.block_num;
+                disk_manager = disk_manager::DiskManager::with_disk(disk);
+                let data = disk_manager.disk_read(0)?;
+                stat = super_block::SuperStat::decode(&data).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "no WondFS superblock, format the image with mkfs.wondfs first")
+                })?;
+                if stat.get_block_num() > block_num {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "image is smaller than its superblock geometry"));
+                }
+            },
+            None => {
+                stat = mkfs::MkfsOptions::new(config.block_num)?.super_stat()?;
+                let size = config.block_num.checked_mul(128).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "image too large for a virtual disk")
+                })?;
+                disk_manager = disk_manager::DiskManager::with_fake_disk(fake_disk::FakeDisk::new(size));
+            },
+        }
+        let mut tl = TranslationLayer::with_disk_manager(disk_manager);
+        tl.set_geometry(stat.get_block_num(), stat.get_reserved_offset());
+        tl.init()?;
+        let mut fs = WondFS::open(tl, config.read_only)?;
+        fs.is_virtual = config.image.is_none();
+        fs.atime = config.atime;
+        fs.kv.set_compress_policy(config.compress);
+        // scrubbing and gc relocate and rewrite blocks, which a read-only mount must not do
+        if !fs.read_only {
+            let tl = fs.tl.clone();
+            let rate = config.scrub_rate;
+            thread::spawn(move || {
+                tl.scrub_loop(rate);
+            });
+            let kv = fs.kv.clone();
+            let gc = config.gc;
+            thread::spawn(move || {
+                kv.background_gc(gc);
+            });
+        }
+        Ok(fs)
+    }
+
+    pub fn with_translation_layer(tl: TranslationLayer) -> WondResult<Self> {
+        WondFS::open(tl, false)
+    }
+
+    pub fn open(tl: TranslationLayer, read_only: bool) -> WondResult<Self> {
         let tl = Arc::new(tl);
         let a_tl = tl.clone();
         thread::spawn( move || {
             a_tl.write_loop();
         });
         let kv = KV::new(Arc::clone(&tl));
-        kv.mount();
+        kv.set_read_only(read_only);
+        kv.mount()?;
         let kv = Arc::new(kv);
-        // let a_kv = kv.clone();
-        // thread::spawn( move || {
-        //     a_kv.background_gc();
-        // });
         let inode_manager = InodeManager::new(Arc::clone(&kv));
-        WondFS {
+        Ok(WondFS {
             tl,
             kv,
             is_virtual: false,
+            read_only,
+            atime: AtimePolicy::Relative,
             inode_manager: Some(Arc::new(RwLock::new(inode_manager))),
             next_file_handle: AtomicU64::new(1),
-        }
+            on_init: None,
+        })
     }
 }
 
 impl WondFS {
-    pub fn new_inode_file(&self) -> Option<Arc<inode::Inode>> {
+    pub fn create_root(&self, uid: u32, gid: u32) -> WondResult<()> {
+        let inode = self.inode_manager.as_ref().unwrap().write().i_alloc()?;
+        assert!(inode.stat.read().ino == fuser::FUSE_ROOT_ID);
+        let mut stat = inode.get_stat();
+        stat.file_type = inode::InodeFileType::Directory;
+        stat.size = 0;
+        stat.ref_cnt = 0;
+        stat.n_link = 2;
+        stat.mode = 0o755;
+        stat.uid = uid;
+        stat.gid = gid;
+        let now = time_now();
+        stat.last_accessed = now;
+        stat.last_modified = now;
+        stat.last_metadata_changed = now;
+        stat.created = now;
+        let ret = inode.modify_stat(stat).and_then(|_| directory::dir_link(&inode, fuser::FUSE_ROOT_ID, ".".to_string()));
+        self.inode_manager.as_ref().unwrap().write().i_put(inode);
+        ret
+    }
+
+    pub fn flush(&self) -> WondResult<()> {
+        self.kv.flush()?;
+        self.tl.flush()
+    }
+
+    // the data pages go first, the log records that point at them are committed behind them
+    pub fn sync(&self) -> WondResult<()> {
+        self.tl.flush()?;
+        self.kv.sync()
+    }
+}
+
+impl WondFS {
+    pub fn new_inode_file(&self) -> WondResult<Arc<inode::Inode>> {
         self.inode_manager.as_ref().unwrap().write().i_alloc()
     }
 
-    pub fn new_inode_dir(&self, parent: u32) -> Option<Arc<inode::Inode>> {
+    pub fn new_inode_dir(&self, parent: u64) -> WondResult<Arc<inode::Inode>> {
         let inode = self.inode_manager.as_ref().unwrap().write().i_alloc()?;
         let mut stat = inode.get_stat();
         stat.file_type = inode::InodeFileType::Directory;
-        inode.modify_stat(stat);
-        directory::dir_link(&inode, inode.stat.read().ino, ".".to_string());
-        directory::dir_link(&inode, parent, "..".to_string());
-        Some(inode)
+        stat.mode = 0o755;
+        inode.modify_stat(stat)?;
+        let ino = inode.stat.read().ino;
+        directory::dir_link(&inode, ino, ".".to_string())?;
+        directory::dir_link(&inode, parent, "..".to_string())?;
+        Ok(inode)
     }
 
-    pub fn get_inode(&self, ino: u32) -> Option<Arc<inode::Inode>> {
-        self.inode_manager.as_ref().unwrap().write().i_get(ino)
+    pub fn get_inode(&self, ino: u64) -> WondResult<Arc<inode::Inode>> {
+        self.inode_manager.as_ref().unwrap().write().i_get(ino)?.ok_or(WondError::NotFound)
     }
-}
\ No newline at end of file
+}
+impl WondFS {
+    pub fn rename_entry(&self, parent: u64, name: String, newparent: u64, newname: String, flags: u32) -> Result<(), libc::c_int> {
+        let exchange = flags & libc::RENAME_EXCHANGE != 0;
+        let noreplace = flags & libc::RENAME_NOREPLACE != 0;
+        if flags & !(libc::RENAME_EXCHANGE | libc::RENAME_NOREPLACE) != 0 || (exchange && noreplace) {
+            return Err(libc::EINVAL);
+        }
+        if name == "." || name == ".." || newname == "." || newname == ".." {
+            return Err(libc::EINVAL);
+        }
+        let parent_inode = self.get_inode(parent)?;
+        let newparent_inode = if newparent == parent {
+            Arc::clone(&parent_inode)
+        } else {
+            match self.get_inode(newparent) {
+                Ok(inode) => inode,
+                Err(err) => {
+                    self.put_inode(parent_inode);
+                    return Err(err.into());
+                },
+            }
+        };
+        let ret = self.rename_in(&parent_inode, name, &newparent_inode, newname, exchange, noreplace);
+        if newparent != parent {
+            self.put_inode(newparent_inode);
+        }
+        self.put_inode(parent_inode);
+        ret
+    }
+
+    fn rename_in(&self, parent_inode: &inode::Inode, name: String, newparent_inode: &inode::Inode, newname: String, exchange: bool, noreplace: bool) -> Result<(), libc::c_int> {
+        let parent = parent_inode.stat.read().ino;
+        let newparent = newparent_inode.stat.read().ino;
+        if newparent_inode.stat.read().file_type != inode::InodeFileType::Directory {
+            return Err(libc::ENOTDIR);
+        }
+        let src_ino = directory::dir_lookup(parent_inode, name.clone())?.ok_or(libc::ENOENT)?.0;
+        let dst_ino = directory::dir_lookup(newparent_inode, newname.clone())?.map(|entry| entry.0);
+        if dst_ino.is_some() && noreplace {
+            return Err(libc::EEXIST);
+        }
+        if dst_ino.is_none() && exchange {
+            return Err(libc::ENOENT);
+        }
+        if dst_ino == Some(src_ino) {
+            return Ok(());
+        }
+        if parent != newparent && self.is_subdir(newparent, src_ino)? {
+            return Err(libc::EINVAL);
+        }
+        if exchange && parent != newparent && self.is_subdir(parent, dst_ino.unwrap())? {
+            return Err(libc::EINVAL);
+        }
+        let src_inode = self.get_inode(src_ino)?;
+        let dst_inode = match dst_ino {
+            Some(ino) => match self.get_inode(ino) {
+                Ok(inode) => Some(inode),
+                Err(err) => {
+                    self.put_inode(src_inode);
+                    return Err(err.into());
+                },
+            },
+            None => None,
+        };
+        let ret = if exchange {
+            self.exchange_entries(parent_inode, name, &src_inode, newparent_inode, newname, dst_inode.as_ref().unwrap()).map_err(libc::c_int::from)
+        } else {
+            self.move_entry(parent_inode, name, &src_inode, newparent_inode, newname, dst_inode.as_ref())
+        };
+        if let Some(inode) = dst_inode {
+            self.put_inode(inode);
+        }
+        self.put_inode(src_inode);
+        ret
+    }
+
+    fn move_entry(&self, parent_inode: &inode::Inode, name: String, src_inode: &inode::Inode, newparent_inode: &inode::Inode, newname: String, dst_inode: Option<&Arc<inode::Inode>>) -> Result<(), libc::c_int> {
+        let src_ino = src_inode.stat.read().ino;
+        let src_is_dir = src_inode.stat.read().file_type == inode::InodeFileType::Directory;
+        match dst_inode {
+            Some(dst_inode) => {
+                let dst_is_dir = dst_inode.stat.read().file_type == inode::InodeFileType::Directory;
+                if src_is_dir && !dst_is_dir {
+                    return Err(libc::ENOTDIR);
+                }
+                if !src_is_dir && dst_is_dir {
+                    return Err(libc::EISDIR);
+                }
+                if dst_is_dir && dst_inode.stat.read().size > directory::entry_size(self.kv.get_ino_size()) as u64 * 2 {
+                    return Err(libc::ENOTEMPTY);
+                }
+                // overwrite the target entry in place so the new name never goes missing
+                directory::dir_replace(newparent_inode, src_ino, newname)?;
+                let mut stat = dst_inode.get_stat();
+                if dst_is_dir || stat.n_link <= 1 {
+                    stat.n_link = 0;
+                } else {
+                    stat.n_link -= 1;
+                }
+                stat.last_metadata_changed = time_now();
+                dst_inode.modify_stat(stat)?;
+                if stat.n_link == 0 {
+                    dst_inode.delete()?;
+                }
+            },
+            None => directory::dir_link(newparent_inode, src_ino, newname)?,
+        }
+        directory::dir_unlink(parent_inode, src_ino, name)?;
+        let newparent = newparent_inode.stat.read().ino;
+        if src_is_dir && parent_inode.stat.read().ino != newparent {
+            directory::dir_replace(src_inode, newparent, "..".to_string())?;
+        }
+        WondFS::touch_renamed(parent_inode, src_inode, newparent_inode)?;
+        Ok(())
+    }
+
+    fn exchange_entries(&self, parent_inode: &inode::Inode, name: String, src_inode: &inode::Inode, newparent_inode: &inode::Inode, newname: String, dst_inode: &inode::Inode) -> WondResult<()> {
+        let parent = parent_inode.stat.read().ino;
+        let newparent = newparent_inode.stat.read().ino;
+        let src_ino = src_inode.stat.read().ino;
+        let dst_ino = dst_inode.stat.read().ino;
+        directory::dir_replace(parent_inode, dst_ino, name)?;
+        directory::dir_replace(newparent_inode, src_ino, newname)?;
+        if parent != newparent {
+            if src_inode.stat.read().file_type == inode::InodeFileType::Directory {
+                directory::dir_replace(src_inode, newparent, "..".to_string())?;
+            }
+            if dst_inode.stat.read().file_type == inode::InodeFileType::Directory {
+                directory::dir_replace(dst_inode, parent, "..".to_string())?;
+            }
+        }
+        WondFS::touch_renamed(parent_inode, src_inode, newparent_inode)?;
+        let mut stat = dst_inode.get_stat();
+        stat.last_metadata_changed = time_now();
+        dst_inode.modify_stat(stat)
+    }
+
+    fn touch_renamed(parent_inode: &inode::Inode, src_inode: &inode::Inode, newparent_inode: &inode::Inode) -> WondResult<()> {
+        let now = time_now();
+        let mut stat = parent_inode.get_stat();
+        stat.last_modified = now;
+        stat.last_metadata_changed = now;
+        parent_inode.modify_stat(stat)?;
+        if newparent_inode.stat.read().ino != parent_inode.stat.read().ino {
+            let mut stat = newparent_inode.get_stat();
+            stat.last_modified = now;
+            stat.last_metadata_changed = now;
+            newparent_inode.modify_stat(stat)?;
+        }
+        let mut stat = src_inode.get_stat();
+        stat.last_metadata_changed = now;
+        src_inode.modify_stat(stat)
+    }
+
+    // walks ".." straight from the kv so inodes held by the caller stay untouched
+    fn is_subdir(&self, ino: u64, ancestor: u64) -> WondResult<bool> {
+        let mut cur = ino;
+        loop {
+            if cur == ancestor {
+                return Ok(true);
+            }
+            if cur == fuser::FUSE_ROOT_ID {
+                return Ok(false);
+            }
+            match self.kv.get_inode_metadata(cur)? {
+                Some(metadata) if metadata.file_type == inode::InodeFileType::Directory.into() => (),
+                _ => return Ok(false),
+            }
+            let data = self.kv.get_inode_data(cur, 0, 0)?;
+            let mut parent = None;
+            for entry in DirectoryParser::new(&data, self.kv.get_ino_size())? {
+                let entry = entry?;
+                if entry.file_name == ".." {
+                    parent = Some(entry);
+                    break;
+                }
+            }
+            match parent {
+                Some(entry) if entry.ino != cur => cur = entry.ino,
+                _ => return Ok(false),
+            }
+        }
+    }
+
+    pub fn put_inode(&self, inode: Arc<inode::Inode>) {
+        self.inode_manager.as_ref().unwrap().write().i_put(inode);
+    }
+}
+
+impl WondFS {
+    // chmod, only the owner or root may change the bits
+    pub fn change_mode(inode: &inode::Inode, uid: u32, gid: u32, mode: u32) -> Result<(), libc::c_int> {
+        let mut stat = inode.get_stat();
+        if uid != 0 && uid != stat.uid {
+            return Err(libc::EPERM);
+        }
+        let mut mode = mode as u16 & 0o7777;
+        if uid != 0 && gid != stat.gid {
+            mode &= !(libc::S_ISGID as u16);
+        }
+        stat.mode = mode;
+        stat.last_metadata_changed = time_now();
+        inode.modify_stat(stat)?;
+        Ok(())
+    }
+
+    // chown, root may give the inode away, the owner may only move it to its own group
+    pub fn change_owner(inode: &inode::Inode, uid: u32, gid: u32, new_uid: Option<u32>, new_gid: Option<u32>) -> Result<(), libc::c_int> {
+        let mut stat = inode.get_stat();
+        if uid != 0 {
+            if new_uid.is_some() && new_uid != Some(stat.uid) {
+                return Err(libc::EPERM);
+            }
+            if new_gid.is_some() && (uid != stat.uid || (new_gid != Some(gid) && new_gid != Some(stat.gid))) {
+                return Err(libc::EPERM);
+            }
+        }
+        if let Some(new_uid) = new_uid {
+            stat.uid = new_uid;
+        }
+        if let Some(new_gid) = new_gid {
+            stat.gid = new_gid;
+        }
+        if stat.file_type != inode::InodeFileType::Directory {
+            stat.mode &= !((libc::S_ISUID | libc::S_ISGID) as u16);
+        }
+        stat.last_metadata_changed = time_now();
+        inode.modify_stat(stat)?;
+        Ok(())
+    }
+
+    // flash is only claimed when a page is written, so preallocating just moves the size and
+    // a punched range goes back to being a hole
+    pub fn allocate_range(&self, ino: u64, offset: u64, len: u64, mode: i32) -> Result<(), libc::c_int> {
+        let inode = self.get_inode(ino)?;
+        let ret = match mode {
+            0 if offset + len > inode.get_stat().size => inode.resize(offset + len).map_err(libc::c_int::from),
+            0 | libc::FALLOC_FL_KEEP_SIZE => Ok(()),
+            mode if mode == libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE => inode.punch(offset, len).map_err(libc::c_int::from),
+            _ => Err(libc::EOPNOTSUPP),
+        };
+        let ret = ret.and_then(|_| {
+            let mut stat = inode.get_stat();
+            stat.last_modified = time_now();
+            stat.last_metadata_changed = stat.last_modified;
+            inode.modify_stat(stat).map_err(libc::c_int::from)
+        });
+        self.put_inode(inode);
+        ret
+    }
+
+    // reads move the access time as the atime policy says, never on a read-only mount
+    pub fn touch_atime(&self, inode: &inode::Inode) -> WondResult<()> {
+        let mut stat = inode.get_stat();
+        let now = time_now();
+        if self.read_only || !atime_needs_update(self.atime, &stat, now) {
+            return Ok(());
+        }
+        stat.last_accessed = now;
+        inode.modify_stat(stat)
+    }
+
+    // SEEK_DATA and SEEK_HOLE, other whences never reach the filesystem
+    pub fn seek_data(&self, ino: u64, offset: i64, whence: i32) -> Result<i64, libc::c_int> {
+        let inode = self.get_inode(ino)?;
+        let ret = inode.data_ranges().map_err(libc::c_int::from).and_then(|ranges| {
+            seek_data_hole(&ranges, inode.get_stat().size, offset, whence)
+        });
+        self.put_inode(inode);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mkdir(fs: &WondFS, parent: u64, name: &str) -> u64 {
+        let inode = fs.new_inode_dir(parent).unwrap();
+        let ino = inode.stat.read().ino;
+        let parent_inode = fs.get_inode(parent).unwrap();
+        directory::dir_link(&parent_inode, ino, name.to_string()).unwrap();
+        fs.put_inode(parent_inode);
+        fs.put_inode(inode);
+        ino
+    }
+
+    fn touch(fs: &WondFS, parent: u64, name: &str) -> u64 {
+        let inode = fs.new_inode_file().unwrap();
+        let ino = inode.stat.read().ino;
+        let parent_inode = fs.get_inode(parent).unwrap();
+        directory::dir_link(&parent_inode, ino, name.to_string()).unwrap();
+        fs.put_inode(parent_inode);
+        fs.put_inode(inode);
+        ino
+    }
+
+    fn lookup(fs: &WondFS, parent: u64, name: &str) -> Option<u64> {
+        let parent_inode = fs.get_inode(parent).unwrap();
+        let ret = directory::dir_lookup(&parent_inode, name.to_string()).unwrap().map(|entry| entry.0);
+        fs.put_inode(parent_inode);
+        ret
+    }
+
+    #[test]
+    fn rename() {
+        let fs = WondFS::new();
+        fs.create_root(0, 0).unwrap();
+        let a = mkdir(&fs, 1, "a");
+        let b = mkdir(&fs, a, "b");
+        let x = touch(&fs, 1, "x");
+        let y = touch(&fs, a, "y");
+        fs.rename_entry(1, "x".to_string(), a, "z".to_string(), 0).unwrap();
+        assert_eq!(lookup(&fs, 1, "x"), None);
+        assert_eq!(lookup(&fs, a, "z"), Some(x));
+        assert_eq!(fs.rename_entry(a, "z".to_string(), a, "y".to_string(), libc::RENAME_NOREPLACE), Err(libc::EEXIST));
+        fs.rename_entry(a, "z".to_string(), a, "y".to_string(), 0).unwrap();
+        assert_eq!(lookup(&fs, a, "y"), Some(x));
+        assert_eq!(fs.get_inode(y).err(), Some(WondError::NotFound));
+        assert_eq!(fs.rename_entry(1, "a".to_string(), b, "a".to_string(), 0), Err(libc::EINVAL));
+        fs.rename_entry(a, "b".to_string(), 1, "b".to_string(), 0).unwrap();
+        assert_eq!(lookup(&fs, b, ".."), Some(1));
+        fs.rename_entry(1, "a".to_string(), 1, "b".to_string(), libc::RENAME_EXCHANGE).unwrap();
+        assert_eq!(lookup(&fs, 1, "a"), Some(b));
+        assert_eq!(lookup(&fs, 1, "b"), Some(a));
+        assert_eq!(fs.rename_entry(1, "missing".to_string(), 1, "c".to_string(), 0), Err(libc::ENOENT));
+        assert_eq!(fs.rename_entry(1, "a".to_string(), 1, "c".to_string(), libc::RENAME_EXCHANGE), Err(libc::ENOENT));
+    }
+
+    #[test]
+    fn ownership() {
+        let fs = WondFS::new();
+        fs.create_root(1000, 100).unwrap();
+        let root = fs.get_inode(1).unwrap();
+        assert_eq!(root.get_stat().mode, 0o755);
+        assert_eq!(root.get_stat().uid, 1000);
+        fs.put_inode(root);
+        let ino = touch(&fs, 1, "x");
+        let inode = fs.get_inode(ino).unwrap();
+        assert_eq!(WondFS::change_owner(&inode, 0, 0, Some(1000), Some(100)), Ok(()));
+        assert_eq!(WondFS::change_mode(&inode, 1001, 100, 0o777), Err(libc::EPERM));
+        assert_eq!(WondFS::change_mode(&inode, 1000, 1000, 0o2750), Ok(()));
+        assert_eq!(inode.get_stat().mode, 0o750);
+        assert_eq!(WondFS::change_mode(&inode, 1000, 100, 0o4750), Ok(()));
+        assert_eq!(WondFS::change_owner(&inode, 1000, 100, Some(1001), None), Err(libc::EPERM));
+        assert_eq!(WondFS::change_owner(&inode, 1000, 200, None, Some(300)), Err(libc::EPERM));
+        assert_eq!(WondFS::change_owner(&inode, 1000, 200, None, Some(200)), Ok(()));
+        assert_eq!(inode.get_stat().mode, 0o750);
+        fs.put_inode(inode);
+        let metadata = fs.kv.get_inode_metadata(ino).unwrap().unwrap();
+        assert_eq!((metadata.mode, metadata.uid, metadata.gid), (0o750, 1000, 200));
+    }
+
+    #[test]
+    fn atime() {
+        use crate::util::time::Timespec;
+        let mut fs = WondFS::new();
+        fs.create_root(0, 0).unwrap();
+        let ino = touch(&fs, 1, "x");
+        let inode = fs.get_inode(ino).unwrap();
+        let mut stat = inode.get_stat();
+        let accessed = Timespec::new(1_000_000, 123_456_789);
+        stat.last_accessed = accessed;
+        stat.last_modified = Timespec::new(900_000, 1);
+        stat.last_metadata_changed = stat.last_modified;
+        stat.created = Timespec::new(800_000, 2);
+        inode.modify_stat(stat).unwrap();
+        fs.atime = AtimePolicy::Never;
+        fs.touch_atime(&inode).unwrap();
+        assert_eq!(inode.get_stat().last_accessed, accessed);
+        // accessed after the last change and less than a day ago as far as relatime knows
+        fs.atime = AtimePolicy::Relative;
+        stat.last_accessed = time_now();
+        inode.modify_stat(stat).unwrap();
+        fs.touch_atime(&inode).unwrap();
+        assert_eq!(inode.get_stat().last_accessed, stat.last_accessed);
+        fs.atime = AtimePolicy::Strict;
+        fs.touch_atime(&inode).unwrap();
+        assert!(inode.get_stat().last_accessed > stat.last_accessed);
+        fs.put_inode(inode);
+        let metadata = fs.kv.get_inode_metadata(ino).unwrap().unwrap();
+        assert_eq!(metadata.last_modified, Timespec::new(900_000, 1));
+        assert_eq!(metadata.created, Timespec::new(800_000, 2));
+    }
+}
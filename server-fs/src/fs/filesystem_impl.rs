@@ -6,11 +6,12 @@ use std::ffi::OsStr;
 use std::os::unix::prelude::OsStrExt;
 use std::time::Duration;
 use std::sync::atomic::Ordering;
-use libc::ENOENT;
+use libc::{EINVAL, ENODATA, ENOENT};
 use crate::inode::inode;
 use crate::common::directory;
 use crate::common::symlink;
-use crate::common::path;
+use crate::error::{WondError, WondResult};
+use super::consts;
 use super::fuse_helper::*;
 use super::filesystem::*;
 
@@ -19,6 +20,14 @@ pub const FILE_HANDLE_WRITE_BIT: u64 = 1 << 62;
 
 const TTL: Duration = Duration::new(1, 0);
 
+// the inode mknod, mkdir, create and symlink ask for
+struct NodeSpec {
+    file_type: inode::InodeFileType,
+    mode: u16,
+    ref_cnt: u8,
+    link: Option<String>,
+}
+
 impl WondFS {
     fn allocate_next_file_handle(&self, read: bool, write: bool) -> u64 {
         let mut fh = self.next_file_handle.fetch_add(1, Ordering::SeqCst);
@@ -31,29 +40,236 @@ impl WondFS {
         }
         fh
     }
+
+    fn check_inode_access(&self, ino: u64, req: &Request<'_>, mask: i32) -> Result<(), libc::c_int> {
+        let inode = self.get_inode(ino)?;
+        let permitted = check_access(&inode.get_stat(), req.uid(), req.gid(), mask);
+        self.inode_manager.as_ref().unwrap().write().i_put(inode);
+        if permitted {
+            Ok(())
+        } else {
+            Err(libc::EACCES)
+        }
+    }
+
+    fn lookup_entry(&self, req: &Request<'_>, parent: u64, name: String) -> Result<inode::InodeStat, libc::c_int> {
+        let parent_inode = self.get_inode(parent)?;
+        let ino = if check_access(&parent_inode.get_stat(), req.uid(), req.gid(), libc::X_OK) {
+            directory::dir_lookup(&parent_inode, name).map_err(libc::c_int::from)
+        } else {
+            Err(libc::EACCES)
+        };
+        self.put_inode(parent_inode);
+        let inode = self.get_inode(ino?.ok_or(ENOENT)?.0)?;
+        let stat = inode.get_stat();
+        self.put_inode(inode);
+        Ok(stat)
+    }
+
+    // shared by mknod, mkdir, create and symlink
+    fn make_node(&self, req: &Request<'_>, parent: u64, name: String, node: NodeSpec) -> Result<inode::InodeStat, libc::c_int> {
+        let parent_inode = self.get_inode(parent)?;
+        let ret = if !check_access(&parent_inode.get_stat(), req.uid(), req.gid(), libc::W_OK | libc::X_OK) {
+            Err(libc::EACCES)
+        } else {
+            match directory::dir_lookup(&parent_inode, name.clone()) {
+                Ok(Some(_)) => Err(libc::EEXIST),
+                Ok(None) => self.make_node_in(req, &parent_inode, name, node).map_err(libc::c_int::from),
+                Err(err) => Err(err.into()),
+            }
+        };
+        self.put_inode(parent_inode);
+        ret
+    }
+
+    fn make_node_in(&self, req: &Request<'_>, parent_inode: &inode::Inode, name: String, node: NodeSpec) -> WondResult<inode::InodeStat> {
+        let mut stat = parent_inode.get_stat();
+        stat.last_modified = time_now();
+        stat.last_metadata_changed = time_now();
+        parent_inode.modify_stat(stat)?;
+        let inode = self.new_inode_file()?;
+        let ret = init_node(req, parent_inode, &inode, name, node);
+        self.put_inode(inode);
+        ret
+    }
+
+    // shared by unlink and rmdir
+    fn remove_entry(&self, req: &Request<'_>, parent: u64, name: String, is_dir: bool) -> Result<(), libc::c_int> {
+        let parent_inode = self.get_inode(parent)?;
+        let ret = self.remove_entry_in(req, &parent_inode, name, is_dir);
+        self.put_inode(parent_inode);
+        ret
+    }
+
+    fn remove_entry_in(&self, req: &Request<'_>, parent_inode: &inode::Inode, name: String, is_dir: bool) -> Result<(), libc::c_int> {
+        if !check_access(&parent_inode.get_stat(), req.uid(), req.gid(), libc::W_OK | libc::X_OK) {
+            return Err(libc::EACCES);
+        }
+        let ino = directory::dir_lookup(parent_inode, name.clone())?.ok_or(ENOENT)?.0;
+        let inode = self.get_inode(ino)?;
+        let ret = if is_dir && inode.stat.read().size > directory::entry_size(self.kv.get_ino_size()) as u64 * 2 {
+            Err(libc::ENOTEMPTY)
+        } else {
+            unlink_node(parent_inode, &inode, name, is_dir).map_err(libc::c_int::from)
+        };
+        self.put_inode(inode);
+        ret
+    }
+
+    fn link_entry(&self, req: &Request<'_>, ino: u64, newparent: u64, newname: String) -> Result<inode::InodeStat, libc::c_int> {
+        let parent_inode = self.get_inode(newparent)?;
+        if !check_access(&parent_inode.get_stat(), req.uid(), req.gid(), libc::W_OK | libc::X_OK) {
+            self.put_inode(parent_inode);
+            return Err(libc::EACCES);
+        }
+        let inode = match self.get_inode(ino) {
+            Ok(inode) => inode,
+            Err(err) => {
+                self.put_inode(parent_inode);
+                return Err(err.into());
+            },
+        };
+        let ret = directory::dir_link(&parent_inode, ino, newname).and_then(|_| {
+            let mut stat = inode.get_stat();
+            stat.n_link += 1;
+            stat.last_metadata_changed = time_now();
+            inode.modify_stat(stat)?;
+            Ok(stat)
+        });
+        self.put_inode(parent_inode);
+        self.put_inode(inode);
+        Ok(ret?)
+    }
+
+    // the file type comes straight from the kv, entries are not pulled into the inode cache
+    fn read_dir_entries(&self, inode: &inode::Inode) -> WondResult<Vec<(u64, inode::InodeFileType, String)>> {
+        let mut data = vec![];
+        inode.read_all(&mut data)?;
+        let mut entries = vec![];
+        for entry in directory::DirectoryParser::new(&data, self.kv.get_ino_size())? {
+            let entry = entry?;
+            let file_type = match self.kv.get_inode_metadata(entry.ino)? {
+                Some(metadata) => metadata.file_type.into(),
+                None => inode::InodeFileType::File,
+            };
+            entries.push((entry.ino, file_type, entry.file_name));
+        }
+        Ok(entries)
+    }
+}
+
+fn init_node(req: &Request<'_>, parent_inode: &inode::Inode, inode: &inode::Inode, name: String, node: NodeSpec) -> WondResult<inode::InodeStat> {
+    let ino = inode.stat.read().ino;
+    let mut stat = inode.get_stat();
+    stat.file_type = node.file_type;
+    stat.size = 0;
+    stat.ref_cnt = node.ref_cnt;
+    stat.mode = node.mode;
+    stat.uid = req.uid();
+    stat.gid = req.gid();
+    let now = time_now();
+    stat.last_accessed = now;
+    stat.last_modified = now;
+    stat.last_metadata_changed = now;
+    stat.created = now;
+    match node.file_type {
+        inode::InodeFileType::File => stat.n_link = 1,
+        inode::InodeFileType::Directory => stat.n_link = 2,
+        inode::InodeFileType::Symlink => (),
+    }
+    inode.modify_stat(stat)?;
+    if node.file_type == inode::InodeFileType::Directory {
+        directory::dir_link(inode, ino, ".".to_string())?;
+        directory::dir_link(inode, parent_inode.stat.read().ino, "..".to_string())?;
+    }
+    directory::dir_link(parent_inode, ino, name)?;
+    if let Some(link) = node.link {
+        symlink::write_symlink(inode, link)?;
+    }
+    Ok(inode.get_stat())
+}
+
+fn unlink_node(parent_inode: &inode::Inode, inode: &inode::Inode, name: String, is_dir: bool) -> WondResult<()> {
+    let ino = inode.stat.read().ino;
+    let mut stat = parent_inode.get_stat();
+    stat.last_modified = time_now();
+    stat.last_metadata_changed = time_now();
+    parent_inode.modify_stat(stat)?;
+    directory::dir_unlink(parent_inode, ino, name)?;
+    let mut stat = inode.get_stat();
+    if is_dir {
+        stat.n_link = 0;
+    } else {
+        stat.n_link -= 1;
+    }
+    stat.last_metadata_changed = time_now();
+    inode.modify_stat(stat)?;
+    if stat.n_link == 0 {
+        inode.delete()?;
+    }
+    Ok(())
+}
+
+fn update_attr(inode: &inode::Inode, size: Option<u64>, atime: Option<TimeOrNow>, mtime: Option<TimeOrNow>) -> WondResult<()> {
+    if let Some(size) = size {
+        if size != inode.stat.read().size {
+            inode.resize(size)?;
+        }
+    }
+    let now = time_now();
+    if let Some(atime) = atime {
+        let mut stat = inode.get_stat();
+        stat.last_accessed = match atime {
+            TimeOrNow::SpecificTime(time) => time_from_system_time(&time),
+            TimeOrNow::Now => now,
+        };
+        stat.last_metadata_changed = now;
+        inode.modify_stat(stat)?;
+    }
+    if let Some(mtime) = mtime {
+        let mut stat = inode.get_stat();
+        stat.last_modified = match mtime {
+            TimeOrNow::SpecificTime(time) => time_from_system_time(&time),
+            TimeOrNow::Now => now,
+        };
+        stat.last_metadata_changed = now;
+        inode.modify_stat(stat)?;
+    }
+    Ok(())
 }
 
 impl Filesystem for WondFS {
     fn init(&mut self, _req: &Request<'_>, _config: &mut KernelConfig) -> Result<(), libc::c_int> {
-        let mut inode = self.inode_manager.as_ref().unwrap().write().i_alloc().unwrap();
-        assert!(inode.stat.read().ino == FUSE_ROOT_ID as u32);
-        let mut stat = inode.get_stat();
-        stat.file_type = inode::InodeFileType::Directory;
-        stat.size = 0;
-        stat.ref_cnt = 0;
-        stat.n_link = 2;
-        stat.last_accessed = time_now();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        inode.modify_stat(stat);
-        directory::dir_link(&mut inode, FUSE_ROOT_ID as u32, ".".to_string());
-        self.inode_manager.as_ref().unwrap().write().i_put(inode);
+        match self.get_inode(FUSE_ROOT_ID) {
+            Ok(inode) => self.inode_manager.as_ref().unwrap().write().i_put(inode),
+            Err(WondError::NotFound) if self.read_only => return Err(libc::EROFS),
+            Err(WondError::NotFound) => self.create_root(_req.uid(), _req.gid())?,
+            Err(err) => return Err(err.into()),
+        }
+        if let Some(on_init) = self.on_init.take() {
+            on_init();
+        }
         Ok(())
     }
 
+    fn destroy(&mut self, _req: &Request<'_>) {
+        self.tl.stop_scrub();
+        if !self.read_only {
+            if let Err(err) = WondFS::flush(self) {
+                eprintln!("server-fs: flush: {}", err);
+            }
+        }
+    }
+
     fn lookup(&mut self, _req: &Request<'_>, _parent: u64, _name: &std::ffi::OsStr, reply: ReplyEntry) {
-        let parent = _parent as u32;
-        let name = _name.to_str().unwrap().to_string();
+        let parent = _parent;
+        let name = match name_from_os_str(_name, ENOENT) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
         if name == "tls" {
             reply.error(ENOENT);
             return;
@@ -66,91 +282,83 @@ impl Filesystem for WondFS {
             reply.error(ENOENT);
             return;
         }
-        if name[0..3] == "lib".to_string() {
+        if name.starts_with("lib") {
             reply.error(ENOENT);
             return;
         }
         println!("lookup {} {}", parent, name);
-        let parent_inode = self.get_inode(parent);
-        if parent_inode.is_none() {
-            reply.error(ENOENT);
-            return;
-        }
-        let ino = directory::dir_lookup(parent_inode.as_ref().unwrap(), name);
-        if ino.is_none() {
-            reply.error(ENOENT);
-            return;
+        match self.lookup_entry(_req, parent, name) {
+            Ok(stat) => reply.entry(&TTL, &transfer_stat_to_attr(stat), 0),
+            Err(err) => reply.error(err),
         }
-        let inode = self.get_inode(ino.unwrap().0 as u32);
-        if inode.is_none() {
-            reply.error(ENOENT);
-            return;
-        }
-        let stat = inode.as_ref().unwrap().get_stat();
-        let attr = transfer_stat_to_attr(stat);
-        self.inode_manager.as_ref().unwrap().write().i_put(parent_inode.unwrap());
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
-        reply.entry(&TTL, &attr, 0);
     }
 
     fn forget(&mut self, _req: &Request, _ino: u64, _nlookup: u64) {}
 
     fn getattr(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyAttr) {
-        let ino = _ino as u32;
+        let ino = _ino;
         let inode = self.get_inode(ino);
         println!("getattr {}", ino);
         match inode {
-            Some(inode) => {
+            Ok(inode) => {
                 let stat = inode.get_stat();
                 let attr = transfer_stat_to_attr(stat);
                 self.inode_manager.as_ref().unwrap().write().i_put(inode);
                 reply.attr(&TTL, &attr);
             },
-            None => {
-                reply.error(ENOENT);
+            Err(err) => {
+                reply.error(err.errno());
             },
         }
     }
 
     fn setattr(&mut self, _req: &Request<'_>, _ino: u64, _mode: Option<u32>, _uid: Option<u32>, _gid: Option<u32>, _size: Option<u64>, _atime: Option<TimeOrNow>, _mtime: Option<TimeOrNow>, _ctime: Option<std::time::SystemTime>, _fh: Option<u64>, _crtime: Option<std::time::SystemTime>, _chgtime: Option<std::time::SystemTime>, _bkuptime: Option<std::time::SystemTime>, _flags: Option<u32>, reply: ReplyAttr) {
-        let ino = _ino as u32;
-        let inode = self.get_inode(ino);
-        println!("setattr {}", ino);
-        if inode.is_none() {
-            reply.error(ENOENT);
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        let inode = inode.unwrap();
-        if _mode.is_some() {
-            reply.error(ENOENT);
-            return;
+        let ino = _ino;
+        let inode = self.get_inode(ino);
+        println!("setattr {}", ino);
+        let inode = match inode {
+            Ok(inode) => inode,
+            Err(err) => {
+                reply.error(err.errno());
+                return;
+            },
+        };
+        // check everything up front so a refused request leaves the inode untouched
+        let stat = inode.get_stat();
+        let owner = _req.uid() == 0 || _req.uid() == stat.uid;
+        let writable = check_access(&stat, _req.uid(), _req.gid(), libc::W_OK);
+        let mut err = None;
+        if _size.is_some() && !writable {
+            err = Some(libc::EACCES);
+        } else if _size.is_some_and(|size| size > consts::MAX_FILE_SIZE) {
+            err = Some(libc::EFBIG);
+        }
+        for time in [_atime, _mtime].iter() {
+            match time {
+                Some(TimeOrNow::SpecificTime(_)) if !owner => err = Some(libc::EPERM),
+                Some(TimeOrNow::Now) if !owner && !writable => err = Some(libc::EACCES),
+                _ => (),
+            }
         }
-        if _uid.is_some() || _gid.is_some() {
-            reply.error(ENOENT);
-            return;
+        if err.is_none() && (_uid.is_some() || _gid.is_some()) {
+            err = WondFS::change_owner(&inode, _req.uid(), _req.gid(), _uid, _gid).err();
         }
-        if let Some(size) = _size {
-            let len = (inode.stat.read().size - size as u32) as usize;
-            inode.truncate(size as usize, len);
+        if err.is_none() {
+            if let Some(mode) = _mode {
+                err = WondFS::change_mode(&inode, _req.uid(), _req.gid(), mode).err();
+            }
         }
-        let now = time_now();
-        if let Some(atime) = _atime {
-            let mut stat = inode.get_stat();
-            stat.last_accessed = match atime {
-                TimeOrNow::SpecificTime(time) => time_from_system_time(&time).0 as u32,
-                TimeOrNow::Now => now,
-            };
-            stat.last_metadata_changed = now;
-            inode.modify_stat(stat);
+        if err.is_none() {
+            err = update_attr(&inode, _size, _atime, _mtime).err().map(|err| err.errno());
         }
-        if let Some(mtime) = _mtime {
-            let mut stat = inode.get_stat();
-            stat.last_modified = match mtime {
-                TimeOrNow::SpecificTime(time) => time_from_system_time(&time).0 as u32,
-                TimeOrNow::Now => now,
-            };
-            stat.last_metadata_changed = now;
-            inode.modify_stat(stat);
+        if let Some(err) = err {
+            self.inode_manager.as_ref().unwrap().write().i_put(inode);
+            reply.error(err);
+            return;
         }
         let stat = inode.get_stat();
         let attr = transfer_stat_to_attr(stat);
@@ -159,513 +367,566 @@ impl Filesystem for WondFS {
     }
 
     fn readlink(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyData) {
-        let ino =  _ino as u32;
+        let ino =  _ino;
         println!("readlink {}", ino);
         let inode = self.get_inode(ino);
         match inode {
-            Some(inode) => {
-                // let exact_ino = symlink::read_symlink(&inode);
-                // println!("exact_ino: {:?}", exact_ino);
-                // if exact_ino.is_none() {
-                    // reply.error(ENOENT);
-                    // return;
-                // }
-                // let exact_inode = self.get_inode(exact_ino.unwrap());
-                // if exact_inode.is_none() {
-                    // reply.error(ENOENT);
-                    // return;
-                // }
+            Ok(inode) => {
                 let mut data = vec![];
-                inode.read_all(&mut data);
-                println!("aa {:?}", data);
-                println!("bb {:?}", std::str::from_utf8(&data).ok().unwrap().to_string());
+                let ret = inode.read_all(&mut data).and_then(|_| self.touch_atime(&inode));
                 self.inode_manager.as_ref().unwrap().write().i_put(inode);
-                // self.inode_manager.as_ref().unwrap().write().i_put(exact_inode.unwrap());
-                reply.data(&data);
+                match ret {
+                    Ok(_) => reply.data(&data),
+                    Err(err) => reply.error(err.errno()),
+                }
             },
-            None => {
-                reply.error(ENOENT);
+            Err(err) => {
+                reply.error(err.errno());
             },
         }
     }
 
     fn mknod(&mut self, _req: &Request<'_>, _parent: u64, _name: &std::ffi::OsStr, mut _mode: u32, _umask: u32, _rdev: u32, reply: ReplyEntry) {
-        let file_type = _mode & libc::S_IFMT as u32;
-        if file_type != libc::S_IFREG as u32 && file_type != libc::S_IFDIR as u32 {
-            reply.error(ENOENT);
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        let parent = _parent as u32;
-        let name = _name.to_str().unwrap().to_string();
+        let file_type = match as_file_kind(_mode) {
+            Ok(file_type @ (inode::InodeFileType::File | inode::InodeFileType::Directory)) => file_type,
+            Ok(_) => {
+                reply.error(EINVAL);
+                return;
+            },
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        let parent = _parent;
+        let name = match name_from_os_str(_name, EINVAL) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
         println!("mknod {} {}", parent, name);
-        let mut parent_inode = self.get_inode(parent);
-        if parent_inode.is_none() {
-            reply.error(ENOENT);
-            return;
-        }
-        if directory::dir_lookup(parent_inode.as_ref().unwrap(), name.clone()).is_some() {
-            reply.error(libc::ENOENT);
-            return;
-        }
-        let mut stat = parent_inode.as_ref().unwrap().get_stat();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        parent_inode.as_ref().unwrap().modify_stat(stat);
-        let mut inode = self.new_inode_file();
-        if inode.is_none() {
-            reply.error(ENOENT);
-            return;
-        }
-        let ino = inode.as_ref().unwrap().stat.read().ino;
-        let mut stat = inode.as_ref().unwrap().get_stat();
-        stat.file_type = as_file_kind(_mode);
-        stat.size = 0;
-        stat.ref_cnt = 0;
-        stat.last_accessed = time_now();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        if stat.file_type == inode::InodeFileType::File {
-            stat.n_link = 1;
+        let mode = (_mode & !_umask & 0o7777) as u16;
+        match self.make_node(_req, parent, name, NodeSpec { file_type, mode, ref_cnt: 0, link: None }) {
+            Ok(stat) => reply.entry(&TTL, &transfer_stat_to_attr(stat), 0),
+            Err(err) => reply.error(err),
         }
-        if stat.file_type == inode::InodeFileType::Directory {
-            stat.n_link = 2;
-        }
-        inode.as_ref().unwrap().modify_stat(stat);
-        if inode.as_ref().unwrap().stat.read().file_type == inode::InodeFileType::Directory {
-            directory::dir_link(inode.as_mut().unwrap(), ino, ".".to_string());
-            directory::dir_link(inode.as_mut().unwrap(), parent, "..".to_string());
-        }
-        directory::dir_link(parent_inode.as_mut().unwrap(), ino, name);
-        let stat = inode.as_ref().unwrap().get_stat();
-        let attr = transfer_stat_to_attr(stat);
-        self.inode_manager.as_ref().unwrap().write().i_put(parent_inode.unwrap());
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
-        reply.entry(&TTL, &attr, 0);
     }
 
     fn mkdir(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, mut _mode: u32, _umask: u32, reply: ReplyEntry) {
-        let parent = _parent as u32;
-        let name = _name.to_str().unwrap().to_string();
-        println!("mkdir {} {}", parent, name);
-        let mut parent_inode = self.get_inode(parent);
-        if parent_inode.is_none() {
-            reply.error(ENOENT);
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        if directory::dir_lookup(parent_inode.as_ref().unwrap(), name.clone()).is_some() {
-            reply.error(ENOENT);
-            return;
-        }
-        let mut stat = parent_inode.as_ref().unwrap().get_stat();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        parent_inode.as_ref().unwrap().modify_stat(stat);
-        let mut inode = self.new_inode_file();
-        if inode.is_none() {
-            reply.error(ENOENT);
-            return;
+        let parent = _parent;
+        let name = match name_from_os_str(_name, EINVAL) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        println!("mkdir {} {}", parent, name);
+        let mode = (_mode & !_umask & 0o7777) as u16;
+        match self.make_node(_req, parent, name, NodeSpec { file_type: inode::InodeFileType::Directory, mode, ref_cnt: 0, link: None }) {
+            Ok(stat) => reply.entry(&TTL, &transfer_stat_to_attr(stat), 0),
+            Err(err) => reply.error(err),
         }
-        let ino = inode.as_ref().unwrap().stat.read().ino;
-        let mut stat = inode.as_ref().unwrap().get_stat();
-        stat.file_type = inode::InodeFileType::Directory;
-        stat.size = 0;
-        stat.ref_cnt = 0;
-        stat.n_link = 2;
-        stat.last_accessed = time_now();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        inode.as_ref().unwrap().modify_stat(stat);
-        directory::dir_link(inode.as_mut().unwrap(), ino, ".".to_string());
-        directory::dir_link(inode.as_mut().unwrap(), parent, "..".to_string());
-        directory::dir_link(parent_inode.as_mut().unwrap(), ino, name);
-        let stat = inode.as_ref().unwrap().get_stat();
-        let attr = transfer_stat_to_attr(stat);
-        self.inode_manager.as_ref().unwrap().write().i_put(parent_inode.unwrap());
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
-        reply.entry(&TTL, &attr, 0);
     }
 
     fn unlink(&mut self, _req: &Request<'_>, _parent: u64, _name: &std::ffi::OsStr, reply: ReplyEmpty) {
-        let parent = _parent as u32;
-        let name = _name.to_str().unwrap().to_string();
-        println!("unlink {} {}", parent, name);
-        let mut parent_inode = self.get_inode(parent);
-        if parent_inode.is_none() {
-            reply.error(ENOENT);
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        let ino = directory::dir_lookup(parent_inode.as_ref().unwrap(), name.clone());
-        if ino.is_none() {
-            reply.error(ENOENT);
-            return;
+        let parent = _parent;
+        let name = match name_from_os_str(_name, ENOENT) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        println!("unlink {} {}", parent, name);
+        match self.remove_entry(_req, parent, name, false) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
         }
-        let ino = ino.unwrap().0 as u32;
-        let inode = self.get_inode(ino);
-        if inode.is_none() {
-            reply.error(ENOENT);
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, _parent: u64, _name: &std::ffi::OsStr, reply: ReplyEmpty) {
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        let mut stat = parent_inode.as_ref().unwrap().get_stat();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        parent_inode.as_ref().unwrap().modify_stat(stat);
-        directory::dir_unlink(parent_inode.as_mut().unwrap(), ino, name);
-        let mut stat = inode.as_ref().unwrap().get_stat();
-        stat.n_link -= 1;
-        stat.last_metadata_changed = time_now();
-        inode.as_ref().unwrap().modify_stat(stat);
-        if inode.as_ref().unwrap().get_stat().n_link == 0 {
-            inode.as_ref().unwrap().delete();
+        let parent = _parent;
+        let name = match name_from_os_str(_name, ENOENT) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        println!("rmdir {} {}", parent, name);
+        match self.remove_entry(_req, parent, name, true) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
         }
-        self.inode_manager.as_ref().unwrap().write().i_put(parent_inode.unwrap());
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
-        reply.ok();
     }
+    
 
-    fn rmdir(&mut self, _req: &Request<'_>, _parent: u64, _name: &std::ffi::OsStr, reply: ReplyEmpty) {
-        let parent = _parent as u32;
-        let name = _name.to_str().unwrap().to_string();
-        println!("rmdir {} {}", parent, name);
-        let mut parent_inode = self.get_inode(parent);
-        if parent_inode.is_none() {
-            reply.error(ENOENT);
+    fn rename(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _newparent: u64, _newname: &OsStr, _flags: u32, reply: ReplyEmpty) {
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        let ino = directory::dir_lookup(parent_inode.as_ref().unwrap(), name.clone());
-        if ino.is_none() {
-            reply.error(ENOENT);
-            return;
+        let parent = _parent;
+        let name = match name_from_os_str(_name, ENOENT) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        let newparent = _newparent;
+        let newname = match name_from_os_str(_newname, EINVAL) {
+            Ok(newname) => newname,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        println!("rename {} {} {} {}", parent, name, newparent, newname);
+        let mut access = self.check_inode_access(parent, _req, libc::W_OK | libc::X_OK);
+        if access.is_ok() && newparent != parent {
+            access = self.check_inode_access(newparent, _req, libc::W_OK | libc::X_OK);
         }
-        let ino = ino.unwrap().0 as u32;
-        let inode = self.get_inode(ino);
-        if inode.is_none() {
-            reply.error(ENOENT);
+        if let Err(err) = access {
+            reply.error(err);
             return;
         }
-        if inode.as_ref().unwrap().stat.read().size > 259 * 2 {
-            reply.error(ENOENT);
-            return;
+        match self.rename_entry(parent, name, newparent, newname, _flags) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
         }
-        let mut stat = parent_inode.as_ref().unwrap().get_stat();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        parent_inode.as_ref().unwrap().modify_stat(stat);
-        directory::dir_unlink(parent_inode.as_mut().unwrap(), ino, name);
-        let mut stat = inode.as_ref().unwrap().get_stat();
-        stat.n_link = 0;
-        stat.last_metadata_changed = time_now();
-        inode.as_ref().unwrap().modify_stat(stat);
-        inode.as_ref().unwrap().delete();
-        self.inode_manager.as_ref().unwrap().write().i_put(parent_inode.unwrap());
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
-        reply.ok();
-    }
-    
-    fn rename(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _newparent: u64, _newname: &OsStr, _flags: u32, reply: ReplyEmpty) {
-        reply.error(ENOENT);
     }
 
     fn link(&mut self, _req: &Request<'_>, _ino: u64, _newparent: u64, _newname: &std::ffi::OsStr, reply: ReplyEntry) {
-        let ino = _ino as u32;
-        let newparent = _newparent as u32;
-        let newname = _newname.to_str().unwrap().to_string();
-        println!("link {} {} {}", ino, newparent, newname);
-        let mut parent_inode = self.get_inode(newparent);
-        if parent_inode.is_none() {
-            reply.error(ENOENT);
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        let inode = self.get_inode(ino);
-        if inode.is_none() {
-            reply.error(ENOENT);
-            return;
+        let ino = _ino;
+        let newparent = _newparent;
+        let newname = match name_from_os_str(_newname, EINVAL) {
+            Ok(newname) => newname,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        println!("link {} {} {}", ino, newparent, newname);
+        match self.link_entry(_req, ino, newparent, newname) {
+            Ok(stat) => reply.entry(&TTL, &transfer_stat_to_attr(stat), 0),
+            Err(err) => reply.error(err),
         }
-        directory::dir_link(parent_inode.as_mut().unwrap(), ino, newname);
-        let mut stat = inode.as_ref().unwrap().get_stat();
-        stat.n_link += 1;
-        stat.last_metadata_changed = time_now();
-        inode.as_ref().unwrap().modify_stat(stat);
-        let attr = transfer_stat_to_attr(stat);
-        self.inode_manager.as_ref().unwrap().write().i_put(parent_inode.unwrap());
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
-        reply.entry(&TTL, &attr, 0);
     }
 
     fn open(&mut self, _req: &Request<'_>, _ino: u64, _flags: i32, reply: ReplyOpen) {
-        let ino = _ino as u32;
+        let ino = _ino;
         let inode = self.get_inode(ino);
         println!("open {}", ino);
+        let (read, write) = match _flags & libc::O_ACCMODE {
+            libc::O_RDONLY => (true, _flags & libc::O_TRUNC != 0),
+            libc::O_WRONLY => (false, true),
+            libc::O_RDWR => (true, true),
+            _ => {
+                reply.error(libc::EINVAL);
+                return;
+            },
+        };
+        let mut mask = 0;
+        if read {
+            mask |= libc::R_OK;
+        }
+        if write {
+            mask |= libc::W_OK;
+        }
         match inode {
-            Some(inode) => {
+            Ok(inode) => {
+                if !check_access(&inode.get_stat(), _req.uid(), _req.gid(), mask) {
+                    self.inode_manager.as_ref().unwrap().write().i_put(inode);
+                    reply.error(libc::EACCES);
+                    return;
+                }
                 inode.stat.write().ref_cnt += 1;
                 self.inode_manager.as_ref().unwrap().write().i_put(inode);
-                reply.opened(self.allocate_next_file_handle(true, true), 0);
+                reply.opened(self.allocate_next_file_handle(read, write), 0);
             },
-            None => {
-                reply.error(ENOENT);
+            Err(err) => {
+                reply.error(err.errno());
             },
         }
     }
 
     fn read(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _size: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyData) {
-        let ino =  _ino as u32;
-        let offset = _offset as u32;
-        let size = _size as u32;
+        let ino =  _ino;
+        let offset = _offset as u64;
+        let size = _size as u64;
         println!("read {} {} {}", ino, offset, size);
         let inode = self.get_inode(ino);
         match inode {
-            Some(inode) => {
+            Ok(inode) => {
+                // reading at or past the end is a short read, not an error
                 if offset >= inode.get_stat().size {
-                    reply.error(ENOENT);
+                    self.inode_manager.as_ref().unwrap().write().i_put(inode);
+                    reply.data(&[]);
                     return;
                 }
                 let mut data = vec![];
                 let read_size = min(size, inode.get_stat().size - offset);
-                inode.read(offset as usize, read_size as usize, &mut data);
+                let ret = inode.read(offset, read_size as usize, &mut data).and_then(|_| self.touch_atime(&inode));
                 self.inode_manager.as_ref().unwrap().write().i_put(inode);
-                reply.data(&data);
+                match ret {
+                    Ok(_) => reply.data(&data),
+                    Err(err) => reply.error(err.errno()),
+                }
             },
-            None => {
-                reply.error(ENOENT);
+            Err(err) => {
+                reply.error(err.errno());
             },
         }
     }
 
     fn write(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _data: &[u8], _write_flags: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyWrite) {
-        let ino = _ino as u32;
-        let offset = _offset as u32;
+        if self.read_only {
+            reply.error(libc::EROFS);
+            return;
+        }
+        let ino = _ino;
+        let offset = _offset as u64;
         let data = _data;
-        let inode = self.get_inode(ino);
         println!("write {} {} {}", ino, offset, data.len());
+        if offset + data.len() as u64 > consts::MAX_FILE_SIZE {
+            reply.error(libc::EFBIG);
+            return;
+        }
+        let inode = self.get_inode(ino);
         match inode {
-            Some(inode) => {
-                if offset > inode.get_stat().size {
-                    reply.error(ENOENT);
-                    return;
-                }
-                inode.write(offset as usize, data.len(), &data.to_vec());
+            Ok(inode) => {
+                let ret = inode.write(offset, data.len(), &data.to_vec());
                 self.inode_manager.as_ref().unwrap().write().i_put(inode);
-                reply.written(data.len() as u32);
+                match ret {
+                    Ok(()) => reply.written(data.len() as u32),
+                    Err(err) => reply.error(err.errno()),
+                }
             },
-            None => {
-                reply.error(ENOENT);
+            Err(err) => {
+                reply.error(err.errno());
             },
         }
     }
 
+    fn fallocate(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _length: i64, _mode: i32, reply: ReplyEmpty) {
+        if self.read_only {
+            reply.error(libc::EROFS);
+            return;
+        }
+        let ino = _ino;
+        println!("fallocate {} {} {} {}", ino, _offset, _length, _mode);
+        if _offset < 0 || _length <= 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+        if (_offset as u64).saturating_add(_length as u64) > consts::MAX_FILE_SIZE {
+            reply.error(libc::EFBIG);
+            return;
+        }
+        match self.allocate_range(ino, _offset as u64, _length as u64, _mode) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn lseek(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _whence: i32, reply: ReplyLseek) {
+        let ino = _ino;
+        println!("lseek {} {} {}", ino, _offset, _whence);
+        match self.seek_data(ino, _offset, _whence) {
+            Ok(offset) => reply.offset(offset),
+            Err(err) => reply.error(err),
+        }
+    }
+
     fn release(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _flags: i32, _lock_owner: Option<u64>, _flush: bool, reply: ReplyEmpty) {
-        let ino = _ino as u32;
+        let ino = _ino;
         let inode = self.get_inode(ino);
         println!("release {}", ino);
-        if inode.is_none() {
-            reply.error(ENOENT);
-            return;
-        }
-        inode.as_ref().unwrap().stat.write().ref_cnt -= 1;
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
+        let inode = match inode {
+            Ok(inode) => inode,
+            Err(err) => {
+                reply.error(err.errno());
+                return;
+            },
+        };
+        inode.stat.write().ref_cnt -= 1;
+        self.inode_manager.as_ref().unwrap().write().i_put(inode);
         reply.ok();
     }
 
+    fn fsync(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        println!("fsync {}", ino);
+        // puts are committed in groups, an fsync closes the current one
+        match WondFS::sync(self) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err.errno()),
+        }
+    }
+
     fn opendir(&mut self, _req: &Request<'_>, _ino: u64, _flags: i32, reply: ReplyOpen) {
-        let ino = _ino as u32;
+        let ino = _ino;
         let inode = self.get_inode(ino);
         println!("opendir {}", ino);
         match inode {
-            Some(inode) => {
+            Ok(inode) => {
                 inode.stat.write().ref_cnt += 1;
                 self.inode_manager.as_ref().unwrap().write().i_put(inode);
                 reply.opened(self.allocate_next_file_handle(true, true), 1);
             },
-            None => {
-                reply.error(ENOENT);
+            Err(err) => {
+                reply.error(err.errno());
             },
         }   
     }  
 
     fn readdir(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, mut reply: ReplyDirectory) {
-        let ino = _ino as u32;
+        let ino = _ino;
         let offset = _offset as i32;
         let inode = self.get_inode(ino);
         println!("readdir {} {}", ino, offset);
-        if inode.is_none() {
-            reply.error(ENOENT);
-            return;
-        }
-        let mut data = vec![];
-        inode.as_ref().unwrap().read_all(&mut data);
-        let iter = directory::DirectoryParser::new(&data);
-        for (index, entry) in iter.skip(offset as usize).enumerate() {
-            // println!("{}", entry.ino);
-            let file_type = self.get_inode(entry.ino).unwrap().stat.read().file_type;
-            println!("{:?}", file_type);
+        let inode = match inode {
+            Ok(inode) => inode,
+            Err(err) => {
+                reply.error(err.errno());
+                return;
+            },
+        };
+        let entries = self.read_dir_entries(&inode).and_then(|entries| {
+            self.touch_atime(&inode)?;
+            Ok(entries)
+        });
+        self.inode_manager.as_ref().unwrap().write().i_put(inode);
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(err) => {
+                reply.error(err.errno());
+                return;
+            },
+        };
+        for (index, (ino, file_type, file_name)) in entries.into_iter().skip(offset as usize).enumerate() {
             let buffer_full: bool = reply.add(
-                entry.ino as u64,
+                ino,
                 offset as i64 + index as i64 + 1,
                 file_type.into(),
-                OsStr::from_bytes(entry.file_name.as_bytes()),
+                OsStr::from_bytes(file_name.as_bytes()),
             );
             if buffer_full {
                 break;
             }
         }
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
         reply.ok()
     }
 
     fn releasedir(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _flags: i32, reply: ReplyEmpty) {
-        let ino = _ino as u32;
+        let ino = _ino;
         let inode = self.get_inode(ino);
         println!("release {}", ino);
-        if inode.is_none() {
-            reply.error(ENOENT);
-            return;
-        }
-        inode.as_ref().unwrap().stat.write().ref_cnt -= 1;
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
+        let inode = match inode {
+            Ok(inode) => inode,
+            Err(err) => {
+                reply.error(err.errno());
+                return;
+            },
+        };
+        inode.stat.write().ref_cnt -= 1;
+        self.inode_manager.as_ref().unwrap().write().i_put(inode);
         reply.ok();
     }
 
-    // fn statfs(&mut self, _req: &Request, _ino: u64, reply: ReplyStatfs) {
-    //     warn!("statfs() implementation is a stub");
-    //     // TODO: real implementation of this
-    //     reply.statfs(
-    //         10_000,
-    //         10_000,
-    //         10_000,
-    //         1,
-    //         10_000,
-    //         BLOCK_SIZE as u32,
-    //         MAX_NAME_LENGTH,
-    //         BLOCK_SIZE as u32,
-    //     );
-    // }
+    fn fsyncdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        println!("fsyncdir {}", ino);
+        match WondFS::sync(self) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err.errno()),
+        }
+    }
 
-    fn access(&mut self, _req: &Request<'_>, _ino: u64, _mask: i32, reply: ReplyEmpty) {
-        let ino = _ino as u32;
-        println!("access {}", ino);
-        let inode = self.get_inode(ino);
-        if inode.is_none() {
-            reply.error(ENOENT);
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        println!("statfs {}", _ino);
+        // a page is the allocation unit, inode numbers are never reused so what is left is free
+        let (blocks, bfree) = self.kv.get_space_stat();
+        let ffree = self.kv.get_ino_limit() - *self.kv.max_ino.read();
+        let files = self.kv.get_inode_num().saturating_add(ffree);
+        reply.statfs(
+            blocks as u64,
+            bfree as u64,
+            bfree as u64,
+            files,
+            ffree,
+            consts::PAGESIZE as u32,
+            consts::MAX_FNAME_LEN as u32,
+            consts::PAGESIZE as u32,
+        );
+    }
+
+    fn setxattr(&mut self, req: &Request<'_>, _ino: u64, _name: &OsStr, value: &[u8], flags: i32, _position: u32, reply: ReplyEmpty) {
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
-        reply.ok();
+        let ino = _ino;
+        let name = match name_from_os_str(_name, EINVAL) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        println!("setxattr {} {}", ino, name);
+        match self.set_xattr(ino, &name, value, flags, req.uid(), req.gid()) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
+        }
     }
 
-    fn create(&mut self, _req: &Request<'_>, _parent: u64, _name: &std::ffi::OsStr, mut _mode: u32, _umask: u32, _flags: i32, reply: ReplyCreate) {
-        let parent = _parent as u32;
-        let name = _name.to_str().unwrap().to_string();
-        println!("create {} {}", parent, name);
-        let mut parent_inode = self.get_inode(parent);
-        if parent_inode.is_none() {
-            reply.error(ENOENT);
-            return;
+    fn getxattr(&mut self, req: &Request<'_>, _ino: u64, _name: &OsStr, size: u32, reply: ReplyXattr) {
+        let ino = _ino;
+        let name = match name_from_os_str(_name, ENODATA) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        println!("getxattr {} {}", ino, name);
+        match self.get_xattr(ino, &name, req.uid(), req.gid()) {
+            Ok(value) => reply_xattr(&value, size, reply),
+            Err(err) => reply.error(err),
         }
-        let ino = directory::dir_lookup(parent_inode.as_ref().unwrap(), name.clone());
-        if ino.is_some() {
-            reply.error(ENOENT);
-            return;
+    }
+
+    fn listxattr(&mut self, req: &Request<'_>, _ino: u64, size: u32, reply: ReplyXattr) {
+        let ino = _ino;
+        println!("listxattr {}", ino);
+        match self.list_xattr(ino, req.uid()) {
+            Ok(names) => reply_xattr(&names, size, reply),
+            Err(err) => reply.error(err),
         }
-        let mut stat = parent_inode.as_ref().unwrap().get_stat();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        parent_inode.as_ref().unwrap().modify_stat(stat);
-        let mut inode = self.new_inode_file();
-        if inode.is_none() {
-            reply.error(ENOENT);
+    }
+
+    fn removexattr(&mut self, req: &Request<'_>, _ino: u64, _name: &OsStr, reply: ReplyEmpty) {
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        let ino = inode.as_ref().unwrap().stat.read().ino;
-        let mut stat = inode.as_ref().unwrap().get_stat();
-        stat.file_type = as_file_kind(_mode);
-        stat.size = 0;
-        stat.ref_cnt = 1;
-        stat.last_accessed = time_now();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        if stat.file_type == inode::InodeFileType::File {
-            stat.n_link = 1;
-        }
-        if stat.file_type == inode::InodeFileType::Directory {
-            stat.n_link = 2;
+        let ino = _ino;
+        let name = match name_from_os_str(_name, ENODATA) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        println!("removexattr {} {}", ino, name);
+        match self.remove_xattr(ino, &name, req.uid(), req.gid()) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
         }
-        inode.as_ref().unwrap().modify_stat(stat);
-        if inode.as_ref().unwrap().stat.read().file_type == inode::InodeFileType::Directory {
-            directory::dir_link(inode.as_mut().unwrap(), ino, ".".to_string());
-            directory::dir_link(inode.as_mut().unwrap(), parent, "..".to_string());
+    }
+
+    fn access(&mut self, _req: &Request<'_>, _ino: u64, _mask: i32, reply: ReplyEmpty) {
+        let ino = _ino;
+        println!("access {}", ino);
+        match self.check_inode_access(ino, _req, _mask) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
         }
-        directory::dir_link(parent_inode.as_mut().unwrap(), ino, name);
-        // println!("{}", parent_inode.as_ref().unwrap().stat.read().size);
-        let stat = inode.as_ref().unwrap().get_stat();
-        let attr = transfer_stat_to_attr(stat);
-        self.inode_manager.as_ref().unwrap().write().i_put(parent_inode.unwrap());
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
-        reply.created(
-            &TTL,
-            &attr,
-            0,
-            self.allocate_next_file_handle(true, true),
-            0,
-        );
     }
 
-    fn symlink(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _link: &std::path::Path, reply: ReplyEntry) {
-        let parent = _parent as u32;
-        let name = _name.to_str().unwrap().to_string();
-        let path = _link.to_str().unwrap().to_string();
-        // let inode = path::name_i(self.inode_manager.as_ref().unwrap().clone(), path);
-        // if inode.is_none() {
-            // reply.error(ENOENT);
-            // return;
-        // }
-        // let exact_ino = inode.unwrap().stat.read().ino;
-        // println!("{}", exact_ino);
-        let mut parent_inode = self.get_inode(parent);
-        if parent_inode.is_none() {
-            reply.error(ENOENT);
+    fn create(&mut self, _req: &Request<'_>, _parent: u64, _name: &std::ffi::OsStr, mut _mode: u32, _umask: u32, _flags: i32, reply: ReplyCreate) {
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        if directory::dir_lookup(parent_inode.as_ref().unwrap(), name.clone()).is_some() {
-            reply.error(libc::ENOENT);
-            return;
+        let parent = _parent;
+        let name = match name_from_os_str(_name, EINVAL) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        println!("create {} {}", parent, name);
+        let file_type = match as_file_kind(_mode) {
+            Ok(file_type) => file_type,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        let mode = (_mode & !_umask & 0o7777) as u16;
+        match self.make_node(_req, parent, name, NodeSpec { file_type, mode, ref_cnt: 1, link: None }) {
+            Ok(stat) => reply.created(
+                &TTL,
+                &transfer_stat_to_attr(stat),
+                0,
+                self.allocate_next_file_handle(true, true),
+                0,
+            ),
+            Err(err) => reply.error(err),
         }
-        let mut stat = parent_inode.as_ref().unwrap().get_stat();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        parent_inode.as_ref().unwrap().modify_stat(stat);
-        let inode = self.new_inode_file();
-        if inode.is_none() {
-            reply.error(ENOENT);
+    }
+
+    fn symlink(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _link: &std::path::Path, reply: ReplyEntry) {
+        if self.read_only {
+            reply.error(libc::EROFS);
             return;
         }
-        let ino = inode.as_ref().unwrap().stat.read().ino;
-        let mut stat = inode.as_ref().unwrap().get_stat();
-        stat.file_type = inode::InodeFileType::Symlink;
-        stat.size = 0;
-        stat.ref_cnt = 0;
-        stat.last_accessed = time_now();
-        stat.last_modified = time_now();
-        stat.last_metadata_changed = time_now();
-        inode.as_ref().unwrap().modify_stat(stat);
-        directory::dir_link(parent_inode.as_mut().unwrap(), ino, name);
-        symlink::write_symlink(&inode.as_ref().unwrap().clone(), path);
-        let stat = inode.as_ref().unwrap().get_stat();
-        let attr = transfer_stat_to_attr(stat);
-        self.inode_manager.as_ref().unwrap().write().i_put(parent_inode.unwrap());
-        self.inode_manager.as_ref().unwrap().write().i_put(inode.unwrap());
-        reply.entry(&TTL, &attr, 0);
+        let parent = _parent;
+        let name = match name_from_os_str(_name, EINVAL) {
+            Ok(name) => name,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        let path = match name_from_os_str(_link.as_os_str(), EINVAL) {
+            Ok(path) => path,
+            Err(err) => {
+                reply.error(err);
+                return;
+            },
+        };
+        println!("symlink {} {}", parent, name);
+        match self.make_node(_req, parent, name, NodeSpec { file_type: inode::InodeFileType::Symlink, mode: 0o777, ref_cnt: 0, link: Some(path) }) {
+            Ok(stat) => reply.entry(&TTL, &transfer_stat_to_attr(stat), 0),
+            Err(err) => reply.error(err),
+        }
     }
+
 }
 
-pub fn as_file_kind(mut mode: u32) -> inode::InodeFileType {
-    mode &= libc::S_IFMT as u32;
-    if mode == libc::S_IFREG as u32 {
-        return inode::InodeFileType::File;
-    } else if mode == libc::S_IFLNK as u32 {
-        return inode::InodeFileType::Symlink;
-    } else if mode == libc::S_IFDIR as u32 {
-        return inode::InodeFileType::Directory;
+// a zero size only probes for the length the caller has to allocate
+pub fn reply_xattr(data: &[u8], size: u32, reply: ReplyXattr) {
+    if size == 0 {
+        reply.size(data.len() as u32);
+    } else if data.len() > size as usize {
+        reply.error(libc::ERANGE);
     } else {
-        unimplemented!("{}", mode);
+        reply.data(data);
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,12 @@
 extern crate fuser;
 use fuser::*;
+use std::cmp::min;
 use std::time::{UNIX_EPOCH, SystemTime, Duration};
 use crate::inode::inode;
+use crate::util::time::Timespec;
+use super::options::AtimePolicy;
+
+const RELATIME_INTERVAL: i64 = 24 * 60 * 60;
 
 impl From<inode::InodeFileType> for fuser::FileType {
     fn from(kind: inode::InodeFileType) -> Self {
@@ -22,43 +27,126 @@ pub fn transfer_stat_to_attr(stat: inode::InodeStat) -> FileAttr {
     }
     FileAttr {
         ino: stat.ino as u64,
-        size: stat.size as u64,
-        blocks: ((size - 1) / 512 + 1) as u64,
-        atime: system_time_from_time(stat.last_accessed as i64, 0),
-        mtime: system_time_from_time(stat.last_modified as i64, 0),
-        ctime: system_time_from_time(stat.last_metadata_changed as i64, 0),
+        size: stat.size,
+        blocks: (size - 1) / 512 + 1,
+        atime: system_time_from_time(stat.last_accessed),
+        mtime: system_time_from_time(stat.last_modified),
+        ctime: system_time_from_time(stat.last_metadata_changed),
         kind: stat.file_type.into(),
-        perm: 0o777,
+        perm: stat.mode,
         nlink: stat.n_link as u32,
-        uid: 0,
-        gid: 0,
+        uid: stat.uid,
+        gid: stat.gid,
         rdev: 0,
         flags: 0,
         blksize: 512,
         padding: 0,
-        crtime: UNIX_EPOCH,
+        crtime: system_time_from_time(stat.created),
+    }
+}
+
+// mask is made of libc::R_OK, W_OK and X_OK, F_OK always passes
+pub fn check_access(stat: &inode::InodeStat, uid: u32, gid: u32, mask: i32) -> bool {
+    let mode = stat.mode as i32;
+    let mut mask = mask & (libc::R_OK | libc::W_OK | libc::X_OK);
+    if uid == 0 {
+        // root skips read and write checks, exec still needs an x bit on non directories
+        if stat.file_type == inode::InodeFileType::Directory {
+            return true;
+        }
+        return mask & libc::X_OK == 0 || mode & 0o111 != 0;
+    }
+    if uid == stat.uid {
+        mask &= !(mode >> 6);
+    } else if gid == stat.gid {
+        mask &= !(mode >> 3);
+    } else {
+        mask &= !mode;
+    }
+    mask == 0
+}
+
+// names are kept as utf-8, so one that is not can never be found and is refused when made
+pub fn name_from_os_str(name: &std::ffi::OsStr, errno: libc::c_int) -> Result<String, libc::c_int> {
+    name.to_str().map(|name| name.to_string()).ok_or(errno)
+}
+
+// fifos, sockets and device nodes have no inode type to live in
+pub fn as_file_kind(mut mode: u32) -> Result<inode::InodeFileType, libc::c_int> {
+    mode &= libc::S_IFMT;
+    if mode == libc::S_IFREG {
+        Ok(inode::InodeFileType::File)
+    } else if mode == libc::S_IFLNK {
+        Ok(inode::InodeFileType::Symlink)
+    } else if mode == libc::S_IFDIR {
+        Ok(inode::InodeFileType::Directory)
+    } else if [libc::S_IFIFO, libc::S_IFSOCK, libc::S_IFCHR, libc::S_IFBLK].contains(&mode) {
+        Err(libc::EOPNOTSUPP)
+    } else {
+        Err(libc::EINVAL)
     }
 }
 
-pub fn time_now() -> u32 {
-    time_from_system_time(&SystemTime::now()).0 as u32
+pub fn time_now() -> Timespec {
+    time_from_system_time(&SystemTime::now())
 }
 
-pub fn system_time_from_time(secs: i64, nsecs: u32) -> SystemTime {
-    if secs >= 0 {
-        UNIX_EPOCH + Duration::new(secs as u64, nsecs)
+// nsec always counts forward from sec, so 1.5s before the epoch is (-2, 500000000)
+pub fn system_time_from_time(time: Timespec) -> SystemTime {
+    if time.sec >= 0 {
+        UNIX_EPOCH + Duration::new(time.sec as u64, time.nsec)
     } else {
-        UNIX_EPOCH - Duration::new((-secs) as u64, nsecs)
+        UNIX_EPOCH - Duration::from_secs(time.sec.unsigned_abs()) + Duration::from_nanos(time.nsec as u64)
     }
 }
 
-pub fn time_from_system_time(system_time: &SystemTime) -> (i64, u32) {
+pub fn time_from_system_time(system_time: &SystemTime) -> Timespec {
     match system_time.duration_since(UNIX_EPOCH) {
-        Ok(duration) => (duration.as_secs() as i64, duration.subsec_nanos()),
-        Err(before_epoch_error) => (
-            -(before_epoch_error.duration().as_secs() as i64),
-            before_epoch_error.duration().subsec_nanos(),
-        ),
+        Ok(duration) => Timespec::new(duration.as_secs() as i64, duration.subsec_nanos()),
+        Err(before_epoch_error) => {
+            let duration = before_epoch_error.duration();
+            match duration.subsec_nanos() {
+                0 => Timespec::new(-(duration.as_secs() as i64), 0),
+                nsec => Timespec::new(-(duration.as_secs() as i64) - 1, 1_000_000_000 - nsec),
+            }
+        },
+    }
+}
+
+// relatime as linux has it, atime moves when it is not newer than mtime or ctime or is a day old
+pub fn atime_needs_update(policy: AtimePolicy, stat: &inode::InodeStat, now: Timespec) -> bool {
+    match policy {
+        AtimePolicy::Strict => true,
+        AtimePolicy::Never => false,
+        AtimePolicy::Relative => {
+            stat.last_accessed <= stat.last_modified
+                || stat.last_accessed <= stat.last_metadata_changed
+                || now.sec - stat.last_accessed.sec >= RELATIME_INTERVAL
+        },
+    }
+}
+
+// where lseek lands for SEEK_DATA or SEEK_HOLE given the data ranges of a file, the end of the
+// file counts as a hole
+pub fn seek_data_hole(ranges: &[(u64, u64)], size: u64, offset: i64, whence: i32) -> Result<i64, libc::c_int> {
+    if offset < 0 {
+        return Err(libc::EINVAL);
+    }
+    let offset = offset as u64;
+    if offset >= size {
+        return Err(libc::ENXIO);
+    }
+    let range = ranges.iter().find(|range| range.0 + range.1 > offset);
+    match whence {
+        libc::SEEK_DATA => match range {
+            Some(range) => Ok(range.0.max(offset) as i64),
+            None => Err(libc::ENXIO),
+        },
+        libc::SEEK_HOLE => match range {
+            Some(range) if range.0 <= offset => Ok(min(range.0 + range.1, size) as i64),
+            _ => Ok(offset as i64),
+        },
+        _ => Err(libc::EINVAL),
     }
 }
 
@@ -69,7 +157,81 @@ mod tests {
     #[test]
     fn test() {
         let time = time_now();
-        let systime = system_time_from_time(time as i64, 0);
-        assert_eq!(time, time_from_system_time(&systime).0 as u32);
+        let systime = system_time_from_time(time);
+        assert_eq!(time, time_from_system_time(&systime));
+        let before = UNIX_EPOCH - Duration::from_millis(1500);
+        assert_eq!(time_from_system_time(&before), Timespec::new(-2, 500_000_000));
+        assert_eq!(system_time_from_time(Timespec::new(-2, 500_000_000)), before);
+        assert_eq!(time_from_system_time(&(UNIX_EPOCH - Duration::from_secs(3))), Timespec::new(-3, 0));
+    }
+
+    #[test]
+    fn atime() {
+        let mut stat = inode::InodeStat::new();
+        let now = Timespec::new(1_000_000, 5);
+        stat.last_modified = Timespec::new(900_000, 0);
+        stat.last_metadata_changed = stat.last_modified;
+        stat.last_accessed = Timespec::new(900_000, 0);
+        assert!(atime_needs_update(AtimePolicy::Relative, &stat, now));
+        assert!(!atime_needs_update(AtimePolicy::Never, &stat, now));
+        stat.last_accessed = Timespec::new(900_000, 1);
+        assert!(atime_needs_update(AtimePolicy::Relative, &stat, now));
+        stat.last_accessed = Timespec::new(999_000, 0);
+        assert!(!atime_needs_update(AtimePolicy::Relative, &stat, now));
+        assert!(atime_needs_update(AtimePolicy::Strict, &stat, now));
+        stat.last_modified = Timespec::new(999_000, 1);
+        assert!(atime_needs_update(AtimePolicy::Relative, &stat, now));
+    }
+
+    #[test]
+    fn name() {
+        use std::os::unix::ffi::OsStrExt;
+        assert_eq!(name_from_os_str(std::ffi::OsStr::new("a.txt"), libc::EINVAL), Ok("a.txt".to_string()));
+        assert_eq!(name_from_os_str(std::ffi::OsStr::from_bytes(&[0x61, 0xff]), libc::ENOENT), Err(libc::ENOENT));
+    }
+
+    #[test]
+    fn file_kind() {
+        assert_eq!(as_file_kind(libc::S_IFREG | 0o644), Ok(inode::InodeFileType::File));
+        assert_eq!(as_file_kind(libc::S_IFDIR | 0o755), Ok(inode::InodeFileType::Directory));
+        assert_eq!(as_file_kind(libc::S_IFLNK), Ok(inode::InodeFileType::Symlink));
+        assert_eq!(as_file_kind(libc::S_IFIFO | 0o644), Err(libc::EOPNOTSUPP));
+        assert_eq!(as_file_kind(libc::S_IFSOCK), Err(libc::EOPNOTSUPP));
+        assert_eq!(as_file_kind(libc::S_IFCHR), Err(libc::EOPNOTSUPP));
+        assert_eq!(as_file_kind(0o644), Err(libc::EINVAL));
+    }
+
+    #[test]
+    fn access() {
+        let mut stat = inode::InodeStat::new();
+        stat.mode = 0o640;
+        stat.uid = 1000;
+        stat.gid = 100;
+        assert!(check_access(&stat, 1000, 1000, libc::R_OK | libc::W_OK));
+        assert!(!check_access(&stat, 1000, 1000, libc::X_OK));
+        assert!(check_access(&stat, 1001, 100, libc::R_OK));
+        assert!(!check_access(&stat, 1001, 100, libc::W_OK));
+        assert!(!check_access(&stat, 1001, 1001, libc::R_OK));
+        assert!(check_access(&stat, 1001, 1001, libc::F_OK));
+        assert!(check_access(&stat, 0, 0, libc::R_OK | libc::W_OK));
+        assert!(!check_access(&stat, 0, 0, libc::X_OK));
+        stat.mode = 0o700;
+        stat.file_type = inode::InodeFileType::Directory;
+        assert!(check_access(&stat, 0, 0, libc::X_OK));
+    }
+
+    #[test]
+    fn seek() {
+        let ranges = [(0, 4096), (8192, 100)];
+        assert_eq!(seek_data_hole(&ranges, 16384, 0, libc::SEEK_DATA), Ok(0));
+        assert_eq!(seek_data_hole(&ranges, 16384, 10, libc::SEEK_HOLE), Ok(4096));
+        assert_eq!(seek_data_hole(&ranges, 16384, 4096, libc::SEEK_HOLE), Ok(4096));
+        assert_eq!(seek_data_hole(&ranges, 16384, 4096, libc::SEEK_DATA), Ok(8192));
+        assert_eq!(seek_data_hole(&ranges, 16384, 8200, libc::SEEK_HOLE), Ok(8292));
+        assert_eq!(seek_data_hole(&ranges, 16384, 9000, libc::SEEK_DATA), Err(libc::ENXIO));
+        assert_eq!(seek_data_hole(&ranges, 16384, 9000, libc::SEEK_HOLE), Ok(9000));
+        assert_eq!(seek_data_hole(&ranges, 16384, 16384, libc::SEEK_HOLE), Err(libc::ENXIO));
+        assert_eq!(seek_data_hole(&[], 0, 0, libc::SEEK_DATA), Err(libc::ENXIO));
+        assert_eq!(seek_data_hole(&ranges, 16384, 0, libc::SEEK_END), Err(libc::EINVAL));
     }
 }
\ No newline at end of file
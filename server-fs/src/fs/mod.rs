@@ -1,4 +1,6 @@
 pub mod consts;
+pub mod options;
 pub mod filesystem;
 pub mod fuse_helper;
-pub mod filesystem_impl;
\ No newline at end of file
+pub mod filesystem_impl;
+pub mod xattr;
\ No newline at end of file
@@ -0,0 +1,257 @@
+use std::path::PathBuf;
+use fuser::MountOption;
+use crate::kv::component::super_block;
+use crate::tl::scrub;
+use crate::kv::gc::gc_define::GCConfig;
+use crate::compress::compress::CompressPolicy;
+
+pub const USAGE: &str = "usage: server-fs <mountpoint> [--image PATH | --virtual] [--blocks N] [--scrub-rate N] [--gc-interval SECS] [--gc-watermarks LOW,HIGH] [--compress off|fast|best] [--ro] [-f | --foreground | --daemon] [-o OPT[,OPT...]]";
+
+#[derive(Debug, PartialEq)]
+pub struct MountConfig {
+    pub mountpoint: PathBuf,
+    pub image: Option<PathBuf>,
+    pub block_num: u32,
+    pub read_only: bool,
+    pub foreground: bool,
+    // blocks the background scrubber verifies per second, 0 turns it off
+    pub scrub_rate: u32,
+    // background gc, an interval of 0 leaves collection to writes that run out of room
+    pub gc: GCConfig,
+    // codecs tried on every extent written, what is on flash already stays as it is
+    pub compress: CompressPolicy,
+    pub atime: AtimePolicy,
+    pub mount_options: Vec<MountOption>,
+}
+
+// when reads move the access time, fuse leaves it to the filesystem
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AtimePolicy {
+    Strict,
+    Relative,
+    Never,
+}
+
+impl AtimePolicy {
+    // "atime" asks for the default, which is relatime
+    pub fn parse(option: &str) -> Option<AtimePolicy> {
+        match option {
+            "strictatime" => Some(AtimePolicy::Strict),
+            "atime" | "relatime" => Some(AtimePolicy::Relative),
+            "noatime" => Some(AtimePolicy::Never),
+            _ => None,
+        }
+    }
+}
+
+impl MountConfig {
+    pub fn new(mountpoint: PathBuf) -> MountConfig {
+        MountConfig {
+            mountpoint,
+            image: None,
+            block_num: super_block::DEFAULT_BLOCK_NUM,
+            read_only: false,
+            foreground: true,
+            scrub_rate: scrub::DEFAULT_SCRUB_RATE,
+            gc: GCConfig::new(),
+            compress: CompressPolicy::Fast,
+            atime: AtimePolicy::Relative,
+            mount_options: vec![],
+        }
+    }
+
+    pub fn parse(args: &[String]) -> Result<MountConfig, String> {
+        let mut mountpoint = None;
+        let mut image = None;
+        let mut is_virtual = false;
+        let mut block_num = None;
+        let mut read_only = false;
+        let mut foreground = true;
+        let mut scrub_rate = scrub::DEFAULT_SCRUB_RATE;
+        let mut gc = GCConfig::new();
+        let mut compress = CompressPolicy::Fast;
+        let mut atime = AtimePolicy::Relative;
+        let mut mount_options = vec![];
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--image" => image = Some(PathBuf::from(next_value(arg, iter.next())?)),
+                "--virtual" => is_virtual = true,
+                "--blocks" => {
+                    let value = next_value(arg, iter.next())?;
+                    let num = value.parse().map_err(|_| format!("--blocks expects a number, got {}", value))?;
+                    block_num = Some(num);
+                },
+                "--scrub-rate" => {
+                    let value = next_value(arg, iter.next())?;
+                    scrub_rate = value.parse().map_err(|_| format!("--scrub-rate expects a number, got {}", value))?;
+                },
+                "--gc-interval" => {
+                    let value = next_value(arg, iter.next())?;
+                    gc.interval = value.parse().map_err(|_| format!("--gc-interval expects a number, got {}", value))?;
+                },
+                "--gc-watermarks" => {
+                    let value = next_value(arg, iter.next())?;
+                    let watermarks = value.split_once(',').and_then(|(low, high)| Some((low.parse::<u32>().ok()?, high.parse::<u32>().ok()?)));
+                    match watermarks {
+                        Some((low, high)) if low <= high && high <= 100 => {
+                            gc.low_watermark = low;
+                            gc.high_watermark = high;
+                        },
+                        _ => return Err(format!("--gc-watermarks expects LOW,HIGH percents with LOW <= HIGH, got {}", value)),
+                    }
+                },
+                "--compress" => {
+                    let value = next_value(arg, iter.next())?;
+                    compress = CompressPolicy::parse(value).ok_or_else(|| format!("--compress expects off, fast or best, got {}", value))?;
+                },
+                "--ro" => read_only = true,
+                "-f" | "--foreground" => foreground = true,
+                "--daemon" => foreground = false,
+                "-o" => {
+                    for option in next_value(arg, iter.next())?.split(',') {
+                        if let Some(policy) = AtimePolicy::parse(option) {
+                            atime = policy;
+                            continue;
+                        }
+                        match parse_mount_option(option) {
+                            Some(MountOption::RO) => read_only = true,
+                            Some(MountOption::RW) => read_only = false,
+                            Some(option) => mount_options.push(option),
+                            None => (),
+                        }
+                    }
+                },
+                _ => {
+                    if arg.starts_with('-') || mountpoint.is_some() {
+                        return Err(format!("unexpected argument {}", arg));
+                    }
+                    mountpoint = Some(PathBuf::from(arg));
+                },
+            }
+        }
+        let mountpoint = mountpoint.ok_or_else(|| String::from("missing mountpoint"))?;
+        if is_virtual && image.is_some() {
+            return Err(String::from("--virtual and --image are exclusive"));
+        }
+        // a virtual disk starts out empty, read-only it could not even hold a root directory
+        if read_only && image.is_none() {
+            return Err(String::from("--ro needs an --image, a virtual disk starts out empty"));
+        }
+        if image.is_some() && block_num.is_some() {
+            return Err(String::from("--blocks only applies to a virtual disk, an image keeps the geometry from its superblock"));
+        }
+        let mut config = MountConfig::new(mountpoint);
+        config.image = image;
+        config.block_num = block_num.unwrap_or(super_block::DEFAULT_BLOCK_NUM);
+        config.read_only = read_only;
+        config.foreground = foreground;
+        config.scrub_rate = scrub_rate;
+        config.gc = gc;
+        config.compress = compress;
+        config.atime = atime;
+        config.mount_options = mount_options;
+        Ok(config)
+    }
+
+    pub fn fuse_options(&self) -> Vec<MountOption> {
+        let mut options = vec![MountOption::AutoUnmount];
+        if !self.mount_options.iter().any(|option| matches!(option, MountOption::FSName(_))) {
+            options.push(MountOption::FSName(String::from("wondfs")));
+        }
+        if self.read_only {
+            options.push(MountOption::RO);
+        }
+        for option in self.mount_options.iter() {
+            if !options.contains(option) {
+                options.push(option.clone());
+            }
+        }
+        options
+    }
+}
+
+pub fn parse_mount_option(option: &str) -> Option<MountOption> {
+    let option = match option {
+        "" => return None,
+        "auto_unmount" => MountOption::AutoUnmount,
+        "allow_other" => MountOption::AllowOther,
+        "allow_root" => MountOption::AllowRoot,
+        "default_permissions" => MountOption::DefaultPermissions,
+        "dev" => MountOption::Dev,
+        "nodev" => MountOption::NoDev,
+        "suid" => MountOption::Suid,
+        "nosuid" => MountOption::NoSuid,
+        "ro" => MountOption::RO,
+        "rw" => MountOption::RW,
+        "exec" => MountOption::Exec,
+        "noexec" => MountOption::NoExec,
+        "atime" => MountOption::Atime,
+        "noatime" => MountOption::NoAtime,
+        "dirsync" => MountOption::DirSync,
+        "sync" => MountOption::Sync,
+        "async" => MountOption::Async,
+        _ => {
+            if let Some(name) = option.strip_prefix("fsname=") {
+                MountOption::FSName(name.to_string())
+            } else if let Some(name) = option.strip_prefix("subtype=") {
+                MountOption::Subtype(name.to_string())
+            } else {
+                MountOption::CUSTOM(option.to_string())
+            }
+        },
+    };
+    Some(option)
+}
+
+fn next_value<'a>(flag: &str, value: Option<&'a String>) -> Result<&'a String, String> {
+    value.ok_or_else(|| format!("{} expects a value", flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn basics() {
+        let config = MountConfig::parse(&args("/mnt/a")).unwrap();
+        assert_eq!(config, MountConfig::new(PathBuf::from("/mnt/a")));
+        let config = MountConfig::parse(&args("/mnt/b --image b.img --ro --daemon -o allow_other,fsname=b,direct_io")).unwrap();
+        assert_eq!(config.image, Some(PathBuf::from("b.img")));
+        assert!(config.read_only);
+        assert!(!config.foreground);
+        assert_eq!(config.mount_options, vec![MountOption::AllowOther, MountOption::FSName(String::from("b")), MountOption::CUSTOM(String::from("direct_io"))]);
+        let config = MountConfig::parse(&args("--virtual --blocks 612 /mnt/c")).unwrap();
+        assert_eq!(config.block_num, 612);
+        assert_eq!(config.scrub_rate, scrub::DEFAULT_SCRUB_RATE);
+        let config = MountConfig::parse(&args("--image c.img /mnt/c -o ro")).unwrap();
+        assert!(config.read_only);
+        assert!(config.fuse_options().contains(&MountOption::RO));
+        assert!(MountConfig::parse(&args("--virtual /mnt/c -o ro")).is_err());
+        assert!(MountConfig::parse(&args("/mnt/c --ro")).is_err());
+        let config = MountConfig::parse(&args("/mnt/g --scrub-rate 0")).unwrap();
+        assert_eq!(config.scrub_rate, 0);
+        assert!(MountConfig::parse(&args("/mnt/g --scrub-rate fast")).is_err());
+        let config = MountConfig::parse(&args("/mnt/h --gc-interval 0 --gc-watermarks 10,30")).unwrap();
+        assert_eq!(config.gc.interval, 0);
+        assert_eq!((config.gc.low_watermark, config.gc.high_watermark), (10, 30));
+        assert!(MountConfig::parse(&args("/mnt/h --gc-watermarks 30,10")).is_err());
+        assert!(MountConfig::parse(&args("/mnt/h --gc-watermarks 30")).is_err());
+        let config = MountConfig::parse(&args("/mnt/i --compress best")).unwrap();
+        assert_eq!(config.compress, CompressPolicy::Best);
+        assert!(MountConfig::parse(&args("/mnt/i --compress zstd")).is_err());
+        let config = MountConfig::parse(&args("/mnt/j -o noatime,allow_other")).unwrap();
+        assert_eq!(config.atime, AtimePolicy::Never);
+        assert_eq!(config.mount_options, vec![MountOption::AllowOther]);
+        assert_eq!(MountConfig::parse(&args("/mnt/j -o strictatime")).unwrap().atime, AtimePolicy::Strict);
+        assert_eq!(MountConfig::parse(&args("/mnt/j -o noatime,relatime")).unwrap().atime, AtimePolicy::Relative);
+        assert!(MountConfig::parse(&args("")).is_err());
+        assert!(MountConfig::parse(&args("/mnt/d --image d.img --blocks 10")).is_err());
+        assert!(MountConfig::parse(&args("/mnt/e --virtual --image e.img")).is_err());
+        assert!(MountConfig::parse(&args("/mnt/f --blocks x")).is_err());
+    }
+}
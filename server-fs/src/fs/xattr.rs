@@ -0,0 +1,148 @@
+use crate::inode::inode;
+use crate::error::WondResult;
+use super::fuse_helper::{check_access, time_now};
+use super::filesystem::WondFS;
+
+pub const XATTR_NAME_MAX: usize = 255;
+pub const XATTR_SIZE_MAX: usize = 65536;
+
+impl WondFS {
+    pub fn get_xattr(&self, ino: u64, name: &str, uid: u32, gid: u32) -> Result<Vec<u8>, libc::c_int> {
+        let inode = self.get_inode(ino)?;
+        let ret = check_xattr_access(&inode.get_stat(), name, uid, gid, false).and_then(|_| {
+            let xattr = self.kv.get_inode_xattr(ino)?;
+            let entry = xattr.entries.into_iter().find(|entry| entry.0 == name);
+            entry.map(|entry| entry.1).ok_or(libc::ENODATA)
+        });
+        self.put_inode(inode);
+        ret
+    }
+
+    pub fn set_xattr(&self, ino: u64, name: &str, value: &[u8], flags: i32, uid: u32, gid: u32) -> Result<(), libc::c_int> {
+        if flags & !(libc::XATTR_CREATE | libc::XATTR_REPLACE) != 0 || flags == libc::XATTR_CREATE | libc::XATTR_REPLACE {
+            return Err(libc::EINVAL);
+        }
+        if value.len() > XATTR_SIZE_MAX {
+            return Err(libc::E2BIG);
+        }
+        let inode = self.get_inode(ino)?;
+        let ret = check_xattr_access(&inode.get_stat(), name, uid, gid, true).and_then(|_| {
+            let mut xattr = self.kv.get_inode_xattr(ino)?;
+            match xattr.entries.iter().position(|entry| entry.0 == name) {
+                Some(_) if flags & libc::XATTR_CREATE != 0 => return Err(libc::EEXIST),
+                Some(index) => xattr.entries[index].1 = value.to_vec(),
+                None if flags & libc::XATTR_REPLACE != 0 => return Err(libc::ENODATA),
+                None => xattr.entries.push((name.to_string(), value.to_vec())),
+            }
+            // all attributes of an inode share one record, cap it like a single value
+            let total: usize = xattr.entries.iter().map(|entry| entry.0.len() + entry.1.len()).sum();
+            if total > XATTR_SIZE_MAX {
+                return Err(libc::ENOSPC);
+            }
+            self.kv.set_inode_xattr(ino, &xattr)?;
+            Ok(())
+        });
+        let ret = ret.and_then(|_| touch_xattr(&inode).map_err(libc::c_int::from));
+        self.put_inode(inode);
+        ret
+    }
+
+    // names are nul terminated and packed back to back, as listxattr(2) returns them
+    pub fn list_xattr(&self, ino: u64, uid: u32) -> Result<Vec<u8>, libc::c_int> {
+        let inode = self.get_inode(ino)?;
+        let ret = self.kv.get_inode_xattr(ino).map(|xattr| {
+            let mut data = vec![];
+            for entry in xattr.entries {
+                if entry.0.starts_with("trusted.") && uid != 0 {
+                    continue;
+                }
+                data.extend_from_slice(entry.0.as_bytes());
+                data.push(0);
+            }
+            data
+        });
+        self.put_inode(inode);
+        Ok(ret?)
+    }
+
+    pub fn remove_xattr(&self, ino: u64, name: &str, uid: u32, gid: u32) -> Result<(), libc::c_int> {
+        let inode = self.get_inode(ino)?;
+        let ret = check_xattr_access(&inode.get_stat(), name, uid, gid, true).and_then(|_| {
+            let mut xattr = self.kv.get_inode_xattr(ino)?;
+            let index = xattr.entries.iter().position(|entry| entry.0 == name).ok_or(libc::ENODATA)?;
+            xattr.entries.remove(index);
+            self.kv.set_inode_xattr(ino, &xattr)?;
+            Ok(())
+        });
+        let ret = ret.and_then(|_| touch_xattr(&inode).map_err(libc::c_int::from));
+        self.put_inode(inode);
+        ret
+    }
+}
+
+fn check_xattr_access(stat: &inode::InodeStat, name: &str, uid: u32, gid: u32, write: bool) -> Result<(), libc::c_int> {
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(libc::ERANGE);
+    }
+    if name.starts_with("user.") {
+        // user attributes follow the permission bits, which symlinks do not have
+        if stat.file_type == inode::InodeFileType::Symlink {
+            return Err(libc::EPERM);
+        }
+        let mask = if write { libc::W_OK } else { libc::R_OK };
+        if !check_access(stat, uid, gid, mask) {
+            return Err(libc::EACCES);
+        }
+    } else if name.starts_with("trusted.") {
+        if uid != 0 {
+            return Err(libc::EPERM);
+        }
+    } else if name.starts_with("security.") {
+        if write && uid != 0 && uid != stat.uid {
+            return Err(libc::EPERM);
+        }
+    } else {
+        return Err(libc::EOPNOTSUPP);
+    }
+    Ok(())
+}
+
+fn touch_xattr(inode: &inode::Inode) -> WondResult<()> {
+    let mut stat = inode.get_stat();
+    stat.last_metadata_changed = time_now();
+    inode.modify_stat(stat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basics() {
+        let fs = WondFS::new();
+        fs.create_root(0, 0).unwrap();
+        let inode = fs.new_inode_file().unwrap();
+        let ino = inode.stat.read().ino;
+        fs.put_inode(inode);
+        assert_eq!(fs.get_xattr(ino, "user.tag", 0, 0), Err(libc::ENODATA));
+        assert_eq!(fs.set_xattr(ino, "user.tag", b"a", libc::XATTR_REPLACE, 0, 0), Err(libc::ENODATA));
+        assert_eq!(fs.set_xattr(ino, "user.tag", b"a", libc::XATTR_CREATE, 0, 0), Ok(()));
+        assert_eq!(fs.set_xattr(ino, "user.tag", b"b", libc::XATTR_CREATE, 0, 0), Err(libc::EEXIST));
+        assert_eq!(fs.set_xattr(ino, "user.tag", b"b", libc::XATTR_REPLACE, 0, 0), Ok(()));
+        assert_eq!(fs.set_xattr(ino, "user.empty", b"", 0, 0, 0), Ok(()));
+        assert_eq!(fs.set_xattr(ino, "trusted.a", b"c", 0, 0, 0), Ok(()));
+        assert_eq!(fs.set_xattr(ino, "os2.a", b"c", 0, 0, 0), Err(libc::EOPNOTSUPP));
+        assert_eq!(fs.set_xattr(ino, "user.tag", b"c", 0, 1000, 1000), Err(libc::EACCES));
+        assert_eq!(fs.get_xattr(ino, "user.tag", 1000, 1000), Ok(b"b".to_vec()));
+        assert_eq!(fs.get_xattr(ino, "user.empty", 0, 0), Ok(vec![]));
+        assert_eq!(fs.list_xattr(ino, 0), Ok(b"user.tag\0user.empty\0trusted.a\0".to_vec()));
+        assert_eq!(fs.list_xattr(ino, 1000), Ok(b"user.tag\0user.empty\0".to_vec()));
+        assert_eq!(fs.remove_xattr(ino, "user.tag", 0, 0), Ok(()));
+        assert_eq!(fs.remove_xattr(ino, "user.tag", 0, 0), Err(libc::ENODATA));
+        assert_eq!(fs.set_xattr(ino, "user.big", &vec![0; XATTR_SIZE_MAX + 1], 0, 0, 0), Err(libc::E2BIG));
+        let inode = fs.get_inode(ino).unwrap();
+        inode.delete().unwrap();
+        fs.put_inode(inode);
+        assert!(fs.kv.get_inode_xattr(ino).unwrap().entries.is_empty());
+    }
+}
@@ -2,6 +2,8 @@ extern crate alloc;
 use spin::RwLock;
 use alloc::sync::Arc;
 use crate::kv::kv::*;
+use crate::error::WondResult;
+use crate::util::time::Timespec;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum InodeFileType {
@@ -20,16 +22,36 @@ impl From<InodeFileType> for u8 {
     }
 }
 
+impl From<u8> for InodeFileType {
+    fn from(kind: u8) -> Self {
+        match kind {
+            1 => InodeFileType::Directory,
+            2 => InodeFileType::Symlink,
+            _ => InodeFileType::File,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct InodeStat {
     pub file_type: InodeFileType,
-    pub ino: u32,
-    pub size: u32,
+    pub ino: u64,
+    pub size: u64,
     pub ref_cnt: u8,
     pub n_link: u8,
-    pub last_accessed: u32,
-    pub last_modified: u32,
-    pub last_metadata_changed: u32,
+    pub last_accessed: Timespec,
+    pub last_modified: Timespec,
+    pub last_metadata_changed: Timespec,
+    pub created: Timespec,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Default for InodeStat {
+    fn default() -> Self {
+        InodeStat::new()
+    }
 }
 
 impl InodeStat {
@@ -40,9 +62,13 @@ impl InodeStat {
             size: 0,
             ref_cnt: 0,
             n_link: 0,
-            last_accessed: 0,
-            last_modified: 0,
-            last_metadata_changed: 0,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0,
+            uid: 0,
+            gid: 0,
         }
     }
 }
@@ -62,38 +88,64 @@ impl Inode {
         }
     }
 
-    pub fn read_all(&self, buf: &mut Vec<u8>) -> usize {
+    pub fn read_all(&self, buf: &mut Vec<u8>) -> WondResult<usize> {
         self.read(0, self.stat.read().size as usize, buf)
     }
 
-    pub fn read(&self, offset: usize, len: usize, buf: &mut Vec<u8>) -> usize {
+    pub fn read(&self, offset: u64, len: usize, buf: &mut Vec<u8>) -> WondResult<usize> {
         assert!(*self.valid.read());
         buf.clear();
-        let data = self.kv.get_inode_data(self.stat.read().ino, offset, len);
-        buf.extend(&data.unwrap());
-        buf.len()
+        // a zero length asks the kv for the whole object
+        if len == 0 {
+            return Ok(0);
+        }
+        let data = self.kv.get_inode_data(self.stat.read().ino, offset, len)?;
+        buf.extend(&data);
+        Ok(buf.len())
     }
 
-    pub fn write(&self, offset: usize, len: usize, buf: &Vec<u8>) {
+    pub fn write(&self, offset: u64, len: usize, buf: &Vec<u8>) -> WondResult<()> {
         assert!(*self.valid.read());
-        let size = self.kv.set_inode_data(self.stat.read().ino, offset, len, buf);
-        self.stat.write().size = size as u32;
+        let size = self.kv.set_inode_data(self.stat.read().ino, offset, len, buf)?;
+        self.stat.write().size = size;
+        Ok(())
     }
 
-    pub fn truncate_to_end(&self, offset: usize) {
-        self.truncate(offset, self.stat.read().size as usize - offset);
+    pub fn truncate_to_end(&self, offset: u64) -> WondResult<()> {
+        let size = self.stat.read().size;
+        self.truncate(offset, size.saturating_sub(offset))
+    }
+
+    pub fn truncate(&self, offset: u64, len: u64) -> WondResult<()> {
+        assert!(*self.valid.read());
+        let size = self.kv.delete_inode_data(self.stat.read().ino, offset, len)?;
+        self.stat.write().size = size;
+        Ok(())
+    }
+
+    // sets the size, growing leaves a hole that reads back as zeros
+    pub fn resize(&self, size: u64) -> WondResult<()> {
+        assert!(*self.valid.read());
+        let size = self.kv.resize_inode_data(self.stat.read().ino, size)?;
+        self.stat.write().size = size;
+        Ok(())
+    }
+
+    pub fn punch(&self, offset: u64, len: u64) -> WondResult<()> {
+        assert!(*self.valid.read());
+        self.kv.punch_inode_data(self.stat.read().ino, offset, len)
     }
 
-    pub fn truncate(&self, offset: usize, len: usize) {
+    pub fn data_ranges(&self) -> WondResult<Vec<(u64, u64)>> {
         assert!(*self.valid.read());
-        let size = self.kv.delete_inode_data(self.stat.read().ino, offset, len);
-        self.stat.write().size = size as u32;
+        self.kv.get_inode_data_ranges(self.stat.read().ino)
     }
 
-    pub fn delete(&self) {
+    pub fn delete(&self) -> WondResult<()> {
         assert!(*self.valid.read());
-        self.kv.delete_inode(self.stat.read().ino);
+        self.kv.delete_inode(self.stat.read().ino)?;
         *self.valid.write() = false;
+        Ok(())
     }
 
     pub fn get_stat(&self) -> InodeStat {
@@ -101,7 +153,7 @@ impl Inode {
         *self.stat.read()
     }
 
-    pub fn modify_stat(&self, stat: InodeStat) {
+    pub fn modify_stat(&self, stat: InodeStat) -> WondResult<()> {
         assert!(*self.valid.read());
         self.stat.write().file_type = stat.file_type;
         self.stat.write().size = stat.size;
@@ -110,6 +162,10 @@ impl Inode {
         self.stat.write().last_accessed = stat.last_accessed;
         self.stat.write().last_modified = stat.last_modified;
         self.stat.write().last_metadata_changed = stat.last_metadata_changed;
+        self.stat.write().created = stat.created;
+        self.stat.write().mode = stat.mode;
+        self.stat.write().uid = stat.uid;
+        self.stat.write().gid = stat.gid;
         let metadata = InodeMetadata {
             file_type: stat.file_type.into(),
             ino: self.stat.read().ino,
@@ -118,8 +174,12 @@ impl Inode {
             last_accessed: self.stat.read().last_accessed,
             last_modified: self.stat.read().last_modified,
             last_metadata_changed: self.stat.read().last_metadata_changed,
+            created: self.stat.read().created,
+            mode: self.stat.read().mode,
+            uid: self.stat.read().uid,
+            gid: self.stat.read().gid,
         };
-        self.kv.set_inode_metadata(self.stat.read().ino, &metadata);
+        self.kv.set_inode_metadata(self.stat.read().ino, &metadata)
     }
 
     pub fn invalidate(&self) {
@@ -130,7 +190,7 @@ impl Inode {
         *self.valid.write() = true;
     }
 
-    pub fn nlinks_inc(&self) {
+    pub fn nlinks_inc(&self) -> WondResult<()> {
         assert!(*self.valid.read());
         self.stat.write().n_link += 1;
         let metadata = InodeMetadata {
@@ -141,16 +201,20 @@ impl Inode {
             last_accessed: self.stat.read().last_accessed,
             last_modified: self.stat.read().last_modified,
             last_metadata_changed: self.stat.read().last_metadata_changed,
+            created: self.stat.read().created,
+            mode: self.stat.read().mode,
+            uid: self.stat.read().uid,
+            gid: self.stat.read().gid,
         };
-        self.kv.set_inode_metadata(self.stat.read().ino, &metadata);
+        self.kv.set_inode_metadata(self.stat.read().ino, &metadata)
     }
 
-    pub fn nlinks_dec(&self) {
+    pub fn nlinks_dec(&self) -> WondResult<()> {
         assert!(*self.valid.read());
         assert!(self.stat.read().n_link > 0);
         self.stat.write().n_link -= 1;
         if self.stat.read().n_link == 0 {
-            self.delete();
+            self.delete()?;
         }
         let metadata = InodeMetadata {
             file_type: self.stat.read().file_type.into(),
@@ -160,7 +224,11 @@ impl Inode {
             last_accessed: self.stat.read().last_accessed,
             last_modified: self.stat.read().last_modified,
             last_metadata_changed: self.stat.read().last_metadata_changed,
+            created: self.stat.read().created,
+            mode: self.stat.read().mode,
+            uid: self.stat.read().uid,
+            gid: self.stat.read().gid,
         };
-        self.kv.set_inode_metadata(self.stat.read().ino, &metadata);
+        self.kv.set_inode_metadata(self.stat.read().ino, &metadata)
     }
 }
\ No newline at end of file
@@ -1,6 +1,8 @@
 extern crate alloc;
 use alloc::sync::Arc;
 use crate::kv::kv::*;
+use crate::error::{WondError, WondResult};
+use crate::util::time::Timespec;
 use super::inode::*;
 
 pub type InodeLink = Arc<Inode>;
@@ -31,7 +33,7 @@ impl InodeManager {
 }
 
 impl InodeManager {
-    pub fn i_alloc(&mut self) -> Option<InodeLink> {
+    pub fn i_alloc(&mut self) -> WondResult<InodeLink> {
         let mut empty_index = -1;
         for (index, ip) in self.inode_buffer.iter().enumerate() {
             if empty_index == -1 && ip.stat.read().ref_cnt == 0 {
@@ -39,75 +41,84 @@ impl InodeManager {
             }
         }
         if empty_index == -1 {
-            panic!("InodeManager: alloc no spare cache to store");
+            return Err(WondError::NoMemory);
         }
         let mut inode_metadata = InodeMetadata {
             file_type: InodeFileType::File.into(),
             ino: 0,
             size: 0,
             n_link: 1,
-            last_accessed: 0,
-            last_modified: 0,
-            last_metadata_changed: 0,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
         };
-        let ino = self.kv.allocate_indoe(&mut inode_metadata);
+        let ino = self.kv.allocate_indoe(&mut inode_metadata)?;
         let inode_stat = InodeStat {
             ino,
             file_type: InodeFileType::File,
             size: 0,
             ref_cnt: 1,
             n_link: 1,
-            last_accessed: 0,
-            last_modified: 0,
-            last_metadata_changed: 0,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
         };
         let inode = Inode::new(Arc::clone(&self.kv));
         *inode.stat.write() = inode_stat;
         inode.validate();
         let link = Arc::new(inode);
         self.inode_buffer[empty_index as usize] = Arc::clone(&link);
-        Some(link)
+        Ok(link)
     }
 
-    pub fn i_get(&mut self, ino: u32) -> Option<InodeLink> {
+    pub fn i_get(&mut self, ino: u64) -> WondResult<Option<InodeLink>> {
         // println!("{}", ino);
         let mut empty_index = -1;
         for (index, ip) in self.inode_buffer.iter().enumerate() {
             if ip.stat.read().ref_cnt > 0 && ip.stat.read().ino == ino {
                 // ip.stat.write().ref_cnt += 1;
-                return Some(Arc::clone(ip));
+                return Ok(Some(Arc::clone(ip)));
             }
             if empty_index == -1 && ip.stat.read().ref_cnt == 0 {
                 empty_index = index as i32;
             }
         }
         if empty_index == -1 {
-            panic!("InodeManager: get no spare cache to store");
+            return Err(WondError::NoMemory);
         }
-        let metadata = self.kv.get_inode_metadata(ino);
+        let metadata = self.kv.get_inode_metadata(ino)?;
         if metadata.is_none() {
-            return None;
+            return Ok(None);
         }
         let metadata = metadata.unwrap();
-        let mut inode_stat = InodeStat {
+        let inode_stat = InodeStat {
             ino,
-            file_type: InodeFileType::File,
+            file_type: metadata.file_type.into(),
             size: metadata.size,
             ref_cnt: 1,
             n_link: metadata.n_link,
             last_accessed: metadata.last_accessed,
             last_modified: metadata.last_modified,
             last_metadata_changed: metadata.last_metadata_changed,
+            created: metadata.created,
+            mode: metadata.mode,
+            uid: metadata.uid,
+            gid: metadata.gid,
         };
-        if metadata.file_type == 1 {
-            inode_stat.file_type = InodeFileType::Directory;
-        }
         let inode = Inode::new(Arc::clone(&self.kv));
         *inode.stat.write() = inode_stat;
         inode.validate();
         let link = Arc::new(inode);
         self.inode_buffer[empty_index as usize] = Arc::clone(&link);
-        Some(link)
+        Ok(Some(link))
     }
 
     pub fn i_put(&mut self, inode: InodeLink) {
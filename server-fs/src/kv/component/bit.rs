@@ -18,6 +18,12 @@ pub struct BIT {
     pub is_op: bool,
 }
 
+impl Default for BIT {
+    fn default() -> Self {
+        BIT::new()
+    }
+}
+
 impl BIT {
     pub fn new() -> BIT {
         BIT {
@@ -8,11 +8,20 @@ pub enum JournalType {
     None,
 }
 
+// moves are keyed by their first page and carry (d_address, size, ino)
 pub struct Journal {
-    pub table: HashMap<u32, u32>,
+    pub table: HashMap<u32, (u32, u32, u64)>,
     pub sync: bool,
     pub is_op: bool,
     pub erase_block_no: u32,
+    // bytes of an inode number in an entry, 4 on images from before 64 bit inode numbers
+    pub ino_size: u32,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Journal::new()
+    }
 }
 
 impl Journal {
@@ -22,6 +31,7 @@ impl Journal {
             sync: false,
             is_op: false,
             erase_block_no: 0,
+            ino_size: 4,
         }
     }
 
@@ -31,15 +41,22 @@ impl Journal {
 
     pub fn set_erase_block_no(&mut self, block_no: u32) {
         self.erase_block_no = block_no;
+        self.sync = true;
     }
 
-    pub fn set_journal(&mut self, o_address: u32, address: u32) {
+    pub fn set_ino_size(&mut self, ino_size: u32) {
+        self.ino_size = ino_size;
+    }
+
+    pub fn set_journal(&mut self, o_address: u32, address: u32, size: u32, ino: u64) {
         if self.table.contains_key(&o_address) {
             panic!("Journal: set journal has conflicts");
         }
-        self.table.insert(o_address, address);
+        self.table.insert(o_address, (address, size, ino));
+        self.sync = true;
     }
 
+
     pub fn need_sync(&self) -> bool {
         if self.is_op {
             return false;
@@ -82,23 +99,16 @@ impl Journal {
         data.set(7, byte_4);
         let mut index = 0;
         for (key, value) in &self.table {
-            let start_index = 8 + index * 8;
-            let byte_1 = (*key >> 24) as u8;
-            let byte_2 = (*key >> 16) as u8;
-            let byte_3 = (*key >> 8) as u8;
-            let byte_4 = *key as u8;
-            data.set(start_index, byte_1);
-            data.set(start_index + 1, byte_2);
-            data.set(start_index + 2, byte_3);
-            data.set(start_index + 3, byte_4);
-            let byte_1 = (*value >> 24) as u8;
-            let byte_2 = (*value >> 16) as u8;
-            let byte_3 = (*value >> 8) as u8;
-            let byte_4 = *value as u8;
-            data.set(start_index + 4, byte_1);
-            data.set(start_index + 5, byte_2);
-            data.set(start_index + 6, byte_3);
-            data.set(start_index + 7, byte_4);
+            let start_index = 8 + index * (12 + self.ino_size);
+            for (i, field) in [*key, value.0, value.1].iter().enumerate() {
+                data.set(start_index + i as u32 * 4, (*field >> 24) as u8);
+                data.set(start_index + i as u32 * 4 + 1, (*field >> 16) as u8);
+                data.set(start_index + i as u32 * 4 + 2, (*field >> 8) as u8);
+                data.set(start_index + i as u32 * 4 + 3, *field as u8);
+            }
+            for i in 0..self.ino_size {
+                data.set(start_index + 12 + i, (value.2 >> ((self.ino_size - 1 - i) * 8)) as u8);
+            }
             index += 1;
         }
         data
@@ -107,43 +117,50 @@ impl Journal {
 
 pub struct DataRegion<'a> {
     count: u32,
+    ino_size: u32,
     data: &'a array::Array1<[u8; 4096]>,   
 }
 
 impl DataRegion<'_> {
-    pub fn new(data: &array::Array1::<[u8; 4096]>) -> DataRegion {
+    pub fn new(data: &array::Array1::<[u8; 4096]>, ino_size: u32) -> DataRegion<'_> {
         if data.len() != 128 {
             panic!("DataRegion: new not matched size");
         }
         DataRegion {
             count: 8,
+            ino_size,
             data,
         }
     }
 }
 
+impl DataRegion<'_> {
+    fn get(&self, index: u32, size: u32) -> u64 {
+        let mut value = 0;
+        for i in index..index + size {
+            value = value << 8 | self.data.get(i / 4096)[(i % 4096) as usize] as u64;
+        }
+        value
+    }
+}
+
 impl Iterator for DataRegion<'_> {
-    type Item = (u32, u32);
+    type Item = (u32, u32, u32, u64);
     fn next(&mut self) -> Option<Self::Item> {
-        if self.count < 128 * 4096 {
-            let byte_1 = (self.data.get(self.count / 4096)[(self.count % 4096) as usize] as u32) << 24;
-            let byte_2 = (self.data.get((self.count + 1) / 4096)[((self.count + 1) % 4096) as usize] as u32) << 16;
-            let byte_3 = (self.data.get((self.count + 2) / 4096)[((self.count + 2) % 4096) as usize] as u32) << 8;
-            let byte_4 = self.data.get((self.count + 3) / 4096)[((self.count + 3) % 4096) as usize] as u32;
-            let o_address = byte_1 + byte_2 + byte_3 + byte_4;
-            let byte_1 = (self.data.get((self.count + 4) / 4096)[((self.count + 4) % 4096) as usize] as u32) << 24;
-            let byte_2 = (self.data.get((self.count + 5) / 4096)[((self.count + 5) % 4096) as usize] as u32) << 16;
-            let byte_3 = (self.data.get((self.count + 6) / 4096)[((self.count + 6) % 4096) as usize] as u32) << 8;
-            let byte_4 = self.data.get((self.count + 7) / 4096)[((self.count + 7) % 4096) as usize] as u32;
-            let address = byte_1 + byte_2 + byte_3 + byte_4;
-            self.count += 8;
-            if o_address == 0 && address == 0 {
+        if self.count + 12 + self.ino_size <= 128 * 4096 {
+            let o_address = self.get(self.count, 4) as u32;
+            let address = self.get(self.count + 4, 4) as u32;
+            let size = self.get(self.count + 8, 4) as u32;
+            let ino = self.get(self.count + 12, self.ino_size);
+            self.count += 12 + self.ino_size;
+            // every move covers at least a page, an empty slot ends the table
+            if size == 0 {
                 None
             } else {
-                Some((o_address, address))
+                Some((o_address, address, size, ino))
             }
         } else {
             None
         }
     }
-}
\ No newline at end of file
+}
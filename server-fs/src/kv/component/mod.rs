@@ -62,16 +62,42 @@ mod tests {
         let mut temp = data.get(121);
         temp[2332] = 123;
         data.set(121, temp);
-        let iter = pit::DataRegion::new(&data, pit::PITStrategy::Serial);
+        let iter = pit::DataRegion::new(&data, pit::PITStrategy::Serial, 4);
         for (index, ino) in iter {
             if ino != 0 {
                 pit.init_page(index, ino);
             }
         }
-        assert_eq!(kv_manager::KVManager::transfer(&pit.encode()), data);
+        assert_eq!(kv_manager::KVManager::transfer(&pit.encode()[0]), data);
         assert_eq!(pit.need_sync(), false);
         pit.set_page(200, 100);
         assert_eq!(pit.get_page(200), 100);
         assert_eq!(pit.need_sync(), true);
     }
+
+    #[test]
+    fn test_pit_wide() {
+        // 64 bit owners of more pages than one block holds take a copy of two blocks
+        for (page_num, step, strategy) in [(100, 4, pit::PITStrategy::Map), (100000, 2, pit::PITStrategy::Serial)] {
+            let mut pit = pit::PIT::new();
+            pit.set_layout(8, 2);
+            pit.set_page_num(page_num);
+            for address in (0..page_num).step_by(step) {
+                pit.init_page(address, (1 << 40) + address as u64);
+            }
+            let blocks = pit.encode();
+            assert_eq!(blocks.len(), 2);
+            let mut data = array::Array1::<[u8; 4096]>::new(256, [0; 4096]);
+            for (block_no, block) in blocks.iter().enumerate() {
+                for (index, page) in kv_manager::KVManager::transfer(block).iter().enumerate() {
+                    data.set((block_no * 128 + index) as u32, page);
+                }
+            }
+            let decoded: Vec<(u32, u64)> = pit::DataRegion::new(&data, strategy, 8).filter(|(_, ino)| *ino != 0).collect();
+            assert_eq!(decoded.len(), pit.table.len());
+            for (address, ino) in decoded {
+                assert_eq!(pit.get_page(address), ino);
+            }
+        }
+    }
 }
\ No newline at end of file
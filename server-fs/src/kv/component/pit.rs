@@ -13,9 +13,18 @@ pub enum PITStrategy {
 
 pub struct PIT {
     pub page_num: u32,
-    pub table: HashMap<u32, u32>,
+    pub table: HashMap<u32, u64>,
     pub sync: bool,
     pub is_op: bool,
+    // bytes of an owner and blocks of a copy, 4 and 1 on images from before 64 bit inode numbers
+    pub ino_size: u32,
+    pub block_num: u32,
+}
+
+impl Default for PIT {
+    fn default() -> Self {
+        PIT::new()
+    }
 }
 
 impl PIT {
@@ -25,6 +34,8 @@ impl PIT {
             sync: false,
             is_op: false,
             page_num: 0,
+            ino_size: 4,
+            block_num: 1,
         }
     }
 
@@ -32,21 +43,26 @@ impl PIT {
         self.page_num = page_num;
     }
 
-    pub fn init_page(&mut self, address: u32, status: u32) {
+    pub fn set_layout(&mut self, ino_size: u32, block_num: u32) {
+        self.ino_size = ino_size;
+        self.block_num = block_num;
+    }
+
+    pub fn init_page(&mut self, address: u32, status: u64) {
         if self.table.contains_key(&address) {
             panic!("PIT: init page has exist");
         }
         self.table.insert(address, status);
     }
 
-    pub fn get_page(&self, address: u32) -> u32 {
+    pub fn get_page(&self, address: u32) -> u64 {
         if !self.table.contains_key(&address) {
             panic!("PIT: get not that page");
         }
         self.table.get(&address).unwrap().clone()
     }
 
-    pub fn set_page(&mut self, address: u32, status: u32) {
+    pub fn set_page(&mut self, address: u32, status: u64) {
         if !self.table.contains_key(&address) {
             self.table.insert(address, status);
             self.sync = true;
@@ -93,13 +109,21 @@ impl PIT {
 }
 
 impl PIT {
-    pub fn encode(&self) -> array::Array1::<u8> {
+    // a copy, one array per block
+    pub fn encode(&self) -> Vec<array::Array1::<u8>> {
         let strategy = self.choose_strategy();
-        if strategy == PITStrategy::Map {
+        let data = if strategy == PITStrategy::Map {
             self.encode_map()
         } else {
             self.encode_serial()
-        }
+        };
+        data.chunks(128 * 4096).map(|block| {
+            let mut res = array::Array1::<u8>::new(128 * 4096, 0);
+            for (index, byte) in block.iter().enumerate() {
+                res.set(index as u32, *byte);
+            }
+            res
+        }).collect()
     }
 }
 
@@ -114,56 +138,32 @@ impl PIT {
         }
     }
 
-    fn encode_serial(&self) -> array::Array1::<u8> {
-        let mut res = array::Array1::<u32>::new(128 * 4096 / 4 - 2, 0);
+    fn put(&self, data: &mut [u8], start_index: usize, value: u64) {
+        let size = self.ino_size as usize;
+        data[start_index..start_index + size].copy_from_slice(&value.to_be_bytes()[8 - size..]);
+    }
+
+    fn encode_serial(&self) -> Vec<u8> {
+        let mut data = vec![0; (self.block_num * 128 * 4096) as usize];
+        data[..4].copy_from_slice(&MAGIC_NUMBER_2.to_be_bytes());
+        let size = self.ino_size as usize;
         for (key, value) in &self.table {
-            res.set(*key, *value);
-        }
-        let mut data = array::Array1::<u8>::new(128 * 4096, 0);
-        data.set(0, 0x77);
-        data.set(1, 0x77);
-        data.set(2, 0xee);
-        data.set(3, 0xee);
-        for (index, value) in res.iter().enumerate() {
-            let byte_1 = (value >> 24) as u8;
-            let byte_2 = (value >> 16) as u8;
-            let byte_3 = (value >> 8) as u8;
-            let byte_4 = value as u8;
-            let start_index = 8 + index * 4;
-            data.set(start_index as u32, byte_1);
-            data.set((start_index + 1) as u32, byte_2);
-            data.set((start_index + 2) as u32, byte_3);
-            data.set((start_index + 3) as u32, byte_4);
+            let start_index = 8 + *key as usize * size;
+            if start_index + size <= data.len() {
+                self.put(&mut data, start_index, *value);
+            }
         }
         data
     }
 
-    fn encode_map(&self) -> array::Array1::<u8> {
-        let mut data = array::Array1::<u8>::new(128 * 4096, 0);
-        data.set(0, 0x77);
-        data.set(1, 0x77);
-        data.set(2, 0xdd);
-        data.set(3, 0xdd);
-        let mut index = 0;
-        for (key, value) in &self.table {
-            let start_index = 8 + index * 8;
-            let byte_1 = (*key >> 24) as u8;
-            let byte_2 = (*key >> 16) as u8;
-            let byte_3 = (*key >> 8) as u8;
-            let byte_4 = *key as u8;
-            data.set(start_index, byte_1);
-            data.set(start_index + 1, byte_2);
-            data.set(start_index + 2, byte_3);
-            data.set(start_index + 3, byte_4);
-            let byte_1 = (*value >> 24) as u8;
-            let byte_2 = (*value >> 16) as u8;
-            let byte_3 = (*value >> 8) as u8;
-            let byte_4 = *value as u8;
-            data.set(start_index + 4, byte_1);
-            data.set(start_index + 5, byte_2);
-            data.set(start_index + 6, byte_3);
-            data.set(start_index + 7, byte_4);
-            index += 1;
+    fn encode_map(&self) -> Vec<u8> {
+        let mut data = vec![0; (self.block_num * 128 * 4096) as usize];
+        data[..4].copy_from_slice(&MAGIC_NUMBER_1.to_be_bytes());
+        let size = 4 + self.ino_size as usize;
+        for (index, (key, value)) in self.table.iter().enumerate() {
+            let start_index = 8 + index * size;
+            data[start_index..start_index + 4].copy_from_slice(&key.to_be_bytes());
+            self.put(&mut data, start_index + 4, *value);
         }
         data
     }
@@ -173,12 +173,13 @@ pub struct DataRegion<'a> {
     count: u32,
     index: u32,
     strategy: PITStrategy,
+    ino_size: u32,
     data: &'a array::Array1<[u8; 4096]>,
 }
 
 impl DataRegion<'_> {
-    pub fn new(data: &array::Array1::<[u8; 4096]>, strategy: PITStrategy) -> DataRegion {
-        if data.len() != 128 {
+    pub fn new(data: &array::Array1::<[u8; 4096]>, strategy: PITStrategy, ino_size: u32) -> DataRegion<'_> {
+        if data.is_empty() || !data.len().is_multiple_of(128) {
             panic!("DataRegion: new not matched size");
         }
         DataRegion {
@@ -186,49 +187,41 @@ impl DataRegion<'_> {
             index: 0,
             data,
             strategy,
+            ino_size,
+        }
+    }
+
+    fn get(&self, index: u32, size: u32) -> u64 {
+        let mut value = 0;
+        for i in index..index + size {
+            value = value << 8 | self.data.get(i / 4096)[(i % 4096) as usize] as u64;
         }
+        value
     }
 }
 
 impl Iterator for DataRegion<'_> {
-    type Item = (u32, u32);
+    type Item = (u32, u64);
     fn next(&mut self) -> Option<Self::Item> {
-        if self.count < 128 * 4096 {
-            match self.strategy {
-                PITStrategy::Map => {
-                    let byte_1 = (self.data.get(self.count / 4096)[(self.count % 4096) as usize] as u32) << 24;
-                    let byte_2 = (self.data.get((self.count + 1) / 4096)[((self.count + 1) % 4096) as usize] as u32) << 16;
-                    let byte_3 = (self.data.get((self.count + 2) / 4096)[((self.count + 2) % 4096) as usize] as u32) << 8;
-                    let byte_4 = self.data.get((self.count + 3) / 4096)[((self.count + 3) % 4096) as usize] as u32;
-                    let index = byte_1 + byte_2 + byte_3 + byte_4;
-                    let byte_1 = (self.data.get((self.count + 4) / 4096)[((self.count + 4) % 4096) as usize] as u32) << 24;
-                    let byte_2 = (self.data.get((self.count + 5) / 4096)[((self.count + 5) % 4096) as usize] as u32) << 16;
-                    let byte_3 = (self.data.get((self.count + 6) / 4096)[((self.count + 6) % 4096) as usize] as u32) << 8;
-                    let byte_4 = self.data.get((self.count + 7) / 4096)[((self.count + 7) % 4096) as usize] as u32;
-                    let ino = byte_1 + byte_2 + byte_3 + byte_4;
-                    self.count += 8;
-                    if index == 0 && ino == 0 {
-                        None
-                    } else {
-                        Some((index, ino))
-                    }
-                },
-                PITStrategy::Serial => {
-                    let byte_1 = (self.data.get(self.count / 4096)[(self.count % 4096) as usize] as u32) << 24;
-                    let byte_2 = (self.data.get((self.count + 1) / 4096)[((self.count + 1) % 4096) as usize] as u32) << 16;
-                    let byte_3 = (self.data.get((self.count + 2) / 4096)[((self.count + 2) % 4096) as usize] as u32) << 8;
-                    let byte_4 = self.data.get((self.count + 3) / 4096)[((self.count + 3) % 4096) as usize] as u32;
-                    let ino = byte_1 + byte_2 + byte_3 + byte_4;
-                    self.count += 4;
-                    self.index += 1;
-                    Some((self.index - 1, ino))
-                },
-                PITStrategy::None => {
+        let end = self.data.len() * 4096;
+        match self.strategy {
+            PITStrategy::Map if self.count + 4 + self.ino_size <= end => {
+                let index = self.get(self.count, 4) as u32;
+                let ino = self.get(self.count + 4, self.ino_size);
+                self.count += 4 + self.ino_size;
+                if index == 0 && ino == 0 {
                     None
-                },
-            }
-        } else {
-            None
+                } else {
+                    Some((index, ino))
+                }
+            },
+            PITStrategy::Serial if self.count + self.ino_size <= end => {
+                let ino = self.get(self.count, self.ino_size);
+                self.count += self.ino_size;
+                self.index += 1;
+                Some((self.index - 1, ino))
+            },
+            _ => None,
         }
     }
 }
@@ -258,13 +251,13 @@ mod test {
         let mut temp = data.get(121);
         temp[2332] = 123;
         data.set(121, temp);
-        let iter = DataRegion::new(&data, PITStrategy::Serial);
+        let iter = DataRegion::new(&data, PITStrategy::Serial, 4);
         for (index, ino) in iter {
             if ino != 0 {
                 pit.init_page(index, ino);
             }
         }
-        assert_eq!(kv_manager::KVManager::transfer(&pit.encode()), data);
+        assert_eq!(kv_manager::KVManager::transfer(&pit.encode()[0]), data);
         assert_eq!(pit.need_sync(), false);
         pit.set_page(200, 100);
         assert_eq!(pit.get_page(200), 100);
@@ -1,8 +1,14 @@
+use crate::util::archive::{self, Versioned};
+use crate::kv::lsm_tree::wal;
 use rkyv::{Archive, Deserialize, Serialize};
 
 pub const MAGICNUMBER: u32 = 0x3bf7444d;
+pub const DEFAULT_BLOCK_NUM: u32 = 1224;
+pub const DEFAULT_KV_BLOCK_NUM: u32 = 99;
+pub const DEFAULT_RESERVED_BLOCK_NUM: u32 = 200;
 
 #[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(check_bytes)]
 pub struct SuperStat {
     pub magic_code: u32,
     pub block_num: u32,
@@ -15,6 +21,96 @@ pub struct SuperStat {
     pub reserved_block_num: u32,
     pub page_size: u32,
     pub page_num_per_block: u32,
+    // the last blocks of the kv area, none on an image the log has not been moved onto yet
+    pub wal_block_num: u32,
+    // bytes an inode number takes in directory entries, the PIT and the journal
+    pub ino_size: u32,
+}
+
+// version 2 gave the write-ahead log its own blocks, version 3 made room for 64 bit inode numbers
+impl Versioned for SuperStat {
+    const KIND: u8 = archive::KIND_SUPER_STAT;
+    const VERSION: u8 = 3;
+}
+
+// superblock layout of version 2, its images keep 32 bit inode numbers on disk
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(check_bytes)]
+pub struct SuperStatV2 {
+    pub magic_code: u32,
+    pub block_num: u32,
+    pub super_block_num: u32,
+    pub bit_block_num: u32,
+    pub pit_block_num: u32,
+    pub journal_block_num: u32,
+    pub kv_block_num: u32,
+    pub main_area_block_num: u32,
+    pub reserved_block_num: u32,
+    pub page_size: u32,
+    pub page_num_per_block: u32,
+    pub wal_block_num: u32,
+}
+
+impl From<SuperStatV2> for SuperStat {
+    fn from(old: SuperStatV2) -> Self {
+        SuperStat {
+            magic_code: old.magic_code,
+            block_num: old.block_num,
+            super_block_num: old.super_block_num,
+            bit_block_num: old.bit_block_num,
+            pit_block_num: old.pit_block_num,
+            journal_block_num: old.journal_block_num,
+            kv_block_num: old.kv_block_num,
+            main_area_block_num: old.main_area_block_num,
+            reserved_block_num: old.reserved_block_num,
+            page_size: old.page_size,
+            page_num_per_block: old.page_num_per_block,
+            wal_block_num: old.wal_block_num,
+            ino_size: 4,
+        }
+    }
+}
+
+// superblock layout of version 1, the whole kv area held tables
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(check_bytes)]
+pub struct SuperStatV1 {
+    pub magic_code: u32,
+    pub block_num: u32,
+    pub super_block_num: u32,
+    pub bit_block_num: u32,
+    pub pit_block_num: u32,
+    pub journal_block_num: u32,
+    pub kv_block_num: u32,
+    pub main_area_block_num: u32,
+    pub reserved_block_num: u32,
+    pub page_size: u32,
+    pub page_num_per_block: u32,
+}
+
+impl From<SuperStatV1> for SuperStat {
+    fn from(old: SuperStatV1) -> Self {
+        SuperStat::from(SuperStatV2 {
+            magic_code: old.magic_code,
+            block_num: old.block_num,
+            super_block_num: old.super_block_num,
+            bit_block_num: old.bit_block_num,
+            pit_block_num: old.pit_block_num,
+            journal_block_num: old.journal_block_num,
+            kv_block_num: old.kv_block_num,
+            main_area_block_num: old.main_area_block_num,
+            reserved_block_num: old.reserved_block_num,
+            page_size: old.page_size,
+            page_num_per_block: old.page_num_per_block,
+            wal_block_num: 0,
+        })
+    }
+}
+
+impl Default for SuperStat {
+    fn default() -> Self {
+        SuperStat::new()
+    }
 }
 
 impl SuperStat {
@@ -31,7 +127,88 @@ impl SuperStat {
             reserved_block_num: 0,
             page_size: 0,
             page_num_per_block: 0,
+            wal_block_num: 0,
+            ino_size: 0,
+        }
+    }
+
+    // the PIT takes whatever the other regions leave over, at least pit_block_num(main_area_block_num)
+    pub fn build(block_num: u32, kv_block_num: u32, main_area_block_num: u32, reserved_block_num: u32) -> SuperStat {
+        SuperStat {
+            magic_code: MAGICNUMBER,
+            block_num,
+            super_block_num: 1,
+            bit_block_num: 2,
+            pit_block_num: block_num.saturating_sub(4 + kv_block_num + main_area_block_num + reserved_block_num),
+            journal_block_num: 1,
+            kv_block_num,
+            main_area_block_num,
+            reserved_block_num,
+            page_size: 4096,
+            page_num_per_block: 128,
+            wal_block_num: wal::WAL_BLOCK_NUM as u32,
+            ino_size: 8,
+        }
+    }
+
+    // none when the blocks do not leave room for the fixed regions and some main area
+    pub fn default_geometry(block_num: u32) -> Option<SuperStat> {
+        let kv_block_num = (block_num as u64 * DEFAULT_KV_BLOCK_NUM as u64 / DEFAULT_BLOCK_NUM as u64) as u32;
+        let reserved_block_num = (block_num as u64 * DEFAULT_RESERVED_BLOCK_NUM as u64 / DEFAULT_BLOCK_NUM as u64) as u32;
+        let left = block_num.checked_sub(4)?.checked_sub(kv_block_num)?.checked_sub(reserved_block_num)?;
+        let main_area_block_num = SuperStat::fit_main_area(left);
+        if main_area_block_num == 0 {
+            return None;
         }
+        Some(SuperStat::build(block_num, kv_block_num, main_area_block_num, reserved_block_num))
+    }
+
+    // two copies, each with a 64 bit owner for every page of the main area behind its header
+    pub fn pit_block_num(main_area_block_num: u32) -> u32 {
+        2 * (8 + main_area_block_num as u64 * 128 * 8).div_ceil(128 * 4096) as u32
+    }
+
+    // the largest main area that fits in the blocks along with its PIT
+    pub fn fit_main_area(block_num: u32) -> u32 {
+        let mut main_area_block_num = block_num.saturating_sub(2);
+        while main_area_block_num > 0 && main_area_block_num + SuperStat::pit_block_num(main_area_block_num) > block_num {
+            main_area_block_num -= 1;
+        }
+        main_area_block_num
+    }
+
+    pub fn encode(&self) -> [u8; 4096] {
+        let stat_data = archive::encode(self);
+        let len = stat_data.len();
+        let mut data = [0; 4096];
+        data[0] = (len >> 24) as u8;
+        data[1] = (len >> 16) as u8;
+        data[2] = (len >> 8) as u8;
+        data[3] = len as u8;
+        data[4..4+len].copy_from_slice(&stat_data);
+        data
+    }
+
+    pub fn decode(data: &[u8; 4096]) -> Option<SuperStat> {
+        let byte1 = (data[0] as u32) << 24;
+        let byte2 = (data[1] as u32) << 16;
+        let byte3 = (data[2] as u32) << 8;
+        let byte4 = data[3] as u32;
+        let len = byte1 | byte2 | byte3 | byte4;
+        if len == 0 || len > 4092 {
+            return None;
+        }
+        let data = &data[4..4+len as usize];
+        let stat = match archive::split_tag::<SuperStat>(data) {
+            // superblocks from before tags share the first layout
+            (payload, None | Some(1)) => SuperStat::from(archive::check::<SuperStatV1>(payload).ok()?),
+            (payload, Some(2)) => SuperStat::from(archive::check::<SuperStatV2>(payload).ok()?),
+            _ => archive::decode::<SuperStat>(data).ok()?,
+        };
+        if stat.magic_code != MAGICNUMBER {
+            return None;
+        }
+        Some(stat)
     }
 
     pub fn get_bit_offset(&self) -> u32 {
@@ -66,6 +243,14 @@ impl SuperStat {
         self.kv_block_num
     }
 
+    pub fn get_wal_size(&self) -> u32 {
+        self.wal_block_num
+    }
+
+    pub fn get_ino_size(&self) -> u32 {
+        self.ino_size
+    }
+
     pub fn get_main_offset(&self) -> u32 {
         self.super_block_num + self.bit_block_num + self.pit_block_num + self.journal_block_num + self.kv_block_num
     }
@@ -71,6 +71,28 @@ impl BlockTable {
         self.table[block_no as usize].erase();
     }
 
+    // (clean, dirty, used) pages over every block
+    pub fn get_page_stat(&self) -> (u32, u32, u32) {
+        let mut stat = (0, 0, 0);
+        for block in self.table.iter() {
+            stat.0 += block.clean_num;
+            stat.1 += block.dirty_num;
+            stat.2 += block.used_num;
+        }
+        stat
+    }
+
+    // pages still writable without an erase
+    pub fn get_free_num(&self) -> u32 {
+        self.table.iter().map(|block| block.reserved_size).sum()
+    }
+
+    pub fn reset_reserved(&mut self) {
+        for block in self.table.iter_mut() {
+            block.reset_reserved();
+        }
+    }
+
     pub fn set_erase_count(&mut self, block_no: u32, erase_count: u32) {
         if block_no >= self.size {
             panic!("BlockTable: set at too big block_no");
@@ -128,6 +150,10 @@ impl BlockInfo {
                 self.used_num += 1;
                 self.reserved_offset += 1;
                 self.reserved_size -= 1;
+                // the mean write time of the live pages, erase starts it over
+                let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok().unwrap().as_secs();
+                let total = self.average_age as u64 * (self.used_num - 1) as u64 + now;
+                self.average_age = (total / self.used_num as u64) as u32;
             },
         }
         self.used_map[offset as usize] = status;
@@ -156,6 +182,37 @@ impl BlockInfo {
         self.last_erase_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok().unwrap().as_secs() as u32;
     }
 
+    pub fn reset_reserved(&mut self) {
+        let mut offset = 0;
+        for (index, status) in self.used_map.iter().enumerate() {
+            if *status != PageUsedStatus::Clean {
+                offset = index as u32 + 1;
+            }
+        }
+        self.reserved_offset = offset;
+        self.reserved_size = 128 - offset;
+    }
+
+    pub fn get_dirty_num(&self) -> u32 {
+        self.dirty_num
+    }
+
+    pub fn get_used_num(&self) -> u32 {
+        self.used_num
+    }
+
+    // images written before ages were kept read as written at 0, so as cold
+    pub fn get_age(&self, now: u32) -> u32 {
+        now.saturating_sub(self.average_age)
+    }
+
+    // lfs cost-benefit, the pages won weighted by age over the cost of copying the live ones
+    pub fn get_cost_benefit(&self, now: u32) -> f32 {
+        let gain = self.dirty_num as f32 / self.size as f32;
+        let used = self.used_num as f32 / self.size as f32;
+        gain * (self.get_age(now) as f32 + 1.0) / (1.0 + used)
+    }
+
     pub fn get_utilize_ratio(&self) -> f32 {
         (self.clean_num + self.used_num) as f32 / self.dirty_num as f32
     }
@@ -1,23 +1,74 @@
+use crate::error::WondError;
+
 pub const HOTAGEKEY: u32 = 60 * 60 * 24;
 pub const COLDAGEKEY: u32 = 60 * 60 * 24 * 14;
+pub const DEFAULT_GC_INTERVAL: u64 = 10;
+pub const DEFAULT_GC_LOW_WATERMARK: u32 = 20;
+pub const DEFAULT_GC_HIGH_WATERMARK: u32 = 40;
+pub const DEFAULT_WEAR_LEVEL_THRESHOLD: u32 = 32;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum PageUsedStatus {
     Clean,
     Dirty,
-    Busy(u32),
+    Busy(u64),
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum GCStrategy {
     Forward,
     BackgroundSimple,
     BackgroundCold,
 }
 
+// watermarks are percents of free main region pages, a pass starts below low and runs up to high
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GCConfig {
+    pub interval: u64,
+    pub low_watermark: u32,
+    pub high_watermark: u32,
+    pub strategy: GCStrategy,
+    pub wear_level_threshold: u32,
+}
+
+impl GCConfig {
+    pub fn new() -> GCConfig {
+        GCConfig {
+            interval: DEFAULT_GC_INTERVAL,
+            low_watermark: DEFAULT_GC_LOW_WATERMARK,
+            high_watermark: DEFAULT_GC_HIGH_WATERMARK,
+            strategy: GCStrategy::BackgroundCold,
+            wear_level_threshold: DEFAULT_WEAR_LEVEL_THRESHOLD,
+        }
+    }
+}
+
+impl Default for GCConfig {
+    fn default() -> Self {
+        GCConfig::new()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GCStatus {
+    pub running: bool,
+    pub passes: u64,
+    pub collected_blocks: u64,
+    pub reclaimed_pages: u64,
+    pub leveled_blocks: u64,
+    pub last_error: Option<WondError>,
+}
+
 pub struct GCEventGroup {
     pub events: Vec<GCEvent>,
 }
 
+impl Default for GCEventGroup {
+    fn default() -> Self {
+        GCEventGroup::new()
+    }
+}
+
 impl GCEventGroup {
     pub fn new() -> GCEventGroup {
         GCEventGroup {
@@ -54,7 +105,7 @@ pub struct EraseGCEvent {
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct MoveGCEvent {
     pub index: u32,
-    pub ino: u32,
+    pub ino: u64,
     pub size: u32,
     pub o_address: u32,
     pub d_address: u32,
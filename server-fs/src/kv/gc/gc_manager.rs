@@ -1,41 +1,82 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
 use super::gc_define::*;
 use super::block_table;
 
 pub struct GCManager {
-    need_sync: bool,
     hot_blocks: Vec<u32>,
     normal_blocks: Vec<u32>,
     cold_blocks: Vec<u32>,
     block_table: block_table::BlockTable,
+    // the erase count spread that sets off static wear leveling, 0 turns it off
+    pub wear_level_threshold: u32,
+}
+
+impl Default for GCManager {
+    fn default() -> Self {
+        GCManager::new()
+    }
 }
 
 impl GCManager {
     pub fn new() -> GCManager {
         GCManager {
-            need_sync: true,
             hot_blocks: vec![],
             normal_blocks: vec![],
             cold_blocks: vec![],
             block_table: block_table::BlockTable::new(1024-105),
+            wear_level_threshold: DEFAULT_WEAR_LEVEL_THRESHOLD,
         }
     }
 }
 
 impl GCManager {
+    pub fn set_block_num(&mut self, block_num: u32) {
+        self.block_table = block_table::BlockTable::new(block_num);
+    }
+
+    pub fn reset_reserved(&mut self) {
+        self.block_table.reset_reserved();
+    }
+
     pub fn find_write_pos(&self, size: usize) -> Option<u32> {
+        self.find_write_pos_except(size as u32, None, &HashMap::new(), false)
+    }
+
+    // none when no block has dirty pages or the live ones have nowhere to go
+    pub fn new_gc_event(&mut self, strategy: GCStrategy) -> Option<GCEventGroup> {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok().unwrap().as_secs() as u32;
+        self.sync(now);
+        // the best block whose live pages still fit somewhere else
+        self.rank_gc_blocks(strategy, now).into_iter().find_map(|block_no| self.generate_gc_group(block_no))
+    }
+
+    // static wear leveling, once the erase counts spread too far the least erased block holding
+    // data gives it up to the most worn blocks and goes back into rotation
+    pub fn new_wear_level_event(&mut self) -> Option<GCEventGroup> {
+        if self.wear_level_threshold == 0 {
+            return None;
+        }
+        let max_count = self.block_table.table.iter().map(|block| block.erase_count).max()?;
+        let mut gc_block: Option<&block_table::BlockInfo> = None;
         for block in self.block_table.table.iter() {
-            if block.reserved_size >= size as u32 {
-                let offset = block.reserved_offset;
-                return Some((block.block_no * 128) as u32 + offset);
+            if block.get_used_num() == 0 || block.erase_count + self.wear_level_threshold >= max_count {
+                continue;
+            }
+            if gc_block.is_none_or(|gc_block| block.erase_count < gc_block.erase_count) {
+                gc_block = Some(block);
             }
         }
-        None
+        let block_no = gc_block?.block_no;
+        self.generate_group(block_no, true)
     }
 
-    pub fn new_gc_event(&mut self, strategy: GCStrategy) -> GCEventGroup {
-        self.sync();
-        let block_no = self.choose_gc_block(strategy);
-        self.generate_gc_group(block_no)
+    pub fn get_free_percent(&self) -> u32 {
+        let total = self.block_table.size * 128;
+        if total == 0 {
+            return 100;
+        }
+        (self.block_table.get_free_num() as u64 * 100 / total as u64) as u32
     }
 
     pub fn get_page(&self, address: u32) -> PageUsedStatus {
@@ -59,15 +100,23 @@ impl GCManager {
     pub fn erase_block(&mut self, block_no: u32) {
         self.block_table.erase_block(block_no);
     }
+
+    pub fn get_page_stat(&self) -> (u32, u32, u32) {
+        self.block_table.get_page_stat()
+    }
 }
 
 impl GCManager {
-    fn generate_gc_group(&self, block_no: u32) -> GCEventGroup {
-        let mut used_entries: Vec<(u32, u32, u32, u32)> = vec![];
+    fn generate_gc_group(&self, block_no: u32) -> Option<GCEventGroup> {
+        self.generate_group(block_no, false)
+    }
+
+    fn generate_group(&self, block_no: u32, worn: bool) -> Option<GCEventGroup> {
+        let mut used_entries: Vec<(u64, u32, u32, u32)> = vec![];
         let start_index = block_no * 128;
         let end_index = (block_no + 1) * 128;
         let mut size = 0;
-        let mut last_entry: Option<(u32, u32, u32, u32)> = None;
+        let mut last_entry: Option<(u64, u32, u32, u32)> = None;
         for address in start_index..end_index {
             let status = self.block_table.get_page(address);
             match status {
@@ -79,6 +128,7 @@ impl GCManager {
                             last_entry.as_mut().unwrap().1 = size;
                             used_entries.push(last_entry.unwrap());
                             last_entry = Some((ino, 0, address, 0));
+                            size = 1;
                         }
                     } else {
                         last_entry = Some((ino, 0, address, 0));
@@ -99,9 +149,12 @@ impl GCManager {
             last_entry.as_mut().unwrap().1 = size;
             used_entries.push(last_entry.unwrap());
         }
+        // moves of one group must not land on each other
+        let mut planned = HashMap::new();
         for entry in used_entries.iter_mut() {
-            let d_address = self.find_write_pos_except(entry.1, block_no);
-            entry.3 = d_address.unwrap();
+            let d_address = self.find_write_pos_except(entry.1, Some(block_no), &planned, worn)?;
+            *planned.entry(d_address / 128).or_insert(0) += entry.1;
+            entry.3 = d_address;
         }
         let mut gc_group = GCEventGroup::new();
         let mut index = 0;
@@ -121,66 +174,87 @@ impl GCManager {
             block_no,
         };
         gc_group.events.push(GCEvent::Erase(event));
-        gc_group
+        Some(gc_group)
     }
 
-    fn choose_gc_block(&self, strategy: GCStrategy) -> u32 {
+    // best first, forward takes the dirtiest block, background ones leave hot blocks for last since their pages die soon anyway
+    fn rank_gc_blocks(&self, strategy: GCStrategy, now: u32) -> Vec<u32> {
         match strategy {
             GCStrategy::Forward => {
-                let mut gc_block = self.block_table.get_block_info(0);
-                for block in self.block_table.table.iter() {
-                    if block.get_utilize_ratio() < gc_block.get_utilize_ratio() {
-                        gc_block = block;
-                    }
-                }
-                gc_block.block_no
+                let blocks: Vec<u32> = (0..self.block_table.size).collect();
+                self.rank_greedy(&blocks)
             },
             GCStrategy::BackgroundSimple => {
-                let mut gc_block = self.block_table.get_block_info(0);
-                for block in self.block_table.table.iter() {
-                    if block.get_utilize_ratio() < gc_block.get_utilize_ratio() {
-                        gc_block = block;
-                    }
-                }
-                gc_block.block_no
+                let mut blocks = self.cold_blocks.clone();
+                blocks.extend_from_slice(&self.normal_blocks);
+                let mut ranked = self.rank_greedy(&blocks);
+                ranked.extend(self.rank_greedy(&self.hot_blocks));
+                ranked
             },
             GCStrategy::BackgroundCold => {
-                let mut gc_block = self.block_table.get_block_info(0);
-                for block in self.block_table.table.iter() {
-                    if block.get_utilize_ratio() < gc_block.get_utilize_ratio() {
-                        gc_block = block;
-                    }
-                }
-                gc_block.block_no
+                let mut ranked = self.rank_cost_benefit(&self.cold_blocks, now);
+                ranked.extend(self.rank_cost_benefit(&self.normal_blocks, now));
+                ranked.extend(self.rank_cost_benefit(&self.hot_blocks, now));
+                ranked
             },
         }
     }
 
-    fn find_write_pos_except(&self, size: u32, block_no: u32) -> Option<u32> {
+    fn rank_greedy(&self, blocks: &[u32]) -> Vec<u32> {
+        let mut ranked: Vec<&block_table::BlockInfo> = blocks.iter().map(|block_no| self.block_table.get_block_info(*block_no)).filter(|block| block.get_dirty_num() > 0).collect();
+        // of equally dirty blocks the least erased goes first
+        ranked.sort_by(|a, b| a.get_utilize_ratio().total_cmp(&b.get_utilize_ratio()).then(a.erase_count.cmp(&b.erase_count)));
+        ranked.iter().map(|block| block.block_no).collect()
+    }
+
+    fn rank_cost_benefit(&self, blocks: &[u32], now: u32) -> Vec<u32> {
+        let mut ranked: Vec<&block_table::BlockInfo> = blocks.iter().map(|block_no| self.block_table.get_block_info(*block_no)).filter(|block| block.get_dirty_num() > 0).collect();
+        ranked.sort_by(|a, b| b.get_cost_benefit(now).total_cmp(&a.get_cost_benefit(now)).then(a.erase_count.cmp(&b.erase_count)));
+        ranked.iter().map(|block| block.block_no).collect()
+    }
+
+    // dynamic wear leveling, an open block is filled first and a fresh one is the least erased,
+    // worn asks for the most erased instead for data that is not going to change
+    fn find_write_pos_except(&self, size: u32, except: Option<u32>, planned: &HashMap<u32, u32>, worn: bool) -> Option<u32> {
+        let mut write_block: Option<(&block_table::BlockInfo, u32)> = None;
         for block in self.block_table.table.iter() {
-            if block.reserved_size >= size && block.block_no != block_no {
-                let offset = block.reserved_offset;
-                return Some((block.block_no * 128) as u32 + offset);
+            let used = planned.get(&block.block_no).copied().unwrap_or(0);
+            if block.reserved_size < size + used || Some(block.block_no) == except {
+                continue;
+            }
+            let is_open = block.reserved_offset + used > 0;
+            if is_open && !worn {
+                return Some(block.block_no * 128 + block.reserved_offset + used);
+            }
+            let is_better = match write_block {
+                None => true,
+                Some((write_block, _)) if worn => block.erase_count > write_block.erase_count,
+                Some((write_block, _)) => block.erase_count < write_block.erase_count,
+            };
+            if is_better {
+                write_block = Some((block, used));
             }
         }
-        None
+        write_block.map(|(block, used)| block.block_no * 128 + block.reserved_offset + used)
     }
 
-    fn sync(&mut self) {
-        if self.need_sync {
-            for index in 0..self.block_table.size {
-                let info = self.get_block_info(index);
-                if info.average_age > COLDAGEKEY {
-                    self.cold_blocks.push(index);
-                    continue;
-                }
-                if info.average_age < HOTAGEKEY {
-                    self.hot_blocks.push(index);
-                    continue;
-                }
-                self.normal_blocks.push(index);
+    // ages move on, so blocks are sorted again for every event
+    fn sync(&mut self, now: u32) {
+        self.hot_blocks.clear();
+        self.normal_blocks.clear();
+        self.cold_blocks.clear();
+        for index in 0..self.block_table.size {
+            let info = self.get_block_info(index);
+            // a block without live pages costs only its erase
+            if info.get_used_num() == 0 || info.get_age(now) > COLDAGEKEY {
+                self.cold_blocks.push(index);
+                continue;
+            }
+            if info.get_age(now) < HOTAGEKEY {
+                self.hot_blocks.push(index);
+                continue;
             }
-            self.need_sync = false;
+            self.normal_blocks.push(index);
         }
     }
 
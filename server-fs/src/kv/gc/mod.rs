@@ -18,6 +18,8 @@ mod test {
         assert_eq!(table.table[0].block_no, 0);
         assert_eq!(table.table[0].reserved_offset, 4);
         assert_eq!(table.table[0].reserved_size, 124);
+        table.set_page(3, PageUsedStatus::Dirty);
+        assert_eq!(table.get_page_stat(), (32 * 128 - 4, 1, 3));
         table.erase_block(0);
         assert_eq!(table.table[0].reserved_offset, 0);
         assert_eq!(table.table[0].reserved_size, 128);
@@ -32,10 +34,77 @@ mod test {
         manager.set_page(2, PageUsedStatus::Busy(0));
         manager.set_page(3, PageUsedStatus::Busy(0));
         manager.set_page(4, PageUsedStatus::Busy(0));
+        manager.set_page(5, PageUsedStatus::Busy(0));
+        manager.set_page(5, PageUsedStatus::Dirty);
         assert_eq!(manager.get_page(0), PageUsedStatus::Busy(0));
         assert_eq!(manager.find_write_pos(128), Some(128));
-        let event = manager.new_gc_event(GCStrategy::Forward);
+        let event = manager.new_gc_event(GCStrategy::Forward).unwrap();
         assert_eq!(event.events[0], GCEvent::Move(MoveGCEvent{ index: 0, ino: 0, size: 5, o_address: 0, d_address: 128 }));
         assert_eq!(event.events[1], GCEvent::Erase(EraseGCEvent{ index: 1, block_no: 0 }));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_gc_strategy() {
+        let mut manager = GCManager::new();
+        manager.set_block_num(8);
+        let fill = |manager: &mut GCManager, block_no: u32, used: u32, dirty: u32| {
+            for i in 0..used + dirty {
+                manager.set_page(block_no * 128 + i, PageUsedStatus::Busy(block_no as u64));
+            }
+            for i in used..used + dirty {
+                manager.set_page(block_no * 128 + i, PageUsedStatus::Dirty);
+            }
+        };
+        let victim = |manager: &mut GCManager, strategy: GCStrategy| {
+            manager.new_gc_event(strategy).map(|group| match group.events.last() {
+                Some(GCEvent::Erase(event)) => event.block_no,
+                _ => panic!(),
+            })
+        };
+        assert_eq!(victim(&mut manager, GCStrategy::Forward), None);
+        // block 0 is hot and the dirtiest, block 1 is cold, block 2 sits in between
+        let now = std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH).unwrap().as_secs() as u32;
+        fill(&mut manager, 0, 4, 124);
+        fill(&mut manager, 1, 100, 28);
+        fill(&mut manager, 2, 10, 90);
+        manager.set_block_info(1, (0, 0, now - COLDAGEKEY - 1));
+        manager.set_block_info(2, (0, 0, now - HOTAGEKEY * 3));
+        assert!(manager.get_block_info(0).get_age(now) < HOTAGEKEY);
+        assert_eq!(victim(&mut manager, GCStrategy::Forward), Some(0));
+        assert_eq!(victim(&mut manager, GCStrategy::BackgroundSimple), Some(2));
+        assert_eq!(victim(&mut manager, GCStrategy::BackgroundCold), Some(1));
+        // hot blocks are still taken once nothing else is left
+        manager.erase_block(1);
+        manager.erase_block(2);
+        assert_eq!(victim(&mut manager, GCStrategy::BackgroundCold), Some(0));
+        assert_eq!(victim(&mut manager, GCStrategy::BackgroundSimple), Some(0));
+        manager.erase_block(0);
+        assert_eq!(victim(&mut manager, GCStrategy::BackgroundCold), None);
+        assert_eq!(manager.get_free_percent(), 100);
+    }
+
+    #[test]
+    fn test_wear_leveling() {
+        let mut manager = GCManager::new();
+        manager.set_block_num(4);
+        manager.wear_level_threshold = 4;
+        for (block_no, erase_count) in [9, 3, 1, 5].iter().enumerate() {
+            manager.set_block_info(block_no as u32, (0, *erase_count, 0));
+        }
+        // a fresh block is the least erased one, then it is filled before another is opened
+        assert_eq!(manager.find_write_pos(10), Some(2 * 128));
+        for i in 0..10 {
+            manager.set_page(2 * 128 + i, PageUsedStatus::Busy(1));
+        }
+        assert_eq!(manager.find_write_pos(10), Some(2 * 128 + 10));
+        assert_eq!(manager.find_write_pos(120), Some(128));
+        // the spread is 8, the data of the least erased block goes to the most worn one
+        let event = manager.new_wear_level_event().unwrap();
+        assert_eq!(event.events[0], GCEvent::Move(MoveGCEvent{ index: 0, ino: 1, size: 10, o_address: 2 * 128, d_address: 0 }));
+        assert_eq!(event.events[1], GCEvent::Erase(EraseGCEvent{ index: 1, block_no: 2 }));
+        manager.wear_level_threshold = 8;
+        assert!(manager.new_wear_level_event().is_none());
+        manager.wear_level_threshold = 0;
+        assert!(manager.new_wear_level_event().is_none());
+    }
+}
@@ -1,13 +1,112 @@
 extern crate alloc;
 use spin::RwLock;
 use crate::tl::tl;
+use crate::error::{WondError, WondResult};
 use alloc::sync::Arc;
 use super::kv_manager::KVManager;
-use rkyv::ser::{Serializer, serializers::AllocSerializer};
+use super::gc::gc_define::{GCConfig, GCStatus};
+use crate::compress::compress::{CompressPolicy, CompressStat};
+use crate::util::archive::{self, Versioned};
+use crate::util::time::Timespec;
 use rkyv::{Archive, Deserialize, Serialize};
 
 #[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(check_bytes)]
 pub struct InodeMetadata {
+    pub file_type: u8,
+    pub ino: u64,
+    pub size: u64,
+    pub n_link: u8,
+    pub last_accessed: Timespec,
+    pub last_modified: Timespec,
+    pub last_metadata_changed: Timespec,
+    pub created: Timespec,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+// version 2 widened size to 64 bits, version 3 brought nanoseconds and the creation time,
+// version 4 widened ino to 64 bits
+impl Versioned for InodeMetadata {
+    const KIND: u8 = archive::KIND_INODE_METADATA;
+    const VERSION: u8 = 4;
+}
+
+// metadata layout of version 3, converted on first read
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(check_bytes)]
+pub struct InodeMetadataV3 {
+    pub file_type: u8,
+    pub ino: u32,
+    pub size: u64,
+    pub n_link: u8,
+    pub last_accessed: Timespec,
+    pub last_modified: Timespec,
+    pub last_metadata_changed: Timespec,
+    pub created: Timespec,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl From<InodeMetadataV3> for InodeMetadata {
+    fn from(old: InodeMetadataV3) -> Self {
+        InodeMetadata {
+            file_type: old.file_type,
+            ino: old.ino as u64,
+            size: old.size,
+            n_link: old.n_link,
+            last_accessed: old.last_accessed,
+            last_modified: old.last_modified,
+            last_metadata_changed: old.last_metadata_changed,
+            created: old.created,
+            mode: old.mode,
+            uid: old.uid,
+            gid: old.gid,
+        }
+    }
+}
+
+// metadata layout of version 2, converted on first read
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(check_bytes)]
+pub struct InodeMetadataV2 {
+    pub file_type: u8,
+    pub ino: u32,
+    pub size: u64,
+    pub n_link: u8,
+    pub last_accessed: u32,
+    pub last_modified: u32,
+    pub last_metadata_changed: u32,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+// the creation time of an inode from before it was kept is unknown and left at the epoch
+impl From<InodeMetadataV2> for InodeMetadata {
+    fn from(old: InodeMetadataV2) -> Self {
+        InodeMetadata::from(InodeMetadataV3 {
+            file_type: old.file_type,
+            ino: old.ino,
+            size: old.size,
+            n_link: old.n_link,
+            last_accessed: old.last_accessed.into(),
+            last_modified: old.last_modified.into(),
+            last_metadata_changed: old.last_metadata_changed.into(),
+            created: Timespec::default(),
+            mode: old.mode,
+            uid: old.uid,
+            gid: old.gid,
+        })
+    }
+}
+
+// metadata layout of version 1, converted on first read
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(check_bytes)]
+pub struct InodeMetadataV1 {
     pub file_type: u8,
     pub ino: u32,
     pub size: u32,
@@ -15,11 +114,76 @@ pub struct InodeMetadata {
     pub last_accessed: u32,
     pub last_modified: u32,
     pub last_metadata_changed: u32,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl From<InodeMetadataV1> for InodeMetadata {
+    fn from(old: InodeMetadataV1) -> Self {
+        InodeMetadata::from(InodeMetadataV2 {
+            file_type: old.file_type,
+            ino: old.ino,
+            size: old.size as u64,
+            n_link: old.n_link,
+            last_accessed: old.last_accessed,
+            last_modified: old.last_modified,
+            last_metadata_changed: old.last_metadata_changed,
+            mode: old.mode,
+            uid: old.uid,
+            gid: old.gid,
+        })
+    }
+}
+
+// metadata layout written before permission bits were stored, converted on first read
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(check_bytes)]
+pub struct LegacyInodeMetadata {
+    pub file_type: u8,
+    pub ino: u32,
+    pub size: u32,
+    pub n_link: u8,
+    pub last_accessed: u32,
+    pub last_modified: u32,
+    pub last_metadata_changed: u32,
+}
+
+impl From<LegacyInodeMetadata> for InodeMetadata {
+    fn from(legacy: LegacyInodeMetadata) -> Self {
+        // old images reported every inode as 0o777 owned by root
+        InodeMetadata::from(InodeMetadataV1 {
+            file_type: legacy.file_type,
+            ino: legacy.ino,
+            size: legacy.size,
+            n_link: legacy.n_link,
+            last_accessed: legacy.last_accessed,
+            last_modified: legacy.last_modified,
+            last_metadata_changed: legacy.last_metadata_changed,
+            mode: 0o777,
+            uid: 0,
+            gid: 0,
+        })
+    }
+}
+
+// every extended attribute of an inode, kept as a single extra object
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq, Default)]
+#[archive(check_bytes)]
+pub struct InodeXattr {
+    pub entries: Vec<(String, Vec<u8>)>,
+}
+
+impl Versioned for InodeXattr {
+    const KIND: u8 = archive::KIND_INODE_XATTR;
+    const VERSION: u8 = 1;
 }
 
 pub struct KV {
     pub manager: Arc<RwLock<KVManager>>,
-    pub max_ino: Arc<RwLock<u32>>,
+    pub max_ino: Arc<RwLock<u64>>,
+    pub inode_num: Arc<RwLock<u64>>,
+    pub gc_status: Arc<RwLock<GCStatus>>,
 }
 
 impl KV {
@@ -27,96 +191,295 @@ impl KV {
         KV {
             manager: Arc::new(RwLock::new(KVManager::new(tl))),
             max_ino: Arc::new(RwLock::new(0)),
+            inode_num: Arc::new(RwLock::new(0)),
+            gc_status: Arc::new(RwLock::new(GCStatus::default())),
         }
     }
 
-    pub fn mount(&self) {
-        self.manager.write().mount();
+    pub fn mount(&self) -> WondResult<()> {
+        self.manager.write().mount()?;
+        if let Some(data) = self.get_extra_value(String::from("max_ino"))? {
+            *self.max_ino.write() = decode_uint(&data);
+        }
+        match self.get_extra_value(String::from("inode_num"))? {
+            Some(data) => *self.inode_num.write() = decode_uint(&data),
+            None => {
+                // images from before the counter existed, count the live inodes once
+                let max_ino = *self.max_ino.read();
+                let mut inode_num = 0;
+                for ino in 1..=max_ino {
+                    if self.manager.write().get(&format!("m:{}", ino), 0, 0)?.is_some() {
+                        inode_num += 1;
+                    }
+                }
+                if self.manager.read().read_only {
+                    *self.inode_num.write() = inode_num;
+                } else {
+                    self.set_inode_num(inode_num)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> WondResult<()> {
+        self.manager.write().flush()
     }
 
-    pub fn allocate_indoe(&self, metadata: &mut InodeMetadata) -> u32 {
+    pub fn sync(&self) -> WondResult<()> {
+        self.manager.write().sync()
+    }
+
+    pub fn allocate_indoe(&self, metadata: &mut InodeMetadata) -> WondResult<u64> {
+        // inode numbers are never reused
+        if *self.max_ino.read() == self.get_ino_limit() {
+            return Err(WondError::NoSpace);
+        }
         *self.max_ino.write() += 1;
+        let max_ino = *self.max_ino.read();
+        self.manager.write().set(&String::from("e:max_ino"), 0, 0, &max_ino.to_be_bytes().to_vec(), 0)?;
+        let inode_num = *self.inode_num.read() + 1;
+        self.set_inode_num(inode_num)?;
         metadata.ino = *self.max_ino.read();
         let key = format!("m:{}", *self.max_ino.read());
-        let mut serializer = AllocSerializer::<0>::default();
-        serializer.serialize_value(metadata).unwrap();
-        let data = serializer.into_serializer().into_inner().to_vec();
-        self.manager.write().set(&key, 0, 0, &data, 0);
-        *self.max_ino.read()
+        let data = archive::encode(metadata);
+        self.manager.write().set(&key, 0, 0, &data, 0)?;
+        Ok(*self.max_ino.read())
     }
 
-    pub fn delete_inode(&self, ino: u32) {
+    pub fn delete_inode(&self, ino: u64) -> WondResult<()> {
         let meta_key = format!("m:{}", ino);
         let data_key = format!("d:{}", ino);
-        self.manager.write().delete(&meta_key, 0, 0, 0);
-        self.manager.write().delete(&data_key, 0, 0, 0);
+        if self.manager.write().get(&meta_key, 0, 0)?.is_some() {
+            let inode_num = self.inode_num.read().saturating_sub(1);
+            self.set_inode_num(inode_num)?;
+        }
+        self.manager.write().delete(&meta_key, 0, 0, 0)?;
+        self.manager.write().delete(&data_key, 0, 0, 0)?;
+        self.deleete_extra_value(format!("xattr:{}", ino))
+    }
+
+    pub fn get_inode_num(&self) -> u64 {
+        *self.inode_num.read()
+    }
+
+    // images from before 64 bit inode numbers keep 32 bits of them in directory entries and the PIT
+    pub fn get_ino_limit(&self) -> u64 {
+        match self.manager.read().super_stat.get_ino_size() {
+            4 => u32::MAX as u64,
+            _ => u64::MAX,
+        }
+    }
+
+    pub fn get_ino_size(&self) -> u32 {
+        self.manager.read().super_stat.get_ino_size()
+    }
+
+    fn set_inode_num(&self, inode_num: u64) -> WondResult<()> {
+        *self.inode_num.write() = inode_num;
+        self.set_extra_value(String::from("inode_num"), &inode_num.to_be_bytes().to_vec())
+    }
+
+    // (total, free) pages of the main area, dirty pages stay used until gc erases them
+    pub fn get_space_stat(&self) -> (u32, u32) {
+        let manager = self.manager.read();
+        let (clean, _, _) = manager.gc.get_page_stat();
+        let total = manager.super_stat.get_main_size() * manager.super_stat.get_page_num_per_block();
+        (total, clean)
+    }
+
+    // set before mount, the mount itself already leaves the image alone
+    pub fn set_read_only(&self, read_only: bool) {
+        self.manager.write().read_only = read_only;
+    }
+
+    // applies to extents written from now on, what is on flash keeps the codec it was stored with
+    pub fn set_compress_policy(&self, policy: CompressPolicy) {
+        self.manager.write().compress_manager.policy = policy;
+    }
+
+    pub fn get_compress_stat(&self) -> CompressStat {
+        self.manager.read().compress_manager.stat.clone()
     }
 
-    pub fn get_inode_metadata(&self, ino: u32) -> Option<InodeMetadata> {
+    pub fn get_inode_metadata(&self, ino: u64) -> WondResult<Option<InodeMetadata>> {
         let key = format!("m:{}", ino);
-        let data = self.manager.write().get(&key, 0, 0)?;
-        let archived = unsafe { rkyv::archived_root::<InodeMetadata>(&data) };
-        archived.deserialize(&mut rkyv::Infallible).ok()
+        let data = match self.manager.write().get(&key, 0, 0)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let (payload, version) = archive::split_tag::<InodeMetadata>(&data);
+        // untagged records come from before tags, a legacy one or a version 1 one. neither holds
+        // anything out of line, so the archive is exactly the size of its layout
+        let metadata = match version {
+            None if payload.len() == core::mem::size_of::<ArchivedLegacyInodeMetadata>() => {
+                InodeMetadata::from(archive::check::<LegacyInodeMetadata>(payload)?)
+            },
+            None if payload.len() == core::mem::size_of::<ArchivedInodeMetadataV1>() => {
+                InodeMetadata::from(archive::check::<InodeMetadataV1>(payload)?)
+            },
+            None => return Err(WondError::Corrupted("untagged metadata matches no old layout")),
+            Some(1) => InodeMetadata::from(archive::check::<InodeMetadataV1>(payload)?),
+            Some(2) => InodeMetadata::from(archive::check::<InodeMetadataV2>(payload)?),
+            Some(3) => InodeMetadata::from(archive::check::<InodeMetadataV3>(payload)?),
+            _ => return Ok(Some(archive::decode(&data)?)),
+        };
+        // a read-only mount converts on every read and leaves the old record be
+        if !self.manager.read().read_only {
+            self.set_inode_metadata(ino, &metadata)?;
+        }
+        Ok(Some(metadata))
     }
 
-    pub fn set_inode_metadata(&self, ino: u32, metadata: &InodeMetadata) {
+    pub fn set_inode_metadata(&self, ino: u64, metadata: &InodeMetadata) -> WondResult<()> {
         let key = format!("m:{}", ino);
-        let mut serializer = AllocSerializer::<0>::default();
-        serializer.serialize_value(metadata).unwrap();
-        let data = serializer.into_serializer().into_inner().to_vec();
-        self.manager.write().set(&key, 0, 0, &data, 0);
+        let data = archive::encode(metadata);
+        self.manager.write().set(&key, 0, 0, &data, 0)?;
+        Ok(())
     }
 
-    pub fn get_inode_data(&self, ino: u32, off: usize, len: usize) -> Option<Vec<u8>> {
+    pub fn get_inode_data(&self, ino: u64, off: u64, len: usize) -> WondResult<Vec<u8>> {
         let key = format!("d:{}", ino);
-        let data = self.manager.write().get(&key, off, len);
-        if data.is_none() {
-            return Some(vec![]);
-        }
-        data
+        let data = self.manager.write().get(&key, off as usize, len)?;
+        Ok(data.unwrap_or_default())
+    }
+
+    pub fn set_inode_data(&self, ino: u64, off: u64, len: usize, value: &Vec<u8>) -> WondResult<u64> {
+        let mut metadata = self.get_inode_metadata(ino)?.ok_or(WondError::NotFound)?;
+        let key = format!("d:{}", ino);
+        let size = self.manager.write().set(&key, off as usize, len, value, metadata.ino)?.unwrap_or(0);
+        metadata.size = size;
+        self.set_inode_metadata(ino, &metadata)?;
+        Ok(size)
+    }
+
+    pub fn delete_inode_data(&self, ino: u64, off: u64, len: u64) -> WondResult<u64> {
+        let mut metadata = self.get_inode_metadata(ino)?.ok_or(WondError::NotFound)?;
+        let key = format!("d:{}", ino);
+        let size = self.manager.write().delete(&key, off as usize, len as usize, metadata.ino)?.unwrap_or(0);
+        metadata.size = size;
+        self.set_inode_metadata(ino, &metadata)?;
+        Ok(size)
     }
 
-    pub fn set_inode_data(&self, ino: u32, off: usize, len: usize, value: &Vec<u8>) -> usize {
-        let mut metadata = self.get_inode_metadata(ino).unwrap();
+    pub fn resize_inode_data(&self, ino: u64, size: u64) -> WondResult<u64> {
+        let mut metadata = self.get_inode_metadata(ino)?.ok_or(WondError::NotFound)?;
         let key = format!("d:{}", ino);
-        let size = self.manager.write().set(&key, off, len, value, metadata.ino).unwrap();
-        metadata.size = size as u32;
-        self.set_inode_metadata(ino, &metadata);
-        size
+        let size = self.manager.write().resize(&key, size, metadata.ino)?;
+        metadata.size = size;
+        self.set_inode_metadata(ino, &metadata)?;
+        Ok(size)
     }
 
-    pub fn delete_inode_data(&self, ino: u32, off: usize, len: usize) -> usize {
-        let mut metadata = self.get_inode_metadata(ino).unwrap();
+    pub fn punch_inode_data(&self, ino: u64, off: u64, len: u64) -> WondResult<()> {
+        let metadata = self.get_inode_metadata(ino)?.ok_or(WondError::NotFound)?;
         let key = format!("d:{}", ino);
-        let size = self.manager.write().delete(&key, off, len, metadata.ino).unwrap();
-        metadata.size = size as u32;
-        self.set_inode_metadata(ino, &metadata);
-        size
+        self.manager.write().punch(&key, off, len, metadata.ino)
     }
 
-    pub fn get_extra_value(&self, key: String) -> Option<Vec<u8>> {
+    // (offset, len) of every written range, everything else in the file is a hole
+    pub fn get_inode_data_ranges(&self, ino: u64) -> WondResult<Vec<(u64, u64)>> {
+        let key = format!("d:{}", ino);
+        self.manager.write().data_ranges(&key)
+    }
+
+    pub fn get_inode_xattr(&self, ino: u64) -> WondResult<InodeXattr> {
+        match self.get_extra_value(format!("xattr:{}", ino))? {
+            Some(data) => archive::decode(&data),
+            None => Ok(InodeXattr::default()),
+        }
+    }
+
+    pub fn set_inode_xattr(&self, ino: u64, xattr: &InodeXattr) -> WondResult<()> {
+        let key = format!("xattr:{}", ino);
+        if xattr.entries.is_empty() {
+            return self.deleete_extra_value(key);
+        }
+        self.set_extra_value(key, &archive::encode(xattr))
+    }
+
+    pub fn get_extra_value(&self, key: String) -> WondResult<Option<Vec<u8>>> {
         let key = format!("e:{}", key);
         self.manager.write().get(&key, 0, 0)
     }
 
-    pub fn set_extra_value(&mut self, key: String, value: &Vec<u8>) {
+    pub fn set_extra_value(&self, key: String, value: &Vec<u8>) -> WondResult<()> {
         let key = format!("e:{}", key);
-        self.manager.write().set(&key, 0, 0, value, 0);
+        self.manager.write().set(&key, 0, 0, value, 0)?;
+        Ok(())
     }
 
-    pub fn deleete_extra_value(&self, key: String) {
+    pub fn deleete_extra_value(&self, key: String) -> WondResult<()> {
         let key = format!("e:{}", key);
-        self.manager.write().delete(&key, 0, 0, 0);
+        self.manager.write().delete(&key, 0, 0, 0)?;
+        Ok(())
     }
 
-    pub fn background_gc(&self) {
+    // enumerates m:, d: and e: keys, the prefix is matched against the full key
+    pub fn scan(&self, prefix: &str) -> WondResult<Vec<(String, Vec<u8>)>> {
+        self.manager.write().scan(prefix)
+    }
+
+    pub fn scan_range(&self, start: &str, end: Option<&str>) -> WondResult<Vec<(String, Vec<u8>)>> {
+        self.manager.write().scan_range(start, end)
+    }
+
+    // checks the watermarks every interval seconds until stop_gc, 0 never starts
+    pub fn background_gc(&self, config: GCConfig) {
+        if config.interval == 0 {
+            return;
+        }
+        self.gc_status.write().running = true;
+        self.manager.write().gc.wear_level_threshold = config.wear_level_threshold;
         loop {
-            std::thread::sleep(std::time::Duration::from_secs(10));
-            self.manager.write().background_gc();
+            std::thread::sleep(std::time::Duration::from_secs(config.interval));
+            if !self.gc_status.read().running {
+                return;
+            }
+            if let Err(err) = self.gc_pass(&config) {
+                eprintln!("server-fs: background gc: {}", err);
+                self.gc_status.write().last_error = Some(err);
+            }
+        }
+    }
+
+    // the manager lock is taken for one block at a time, so foreground writes get in between
+    pub fn gc_pass(&self, config: &GCConfig) -> WondResult<()> {
+        if self.manager.read().get_free_percent() < config.low_watermark {
+            self.gc_status.write().passes += 1;
+            while self.manager.read().get_free_percent() < config.high_watermark {
+                let reclaimed = self.manager.write().background_gc(config.strategy)?;
+                match reclaimed {
+                    Some(pages) => {
+                        let mut status = self.gc_status.write();
+                        status.collected_blocks += 1;
+                        status.reclaimed_pages += pages as u64;
+                    },
+                    None => break,
+                }
+            }
+        }
+        // a block a pass is enough to pull the erase counts back together
+        if self.manager.write().wear_level()? {
+            self.gc_status.write().leveled_blocks += 1;
         }
+        Ok(())
+    }
+
+    pub fn stop_gc(&self) {
+        self.gc_status.write().running = false;
+    }
+
+    pub fn get_gc_status(&self) -> GCStatus {
+        self.gc_status.read().clone()
     }
 }
 
+// counters were 4 bytes before 64 bit inode numbers and are 8 bytes since
+fn decode_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |value, byte| value << 8 | *byte as u64)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -124,6 +487,7 @@ mod tests {
     #[test]
     fn test() {
         #[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+        #[archive(check_bytes)]
         struct Test {
             int: u8,
             string: String,
@@ -138,8 +502,200 @@ mod tests {
         let mut serializer = AllocSerializer::<0>::default();
         serializer.serialize_value(&value).unwrap();
         let bytes = serializer.into_serializer().into_inner().to_vec();
-        let archived = unsafe { rkyv::archived_root::<Test>(&bytes) };
-        let deserialized: Test = archived.deserialize(&mut rkyv::Infallible).unwrap();
+        let deserialized: Test = archive::check(&bytes).unwrap();
         assert_eq!(deserialized, value);
     }
+
+    #[test]
+    fn legacy_metadata() {
+        assert_ne!(core::mem::size_of::<ArchivedLegacyInodeMetadata>(), core::mem::size_of::<ArchivedInodeMetadata>());
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let kv = KV::new(Arc::new(tl));
+        kv.mount().unwrap();
+        let legacy = LegacyInodeMetadata {
+            file_type: 1,
+            ino: 7,
+            size: 518,
+            n_link: 2,
+            last_accessed: 1,
+            last_modified: 2,
+            last_metadata_changed: 3,
+        };
+        use rkyv::ser::{Serializer, serializers::AllocSerializer};
+        let mut serializer = AllocSerializer::<0>::default();
+        serializer.serialize_value(&legacy).unwrap();
+        let data = serializer.into_serializer().into_inner().to_vec();
+        kv.manager.write().set(&String::from("m:7"), 0, 0, &data, 0).unwrap();
+        let metadata = kv.get_inode_metadata(7).unwrap().unwrap();
+        assert_eq!(metadata.size, 518);
+        assert_eq!(metadata.n_link, 2);
+        assert_eq!(metadata.mode, 0o777);
+        assert_eq!(metadata.uid, 0);
+        let data = kv.manager.write().get(&String::from("m:7"), 0, 0).unwrap().unwrap();
+        assert_eq!(data.len(), core::mem::size_of::<ArchivedInodeMetadata>() + archive::TAG_SIZE);
+        assert_eq!(archive::split_tag::<InodeMetadata>(&data).1, Some(InodeMetadata::VERSION));
+        assert_eq!(kv.get_inode_metadata(7).unwrap().unwrap(), metadata);
+        // damaged metadata is an error, not whatever the bytes happen to decode to
+        kv.manager.write().set(&String::from("m:7"), 0, 0, &vec![0xff; 8], 0).unwrap();
+        assert_eq!(kv.get_inode_metadata(7), Err(WondError::Corrupted("untagged metadata matches no old layout")));
+        // a current record stripped of its tag is not mistaken for an old one
+        let mut data = archive::encode(&metadata);
+        data.truncate(data.len() - archive::TAG_SIZE);
+        kv.manager.write().set(&String::from("m:7"), 0, 0, &data, 0).unwrap();
+        assert_eq!(kv.get_inode_metadata(7), Err(WondError::Corrupted("untagged metadata matches no old layout")));
+        kv.manager.write().set(&String::from("m:7"), 0, 0, &tagged(vec![0xff; 8], &archive::encode(&metadata), 1), 0).unwrap();
+        assert_eq!(kv.get_inode_metadata(7), Err(WondError::Corrupted("archived value fails validation")));
+    }
+
+    fn tagged(payload: Vec<u8>, tag_of: &[u8], version: u8) -> Vec<u8> {
+        let mut data = payload;
+        data.extend_from_slice(&tag_of[tag_of.len() - archive::TAG_SIZE..]);
+        *data.last_mut().unwrap() = version;
+        data
+    }
+
+    #[test]
+    fn metadata_migration() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let kv = KV::new(Arc::new(tl));
+        kv.mount().unwrap();
+        let old = InodeMetadataV1 {
+            file_type: 0,
+            ino: 9,
+            size: u32::MAX,
+            n_link: 1,
+            last_accessed: 1,
+            last_modified: 2,
+            last_metadata_changed: 3,
+            mode: 0o640,
+            uid: 1000,
+            gid: 100,
+        };
+        use rkyv::ser::{Serializer, serializers::AllocSerializer};
+        let mut serializer = AllocSerializer::<0>::default();
+        serializer.serialize_value(&old).unwrap();
+        let payload = serializer.into_serializer().into_inner().to_vec();
+        let current = archive::encode(&InodeMetadata::from(LegacyInodeMetadata {
+            file_type: 0,
+            ino: 0,
+            size: 0,
+            n_link: 0,
+            last_accessed: 0,
+            last_modified: 0,
+            last_metadata_changed: 0,
+        }));
+        // both untagged and tagged version 1 metadata are widened and written back
+        for data in [payload.clone(), tagged(payload.clone(), &current, 1)] {
+            kv.manager.write().set(&String::from("m:9"), 0, 0, &data, 0).unwrap();
+            let metadata = kv.get_inode_metadata(9).unwrap().unwrap();
+            assert_eq!(metadata.size, u32::MAX as u64);
+            assert_eq!(metadata.mode, 0o640);
+            assert_eq!(metadata.gid, 100);
+            let data = kv.manager.write().get(&String::from("m:9"), 0, 0).unwrap().unwrap();
+            assert_eq!(archive::split_tag::<InodeMetadata>(&data).1, Some(InodeMetadata::VERSION));
+        }
+        // read-only, the record is converted on the way out but stays as it was
+        kv.manager.write().set(&String::from("m:9"), 0, 0, &payload, 0).unwrap();
+        kv.set_read_only(true);
+        assert_eq!(kv.get_inode_metadata(9).unwrap().unwrap().size, u32::MAX as u64);
+        assert_eq!(kv.manager.write().get(&String::from("m:9"), 0, 0).unwrap().unwrap(), payload);
+        kv.set_read_only(false);
+        let old = InodeMetadataV2 {
+            file_type: 0,
+            ino: 9,
+            size: 5 << 30,
+            n_link: 1,
+            last_accessed: 1,
+            last_modified: 2,
+            last_metadata_changed: 3,
+            mode: 0o600,
+            uid: 0,
+            gid: 0,
+        };
+        let mut serializer = AllocSerializer::<0>::default();
+        serializer.serialize_value(&old).unwrap();
+        let payload = serializer.into_serializer().into_inner().to_vec();
+        kv.manager.write().set(&String::from("m:9"), 0, 0, &tagged(payload, &current, 2), 0).unwrap();
+        let metadata = kv.get_inode_metadata(9).unwrap().unwrap();
+        assert_eq!(metadata.size, 5 << 30);
+        assert_eq!(metadata.last_modified, Timespec::new(2, 0));
+        assert_eq!(metadata.created, Timespec::default());
+        let old = InodeMetadataV3 {
+            file_type: 0,
+            ino: 9,
+            size: 5 << 30,
+            n_link: 1,
+            last_accessed: Timespec::new(1, 1),
+            last_modified: Timespec::new(2, 2),
+            last_metadata_changed: Timespec::new(3, 3),
+            created: Timespec::new(4, 4),
+            mode: 0o600,
+            uid: 0,
+            gid: 0,
+        };
+        let mut serializer = AllocSerializer::<0>::default();
+        serializer.serialize_value(&old).unwrap();
+        let payload = serializer.into_serializer().into_inner().to_vec();
+        kv.manager.write().set(&String::from("m:9"), 0, 0, &tagged(payload, &current, 3), 0).unwrap();
+        let metadata = kv.get_inode_metadata(9).unwrap().unwrap();
+        assert_eq!(metadata.ino, 9);
+        assert_eq!(metadata.created, Timespec::new(4, 4));
+        let data = kv.manager.write().get(&String::from("m:9"), 0, 0).unwrap().unwrap();
+        assert_eq!(archive::split_tag::<InodeMetadata>(&data).1, Some(InodeMetadata::VERSION));
+    }
+
+    #[test]
+    fn data_object_v2() {
+        use super::super::kv_manager::{DataObjectValue, DataObjectValueV2, DataObjectValueEntryV2};
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let kv = KV::new(Arc::new(tl));
+        kv.mount().unwrap();
+        let mut metadata = InodeMetadata::from(LegacyInodeMetadata {
+            file_type: 0,
+            ino: 0,
+            size: 0,
+            n_link: 1,
+            last_accessed: 0,
+            last_modified: 0,
+            last_metadata_changed: 0,
+        });
+        let ino = kv.allocate_indoe(&mut metadata).unwrap();
+        kv.set_inode_data(ino, 0, 6000, &vec![7; 6000]).unwrap();
+        kv.set_inode_data(ino, 10000, 100, &vec![8; 100]).unwrap();
+        let key = format!("d:{}", ino);
+        let current = kv.manager.write().lsm_tree.get(&key.as_bytes().to_vec()).unwrap().unwrap();
+        let object = DataObjectValue::decode(&current).unwrap();
+        // the same object in the layout of version 2
+        let old = DataObjectValueV2 {
+            size: object.size as u32,
+            entries: object.entries.iter().map(|entry| DataObjectValueEntryV2 {
+                len: entry.len as u32,
+                archived_len: entry.archived_len as u32,
+                offset: entry.offset as u32,
+                page_pointer: entry.page_pointer,
+                compress_type: entry.compress_type,
+            }).collect(),
+        };
+        use rkyv::ser::{Serializer, serializers::AllocSerializer};
+        let mut serializer = AllocSerializer::<0>::default();
+        serializer.serialize_value(&old).unwrap();
+        let payload = serializer.into_serializer().into_inner().to_vec();
+        for data in [payload.clone(), tagged(payload.clone(), &current, 1), tagged(payload, &current, 2)] {
+            kv.manager.write().lsm_tree.put(&key.as_bytes().to_vec(), &data).unwrap();
+            assert_eq!(DataObjectValue::decode(&data).unwrap(), object);
+            assert!(kv.get_inode_data(ino, 0, 0).is_err());
+            let data = kv.get_inode_data(ino, 0, 10100).unwrap();
+            assert_eq!(data[..6000], vec![7; 6000][..]);
+            assert_eq!(data[6000..10000], vec![0; 4000][..]);
+            assert_eq!(data[10000..], vec![8; 100][..]);
+        }
+        // the next change writes it back in the current layout
+        kv.set_inode_data(ino, 0, 1, &vec![9]).unwrap();
+        let data = kv.manager.write().lsm_tree.get(&key.as_bytes().to_vec()).unwrap().unwrap();
+        assert_eq!(archive::split_tag::<DataObjectValue>(&data).1, Some(DataObjectValue::VERSION));
+        assert_eq!(kv.get_inode_data(ino, 0, 2).unwrap(), vec![9, 7]);
+    }
 }
\ No newline at end of file
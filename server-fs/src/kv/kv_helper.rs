@@ -1,13 +1,12 @@
 extern crate alloc;
-use rkyv::Deserialize;
-use rkyv::ser::Serializer;
-use rkyv::ser::serializers::AllocSerializer;
 use spin::RwLock;
 use alloc::sync::Arc;
 use crate::buf;
 use crate::compress::compress;
 use crate::tl::tl;
+use crate::error::{WondError, WondResult};
 use crate::util::array::array;
+use crate::util::archive;
 use super::gc::gc_manager;
 use super::gc::gc_define::*;
 use super::component::bit;
@@ -15,6 +14,7 @@ use super::component::pit;
 use super::component::journal;
 use super::component::super_block;
 use super::lsm_tree::lsm_tree;
+use super::lsm_tree::wal;
 use super::kv_manager::*;
 
 impl KVManager {
@@ -29,18 +29,42 @@ impl KVManager {
             journal: journal::Journal::new(),
             super_stat: super_block::SuperStat::new(),
             compress_manager: compress::CompressManager::new(),
+            read_only: false,
         }
     }
 
-    pub fn mount(&mut self) {
-        self.read_sb();
-        self.read_bit();
-        self.read_pit();
+    pub fn mount(&mut self) -> WondResult<()> {
+        self.read_sb()?;
+        let ino_size = self.super_stat.get_ino_size();
+        self.pit.set_layout(ino_size, self.super_stat.get_pit_size() / 2);
+        self.journal.set_ino_size(ino_size);
+        if self.super_stat.get_wal_size() == 0 && !self.read_only {
+            self.migrate_wal()?;
+        }
+        let wal_size = self.super_stat.get_wal_size() as usize;
+        self.lsm_tree.mount(self.super_stat.get_kv_offset(), self.super_stat.get_kv_size() as usize, wal_size, self.read_only)?;
+        self.gc.set_block_num(self.super_stat.get_main_size());
+        self.read_bit()?;
+        self.read_pit()?;
+        self.read_journal()?;
+        self.gc.reset_reserved();
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> WondResult<()> {
+        self.lsm_tree.flush()?;
+        self.write_bit()?;
+        self.write_pit()
+    }
+
+    // commits the log records of the puts so far, the tables, BIT and PIT wait for flush
+    pub fn sync(&mut self) -> WondResult<()> {
+        self.lsm_tree.sync()
     }
 }
 
 impl KVManager {
-    pub fn find_write_pos(&mut self, size: usize) -> u32 {
+    pub fn find_write_pos(&mut self, size: usize) -> WondResult<u32> {
         // self.gc.find_write_pos(size).unwrap()
         let mut res;
         loop {
@@ -48,19 +72,48 @@ impl KVManager {
             if res.is_some() {
                 break;
             }
-            self.forward_gc();
+            self.forward_gc()?;
         }
-        res.unwrap()
+        Ok(res.unwrap())
     }
 
-    pub fn forward_gc(&mut self) {
-        let gc_group = self.gc.new_gc_event(GCStrategy::Forward);
-        self.dispose_gc_group(gc_group);
+    pub fn forward_gc(&mut self) -> WondResult<()> {
+        match self.gc.new_gc_event(GCStrategy::Forward) {
+            Some(gc_group) => self.dispose_gc_group(gc_group)?,
+            None => return Err(WondError::NoSpace),
+        }
+        // the erase may have pushed the counts apart
+        self.wear_level()?;
+        Ok(())
+    }
+
+    pub fn wear_level(&mut self) -> WondResult<bool> {
+        match self.gc.new_wear_level_event() {
+            Some(gc_group) => {
+                self.dispose_gc_group(gc_group)?;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
     }
 
-    pub fn background_gc(&mut self) {
-        let gc_group = self.gc.new_gc_event(GCStrategy::Forward);
-        self.dispose_gc_group(gc_group);
+    // collects a single block and returns the dirty pages it won back, none if no block is worth it
+    pub fn background_gc(&mut self, strategy: GCStrategy) -> WondResult<Option<u32>> {
+        let gc_group = match self.gc.new_gc_event(strategy) {
+            Some(gc_group) => gc_group,
+            None => return Ok(None),
+        };
+        let block_no = match gc_group.events.last() {
+            Some(GCEvent::Erase(event)) => event.block_no,
+            _ => return Ok(None),
+        };
+        let dirty_num = self.gc.get_block_info(block_no).get_dirty_num();
+        self.dispose_gc_group(gc_group)?;
+        Ok(Some(dirty_num))
+    }
+
+    pub fn get_free_percent(&self) -> u32 {
+        self.gc.get_free_percent()
     }
 
     pub fn set_page(&mut self, address: u32, status: PageUsedStatus) {
@@ -75,89 +128,121 @@ impl KVManager {
         self.gc.erase_block(block_no);
     }
 
-    pub fn dispose_gc_group(&mut self, gc_group: GCEventGroup) {
-        // let mut gc_group = gc_group;
-        // self.journal_begin_op();
-        // self.update_journal(&gc_group);
-        // self.journal_end_op();
-        // self.bit_begin_op();
-        // self.pit_begin_op();
-        for event in gc_group.events {
-            match event {
-                GCEvent::Erase(event) => {
-                    // let start_index = event.block_no * 128;
-                    // let end_index = (event.block_no + 1) * 128;
-                    // for i in start_index..end_index {
-                    //     self.update_bit(i, false);
-                    //     self.clean_pit(i);
-                    // }
-                    self.erase_block(event.block_no, true);
-                    self.erase_block_in_block_table(event.block_no);
-                }
-                GCEvent::Move(event) => {
-                    let o_address = event.o_address;
-                    let d_address = event.d_address;
-                    let size = event.size;
-                    let ino = event.ino;
-                    let mut data = vec![];
-                    for i in o_address..o_address + size {
-                        data.push(self.read_page(i, true));
-                        self.dirty_pit(i);
-                    }
-                    for i in d_address..d_address + size {
-                        self.update_bit(i, true);
-                        self.update_pit(i, ino);
-                        self.write_page(i, &data[(i - d_address) as usize], true);
-                    }
-                    let key = format!("d:{}", ino);
-                    let value = self.lsm_tree.get(&key.as_bytes().to_vec());
-                    if value.is_none() {
-                        panic!();
-                    }
-                    let archived = unsafe { rkyv::archived_root::<DataObjectValue>(value.as_ref().unwrap()) };
-                    let mut data_object: DataObjectValue = archived.deserialize(&mut rkyv::Infallible).unwrap();
-                    for entry in data_object.entries.iter_mut() {
-                        if entry.page_pointer == o_address {
-                            entry.page_pointer = d_address;
-                            break;
-                        }
-                    }
-                    let mut serializer = AllocSerializer::<0>::default();
-                    serializer.serialize_value(&data_object).unwrap();
-                    let value = serializer.into_serializer().into_inner().to_vec();
-                    self.lsm_tree.put(&key.as_bytes().to_vec(), &value);
-                }
-                _ => ()
+    pub fn dispose_gc_group(&mut self, gc_group: GCEventGroup) -> WondResult<()> {
+        self.begin_gc_group(&gc_group)?;
+        for event in gc_group.events.iter() {
+            self.run_gc_event(event)?;
+        }
+        self.end_gc_group()
+    }
+
+    // the group is journaled before any of it runs, a crash partway is replayed on the next mount
+    pub fn begin_gc_group(&mut self, gc_group: &GCEventGroup) -> WondResult<()> {
+        self.journal_begin_op();
+        self.update_journal(gc_group);
+        self.journal_end_op()?;
+        self.bit_begin_op();
+        self.pit_begin_op();
+        Ok(())
+    }
+
+    pub fn run_gc_event(&mut self, event: &GCEvent) -> WondResult<()> {
+        match event {
+            GCEvent::Erase(event) => {
+                // the copies and the data objects pointing at them are still in the write cache
+                // and the log, they reach the disk before the pages they came from are gone
+                self.sync()?;
+                let translation_layer = Arc::clone(&self.buf.read().translation_layer);
+                translation_layer.flush()?;
+                self.erase_block(event.block_no, true)?;
+                self.erase_block_in_block_table(event.block_no);
             }
+            GCEvent::Move(event) => {
+                self.move_pages(event.ino, event.o_address, event.d_address, event.size)?;
+            }
+            _ => ()
+        }
+        Ok(())
+    }
+
+    pub fn end_gc_group(&mut self) -> WondResult<()> {
+        self.bit_end_op();
+        self.pit_end_op();
+        self.finish_journal()
+    }
+
+    // a replayed journal may find the pages already copied and the pointers already moved
+    pub fn move_pages(&mut self, ino: u64, o_address: u32, d_address: u32, size: u32) -> WondResult<()> {
+        for i in 0..size {
+            let data = self.read_page(o_address + i, true)?;
+            if self.read_page(d_address + i, true)? == [0; 4096] {
+                self.write_page(d_address + i, &data, true);
+            }
+            self.dirty_pit(o_address + i);
+            self.update_bit(d_address + i, true);
+            self.update_pit(d_address + i, ino);
         }
-        // self.bit_end_op();
-        // self.pit_end_op();
-        // self.clear_journal();
+        let key = format!("d:{}", ino);
+        let value = self.lsm_tree.get(&key.as_bytes().to_vec())?;
+        if value.is_none() {
+            return Err(WondError::Corrupted("gc moved a page of a missing data object"));
+        }
+        let mut data_object: DataObjectValue = archive::decode(value.as_ref().unwrap())?;
+        // a run of pages may hold several extents of the same inode
+        for entry in data_object.entries.iter_mut() {
+            if entry.page_pointer >= o_address && entry.page_pointer < o_address + size {
+                entry.page_pointer = d_address + entry.page_pointer - o_address;
+            }
+        }
+        let value = archive::encode(&data_object);
+        self.lsm_tree.put(&key.as_bytes().to_vec(), &value)
     }
 }
 
 impl KVManager {
-    pub fn read_sb(&mut self) {
-        let data = self.read_page(0, false);
-        let byte1 = (data[0] as u32) << 24;
-        let byte2 = (data[1] as u32) << 16;
-        let byte3 = (data[2] as u32) << 8;
-        let byte4 = data[3] as u32;
-        let len = byte1 | byte2 | byte3 | byte4;
-        let data = data[4..4+len as usize].to_vec();
-        let archived = unsafe { rkyv::archived_root::<super_block::SuperStat>(&data) };
-        let stat: super_block::SuperStat = archived.deserialize(&mut rkyv::Infallible).ok().unwrap();
-        self.super_stat = stat;
-        if self.super_stat.magic_code != super_block::MAGICNUMBER {
-            panic!("SuperStat: build error");
+    // a newer superblock goes on the next page of its block instead of erasing it, the last one
+    // that decodes is current, so a crash leaves either the old or the new
+    pub fn read_sb(&mut self) -> WondResult<()> {
+        let mut stat = None;
+        for address in 0..128 {
+            match super_block::SuperStat::decode(&self.read_page(address, false)?) {
+                Some(next) => stat = Some(next),
+                None => break,
+            }
         }
+        self.super_stat = stat.ok_or(WondError::Corrupted("super block does not decode"))?;
+        Ok(())
+    }
+
+    pub fn write_sb(&mut self) -> WondResult<()> {
+        let mut address = 1;
+        while super_block::SuperStat::decode(&self.read_page(address, false)?).is_some() {
+            address += 1;
+            if address == 128 {
+                return Err(WondError::NoSpace);
+            }
+        }
+        let data = self.super_stat.encode();
+        self.write_page(address, &data, false);
+        let translation_layer = Arc::clone(&self.buf.read().translation_layer);
+        translation_layer.flush()
+    }
+
+    // superblock version 2 gave the log the last blocks of the kv area, the tables on them move first
+    fn migrate_wal(&mut self) -> WondResult<()> {
+        let wal_block_num = wal::WAL_BLOCK_NUM;
+        self.lsm_tree.reserve_wal(self.super_stat.get_kv_offset(), self.super_stat.get_kv_size() as usize, wal_block_num)?;
+        self.super_stat.wal_block_num = wal_block_num as u32;
+        self.write_sb()
     }
 }
 
 impl KVManager {
-    pub fn read_bit(&mut self) {
-        let mut data_1 = self.read_block(1, false);
-        let data_2 = self.read_block(2, false);
+    // the second block of the area is the shadow a write lands in before the first is rewritten
+    pub fn read_bit(&mut self) -> WondResult<()> {
+        let offset = self.super_stat.get_bit_offset();
+        let mut data_1 = self.read_block(offset, false)?;
+        let data_2 = self.read_block(offset + 1, false)?;
         let mut flag = false;
         for i in 0..4 {
             if data_2.get(0)[i] & 0b1111_1111 != 0 {
@@ -165,17 +250,21 @@ impl KVManager {
                 break;
             }
         }
+        // a read-only mount takes the newer copy without moving it
+        if flag && !self.read_only {
+            self.erase_block(offset, false)?;
+            self.write_block(offset, &data_2, false);
+            self.erase_block(offset + 1, false)?;
+        }
         if flag {
-            self.erase_block(1, false);
-            self.write_block(1, &data_2, false);
-            self.erase_block(2, false);
             data_1 = data_2;
         }
         self.set_bit(&data_1);
+        Ok(())
     }
 
     pub fn set_bit(&mut self, data: &array::Array1::<[u8; 4096]>) {
-        let iter = bit::DataRegion::new(&data, 18);
+        let iter = bit::DataRegion::new(&data, self.super_stat.get_main_size());
         for (block_no, segment) in iter {
             let bit_map = segment.used_map;
             self.bit.init_bit_segment(block_no, segment);
@@ -228,6 +317,36 @@ impl KVManager {
         }
     }
 
+    pub fn write_bit(&mut self) -> WondResult<()> {
+        let mut bit = bit::BIT::new();
+        for block_no in 0..self.super_stat.get_main_size() {
+            let mut used_map: u128 = 0;
+            for i in 0..128 {
+                if self.gc.get_page(block_no * 128 + i) != PageUsedStatus::Clean {
+                    used_map |= 1 << (127 - i);
+                }
+            }
+            let info = self.gc.get_block_info(block_no);
+            let segment = bit::BITSegement {
+                used_map,
+                last_erase_time: info.last_erase_time,
+                erase_count: info.erase_count,
+                average_age: info.average_age,
+                reserved: [0; 4],
+            };
+            bit.init_bit_segment(block_no, segment);
+        }
+        self.bit = bit;
+        let data = KVManager::transfer(&self.bit.encode());
+        let offset = self.super_stat.get_bit_offset();
+        self.write_block(offset + 1, &data, false);
+        self.erase_block(offset, false)?;
+        self.write_block(offset, &data, false);
+        self.erase_block(offset + 1, false)?;
+        self.bit.sync();
+        Ok(())
+    }
+
     pub fn bit_begin_op(&mut self) {
         self.bit.begin_op();
     }
@@ -239,23 +358,44 @@ impl KVManager {
 }
 
 impl KVManager {
-    pub fn read_pit(&mut self) {
-        let mut data_1 = self.read_block(3, false);
-        let data_2 = self.read_block(4, false);
+    // the region holds two copies of block_num blocks each, the second one only while it is written
+    pub fn read_pit(&mut self) -> WondResult<()> {
+        let offset = self.super_stat.get_pit_offset();
+        let block_num = self.pit.block_num;
+        let mut blocks_1 = vec![];
+        let mut blocks_2 = vec![];
+        for i in 0..block_num {
+            blocks_1.push(self.read_block(offset + i, false)?);
+            blocks_2.push(self.read_block(offset + block_num + i, false)?);
+        }
         let mut flag = false;
         for i in 0..4 {
-            if data_2.get(0)[i] & 0b1111_1111 != 0 {
+            if blocks_2[0].get(0)[i] & 0b1111_1111 != 0 {
                 flag = true;
                 break;
             }
         }
+        // a read-only mount takes the newer copy without moving it
+        if flag && !self.read_only {
+            for (i, block) in blocks_2.iter().enumerate() {
+                self.erase_block(offset + i as u32, false)?;
+                self.write_block(offset + i as u32, block, false);
+            }
+            for i in 0..block_num {
+                self.erase_block(offset + block_num + i, false)?;
+            }
+        }
         if flag {
-            self.erase_block(3, false);
-            self.write_block(3, &data_2, false);
-            self.erase_block(4, false);
-            data_1 = data_2;
+            blocks_1 = blocks_2;
         }
-        self.set_pit(&data_1);
+        let mut data = array::Array1::<[u8; 4096]>::new(block_num * 128, [0; 4096]);
+        for (i, block) in blocks_1.iter().enumerate() {
+            for (index, page) in block.iter().enumerate() {
+                data.set((i * 128 + index) as u32, page);
+            }
+        }
+        self.set_pit(&data);
+        Ok(())
     }
 
     pub fn set_pit(&mut self, data: &array::Array1::<[u8; 4096]>) {
@@ -263,10 +403,10 @@ impl KVManager {
         if data.get(0)[0] == 0x77 && data.get(0)[1] == 0x77 && data.get(0)[2] == 0xdd && data.get(0)[3] == 0xdd {
             startegy = pit::PITStrategy::Map;
         }
-        if data.get(0)[119] == 0x77 && data.get(0)[120] == 0x77 && data.get(0)[121] == 0xee && data.get(0)[122] == 0xee {
+        if data.get(0)[0] == 0x77 && data.get(0)[1] == 0x77 && data.get(0)[2] == 0xee && data.get(0)[3] == 0xee {
             startegy = pit::PITStrategy::Serial;
         }
-        let iter = pit::DataRegion::new(&data, startegy);
+        let iter = pit::DataRegion::new(data, startegy, self.pit.ino_size);
         for (index, ino) in iter {
             if ino != 0 {
                 self.pit.init_page(index, ino);
@@ -276,7 +416,7 @@ impl KVManager {
         self.pit.set_page_num(self.super_stat.get_main_size() * self.super_stat.get_page_num_per_block());
     }
 
-    pub fn update_pit(&mut self, address: u32, status: u32) {
+    pub fn update_pit(&mut self, address: u32, status: u64) {
         // self.pit.set_page(address, status);
         self.set_page(address, PageUsedStatus::Busy(status));
         self.sync_pit();
@@ -306,6 +446,36 @@ impl KVManager {
         }
     }
 
+    pub fn write_pit(&mut self) -> WondResult<()> {
+        let page_num = self.super_stat.get_main_size() * self.super_stat.get_page_num_per_block();
+        let mut pit = pit::PIT::new();
+        pit.set_page_num(page_num);
+        pit.set_layout(self.pit.ino_size, self.pit.block_num);
+        for address in 0..page_num {
+            if let PageUsedStatus::Busy(ino) = self.gc.get_page(address) {
+                pit.init_page(address, ino);
+            }
+        }
+        self.pit = pit;
+        let data: Vec<_> = self.pit.encode().iter().map(KVManager::transfer).collect();
+        let offset = self.super_stat.get_pit_offset();
+        let block_num = self.pit.block_num;
+        for (i, block) in data.iter().enumerate() {
+            self.write_block(offset + block_num + i as u32, block, false);
+        }
+        for i in 0..block_num {
+            self.erase_block(offset + i, false)?;
+        }
+        for (i, block) in data.iter().enumerate() {
+            self.write_block(offset + i as u32, block, false);
+        }
+        for i in 0..block_num {
+            self.erase_block(offset + block_num + i, false)?;
+        }
+        self.pit.sync();
+        Ok(())
+    }
+
     pub fn pit_begin_op(&mut self) {
         self.pit.begin_op();
     }
@@ -317,8 +487,8 @@ impl KVManager {
 }
 
 impl KVManager {
-    pub fn read_journal(&mut self) {
-        let data = self.read_block(5, false);
+    pub fn read_journal(&mut self) -> WondResult<()> {
+        let data = self.read_block(self.super_stat.get_journal_offset(), false)?;
         let mut flag = false;
         for i in 0..4 {
             if data.get(0)[i] & 0b1111_1111 != 0 {
@@ -326,23 +496,26 @@ impl KVManager {
                 break;
             }
         }
-        if flag {
-            self.set_journal(&data);
+        // every pointer still reaches a live copy until the journal is done, so a read-only
+        // mount leaves the replay to the next writable one
+        if flag && !self.read_only {
+            self.set_journal(&data)?;
         }
+        Ok(())
     }
 
-    pub fn set_journal(&mut self, data: &array::Array1::<[u8; 4096]>) {
+    pub fn set_journal(&mut self, data: &array::Array1::<[u8; 4096]>) -> WondResult<()> {
         let byte_1 = (data.get(0)[4] as u32) << 24;
         let byte_2 = (data.get(0)[5] as u32) << 16;
         let byte_3 = (data.get(0)[6] as u32) << 8;
         let byte_4 = data.get(0)[7] as u32;
         let erase_block_no = byte_1 + byte_2 + byte_3 + byte_4;
         self.journal.set_erase_block_no(erase_block_no);
-        let iter = journal::DataRegion::new(&data);
-        for (o_address, address) in iter {
-            self.journal.set_journal(o_address, address);
+        let iter = journal::DataRegion::new(data, self.journal.ino_size);
+        for (o_address, address, size, ino) in iter {
+            self.journal.set_journal(o_address, address, size, ino);
         }
-        self.do_journal();
+        self.do_journal()
     }
 
     pub fn update_journal(&mut self, gc_group: &GCEventGroup) {
@@ -352,110 +525,96 @@ impl KVManager {
                     self.journal.set_erase_block_no(event.block_no);
                 },
                 GCEvent::Move(event) => {
-                    self.journal.set_journal(event.o_address, event.d_address);
+                    self.journal.set_journal(event.o_address, event.d_address, event.size, event.ino);
                 },
                 GCEvent::None => (),
             }
         }
     }
 
-    pub fn sync_journal(&mut self) {
+    // the moves must not reach the disk ahead of the journal that undoes a crash among them
+    pub fn sync_journal(&mut self) -> WondResult<()> {
         if self.journal.need_sync() {
             let data = self.journal.encode();
-            self.write_block(5, &KVManager::transfer(&data), false);
+            let offset = self.super_stat.get_journal_offset();
+            self.write_block(offset, &KVManager::transfer(&data), false);
+            self.buf.read().translation_layer.flush()?;
             self.journal.sync();
         }
+        Ok(())
     }
 
-    pub fn clear_journal(&mut self) {
+    pub fn clear_journal(&mut self) -> WondResult<()> {
         self.journal.clear();
-        self.erase_block(5, false);
+        self.erase_block(self.super_stat.get_journal_offset(), false)
     }
 
-    pub fn do_journal(&mut self) {
+    pub fn do_journal(&mut self) -> WondResult<()> {
         self.bit_begin_op();
         self.pit_begin_op();
-        let block_no = self.journal.get_erase_block_no();
-        let start_index = block_no * 128;
-        let end_index = (block_no + 1) * 128;
-        for i in start_index..end_index {
-            self.update_bit(i, false);
-            self.clean_pit(i);
-        }
-        for entry in &self.journal.table.clone() {
-            let o_address = *entry.0;
-            let d_address = *entry.1;
-            let ino = self.pit.get_page(o_address);
-            let data = self.read_page(o_address, true);
-            self.write_page(d_address, &data, true);
-            self.dirty_pit(o_address);
-            self.update_bit(d_address, true);
-            self.update_pit(d_address, ino);
-            let key = format!("d:{}", ino);
-            let value = self.lsm_tree.get(&key.as_bytes().to_vec());
-            if value.is_none() {
-                panic!();
-            }
-            let archived = unsafe { rkyv::archived_root::<DataObjectValue>(value.as_ref().unwrap()) };
-            let mut data_object: DataObjectValue = archived.deserialize(&mut rkyv::Infallible).unwrap();
-            for entry in data_object.entries.iter_mut() {
-                if entry.page_pointer == o_address {
-                    entry.page_pointer = d_address;
-                    break;
-                }
-            }
-            let mut serializer = AllocSerializer::<0>::default();
-            serializer.serialize_value(&data_object).unwrap();
-            let value = serializer.into_serializer().into_inner().to_vec();
-            self.lsm_tree.put(&key.as_bytes().to_vec(), &value);
+        for (o_address, (d_address, size, ino)) in self.journal.table.clone() {
+            self.move_pages(ino, o_address, d_address, size)?;
         }
+        let block_no = self.journal.get_erase_block_no();
+        self.erase_block(block_no, true)?;
+        self.erase_block_in_block_table(block_no);
         self.bit_end_op();
         self.pit_end_op();
-        self.erase_block(block_no, true);
-        self.erase_block_in_block_table(block_no);
-        self.clear_journal();
+        self.finish_journal()
+    }
+
+    // the journal stays until BIT and PIT on disk agree with the moves it describes
+    pub fn finish_journal(&mut self) -> WondResult<()> {
+        self.write_bit()?;
+        self.write_pit()?;
+        self.buf.read().translation_layer.flush()?;
+        self.clear_journal()
     }
 
     pub fn journal_begin_op(&mut self) {
         self.journal.begin_op();
     }
 
-    pub fn journal_end_op(&mut self) {
+    pub fn journal_end_op(&mut self) -> WondResult<()> {
         self.journal.end_op();
-        self.sync_journal();
+        self.sync_journal()
     }
 }
 
 impl KVManager {
-    pub fn read_page(&mut self, address: u32, is_main: bool) -> [u8; 4096] {
+    pub fn main_offset(&self) -> u32 {
+        self.super_stat.get_main_offset() * self.super_stat.get_page_num_per_block()
+    }
+
+    pub fn read_page(&mut self, address: u32, is_main: bool) -> WondResult<[u8; 4096]> {
         if is_main {
-            self.buf.write().read(0, address + 105 * 128)
+            self.buf.write().read(0, address + self.main_offset())
         } else {
             self.buf.write().read(0, address)
         }
     }
 
-    pub fn read_page_advanced(&mut self, address: u32, is_main: bool, buf: &mut[u8]) {
+    pub fn read_page_advanced(&mut self, address: u32, is_main: bool, buf: &mut[u8]) -> WondResult<()> {
         if is_main {
-            self.buf.write().read_advanced(0, address + 105 * 128, buf);
+            self.buf.write().read_advanced(0, address + self.main_offset(), buf)
         } else {
-            self.buf.write().read_advanced(0, address, buf);
+            self.buf.write().read_advanced(0, address, buf)
         }
     }
 
-    pub fn read_block(&mut self, block_no: u32, is_main: bool) -> array::Array1::<[u8; 4096]> {
+    pub fn read_block(&mut self, block_no: u32, is_main: bool) -> WondResult<array::Array1::<[u8; 4096]>> {
         let address = block_no * 128;
         let mut data = array::Array1::<[u8; 4096]>::new(128, [0; 4096]);
         for index in 0..128 {
-            let page = self.read_page(address + index, is_main);
+            let page = self.read_page(address + index, is_main)?;
             data.set(index, page);
         }
-        data
+        Ok(data)
     }
 
     pub fn write_page(&mut self, address: u32, data: &[u8; 4096], is_main: bool) {
         if is_main {
-            self.buf.write().write(0, address + 105 * 128, &data);
+            self.buf.write().write(0, address + self.main_offset(), &data);
         } else {
             self.buf.write().write(0, address, &data);
         }
@@ -471,11 +630,11 @@ impl KVManager {
         }
     }
 
-    pub fn erase_block(&mut self, block_no: u32, is_main: bool) {
+    pub fn erase_block(&mut self, block_no: u32, is_main: bool) -> WondResult<()> {
         if is_main {
-            self.buf.write().erase(0, block_no + 105);
+            self.buf.write().erase(0, block_no + self.super_stat.get_main_offset())
         } else {
-            self.buf.write().erase(0, block_no);
+            self.buf.write().erase(0, block_no)
         }
     }
 }
@@ -1,17 +1,17 @@
 extern crate alloc;
 use spin::RwLock;
 use alloc::sync::Arc;
-use std::cmp::max;
 use std::cmp::min;
 use crate::buf;
 use crate::compress::compress;
+use crate::error::{WondError, WondResult};
 use super::gc::gc_manager;
 use super::component::bit;
 use super::component::pit;
 use super::component::journal;
 use super::component::super_block;
 use super::lsm_tree::lsm_tree;
-use rkyv::ser::{Serializer, serializers::AllocSerializer};
+use crate::util::archive::{self, Versioned};
 use rkyv::{Archive, Deserialize, Serialize};
 
 pub enum KVOperationsObject {
@@ -21,20 +21,99 @@ pub enum KVOperationsObject {
 }
 
 #[derive(Archive, Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[archive(check_bytes)]
 pub struct DataObjectValueEntry {
-    pub len: usize,
-    pub archived_len: usize,
-    pub offset: usize,
+    pub len: u64,
+    pub archived_len: u64,
+    pub offset: u64,
     pub page_pointer: u32,
     pub compress_type: u8,
 }
 
+// page_pointer of an unwritten range, it owns no pages and reads back as zeros
+pub const HOLE_POINTER: u32 = u32::MAX;
+
+impl DataObjectValueEntry {
+    pub fn hole(offset: u64, len: u64) -> DataObjectValueEntry {
+        DataObjectValueEntry {
+            len,
+            archived_len: 0,
+            offset,
+            page_pointer: HOLE_POINTER,
+            compress_type: 0,
+        }
+    }
+
+    pub fn is_hole(&self) -> bool {
+        self.page_pointer == HOLE_POINTER
+    }
+
+    pub fn page_num(&self) -> u32 {
+        if self.is_hole() {
+            return 0;
+        }
+        ((self.archived_len - 1) / 4096 + 1) as u32
+    }
+}
+
 #[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(check_bytes)]
 pub struct DataObjectValue {
-    pub size: usize,
+    pub size: u64,
     pub entries: Vec<DataObjectValueEntry>,
 }
 
+// version 2 brought holes, version 3 widened sizes and offsets to 64 bits
+impl Versioned for DataObjectValue {
+    const KIND: u8 = archive::KIND_DATA_OBJECT;
+    const VERSION: u8 = 3;
+}
+
+// layout of versions 1 and 2, usize archives as 32 bits
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[archive(check_bytes)]
+pub struct DataObjectValueEntryV2 {
+    pub len: u32,
+    pub archived_len: u32,
+    pub offset: u32,
+    pub page_pointer: u32,
+    pub compress_type: u8,
+}
+
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(check_bytes)]
+pub struct DataObjectValueV2 {
+    pub size: u32,
+    pub entries: Vec<DataObjectValueEntryV2>,
+}
+
+impl From<DataObjectValueV2> for DataObjectValue {
+    fn from(old: DataObjectValueV2) -> Self {
+        DataObjectValue {
+            size: old.size as u64,
+            entries: old.entries.iter().map(|entry| DataObjectValueEntry {
+                len: entry.len as u64,
+                archived_len: entry.archived_len as u64,
+                offset: entry.offset as u64,
+                page_pointer: entry.page_pointer,
+                compress_type: entry.compress_type,
+            }).collect(),
+        }
+    }
+}
+
+impl DataObjectValue {
+    // objects from before version 3 are widened on read and written back in the new layout
+    // with the next change
+    pub fn decode(data: &[u8]) -> WondResult<DataObjectValue> {
+        let (payload, version) = archive::split_tag::<DataObjectValue>(data);
+        if version.unwrap_or(1) < 3 {
+            return Ok(archive::check::<DataObjectValueV2>(payload)?.into());
+        }
+        archive::decode(data)
+    }
+}
+
 pub struct KVManager {
     pub bit: bit::BIT,
     pub pit: pit::PIT,
@@ -44,84 +123,58 @@ pub struct KVManager {
     pub buf: Arc<RwLock<buf::BufCache>>,
     pub lsm_tree: lsm_tree::LSMTree,
     pub compress_manager: compress::CompressManager,
+    // set before mount, a read-only mount leaves the image as it found it
+    pub read_only: bool,
 }
 
 impl KVManager {
-    pub fn get(&mut self, key: &String, off: usize, len: usize) -> Option<Vec<u8>> {
-        let operation_type = KVManager::parse_key(key);
+    pub fn get(&mut self, key: &String, off: usize, len: usize) -> WondResult<Option<Vec<u8>>> {
+        let operation_type = KVManager::parse_key(key)?;
         match operation_type {
             KVOperationsObject::MetaObject => {
-                let value = self.lsm_tree.get(&key.as_bytes().to_vec());
+                let value = self.lsm_tree.get(&key.as_bytes().to_vec())?;
                 if value.is_none() {
-                    return None;
+                    return Ok(None);
                 }
                 if len != 0 {
-                    Some(value.unwrap()[off..off+len].to_vec())
+                    Ok(Some(value.unwrap()[off..off+len].to_vec()))
                 } else {
-                    value
+                    Ok(value)
                 }
             },
             KVOperationsObject::DataObject => {
-                let value = self.lsm_tree.get(&key.as_bytes().to_vec());
+                let value = self.lsm_tree.get(&key.as_bytes().to_vec())?;
                 if value.is_none() {
-                    return None;
+                    return Ok(None);
                 }
-                let archived = unsafe { rkyv::archived_root::<DataObjectValue>(value.as_ref().unwrap()) };
-                let mut data_object: DataObjectValue = archived.deserialize(&mut rkyv::Infallible).unwrap();
+                let data_object = DataObjectValue::decode(value.as_ref().unwrap())?;
                 if len != 0 {
-                    if off + len > data_object.size {
-                        return Some(self.read_data_object_all(&mut data_object)[off..].to_vec());
-                    }
-                    let mut index = 0;
-                    for (i, entry) in data_object.entries.iter().enumerate() {
-                        if off < entry.offset {
-                            index = i - 1;
-                            break;
-                        }
-                        if i == data_object.entries.len() - 1 {
-                            index = data_object.entries.len() - 1;
-                        }
-                    }
-                    let mut result = vec![];
-                    let mut remain_num = len;
-                    let data = self.read_data_object_entry(&data_object.entries[index]);
-                    let read_num = min(data.len(), remain_num);
-                    result.append(&mut data[off - data_object.entries[index].offset..off - data_object.entries[index].offset+read_num].to_vec());
-                    remain_num -= read_num;
-                    index += 1;
-                    while remain_num != 0 {
-                        let data = self.read_data_object_entry(&data_object.entries[index]);
-                        let read_num = min(data.len(), remain_num);
-                        result.append(&mut data[..read_num].to_vec());
-                        remain_num -= read_num;
-                        index += 1;
-                    }
-                    Some(result)
+                    Ok(Some(self.read_data_object_range(&data_object, off as u64, len as u64)?))
                 } else {
-                    Some(self.read_data_object_all(&mut data_object))
+                    Ok(Some(self.read_data_object_all(&data_object)?))
                 }
             },
             KVOperationsObject::ExtraObject => {
-                let value = self.lsm_tree.get(&key.as_bytes().to_vec());
+                let value = self.lsm_tree.get(&key.as_bytes().to_vec())?;
                 if value.is_none() {
-                    return None;
+                    return Ok(None);
                 }
                 if len != 0 {
-                    Some(value.unwrap()[off..off+len].to_vec())
+                    Ok(Some(value.unwrap()[off..off+len].to_vec()))
                 } else {
-                    value
+                    Ok(value)
                 }
             },
         }
     }
 
-    pub fn set(&mut self, key: &String, off: usize, len: usize, value: &Vec<u8>, extra_info: u32) -> Option<usize> {
-        let operation_type = KVManager::parse_key(key);
+    pub fn set(&mut self, key: &String, off: usize, len: usize, value: &Vec<u8>, extra_info: u64) -> WondResult<Option<u64>> {
+        let operation_type = KVManager::parse_key(key)?;
         match operation_type {
             KVOperationsObject::MetaObject => {
-                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec());
+                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec())?;
                 if pre_value.is_none() || len == 0 {
-                    self.lsm_tree.put(&key.as_bytes().to_vec(), value);
+                    self.lsm_tree.put(&key.as_bytes().to_vec(), value)?;
                 } else {
                     let mut pre_value = pre_value.unwrap();
                     if pre_value.len() >= off + len {
@@ -130,12 +183,12 @@ impl KVManager {
                         pre_value.truncate(off);
                         pre_value.append(&mut value.clone());
                     }
-                    self.lsm_tree.put(&key.as_bytes().to_vec(), &pre_value);
+                    self.lsm_tree.put(&key.as_bytes().to_vec(), &pre_value)?;
                 }
-                None
+                Ok(None)
             },
             KVOperationsObject::DataObject => {
-                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec());
+                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec())?;
                 let mut data_object;
                 if pre_value.is_none() {
                     data_object = DataObjectValue {
@@ -143,23 +196,20 @@ impl KVManager {
                         entries: vec![],
                     };
                 } else {
-                    let archived = unsafe { rkyv::archived_root::<DataObjectValue>(pre_value.as_ref().unwrap()) };
-                    data_object = archived.deserialize(&mut rkyv::Infallible).unwrap();
+                    data_object = DataObjectValue::decode(pre_value.as_ref().unwrap())?;
                 }
                 if len == 0 {
                     self.recycle_data_obect_all(&mut data_object);
                 }
-                self.set_data_object(&mut data_object, off, len, value, extra_info);
-                let mut serializer = AllocSerializer::<0>::default();
-                serializer.serialize_value(&data_object).unwrap();
-                let value = serializer.into_serializer().into_inner().to_vec();
-                self.lsm_tree.put(&key.as_bytes().to_vec(), &value);
-                Some(data_object.size)
+                self.set_data_object(&mut data_object, off as u64, len as u64, value, extra_info)?;
+                let value = archive::encode(&data_object);
+                self.lsm_tree.put(&key.as_bytes().to_vec(), &value)?;
+                Ok(Some(data_object.size))
             },
             KVOperationsObject::ExtraObject => {
-                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec());
+                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec())?;
                 if pre_value.is_none() || len == 0 {
-                    self.lsm_tree.put(&key.as_bytes().to_vec(), value);
+                    self.lsm_tree.put(&key.as_bytes().to_vec(), value)?;
                 } else {
                     let mut pre_value = pre_value.unwrap();
                     if pre_value.len() >= off + len {
@@ -168,20 +218,20 @@ impl KVManager {
                         pre_value.truncate(off);
                         pre_value.append(&mut value.clone());
                     }
-                    self.lsm_tree.put(&key.as_bytes().to_vec(), &pre_value);
+                    self.lsm_tree.put(&key.as_bytes().to_vec(), &pre_value)?;
                 }
-                None
+                Ok(None)
             },
         }
     }
 
-    pub fn delete(&mut self, key: &String, off: usize, len: usize, extra_info: u32) -> Option<usize> {
-        let operation_type = KVManager::parse_key(key);
+    pub fn delete(&mut self, key: &String, off: usize, len: usize, extra_info: u64) -> WondResult<Option<u64>> {
+        let operation_type = KVManager::parse_key(key)?;
         match operation_type {
             KVOperationsObject::MetaObject => {
-                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec());
+                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec())?;
                 if pre_value.is_none() {
-                    return None;
+                    return Ok(None);
                 }
                 if len != 0 {
                     let mut pre_value = pre_value.unwrap();
@@ -192,37 +242,36 @@ impl KVManager {
                     } else {
                         pre_value.truncate(off);
                     }
-                    self.lsm_tree.put(&key.as_bytes().to_vec(), &pre_value);
+                    self.lsm_tree.put(&key.as_bytes().to_vec(), &pre_value)?;
                 } else {
-                    self.lsm_tree.delete(&key.as_bytes().to_vec());
+                    self.lsm_tree.delete(&key.as_bytes().to_vec())?;
                 }
-                None
+                Ok(None)
             },
             KVOperationsObject::DataObject => {
-                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec());
+                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec())?;
                 if pre_value.is_none() {
-                    return Some(0);
+                    return Ok(Some(0));
                 }
-                let archived = unsafe { rkyv::archived_root::<DataObjectValue>(pre_value.as_ref().unwrap()) };
-                let mut data_object = archived.deserialize(&mut rkyv::Infallible).unwrap();
+                let mut data_object = DataObjectValue::decode(pre_value.as_ref().unwrap())?;
                 // let mut data_object: DataObjectValue = serde_json::from_slice(&pre_value.unwrap()).unwrap();
-                self.delete_data_object(&mut data_object, off, len, extra_info);
                 if len != 0 {
-                    let mut serializer = AllocSerializer::<0>::default();
-                    serializer.serialize_value(&data_object).unwrap();
-                    let value = serializer.into_serializer().into_inner().to_vec();
+                    self.delete_data_object(&mut data_object, off as u64, len as u64, extra_info)?;
+                    let value = archive::encode(&data_object);
                     // let value = serde_json::to_vec(&data_object).ok().unwrap();
-                    self.lsm_tree.put(&key.as_bytes().to_vec(), &value);
-                    Some(data_object.size)
+                    self.lsm_tree.put(&key.as_bytes().to_vec(), &value)?;
+                    Ok(Some(data_object.size))
                 } else {
-                    self.lsm_tree.delete(&key.as_bytes().to_vec());
-                    Some(0)
+                    // dropping the whole object hands its pages back to gc
+                    self.recycle_data_obect_all(&mut data_object);
+                    self.lsm_tree.delete(&key.as_bytes().to_vec())?;
+                    Ok(Some(0))
                 }
             },
             KVOperationsObject::ExtraObject => {
-                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec());
+                let pre_value = self.lsm_tree.get(&key.as_bytes().to_vec())?;
                 if pre_value.is_none() {
-                    return None;
+                    return Ok(None);
                 }
                 if len != 0 {
                     let mut pre_value = pre_value.unwrap();
@@ -233,318 +282,321 @@ impl KVManager {
                     } else {
                         pre_value.truncate(off);
                     }
-                    self.lsm_tree.put(&key.as_bytes().to_vec(), &pre_value);
+                    self.lsm_tree.put(&key.as_bytes().to_vec(), &pre_value)?;
                 } else {
-                    self.lsm_tree.delete(&key.as_bytes().to_vec());
+                    self.lsm_tree.delete(&key.as_bytes().to_vec())?;
                 }
-                None
+                Ok(None)
             },
         }
     }
 
-    pub fn parse_key(key: &String) -> KVOperationsObject {
-        match &key[0..2] {
-            "m:" => KVOperationsObject::MetaObject,
-            "d:" => KVOperationsObject::DataObject,
-            "e:" => KVOperationsObject::ExtraObject,
-            _ => panic!(),
+    // sets the size of a data object, growing leaves a hole and shrinking cuts the tail off
+    pub fn resize(&mut self, key: &String, size: u64, extra_info: u64) -> WondResult<u64> {
+        let mut data_object = self.load_data_object(key)?;
+        if size < data_object.size {
+            let len = data_object.size - size;
+            self.delete_data_object(&mut data_object, size, len, extra_info)?;
+        } else {
+            self.extend_data_object(&mut data_object, size, extra_info)?;
+        }
+        self.lsm_tree.put(&key.as_bytes().to_vec(), &archive::encode(&data_object))?;
+        Ok(data_object.size)
+    }
+
+    // turns a range of a data object into a hole, the size stays as it is
+    pub fn punch(&mut self, key: &String, off: u64, len: u64, extra_info: u64) -> WondResult<()> {
+        let mut data_object = self.load_data_object(key)?;
+        self.punch_data_object(&mut data_object, off, len, extra_info)?;
+        self.lsm_tree.put(&key.as_bytes().to_vec(), &archive::encode(&data_object))
+    }
+
+    pub fn data_ranges(&mut self, key: &String) -> WondResult<Vec<(u64, u64)>> {
+        Ok(KVManager::data_object_ranges(&self.load_data_object(key)?))
+    }
+
+    fn load_data_object(&mut self, key: &String) -> WondResult<DataObjectValue> {
+        match self.lsm_tree.get(&key.as_bytes().to_vec())? {
+            Some(value) => DataObjectValue::decode(&value),
+            None => Ok(DataObjectValue {
+                size: 0,
+                entries: vec![],
+            }),
+        }
+    }
+
+    // raw values of every live key with start <= key < end, data objects come back encoded
+    pub fn scan_range(&mut self, start: &str, end: Option<&str>) -> WondResult<Vec<(String, Vec<u8>)>> {
+        let iter = self.lsm_tree.scan_range(start.as_bytes(), end.map(|end| end.as_bytes()))?;
+        KVManager::collect_scan(iter)
+    }
+
+    pub fn scan(&mut self, prefix: &str) -> WondResult<Vec<(String, Vec<u8>)>> {
+        let iter = self.lsm_tree.scan(prefix.as_bytes())?;
+        KVManager::collect_scan(iter)
+    }
+
+    fn collect_scan(iter: impl Iterator<Item = (Vec<u8>, Vec<u8>)>) -> WondResult<Vec<(String, Vec<u8>)>> {
+        let mut entries = vec![];
+        for (key, value) in iter {
+            let key = String::from_utf8(key).map_err(|_| WondError::Corrupted("kv key is not utf-8"))?;
+            entries.push((key, value));
+        }
+        Ok(entries)
+    }
+
+    pub fn parse_key(key: &str) -> WondResult<KVOperationsObject> {
+        match key.get(0..2) {
+            Some("m:") => Ok(KVOperationsObject::MetaObject),
+            Some("d:") => Ok(KVOperationsObject::DataObject),
+            Some("e:") => Ok(KVOperationsObject::ExtraObject),
+            _ => Err(WondError::Unsupported("key has no object prefix")),
         }
     }
 }
 
 impl KVManager {
-    pub fn set_data_object(&mut self, object: &mut DataObjectValue, off: usize, len: usize, value: &Vec<u8>, ino: u32) {
+    // a write past the end first fills the gap with a hole
+    pub fn set_data_object(&mut self, object: &mut DataObjectValue, off: u64, len: u64, value: &[u8], ino: u64) -> WondResult<()> {
         if off > object.size {
-            return;
+            let hole = DataObjectValueEntry::hole(object.size, off - object.size);
+            self.place_data_object_entry(object, hole, ino)?;
         }
-        let (value, compress_type) = self.compress_manager.encode(value);
-        let size = (value.len() - 1) / 4096 + 1;
-        let page_pointer = self.find_write_pos(size);
-        let new_entry = DataObjectValueEntry {
-            len,
-            offset: off,
-            page_pointer,
-            compress_type,
-            archived_len: value.len(),
-        };
-        for i in 0..size {
-            let start_index = 4096 * i;
-            let end_index = 4096 * (i + 1);
-            if i == size - 1 {
-                let mut data = value[start_index..].to_vec();
-                data.extend(vec![10; 4096 - data.len()]);
-                self.write_page(page_pointer + i as u32, &self.trans(data), true);
-            } else {
-                self.write_page(page_pointer + i as u32, &self.trans(value[start_index..end_index].to_vec()), true);
-            }
-            self.update_bit(page_pointer + i as u32, true);
-            self.update_pit(page_pointer + i as u32, ino);
+        let new_entry = self.write_data_object_entry(off, len, value, ino)?;
+        self.place_data_object_entry(object, new_entry, ino)
+    }
+
+    // grows the object with a hole, shrinking goes through delete_data_object
+    pub fn extend_data_object(&mut self, object: &mut DataObjectValue, size: u64, ino: u64) -> WondResult<()> {
+        if size <= object.size {
+            return Ok(());
         }
-        let mut remove_list = vec![];
-        let mut insert_index = -1;
-        let mut first_entry = None;
-        let mut second_entry = None;
-        for (index, entry) in object.entries.clone().iter().enumerate() {
-            if entry.offset + entry.len <= new_entry.offset {
+        let hole = DataObjectValueEntry::hole(object.size, size - object.size);
+        self.place_data_object_entry(object, hole, ino)
+    }
+
+    // turns a range inside the object into a hole and hands its pages back to gc
+    pub fn punch_data_object(&mut self, object: &mut DataObjectValue, off: u64, len: u64, ino: u64) -> WondResult<()> {
+        let len = len.min(object.size.saturating_sub(off));
+        if len == 0 {
+            return Ok(());
+        }
+        self.place_data_object_entry(object, DataObjectValueEntry::hole(off, len), ino)
+    }
+
+    // cuts the range out and moves everything after it down
+    pub fn delete_data_object(&mut self, object: &mut DataObjectValue, off: u64, len: u64, ino: u64) -> WondResult<()> {
+        if off >= object.size {
+            return Ok(());
+        }
+        let mut entries = vec![];
+        for entry in object.entries.clone() {
+            if entry.offset + entry.len <= off {
+                entries.push(entry);
                 continue;
-            } else if entry.offset >= new_entry.offset + new_entry.len {
+            } else if entry.offset >= off + len {
+                entries.push(DataObjectValueEntry {
+                    offset: entry.offset - len,
+                    ..entry
+                });
                 continue;
             }
-            let valid_prev = max(0, new_entry.offset as i32 - entry.offset as i32) as usize;
-            let valid_suffix = max(0, entry.offset as i32 + entry.len as i32 - new_entry.offset as i32 - new_entry.len as i32) as usize;
-            remove_list.push(object.entries[index].page_pointer);
-            if insert_index == -1 {
-                insert_index = index as i32;
-            }
+            let valid_prev = off.saturating_sub(entry.offset);
+            let valid_suffix = (entry.offset + entry.len).saturating_sub(off + len);
+            let mut released = false;
             if valid_prev != 0 {
-                let data = self.read_data_object_entry(entry);
-                let data = data[..valid_prev].to_vec();
-                let (data, compress_type) = self.compress_manager.encode(&data);
-                let size = (data.len() - 1) / 4096 + 1;
-                let page_pointer = self.find_write_pos(size);
-                let new_entry = DataObjectValueEntry {
-                    len: valid_prev,
-                    offset: entry.offset,
-                    page_pointer,
-                    compress_type,
-                    archived_len: data.len(),
-                };
-                for i in 0..size {
-                    let start_index = 4096 * i;
-                    let end_index = 4096 * (i + 1);
-                    if i == size - 1 {
-                        let mut data = data[start_index..].to_vec();
-                        data.extend(vec![10; 4096 - data.len()]);
-                        self.write_page(page_pointer + i as u32, &self.trans(data), true);
-                    } else {
-                        self.write_page(page_pointer + i as u32, &self.trans(data[start_index..end_index].to_vec()), true);
+                // an uncompressed extent keeps its first pages and hands the rest back
+                if entry.compress_type == 0 && !entry.is_hole() {
+                    let o_size = (valid_prev - 1) / 4096 + 1;
+                    for i in o_size as u32..entry.page_num() {
+                        self.dirty_pit(entry.page_pointer + i);
                     }
-                    self.update_bit(page_pointer + i as u32, true);
-                    self.update_pit(page_pointer + i as u32, ino);
+                    entries.push(DataObjectValueEntry {
+                        len: valid_prev,
+                        archived_len: valid_prev,
+                        ..entry
+                    });
+                    released = true;
+                } else {
+                    entries.push(self.split_data_object_entry(&entry, 0, valid_prev, ino)?);
                 }
-                first_entry = Some(new_entry);
             }
-            if valid_suffix > 0 {
-                let data = self.read_data_object_entry(entry);
-                let data = data[data.len()-valid_suffix..].to_vec();
-                let (data, compress_type) = self.compress_manager.encode(&data);
-                let size = (data.len() - 1) / 4096 + 1;
-                let page_pointer = self.find_write_pos(size);
-                let new_entry = DataObjectValueEntry {
-                    len: valid_suffix,
-                    offset: entry.offset + entry.len - valid_suffix,
-                    page_pointer,
-                    compress_type,
-                    archived_len: data.len(),
-                };
-                for i in 0..size {
-                    let start_index = 4096 * i;
-                    let end_index = 4096 * (i + 1);
-                    if i == size - 1 {
-                        let mut data = data[start_index..].to_vec();
-                        data.extend(vec![10; 4096 - data.len()]);
-                        self.write_page(page_pointer + i as u32, &self.trans(data), true);
-                    } else {
-                        self.write_page(page_pointer + i as u32, &self.trans(data[start_index..end_index].to_vec()), true);
-                    }
-                    self.update_bit(page_pointer + i as u32, true);
-                    self.update_pit(page_pointer + i as u32, ino);
-                }
-                second_entry = Some(new_entry);
+            if valid_suffix != 0 {
+                let suffix = self.split_data_object_entry(&entry, entry.len - valid_suffix, valid_suffix, ino)?;
+                entries.push(DataObjectValueEntry {
+                    offset: off,
+                    ..suffix
+                });
             }
-            let size = (entry.archived_len - 1) / 4096 + 1;
-            for i in 0..size as u32 {
-                self.dirty_pit(entry.page_pointer + i);
+            if !released {
+                self.release_data_object_entry(&entry);
             }
         }
-        for pointer in remove_list.iter() {
-            for i in 0..object.entries.len() {
-                if object.entries[i].page_pointer == *pointer {
-                    object.entries.remove(i);
-                    break;
-                }
+        object.entries = entries;
+        object.size = object.entries.iter().map(|entry| entry.len).sum();
+        Ok(())
+    }
+
+    // data ranges of the object in order, holes left out and touching extents joined
+    pub fn data_object_ranges(object: &DataObjectValue) -> Vec<(u64, u64)> {
+        let mut ranges: Vec<(u64, u64)> = vec![];
+        for entry in object.entries.iter().filter(|entry| !entry.is_hole()) {
+            match ranges.last_mut() {
+                Some(range) if range.0 + range.1 == entry.offset => range.1 += entry.len,
+                _ => ranges.push((entry.offset, entry.len)),
             }
         }
-        if insert_index == -1 {
-            insert_index = object.entries.len() as i32;
-        }
-        if first_entry.is_some() {
-            object.entries.insert(insert_index as usize, first_entry.unwrap());
-            insert_index += 1;
+        ranges
+    }
+
+    // puts new_entry in place, cutting down every extent it overlaps
+    fn place_data_object_entry(&mut self, object: &mut DataObjectValue, new_entry: DataObjectValueEntry, ino: u64) -> WondResult<()> {
+        let mut entries = vec![];
+        let mut placed = false;
+        for entry in object.entries.clone() {
+            if entry.offset + entry.len <= new_entry.offset {
+                entries.push(entry);
+                continue;
+            } else if entry.offset >= new_entry.offset + new_entry.len {
+                if !placed {
+                    entries.push(new_entry);
+                    placed = true;
+                }
+                entries.push(entry);
+                continue;
+            }
+            let valid_prev = new_entry.offset.saturating_sub(entry.offset);
+            let valid_suffix = (entry.offset + entry.len).saturating_sub(new_entry.offset + new_entry.len);
+            if valid_prev != 0 {
+                entries.push(self.split_data_object_entry(&entry, 0, valid_prev, ino)?);
+            }
+            if !placed {
+                entries.push(new_entry);
+                placed = true;
+            }
+            if valid_suffix != 0 {
+                entries.push(self.split_data_object_entry(&entry, entry.len - valid_suffix, valid_suffix, ino)?);
+            }
+            self.release_data_object_entry(&entry);
         }
-        object.entries.insert(insert_index as usize, new_entry);
-        if second_entry.is_some() {
-            object.entries.insert(insert_index as usize + 1, second_entry.unwrap());
+        if !placed {
+            entries.push(new_entry);
         }
-        let mut len = 0;
-        for entry in object.entries.iter() {
-            len += entry.len;
+        // punching next to a hole grows it instead of leaving a row of them
+        object.entries.clear();
+        for entry in entries {
+            match object.entries.last_mut() {
+                Some(last) if last.is_hole() && entry.is_hole() => last.len += entry.len,
+                _ => object.entries.push(entry),
+            }
         }
-        object.size = len;
+        object.size = object.entries.iter().map(|entry| entry.len).sum();
+        Ok(())
     }
 
-    pub fn delete_data_object(&mut self, object: &mut DataObjectValue, off: usize, len: usize, ino: u32) {
-        if off >= object.size {
-            return;
-        }
-        let mut remove_list = vec![];
-        let mut insert_index = -1;
-        let mut first_entry = None;
-        let mut second_entry = None;
-        for (index, entry) in object.entries.clone().iter().enumerate() {
-            if entry.offset + entry.len <= off {
-                continue
-            } else if entry.offset >= off + len {
-                object.entries[index].offset = entry.offset - len;
-                continue
+    fn write_data_object_entry(&mut self, offset: u64, len: u64, value: &[u8], ino: u64) -> WondResult<DataObjectValueEntry> {
+        let (value, compress_type) = self.compress_manager.encode(value);
+        let size = (value.len() - 1) / 4096 + 1;
+        let page_pointer = self.find_write_pos(size)?;
+        for i in 0..size {
+            let start_index = 4096 * i;
+            let end_index = 4096 * (i + 1);
+            if i == size - 1 {
+                let mut data = value[start_index..].to_vec();
+                data.extend(vec![10; 4096 - data.len()]);
+                self.write_page(page_pointer + i as u32, &self.trans(data), true);
             } else {
-                let valid_prev = max(0, off as i32 - entry.offset as i32) as usize;
-                let valid_suffix = max(0, entry.offset as i32 + entry.len as i32 - off as i32 - len as i32) as usize;
-                if entry.compress_type == 0 {
-                    if valid_prev == 0 {
-                        let size = (entry.len - 1) / 4096 + 1;
-                        for i in 0..size as u32 {
-                            self.dirty_pit(entry.page_pointer + i);
-                        }
-                        remove_list.push(object.entries[index].page_pointer);
-                        if insert_index == -1 {
-                            insert_index = index as i32;
-                        }
-                    } else {
-                        let size = (entry.len - 1) / 4096 + 1;
-                        let o_size = (valid_prev - 1) / 4096 + 1;
-                        for i in o_size as u32..size as u32 {
-                            self.dirty_pit(entry.page_pointer + i);
-                        }
-                        object.entries[index].len = valid_prev;
-                        if insert_index == -1 {
-                            insert_index = (index + 1) as i32;
-                        }
-                    }
-                } else {
-                    remove_list.push(object.entries[index].page_pointer);
-                    if insert_index == -1 {
-                        insert_index = index as i32;
-                    }
-                    if valid_prev != 0 {
-                        let data = self.read_data_object_entry(entry);
-                        let data = data[..valid_prev].to_vec();
-                        let (data, compress_type) = self.compress_manager.encode(&data);
-                        let size = (data.len() - 1) / 4096 + 1;
-                        let page_pointer = self.find_write_pos(size);
-                        let new_entry = DataObjectValueEntry {
-                            len: valid_prev,
-                            offset: entry.offset,
-                            page_pointer,
-                            compress_type,
-                            archived_len: data.len(),
-                        };
-                        for i in 0..size {
-                            let start_index = 4096 * i;
-                            let end_index = 4096 * (i + 1);
-                            if i == size - 1 {
-                                let mut data = data[start_index..].to_vec();
-                                data.extend(vec![10; 4096 - data.len()]);
-                                self.write_page(page_pointer + i as u32, &self.trans(data), true);
-                            } else {
-                                self.write_page(page_pointer + i as u32, &self.trans(data[start_index..end_index].to_vec()), true);
-                            }
-                            self.update_bit(page_pointer + i as u32, true);
-                            self.update_pit(page_pointer + i as u32, ino);
-                        }
-                        first_entry = Some(new_entry);
-                    }
-                }
-                if valid_suffix > 0 {
-                    let data = self.read_data_object_entry(entry);
-                    let data = data[data.len()-valid_suffix..].to_vec();
-                    let (data, compress_type) = self.compress_manager.encode(&data);
-                    let size = (data.len() - 1) / 4096 + 1;
-                    let page_pointer = self.find_write_pos(size);
-                    let new_entry = DataObjectValueEntry {
-                        len: valid_suffix,
-                        offset: entry.offset + entry.len - valid_suffix,
-                        page_pointer,
-                        compress_type,
-                        archived_len: data.len(),
-                    };
-                    for i in 0..size {
-                        let start_index = 4096 * i;
-                        let end_index = 4096 * (i + 1);
-                        if i == size - 1 {
-                            let mut data = data[start_index..].to_vec();
-                            data.extend(vec![10; 4096 - data.len()]);
-                            self.write_page(page_pointer + i as u32, &self.trans(data), true);
-                        } else {
-                            self.write_page(page_pointer + i as u32, &self.trans(data[start_index..end_index].to_vec()), true);
-                        }
-                        self.update_bit(page_pointer + i as u32, true);
-                        self.update_pit(page_pointer + i as u32, ino);
-                    }
-                    second_entry = Some(new_entry);
-                }
-                if entry.compress_type != 0 {
-                    let size = (entry.archived_len - 1) / 4096 + 1;
-                    for i in 0..size as u32 {
-                        self.dirty_pit(entry.page_pointer + i);
-                    }
-                }
+                self.write_page(page_pointer + i as u32, &self.trans(value[start_index..end_index].to_vec()), true);
             }
+            self.update_bit(page_pointer + i as u32, true);
+            self.update_pit(page_pointer + i as u32, ino);
         }
-        for pointer in remove_list.iter() {
-            for i in 0..object.entries.len() {
-                if object.entries[i].page_pointer == *pointer {
-                    object.entries.remove(i);
-                    break;
-                }
-            }
+        Ok(DataObjectValueEntry {
+            len,
+            offset,
+            page_pointer,
+            compress_type,
+            archived_len: value.len() as u64,
+        })
+    }
+
+    // part of an extent rewritten as an extent of its own, a hole splits without touching flash
+    fn split_data_object_entry(&mut self, entry: &DataObjectValueEntry, start: u64, len: u64, ino: u64) -> WondResult<DataObjectValueEntry> {
+        if entry.is_hole() {
+            return Ok(DataObjectValueEntry::hole(entry.offset + start, len));
         }
-        if first_entry.is_some() {
-            object.entries.insert(insert_index as usize, first_entry.unwrap());
-            insert_index += 1;
+        let data = self.read_data_object_entry(entry)?;
+        self.write_data_object_entry(entry.offset + start, len, &data[start as usize..(start + len) as usize], ino)
+    }
+
+    fn release_data_object_entry(&mut self, entry: &DataObjectValueEntry) {
+        for i in 0..entry.page_num() {
+            self.dirty_pit(entry.page_pointer + i);
         }
-        if second_entry.is_some() {
-            object.entries.insert(insert_index as usize, second_entry.unwrap());
+    }
+}
+
+impl KVManager {
+    // a hole would come back as its full size in zeros, a sparse object is only read by range
+    pub fn read_data_object_all(&mut self, object: &DataObjectValue) -> WondResult<Vec<u8>> {
+        if object.entries.iter().any(|entry| entry.is_hole()) {
+            return Err(WondError::Unsupported("a sparse object is only read by range"));
         }
-        let mut len = 0;
+        let mut result = vec![];
         for entry in object.entries.iter() {
-            len += entry.len;
+            result.append(&mut self.read_data_object_entry(entry)?);
         }
-        object.size = len;
+        Ok(result)
     }
-}
 
-impl KVManager {
-    pub fn read_data_object_all(&mut self, object: &mut DataObjectValue) -> Vec<u8> {
+    // bytes [off, off + len) clamped to the object, only the part of a hole that is asked for
+    // is ever materialized
+    pub fn read_data_object_range(&mut self, object: &DataObjectValue, off: u64, len: u64) -> WondResult<Vec<u8>> {
+        let end = min(off.saturating_add(len), object.size);
         let mut result = vec![];
         for entry in object.entries.iter() {
-            result.append(&mut self.read_data_object_entry(entry));
+            if entry.offset + entry.len <= off || entry.offset >= end {
+                continue;
+            }
+            let start = off.saturating_sub(entry.offset) as usize;
+            let stop = (min(entry.offset + entry.len, end) - entry.offset) as usize;
+            if entry.is_hole() {
+                result.resize(result.len() + stop - start, 0);
+            } else {
+                result.extend_from_slice(&self.read_data_object_entry(entry)?[start..stop]);
+            }
         }
-        result
+        Ok(result)
     }
 
-    pub fn read_data_object_entry(&mut self, entry: &DataObjectValueEntry) -> Vec<u8> {
-        let mut data = vec![0; entry.archived_len];
+    // a hole has no pages, callers fill in just the part of it they need
+    pub fn read_data_object_entry(&mut self, entry: &DataObjectValueEntry) -> WondResult<Vec<u8>> {
+        if entry.is_hole() {
+            return Err(WondError::Unsupported("a hole has no pages to read"));
+        }
+        let archived_len = entry.archived_len as usize;
+        let mut data = vec![0; archived_len];
         let mut size = 0;
-        for i in 0..(entry.archived_len-1)/4096+1 {
-            if i == (entry.archived_len-1)/4096 {
-                let remain_num = entry.archived_len - size;
-                self.read_page_advanced(entry.page_pointer + i as u32, true, &mut data[size..size+remain_num]);
+        for i in 0..(archived_len-1)/4096+1 {
+            if i == (archived_len-1)/4096 {
+                let remain_num = archived_len - size;
+                self.read_page_advanced(entry.page_pointer + i as u32, true, &mut data[size..size+remain_num])?;
             } else {
-                self.read_page_advanced(entry.page_pointer + i as u32, true, &mut data[size..size+4096]);
+                self.read_page_advanced(entry.page_pointer + i as u32, true, &mut data[size..size+4096])?;
                 size += 4096;
             }
         }
-        let data = self.compress_manager.decode(&data, entry.compress_type);
-        data
+        let mut data = self.compress_manager.decode(&data, entry.compress_type)?;
+        if (data.len() as u64) < entry.len {
+            return Err(WondError::Corrupted("extent decoded short of its length"));
+        }
+        data.truncate(entry.len as usize);
+        Ok(data)
     }
 
     pub fn recycle_data_obect_all(&mut self, object: &mut DataObjectValue) {
-        for entry in object.entries.iter() {
-            let size = (entry.archived_len - 1) / 4096 + 1;
-            for i in 0..size as u32 {
-                self.dirty_pit(entry.page_pointer + i);
-            }
+        for entry in object.entries.clone().iter() {
+            self.release_data_object_entry(entry);
         }
         object.size = 0;
         object.entries.clear();
@@ -3,6 +3,7 @@ use spin::RwLock;
 use alloc::sync::Arc;
 use super::raw_entry;
 use crate::buf;
+use crate::error::{WondError, WondResult};
 use std::cmp::min;
 use std::collections::BTreeSet;
 
@@ -13,7 +14,13 @@ pub struct BlockIter {
 }
 
 impl BlockIter {
-    pub fn new(block_id: u32, read_buf: Arc<RwLock<buf::BufCache>>) -> BlockIter {
+    pub fn new(block_id: u32, read_buf: Arc<RwLock<buf::BufCache>>) -> WondResult<BlockIter> {
+        BlockIter::new_at(block_id, 0, false, read_buf)
+    }
+
+    // the first block of an indexed table keeps its entries after the filter and index pages,
+    // tables written before checksums are read unchecked
+    pub fn new_at(block_id: u32, start_page: u32, checked: bool, read_buf: Arc<RwLock<buf::BufCache>>) -> WondResult<BlockIter> {
         let eof_key = raw_entry::EOF.as_bytes().to_vec();
         let eof_value = raw_entry::EOF.as_bytes().to_vec();
         let mut entries = BTreeSet::new();
@@ -26,11 +33,11 @@ impl BlockIter {
         let mut value: Option<Vec<u8>> = None;
         let mut read_index: usize = 0;
         let mut temp: Vec<u8> = vec![];
-        for i in 0..128 {
+        for i in start_page..128 {
             if is_end {
                 break;
             }
-            let page_data = read_buf.write().read(0, block_id * 128 + i);
+            let page_data = read_buf.write().read(0, block_id * 128 + i)?;
             let mut j;
             if i == 0 {
                 j = 12
@@ -104,8 +111,8 @@ impl BlockIter {
                             is_end = true;
                             break;
                         }
-                        if !BlockIter::verify_data(crc32.unwrap(), value.as_ref().unwrap()) {
-                            continue;
+                        if checked && !BlockIter::verify_data(crc32.unwrap(), key.as_ref().unwrap(), value.as_ref().unwrap()) {
+                            return Err(WondError::Corrupted("sstable entry fails its checksum"));
                         }
                         entry_num += 1;
                         let query = raw_entry::Entry::new(key.as_ref().unwrap().to_owned(), value.as_ref().unwrap().to_owned());
@@ -115,10 +122,10 @@ impl BlockIter {
                 }
             }
         }
-        BlockIter {
+        Ok(BlockIter {
             entries,
             entry_num,
-        }
+        })
     }
 
     pub fn get(&self, key: &Vec<u8>) -> Option<Vec<u8>> {
@@ -130,8 +137,8 @@ impl BlockIter {
         }
     }
 
-    pub fn verify_data(_: u32, _: &Vec<u8>) -> bool {
-        true
+    pub fn verify_data(crc32: u32, key: &[u8], value: &[u8]) -> bool {
+        raw_entry::Entry::checksum(key, value) == crc32
     }
 
     pub fn decode_u32(data: &Vec<u8>) -> u32 {
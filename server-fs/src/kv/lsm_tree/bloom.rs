@@ -0,0 +1,75 @@
+use crate::error::{WondError, WondResult};
+use crate::util::crc32::crc32;
+
+pub const BITS_PER_KEY: usize = 10;
+pub const HASH_NUM: u8 = 7;
+
+pub struct BloomFilter {
+    pub hash_num: u8,
+    pub bits: Vec<u8>,
+}
+
+impl BloomFilter {
+    pub fn new(key_num: usize) -> BloomFilter {
+        let bit_num = (key_num * BITS_PER_KEY).max(64);
+        BloomFilter {
+            hash_num: HASH_NUM,
+            bits: vec![0; bit_num.div_ceil(8)],
+        }
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        let bit_num = self.bits.len() as u64 * 8;
+        let (h1, h2) = BloomFilter::hash(key);
+        for i in 0..self.hash_num as u64 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % bit_num;
+            self.bits[(bit / 8) as usize] |= 1 << (bit % 8);
+        }
+    }
+
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        let bit_num = self.bits.len() as u64 * 8;
+        let (h1, h2) = BloomFilter::hash(key);
+        for i in 0..self.hash_num as u64 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % bit_num;
+            if self.bits[(bit / 8) as usize] & (1 << (bit % 8)) == 0 {
+                return false;
+            }
+        }
+        true
+    }
+
+    // double hashing, crc32 and fnv-1a stand in for two independent hashes
+    fn hash(key: &[u8]) -> (u64, u64) {
+        let mut fnv: u32 = 0x811c9dc5;
+        for byte in key.iter() {
+            fnv ^= *byte as u32;
+            fnv = fnv.wrapping_mul(0x01000193);
+        }
+        (crc32(key) as u64, fnv as u64 | 1)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&(self.bits.len() as u32).to_be_bytes());
+        data.push(self.hash_num);
+        data.extend_from_slice(&self.bits);
+        data
+    }
+
+    // returns the filter and the bytes it took
+    pub fn decode(data: &[u8]) -> WondResult<(BloomFilter, usize)> {
+        if data.len() < 5 {
+            return Err(WondError::Corrupted("sstable bloom filter is cut short"));
+        }
+        let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        if len == 0 || data.len() < 5 + len {
+            return Err(WondError::Corrupted("sstable bloom filter is cut short"));
+        }
+        let filter = BloomFilter {
+            hash_num: data[4],
+            bits: data[5..5 + len].to_vec(),
+        };
+        Ok((filter, 5 + len))
+    }
+}
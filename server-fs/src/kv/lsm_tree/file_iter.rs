@@ -1,13 +1,35 @@
 extern crate alloc;
 use spin::RwLock;
 use alloc::sync::Arc;
+use std::cmp::Ordering;
 use super::raw_entry;
 use super::block_iter;
+use super::bloom;
+use super::sstable_manager;
+use crate::util::crc32::crc32;
 use crate::buf;
+use crate::error::{WondError, WondResult};
+
+// key and value pairs of one table in key order
+pub type Run = Vec<(Vec<u8>, Vec<u8>)>;
+
+// the first entry starting in a page, so a lookup only parses from there
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexEntry {
+    pub key: Vec<u8>,
+    pub block: u8,
+    pub page: u8,
+    pub offset: u16,
+}
 
 pub struct FileIter {
     pub block_id: u32,
     pub block_num: usize,
+    pub start_page: u32,
+    // entries and block footers carry checksums, only tables from before them go without
+    pub checked: bool,
+    pub filter: Option<bloom::BloomFilter>,
+    pub index: Vec<IndexEntry>,
     pub block_iter: Vec<Option<block_iter::BlockIter>>,
     pub read_buf: Arc<RwLock<buf::BufCache>>,
 }
@@ -18,21 +40,226 @@ impl FileIter {
         FileIter {
             block_id,
             block_num,
+            start_page: 0,
+            checked: false,
+            filter: None,
+            index: vec![],
             block_iter,
             read_buf,
         }
     }
 
-    pub fn get(&mut self, key: &Vec<u8>) -> Option<Vec<u8>> {
+    // loads the filter and index of a table, tables written without them fall back to a scan
+    pub fn open(block_id: u32, block_num: usize, read_buf: Arc<RwLock<buf::BufCache>>) -> WondResult<FileIter> {
+        let mut file_iter = FileIter::new(block_id, block_num, read_buf);
+        let page = file_iter.read_buf.write().read(0, block_id * 128)?;
+        file_iter.checked = u32::from_be_bytes([page[0], page[1], page[2], page[3]]) == sstable_manager::MAGIC_NUMBER;
+        file_iter.start_page = page[11] as u32;
+        if file_iter.start_page == 0 {
+            return Ok(file_iter);
+        }
+        let mut data = page.to_vec();
+        for i in 1..file_iter.start_page {
+            data.extend_from_slice(&file_iter.read_buf.write().read(0, block_id * 128 + i)?);
+        }
+        let meta = &data[12..];
+        let (filter, mut pos) = bloom::BloomFilter::decode(meta)?;
+        file_iter.filter = Some(filter);
+        let index_num = FileIter::decode_u32(meta, pos)? as usize;
+        pos += 4;
+        for _ in 0..index_num {
+            let key_size = FileIter::decode_u32(meta, pos)? as usize;
+            pos += 4;
+            if pos + key_size + 4 > meta.len() {
+                return Err(WondError::Corrupted("sstable index is cut short"));
+            }
+            file_iter.index.push(IndexEntry {
+                key: meta[pos..pos + key_size].to_vec(),
+                block: meta[pos + key_size],
+                page: meta[pos + key_size + 1],
+                offset: (meta[pos + key_size + 2] as u16) << 8 | meta[pos + key_size + 3] as u16,
+            });
+            pos += key_size + 4;
+        }
+        Ok(file_iter)
+    }
+
+    pub fn get(&mut self, key: &Vec<u8>) -> WondResult<Option<Vec<u8>>> {
+        if let Some(filter) = self.filter.as_ref() {
+            if !filter.may_contain(key) {
+                return Ok(None);
+            }
+        }
+        if self.start_page != 0 {
+            return self.seek(key);
+        }
         for i in 0..self.block_num {
             if self.block_iter[i].is_none() {
-                self.block_iter[i] = Some(block_iter::BlockIter::new(self.block_id+i as u32, Arc::clone(&self.read_buf)));
+                self.block_iter[i] = Some(block_iter::BlockIter::new(self.block_id+i as u32, Arc::clone(&self.read_buf))?);
             }
             let ret = self.block_iter[i].as_ref().unwrap().get(key);
             if ret.is_some() {
-                return Some(ret.unwrap());
+                return Ok(Some(ret.unwrap()));
             }
         }
-        None
+        Ok(None)
     }
-}
\ No newline at end of file
+
+    // parses forward from the last index entry not past the key
+    pub fn seek(&self, key: &Vec<u8>) -> WondResult<Option<Vec<u8>>> {
+        let index = self.index.partition_point(|entry| entry.key <= *key);
+        if index == 0 {
+            return Ok(None);
+        }
+        let entry = &self.index[index - 1];
+        let block_no = self.block_id + entry.block as u32;
+        let mut pos = entry.page as usize * 4096 + entry.offset as usize;
+        let mut page = (u32::MAX, [0; 4096]);
+        while let Some((cur_key, value)) = self.read_entry(block_no, &mut pos, &mut page)? {
+            match cur_key.cmp(key) {
+                Ordering::Less => continue,
+                Ordering::Equal => return Ok(Some(value)),
+                Ordering::Greater => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+
+    // entries with start <= key < end in key order, no end runs to the last entry
+    pub fn scan(&mut self, start: &[u8], end: Option<&[u8]>) -> WondResult<Run> {
+        let in_range = |key: &[u8]| key >= start && end.is_none_or(|end| key < end);
+        if self.start_page == 0 {
+            let entries = self.entries()?;
+            return Ok(entries.into_iter().filter(|entry| in_range(&entry.key)).map(|entry| (entry.key, entry.value)).collect());
+        }
+        let mut entries = vec![];
+        if self.index.is_empty() {
+            return Ok(entries);
+        }
+        let index = self.index.partition_point(|entry| entry.key.as_slice() <= start).max(1);
+        let entry = &self.index[index - 1];
+        let mut block = entry.block as usize;
+        let mut pos = entry.page as usize * 4096 + entry.offset as usize;
+        let mut page = (u32::MAX, [0; 4096]);
+        loop {
+            match self.read_entry(self.block_id + block as u32, &mut pos, &mut page)? {
+                Some((key, value)) => {
+                    if end.is_some_and(|end| key.as_slice() >= end) {
+                        break;
+                    }
+                    if key.as_slice() >= start {
+                        entries.push((key, value));
+                    }
+                },
+                // later blocks start right after their zeroed header
+                None => {
+                    block += 1;
+                    if block >= self.block_num {
+                        break;
+                    }
+                    pos = 12;
+                },
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn entries(&mut self) -> WondResult<Vec<raw_entry::Entry>> {
+        let mut entries = vec![];
+        for i in 0..self.block_num {
+            if self.block_iter[i].is_none() {
+                let start_page = if i == 0 { self.start_page } else { 0 };
+                self.block_iter[i] = Some(block_iter::BlockIter::new_at(self.block_id+i as u32, start_page, self.checked, Arc::clone(&self.read_buf))?);
+            }
+            entries.extend(self.block_iter[i].as_ref().unwrap().entries.iter().cloned());
+        }
+        Ok(entries)
+    }
+
+    // reads every block through to its eof entry, checking each entry and the footer checksum
+    // the eof entry keeps for all bytes before it, header and index included
+    pub fn verify(&self) -> WondResult<()> {
+        if !self.checked {
+            return Ok(());
+        }
+        let mut page = (u32::MAX, [0; 4096]);
+        for i in 0..self.block_num {
+            let block_no = self.block_id + i as u32;
+            let start = if i == 0 { self.start_page as usize * 4096 } else { 12 };
+            let mut pos = 0;
+            let mut data = self.read_bytes(block_no, &mut pos, &mut page, start)?;
+            loop {
+                let (head, key, value) = self.read_raw(block_no, &mut pos, &mut page)?;
+                let crc = FileIter::decode_u32(&head, 0)?;
+                if FileIter::is_eof(&key, &value) {
+                    if crc32(&data) != crc {
+                        return Err(WondError::Corrupted("sstable block fails its footer checksum"));
+                    }
+                    break;
+                }
+                if raw_entry::Entry::checksum(&key, &value) != crc {
+                    return Err(WondError::Corrupted("sstable entry fails its checksum"));
+                }
+                data.extend_from_slice(&head);
+                data.extend_from_slice(&key);
+                data.extend_from_slice(&value);
+            }
+        }
+        Ok(())
+    }
+
+    // none once the block's eof entry is reached
+    fn read_entry(&self, block_no: u32, pos: &mut usize, page: &mut (u32, [u8; 4096])) -> WondResult<Option<(Vec<u8>, Vec<u8>)>> {
+        let (head, key, value) = self.read_raw(block_no, pos, page)?;
+        if FileIter::is_eof(&key, &value) {
+            return Ok(None);
+        }
+        if self.checked && raw_entry::Entry::checksum(&key, &value) != FileIter::decode_u32(&head, 0)? {
+            return Err(WondError::Corrupted("sstable entry fails its checksum"));
+        }
+        Ok(Some((key, value)))
+    }
+
+    fn read_raw(&self, block_no: u32, pos: &mut usize, page: &mut (u32, [u8; 4096])) -> WondResult<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+        let head = self.read_bytes(block_no, pos, page, 12)?;
+        let key_size = FileIter::decode_u32(&head, 4)? as usize;
+        let value_size = FileIter::decode_u32(&head, 8)? as usize;
+        if key_size == 0 || value_size == 0 {
+            return Err(WondError::Corrupted("sstable index points between entries"));
+        }
+        let key = self.read_bytes(block_no, pos, page, key_size)?;
+        let value = self.read_bytes(block_no, pos, page, value_size)?;
+        Ok((head, key, value))
+    }
+
+    fn is_eof(key: &[u8], value: &[u8]) -> bool {
+        let eof = raw_entry::EOF.as_bytes();
+        key == eof && value == eof
+    }
+
+    // entries never cross a block, so running off its end means the table is damaged
+    fn read_bytes(&self, block_no: u32, pos: &mut usize, page: &mut (u32, [u8; 4096]), len: usize) -> WondResult<Vec<u8>> {
+        if *pos + len > 128 * 4096 {
+            return Err(WondError::Corrupted("sstable entry runs past its block"));
+        }
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            let address = block_no * 128 + (*pos / 4096) as u32;
+            if page.0 != address {
+                *page = (address, self.read_buf.write().read(0, address)?);
+            }
+            let offset = *pos % 4096;
+            let read_num = (len - data.len()).min(4096 - offset);
+            data.extend_from_slice(&page.1[offset..offset + read_num]);
+            *pos += read_num;
+        }
+        Ok(data)
+    }
+
+    fn decode_u32(data: &[u8], pos: usize) -> WondResult<u32> {
+        if pos + 4 > data.len() {
+            return Err(WondError::Corrupted("sstable index is cut short"));
+        }
+        Ok((data[pos] as u32) << 24 | (data[pos + 1] as u32) << 16 | (data[pos + 2] as u32) << 8 | data[pos + 3] as u32)
+    }
+}
@@ -2,54 +2,148 @@ extern crate alloc;
 use spin::RwLock;
 use alloc::sync::Arc;
 use crate::buf;
+use crate::error::{WondError, WondResult};
 use super::memtable;
 use super::entry;
 use super::sstable_manager;
+use super::merge_iter;
+use super::wal;
 
 pub struct LSMTree {
     memtable: memtable::Memtable,
     sstable_manager: sstable_manager::SSTableManager,
+    wal: wal::WAL,
 }
 
 impl LSMTree {
     pub fn new(buf: Arc<RwLock<buf::BufCache>>) -> LSMTree {
         LSMTree {
             memtable: memtable::Memtable::new(128 * 4096),
-            sstable_manager: sstable_manager::SSTableManager::new(5, 100, buf),
+            sstable_manager: sstable_manager::SSTableManager::new(5, 100 - wal::WAL_BLOCK_NUM, Arc::clone(&buf)),
+            wal: wal::WAL::new(105 - wal::WAL_BLOCK_NUM as u32, wal::WAL_BLOCK_NUM, buf),
         }
     }
 
-    pub fn flush(&mut self) {
-        self.sstable_manager.flush(&self.memtable.flush());
+    // the log takes the last wal_block_num blocks of the region, the tables the rest. a read-only
+    // mount keeps replayed records in the memtable and leaves the log as it is
+    pub fn mount(&mut self, block_id: u32, block_num: usize, wal_block_num: usize, read_only: bool) -> WondResult<()> {
+        if block_num <= wal_block_num {
+            return Err(WondError::NoSpace);
+        }
+        let buf = Arc::clone(&self.sstable_manager.buf);
+        let table_num = block_num - wal_block_num;
+        self.sstable_manager = sstable_manager::SSTableManager::new(block_id, table_num, Arc::clone(&buf));
+        self.sstable_manager.build()?;
+        self.memtable.clear();
+        self.wal = wal::WAL::new(block_id + table_num as u32, wal_block_num, buf);
+        let records = self.wal.replay()?;
+        for (key, value) in records.iter() {
+            self.memtable.put(key, value);
+        }
+        if read_only {
+            return Ok(());
+        }
+        if records.is_empty() {
+            // only a torn record leaves anything to erase
+            return self.wal.truncate();
+        }
+        // replayed records go to a table so the log starts out clean
+        self.flush()
+    }
+
+    // images from before the log gave every block of the region to the tables, the ones on the
+    // blocks the log takes over are written again below them
+    pub fn reserve_wal(&mut self, block_id: u32, block_num: usize, wal_block_num: usize) -> WondResult<()> {
+        if block_num <= wal_block_num {
+            return Err(WondError::NoSpace);
+        }
+        let buf = Arc::clone(&self.sstable_manager.buf);
+        let table_num = block_num - wal_block_num;
+        let mut sstable_manager = sstable_manager::SSTableManager::new(block_id, block_num, Arc::clone(&buf));
+        sstable_manager.build()?;
+        sstable_manager.shrink(table_num)?;
+        for block_no in block_id + table_num as u32..block_id + block_num as u32 {
+            buf.write().erase(0, block_no)?;
+        }
+        let translation_layer = Arc::clone(&buf.read().translation_layer);
+        translation_layer.flush()
     }
 
-    pub fn put(&mut self, key: &Vec<u8>, value: &Vec<u8>) {
-        if !self.memtable.can_put(key.len() + value.len() + 12) {
-            self.flush();
+    pub fn flush(&mut self) -> WondResult<()> {
+        if self.memtable.entries.is_empty() {
+            return Ok(());
         }
+        self.sstable_manager.flush(&self.memtable.flush())?;
+        // the table has to be on the disk before the log that backs it is erased
+        let translation_layer = Arc::clone(&self.sstable_manager.buf.read().translation_layer);
+        translation_layer.flush()?;
+        self.wal.truncate()
+    }
+
+    // puts since the last sync are only in memory until their group of log records is written
+    pub fn sync(&mut self) -> WondResult<()> {
+        self.wal.sync()
+    }
+
+    // tables the last mount found damaged and left out, oldest first
+    pub fn get_quarantine(&self) -> Vec<u32> {
+        self.sstable_manager.quarantine.keys().cloned().collect()
+    }
+
+    pub fn put(&mut self, key: &Vec<u8>, value: &Vec<u8>) -> WondResult<()> {
+        if !self.memtable.can_put(key.len() + value.len() + 12) || !self.wal.can_append(key, value) {
+            self.flush()?;
+        }
+        self.wal.append(key, value)?;
         self.memtable.put(key, value);
+        Ok(())
     }
 
-    pub fn get(&mut self, key: &Vec<u8>) -> Option<Vec<u8>> {
+    pub fn get(&mut self, key: &Vec<u8>) -> WondResult<Option<Vec<u8>>> {
         match self.memtable.get(key) {
             Some(v) => {
                 if v != entry::TOMBSTONE.as_bytes().to_vec() {
-                    return Some(v);
+                    return Ok(Some(v));
                 } else {
-                    return None;
+                    return Ok(None);
                 }
             }
             _ => {
-                return self.sstable_manager.get(key);
+                // a tombstone flushed out of the memtable still hides older tables
+                let value = self.sstable_manager.get(key)?;
+                if value == Some(entry::TOMBSTONE.as_bytes().to_vec()) {
+                    return Ok(None);
+                }
+                return Ok(value);
             }
         }
     }
 
-    pub fn delete(&mut self, key: &Vec<u8>) {
-        let value = entry::TOMBSTONE.as_bytes().to_vec();
-        if !self.memtable.can_put(key.len() + value.len() + 12) {
-            self.flush();
+    // live entries with start <= key < end in key order, no end runs to the last key
+    pub fn scan_range(&mut self, start: &[u8], end: Option<&[u8]>) -> WondResult<merge_iter::MergeIter> {
+        let mut runs = vec![self.memtable.scan(start, end)];
+        runs.extend(self.sstable_manager.scan(start, end)?);
+        Ok(merge_iter::MergeIter::new(runs))
+    }
+
+    pub fn scan(&mut self, prefix: &[u8]) -> WondResult<merge_iter::MergeIter> {
+        let end = LSMTree::prefix_end(prefix);
+        self.scan_range(prefix, end.as_deref())
+    }
+
+    // the smallest key past every key with the prefix, none if the prefix is all 0xff
+    pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+        let mut end = prefix.to_vec();
+        while let Some(byte) = end.pop() {
+            if byte != 0xff {
+                end.push(byte + 1);
+                return Some(end);
+            }
         }
-        self.memtable.put(key, &value);
+        None
+    }
+
+    pub fn delete(&mut self, key: &Vec<u8>) -> WondResult<()> {
+        self.put(key, &entry::TOMBSTONE.as_bytes().to_vec())
     }
 }
\ No newline at end of file
@@ -28,6 +28,18 @@ impl Memtable {
         }
     }
 
+    // tombstones included, the caller merges them against older tables
+    pub fn scan(&self, start: &[u8], end: Option<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let query = entry::Entry {
+            key: start.to_vec(),
+            value: vec![],
+        };
+        self.entries.range(query..)
+            .take_while(|entry| end.is_none_or(|end| entry.key.as_slice() < end))
+            .map(|entry| (entry.key.clone(), entry.value.clone()))
+            .collect()
+    }
+
     pub fn put(&mut self, key: &Vec<u8>, value: &Vec<u8>) {
         let query = entry::Entry {
             key: key.to_owned(),
@@ -0,0 +1,44 @@
+use std::iter::Peekable;
+use std::vec::IntoIter;
+use super::entry;
+
+pub type Source = Peekable<IntoIter<(Vec<u8>, Vec<u8>)>>;
+
+// merges sorted runs ordered newest first, the newest copy of a key wins and tombstones hide it
+pub struct MergeIter {
+    pub sources: Vec<Source>,
+}
+
+impl MergeIter {
+    pub fn new(sources: Vec<Vec<(Vec<u8>, Vec<u8>)>>) -> MergeIter {
+        MergeIter {
+            sources: sources.into_iter().map(|source| source.into_iter().peekable()).collect(),
+        }
+    }
+}
+
+impl Iterator for MergeIter {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut min: Option<(usize, &Vec<u8>)> = None;
+            for (i, source) in self.sources.iter_mut().enumerate() {
+                if let Some((key, _)) = source.peek() {
+                    if min.is_none_or(|(_, min_key)| key < min_key) {
+                        min = Some((i, key));
+                    }
+                }
+            }
+            let (newest, _) = min?;
+            let (key, value) = self.sources[newest].next().unwrap();
+            // older copies of the key are shadowed
+            for source in self.sources[newest + 1..].iter_mut() {
+                source.next_if(|(cur_key, _)| *cur_key == key);
+            }
+            if value != entry::TOMBSTONE.as_bytes() {
+                return Some((key, value));
+            }
+        }
+    }
+}
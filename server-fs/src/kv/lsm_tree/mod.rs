@@ -5,12 +5,16 @@ pub mod memtable;
 pub mod file_iter;
 pub mod block_iter;
 pub mod sstable_manager;
+pub mod bloom;
+pub mod merge_iter;
+pub mod wal;
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::buf;
     use crate::tl::tl;
+    use crate::error::WondError;
     extern crate alloc;
     use spin::RwLock;
     use alloc::sync::Arc;
@@ -18,15 +22,15 @@ mod test {
     #[test]
     fn test_block_iter() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let buf = buf::BufCache::new(Arc::new(tl));
-        block_iter::BlockIter::new(0, Arc::new(RwLock::new(buf)));
+        block_iter::BlockIter::new(0, Arc::new(RwLock::new(buf))).unwrap();
     }
 
     #[test]
     fn test_file_iter() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let buf = buf::BufCache::new(Arc::new(tl));
         file_iter::FileIter::new(0, 3, Arc::new(RwLock::new(buf)));
     }
@@ -66,10 +70,10 @@ mod test {
     #[test]
     fn test_sstable() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let buf = buf::BufCache::new(Arc::new(tl));
         let mut manager = sstable_manager::SSTableManager::new(0, 10, Arc::new(RwLock::new(buf)));
-        manager.build();
+        manager.build().unwrap();
         let mut memtable = memtable::Memtable::new(4096 * 128);
         for i in 0..12345 as usize {
             let mut ret = [0; 4];
@@ -86,7 +90,7 @@ mod test {
         }
         assert_eq!(memtable.get_size(), 320970);
         let entries = memtable.flush();
-        manager.flush(&entries);
+        manager.flush(&entries).unwrap();
         assert_eq!(manager.cur_block_id, 1);
         for i in 0..12345 as usize {
             let mut ret = [0; 4];
@@ -99,10 +103,10 @@ mod test {
             key.extend_from_slice(&ret);
             let mut value = vec![3, 4];
             value.extend_from_slice(&ret);
-            assert_eq!(manager.get(&key).unwrap(), value);
+            assert_eq!(manager.get(&key).unwrap().unwrap(), value);
         }
         manager.clear();
-        manager.build();
+        manager.build().unwrap();
         for i in 0..12345 as usize {
             let mut ret = [0; 4];
             ret[0] = (i >> 24) as u8;
@@ -114,7 +118,7 @@ mod test {
             key.extend_from_slice(&ret);
             let mut value = vec![3, 4];
             value.extend_from_slice(&ret);
-            assert_eq!(manager.get(&key).unwrap(), value);
+            assert_eq!(manager.get(&key).unwrap().unwrap(), value);
         }
         for i in 0..12345 as usize {
             let mut ret = [0; 4];
@@ -131,7 +135,7 @@ mod test {
         }
         assert_eq!(memtable.get_size(), 320970);
         let entries = memtable.flush();
-        manager.flush(&entries);
+        manager.flush(&entries).unwrap();
         assert_eq!(manager.cur_block_id, 2);
         assert_eq!(manager.files.len(), 2);
         for i in 0..12345 as usize {
@@ -145,22 +149,292 @@ mod test {
             key.extend_from_slice(&ret);
             let mut value = vec![222, 222];
             value.extend_from_slice(&ret);
-            assert_eq!(manager.get(&key).unwrap(), value);
+            assert_eq!(manager.get(&key).unwrap().unwrap(), value);
         }
     }
 
+    #[test]
+    fn test_compaction() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let buf = buf::BufCache::new(Arc::new(tl));
+        let mut manager = sstable_manager::SSTableManager::new(0, 10, Arc::new(RwLock::new(buf)));
+        manager.build().unwrap();
+        let tombstone = entry::TOMBSTONE.as_bytes().to_vec();
+        for i in 0..3u8 {
+            let entries = vec![entry::Entry::new(vec![1], vec![i]), entry::Entry::new(vec![2, i], vec![i])];
+            manager.flush(&entries).unwrap();
+        }
+        assert_eq!(manager.files.len(), 3);
+        // the fourth table closes a run, the merge keeps the newest values and drops tombstones
+        let entries = vec![entry::Entry::new(vec![2, 0], tombstone.clone()), entry::Entry::new(vec![3], vec![3])];
+        manager.flush(&entries).unwrap();
+        assert_eq!(manager.files.len(), 1);
+        assert_eq!(manager.sstable_num, 1);
+        assert_eq!(manager.block_table.len(), 1);
+        assert!(manager.files.contains_key(&4));
+        assert_eq!(manager.get(&vec![1]).unwrap(), Some(vec![2]));
+        assert_eq!(manager.get(&vec![2, 0]).unwrap(), None);
+        assert_eq!(manager.get(&vec![2, 2]).unwrap(), Some(vec![2]));
+        manager.clear();
+        manager.build().unwrap();
+        assert_eq!(manager.files.len(), 1);
+        assert_eq!(manager.get(&vec![3]).unwrap(), Some(vec![3]));
+        // unmerged tombstones still shadow the older table
+        manager.flush(&vec![entry::Entry::new(vec![1], tombstone.clone())]).unwrap();
+        manager.flush(&vec![entry::Entry::new(vec![4], vec![4])]).unwrap();
+        assert_eq!(manager.files.len(), 3);
+        assert_eq!(manager.get(&vec![1]).unwrap(), Some(tombstone.clone()));
+        // a merged table whose inputs were never erased wins over them on the next build
+        let table = sstable_manager::SSTableManager::pack(&[(vec![7], vec![7])]).unwrap();
+        let block_id = manager.alloc_blocks(1).unwrap();
+        manager.write_table(7, 5, block_id, &table).unwrap();
+        manager.clear();
+        manager.build().unwrap();
+        assert_eq!(manager.files.len(), 2);
+        assert_eq!(manager.block_table.len(), 2);
+        assert_eq!(manager.get(&vec![7]).unwrap(), Some(vec![7]));
+        assert_eq!(manager.get(&vec![1]).unwrap(), Some(vec![2]));
+        assert_eq!(manager.get(&vec![4]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_bloom() {
+        let mut filter = bloom::BloomFilter::new(1000);
+        for i in 0..1000u32 {
+            filter.insert(&i.to_be_bytes());
+        }
+        let (filter, len) = bloom::BloomFilter::decode(&filter.encode()).unwrap();
+        assert_eq!(len, 5 + 1250);
+        assert!((0..1000u32).all(|i| filter.may_contain(&i.to_be_bytes())));
+        let false_positive = (1000..11000u32).filter(|i| filter.may_contain(&i.to_be_bytes())).count();
+        assert!(false_positive < 300);
+    }
+
+    #[test]
+    fn test_sstable_index() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let buf = Arc::new(RwLock::new(buf::BufCache::new(Arc::new(tl))));
+        let mut manager = sstable_manager::SSTableManager::new(0, 10, Arc::clone(&buf));
+        manager.build().unwrap();
+        // a table from before the filter and index, entries right after the header
+        let mut raw = vec![0x22, 0x22, 0xff, 0xff, 1, 0, 0, 1, 0, 0, 0, 0];
+        raw.append(&mut raw_entry::Entry::new(vec![9, 9], vec![1]).encode_entry());
+        raw.append(&mut raw_entry::Entry::new(raw_entry::EOF.as_bytes().to_vec(), raw_entry::EOF.as_bytes().to_vec()).encode_entry());
+        let mut page = [0; 4096];
+        page[..raw.len()].copy_from_slice(&raw);
+        buf.write().erase(0, 0).unwrap();
+        buf.write().write(0, 0, &page);
+        manager.clear();
+        manager.build().unwrap();
+        let entries: Vec<entry::Entry> = (0..20000u32).map(|i| entry::Entry::new((i * 2).to_be_bytes().to_vec(), vec![i as u8; 20])).collect();
+        manager.flush(&entries).unwrap();
+        manager.clear();
+        manager.build().unwrap();
+        assert_eq!(manager.files.len(), 2);
+        let file_iter = manager.file_iter.get(&2).unwrap();
+        assert_eq!(file_iter.block_num, 2);
+        assert!(file_iter.start_page > 0);
+        assert!(file_iter.filter.is_some());
+        assert!(file_iter.index.len() > 128);
+        assert_eq!(manager.file_iter.get(&1).unwrap().start_page, 0);
+        for i in (0..20000u32).step_by(7) {
+            assert_eq!(manager.get(&(i * 2).to_be_bytes().to_vec()).unwrap(), Some(vec![i as u8; 20]));
+            assert_eq!(manager.get(&(i * 2 + 1).to_be_bytes().to_vec()).unwrap(), None);
+        }
+        assert_eq!(manager.get(&vec![9, 9]).unwrap(), Some(vec![1]));
+        // lookups seek by the index, they never parse whole blocks
+        assert!(manager.file_iter.get(&2).unwrap().block_iter.iter().all(|block| block.is_none()));
+        let mut file_iter = file_iter::FileIter::open(0, 1, Arc::clone(&buf)).unwrap();
+        assert_eq!(file_iter.entries().unwrap().len(), 1);
+        // a range scan carries on into the next block of the table
+        let start = (13000 * 2u32).to_be_bytes();
+        let end = (15000 * 2u32).to_be_bytes();
+        let runs = manager.scan(&start, Some(&end)).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert!(runs[1].is_empty());
+        assert_eq!(runs[0].len(), 2000);
+        assert!(runs[0].iter().enumerate().all(|(i, (key, _))| *key == ((13000 + i as u32) * 2).to_be_bytes()));
+        assert_eq!(manager.scan(&[9], None).unwrap()[1], vec![(vec![9, 9], vec![1])]);
+    }
+
+    #[test]
+    fn test_sstable_checksum() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let buf = Arc::new(RwLock::new(buf::BufCache::new(Arc::new(tl))));
+        let mut manager = sstable_manager::SSTableManager::new(0, 10, Arc::clone(&buf));
+        manager.build().unwrap();
+        for i in 1..4u8 {
+            manager.flush(&vec![entry::Entry::new(vec![i], vec![i; 10]), entry::Entry::new(vec![i, i], vec![i; 10])]).unwrap();
+        }
+        assert!((1..4).all(|i| manager.file_iter[&i].checked && manager.file_iter[&i].verify().is_ok()));
+        // a flipped bit in the first entry of table 1 and in the filter of table 2
+        let (block_1, _) = manager.files[&1];
+        let address = block_1 * 128 + manager.file_iter[&1].start_page;
+        let mut page = buf.write().read(0, address).unwrap();
+        page[13] ^= 0x10;
+        buf.write().table.insert(address, page);
+        let (block_2, _) = manager.files[&2];
+        let mut page = buf.write().read(0, block_2 * 128).unwrap();
+        page[20] ^= 0x01;
+        buf.write().table.insert(block_2 * 128, page);
+        assert_eq!(manager.get(&vec![1]), Err(WondError::Corrupted("sstable entry fails its checksum")));
+        assert_eq!(manager.file_iter[&2].verify(), Err(WondError::Corrupted("sstable block fails its footer checksum")));
+        // build leaves both out and keeps their blocks from being reused
+        manager.clear();
+        manager.build().unwrap();
+        assert_eq!(manager.quarantine.keys().cloned().collect::<Vec<u32>>(), vec![1, 2]);
+        assert_eq!(manager.files.len(), 1);
+        assert!(manager.is_used(block_1) && manager.is_used(block_2));
+        assert_eq!(manager.get(&vec![1]).unwrap(), None);
+        assert_eq!(manager.get(&vec![3, 3]).unwrap(), Some(vec![3; 10]));
+        manager.flush(&vec![entry::Entry::new(vec![4], vec![4])]).unwrap();
+        assert!(manager.files.contains_key(&4));
+    }
+
+    #[test]
+    fn test_scan() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let buf = Arc::new(RwLock::new(buf::BufCache::new(Arc::new(tl))));
+        let mut kv = lsm_tree::LSMTree::new(Arc::clone(&buf));
+        kv.mount(10, 20, wal::WAL_BLOCK_NUM, false).unwrap();
+        for i in 1..6u8 {
+            kv.put(&vec![b'a', i], &vec![i]).unwrap();
+        }
+        kv.put(&vec![b'b', 1], &vec![1]).unwrap();
+        kv.flush().unwrap();
+        kv.delete(&vec![b'a', 2]).unwrap();
+        kv.put(&vec![b'a', 3], &vec![30]).unwrap();
+        kv.flush().unwrap();
+        // the memtable is newer than both tables
+        kv.put(&vec![b'a', 4], &vec![40]).unwrap();
+        kv.delete(&vec![b'a', 5]).unwrap();
+        let expect = vec![(vec![b'a', 1], vec![1]), (vec![b'a', 3], vec![30]), (vec![b'a', 4], vec![40])];
+        assert_eq!(kv.scan(b"a").unwrap().collect::<Vec<_>>(), expect);
+        assert_eq!(kv.scan_range(&[b'a', 2], Some(&[b'a', 4])).unwrap().collect::<Vec<_>>(), expect[1..2].to_vec());
+        assert_eq!(kv.scan_range(&[], None).unwrap().count(), 4);
+        kv.sync().unwrap();
+        let mut kv = lsm_tree::LSMTree::new(Arc::clone(&buf));
+        kv.mount(10, 20, wal::WAL_BLOCK_NUM, false).unwrap();
+        assert_eq!(kv.scan(b"a").unwrap().collect::<Vec<_>>(), expect);
+        assert_eq!(lsm_tree::LSMTree::prefix_end(&[1, 0xff]), Some(vec![2]));
+        assert_eq!(lsm_tree::LSMTree::prefix_end(&[0xff]), None);
+    }
+
+    #[test]
+    fn test_wal() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let buf = Arc::new(RwLock::new(buf::BufCache::new(Arc::new(tl))));
+        let mut kv = lsm_tree::LSMTree::new(Arc::clone(&buf));
+        kv.mount(10, 20, wal::WAL_BLOCK_NUM, false).unwrap();
+        kv.put(&vec![1], &vec![1; 5000]).unwrap();
+        kv.put(&vec![2], &vec![2]).unwrap();
+        kv.delete(&vec![1]).unwrap();
+        kv.sync().unwrap();
+        // nothing was flushed, the log alone brings the records back, a read-only mount leaves it be
+        let buf = reopen(&buf);
+        let mut kv = lsm_tree::LSMTree::new(Arc::clone(&buf));
+        kv.mount(10, 20, wal::WAL_BLOCK_NUM, true).unwrap();
+        assert_eq!(kv.get(&vec![1]).unwrap(), None);
+        assert_eq!(kv.get(&vec![2]).unwrap(), Some(vec![2]));
+        assert_ne!(buf.write().read(0, 26 * 128).unwrap(), [0; 4096]);
+        let mut kv = lsm_tree::LSMTree::new(Arc::clone(&buf));
+        kv.mount(10, 20, wal::WAL_BLOCK_NUM, false).unwrap();
+        assert_eq!(kv.get(&vec![2]).unwrap(), Some(vec![2]));
+        assert_eq!(buf.write().read(0, 26 * 128).unwrap(), [0; 4096]);
+        // a clean log is not erased again
+        let erase_count = |buf: &Arc<RwLock<buf::BufCache>>| buf.read().translation_layer.disk_manager.read().fake_disk.as_ref().unwrap().erase_count[26];
+        let count = erase_count(&buf);
+        let mut kv = lsm_tree::LSMTree::new(Arc::clone(&buf));
+        kv.mount(10, 20, wal::WAL_BLOCK_NUM, false).unwrap();
+        assert_eq!(erase_count(&buf), count);
+        // a torn record ends the replay
+        let mut log = wal::WAL::new(26, wal::WAL_BLOCK_NUM, Arc::clone(&buf));
+        log.append(&[3], &[3]).unwrap();
+        log.sync().unwrap();
+        log.append(&[4], &[4; 5000]).unwrap();
+        log.sync().unwrap();
+        let mut pages = vec![];
+        for i in 0..3 {
+            pages.push(buf.write().read(0, 26 * 128 + i).unwrap());
+        }
+        pages[2][16] ^= 0xff;
+        buf.write().erase(0, 26).unwrap();
+        for (i, page) in pages.iter().enumerate() {
+            buf.write().write(0, 26 * 128 + i as u32, page);
+        }
+        buf.read().translation_layer.flush().unwrap();
+        let buf = reopen(&buf);
+        let mut kv = lsm_tree::LSMTree::new(Arc::clone(&buf));
+        kv.mount(10, 20, wal::WAL_BLOCK_NUM, false).unwrap();
+        assert_eq!(kv.get(&vec![3]).unwrap(), Some(vec![3]));
+        assert_eq!(kv.get(&vec![4]).unwrap(), None);
+        assert_eq!(kv.get(&vec![2]).unwrap(), Some(vec![2]));
+        let mut log = wal::WAL::new(26, wal::WAL_BLOCK_NUM, Arc::clone(&buf));
+        assert!(log.replay().unwrap().is_empty());
+        assert_eq!(log.offset, 0);
+    }
+
+    #[test]
+    fn test_wal_group_commit() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let buf = Arc::new(RwLock::new(buf::BufCache::new(Arc::new(tl))));
+        let mut kv = lsm_tree::LSMTree::new(Arc::clone(&buf));
+        kv.mount(10, 20, wal::WAL_BLOCK_NUM, false).unwrap();
+        for i in 0..100 {
+            kv.put(&vec![i], &vec![i; 10]).unwrap();
+        }
+        // a sync takes the log to the disk and leaves the other cached pages where they are
+        buf.write().write(0, 5 * 128, &[1; 4096]);
+        kv.sync().unwrap();
+        kv.put(&vec![200], &vec![200]).unwrap();
+        let buf = reopen(&buf);
+        assert_eq!(buf.write().read(0, 5 * 128).unwrap(), [0; 4096]);
+        // the hundred records share one page
+        assert_ne!(buf.write().read(0, 26 * 128).unwrap(), [0; 4096]);
+        assert_eq!(buf.write().read(0, 26 * 128 + 1).unwrap(), [0; 4096]);
+        let mut kv = lsm_tree::LSMTree::new(Arc::clone(&buf));
+        kv.mount(10, 20, wal::WAL_BLOCK_NUM, true).unwrap();
+        for i in 0..100 {
+            assert_eq!(kv.get(&vec![i]).unwrap(), Some(vec![i; 10]));
+        }
+        assert_eq!(kv.get(&vec![200]).unwrap(), None);
+        // a second group starts on a fresh page behind the padding of the first
+        let mut log = wal::WAL::new(26, wal::WAL_BLOCK_NUM, Arc::clone(&buf));
+        assert_eq!(log.replay().unwrap().len(), 100);
+        assert_eq!(log.offset, 1);
+        log.append(&[201], &[201]).unwrap();
+        log.sync().unwrap();
+        let mut log = wal::WAL::new(26, wal::WAL_BLOCK_NUM, Arc::clone(&buf));
+        assert_eq!(log.replay().unwrap().len(), 101);
+        assert_eq!(log.offset, 2);
+    }
+
+    // a fresh translation layer over the disk image, the write cache of the old one is lost
+    fn reopen(buf: &Arc<RwLock<buf::BufCache>>) -> Arc<RwLock<buf::BufCache>> {
+        let disk = buf.read().translation_layer.disk_manager.read().fake_disk.as_ref().unwrap().clone();
+        let mut tl = tl::TranslationLayer::with_disk_manager(crate::driver::disk_manager::DiskManager::with_fake_disk(disk));
+        tl.init().unwrap();
+        Arc::new(RwLock::new(buf::BufCache::new(Arc::new(tl))))
+    }
+
     #[test]
     fn test_lsm_tree() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let buf = buf::BufCache::new(Arc::new(tl));
         let mut kv = lsm_tree::LSMTree::new(Arc::new(RwLock::new(buf)));
-        kv.put(&"a".as_bytes().to_vec(), &"b".as_bytes().to_vec());
-        kv.put(&"ssa".as_bytes().to_vec(), &"ada".as_bytes().to_vec());
-        assert_eq!(kv.get(&"a".as_bytes().to_vec()).unwrap(), "b".as_bytes().to_vec());
-        assert_eq!(kv.get(&"ssa".as_bytes().to_vec()).unwrap(), "ada".as_bytes().to_vec());
-        kv.delete(&"a".as_bytes().to_vec());
-        assert_eq!(kv.get(&"a".as_bytes().to_vec()), None);
+        kv.put(&"a".as_bytes().to_vec(), &"b".as_bytes().to_vec()).unwrap();
+        kv.put(&"ssa".as_bytes().to_vec(), &"ada".as_bytes().to_vec()).unwrap();
+        assert_eq!(kv.get(&"a".as_bytes().to_vec()).unwrap().unwrap(), "b".as_bytes().to_vec());
+        assert_eq!(kv.get(&"ssa".as_bytes().to_vec()).unwrap().unwrap(), "ada".as_bytes().to_vec());
+        kv.delete(&"a".as_bytes().to_vec()).unwrap();
+        assert_eq!(kv.get(&"a".as_bytes().to_vec()).unwrap(), None);
         for i in 0..100 {
             let mut ret = [0; 4];
             ret[0] = (i >> 24) as u8;
@@ -170,7 +444,7 @@ mod test {
             let ret = ret.to_vec();
             let mut key = vec![1, 2, 3, 4];
             key.extend_from_slice(&ret);
-            kv.put(&key, &"test".as_bytes().to_vec());
+            kv.put(&key, &"test".as_bytes().to_vec()).unwrap();
         }
         for i in 0..100 {
             let mut ret = [0; 4];
@@ -181,7 +455,7 @@ mod test {
             let ret = ret.to_vec();
             let mut key = vec![1, 2, 3, 4];
             key.extend_from_slice(&ret);
-            assert_eq!(kv.get(&key).unwrap(), "test".as_bytes().to_vec());
+            assert_eq!(kv.get(&key).unwrap().unwrap(), "test".as_bytes().to_vec());
         }
         for i in 0..82345 as usize {
             let mut ret = [0; 4];
@@ -194,7 +468,7 @@ mod test {
             key.extend_from_slice(&ret);
             let mut value = vec![3, 4];
             value.extend_from_slice(&ret);
-            kv.put(&key, &value);
+            kv.put(&key, &value).unwrap();
         }
         for i in 0..82345 as usize {
             let mut ret = [0; 4];
@@ -207,7 +481,7 @@ mod test {
             key.extend_from_slice(&ret);
             let mut value = vec![3, 4];
             value.extend_from_slice(&ret);
-            assert_eq!(kv.get(&key).unwrap(), value);
+            assert_eq!(kv.get(&key).unwrap().unwrap(), value);
         }
     }
  } 
\ No newline at end of file
@@ -1,4 +1,5 @@
 use std::cmp::Ordering;
+use crate::util::crc32::crc32;
 
 pub static EOF: &str = "EOF";
 
@@ -24,8 +25,8 @@ impl Entry {
 
     pub fn encode_entry(&mut self) -> Vec<u8> {
         let mut data = vec![];
-        let crc32 = 0;
-        data.append(&mut Entry::encode_u32(crc32));
+        self.crc32 = Entry::checksum(&self.key, &self.value);
+        data.append(&mut Entry::encode_u32(self.crc32));
         data.append(&mut Entry::encode_u32(self.key_size));
         data.append(&mut Entry::encode_u32(self.value_size));
         data.append(&mut self.key);
@@ -33,6 +34,15 @@ impl Entry {
         data
     }
 
+    // covers the sizes too, so a flipped length is caught before it misreads the next entry
+    pub fn checksum(key: &[u8], value: &[u8]) -> u32 {
+        let mut data = Entry::encode_u32(key.len() as u32);
+        data.append(&mut Entry::encode_u32(value.len() as u32));
+        data.extend_from_slice(key);
+        data.extend_from_slice(value);
+        crc32(&data)
+    }
+
     pub fn encode_u32(data: u32) -> Vec<u8> {
         let mut ret = [0; 4];
         ret[0] = (data >> 24) as u8;
@@ -3,14 +3,27 @@ use spin::RwLock;
 use alloc::sync::Arc;
 use std::collections::HashMap;
 use std::collections::BTreeMap;
-use std::cmp::Ordering;
-use std::cmp::min;
 use super::file_iter;
 use super::entry;
 use super::raw_entry;
+use super::bloom;
 use crate::buf;
+use crate::util::crc32::crc32;
+use crate::error::{WondError, WondResult};
 
-pub const MAGIC_NUMBER: u32 = 0x2222ffff;
+pub const MAGIC_NUMBER: u32 = 0x2222fffe;
+// tables written before entries and blocks carried checksums, still read but never verified
+pub const LEGACY_MAGIC_NUMBER: u32 = 0x2222ffff;
+pub const HEADER_SIZE: usize = 12;
+pub const EOF_SIZE: usize = 18;
+pub const MERGE_NUM: usize = 4;
+
+// a table laid out for writing, the filter and index come first and entries start on start_page
+pub struct TableData {
+    pub start_page: usize,
+    pub meta: Vec<u8>,
+    pub blocks: Vec<Vec<u8>>,
+}
 
 pub struct SSTableManager {
     pub sstable_num: usize,
@@ -22,6 +35,9 @@ pub struct SSTableManager {
     pub file_table: HashMap<u32, Vec<u32>>,
     pub block_table: HashMap<u32, bool>,
     pub file_iter: HashMap<u32, file_iter::FileIter>,
+    // tables that failed their checksums on build, their blocks stay reserved so nothing
+    // overwrites what is left of them until the region is formatted
+    pub quarantine: BTreeMap<u32, (u32, usize)>,
     pub buf: Arc<RwLock<buf::BufCache>>,
 }
 
@@ -38,225 +54,354 @@ impl SSTableManager {
             file_table: HashMap::new(),
             block_table: HashMap::new(),
             file_iter: HashMap::new(),
+            quarantine: BTreeMap::new(),
         }
     }
 
-    pub fn build(&mut self) {
+    pub fn build(&mut self) -> WondResult<()> {
+        let mut tables = vec![];
         let mut index = self.block_id;
-        while index <= self.block_id+self.block_num as u32 {
+        while index < self.block_id+self.block_num as u32 {
             let address = index * 128;
-            let data = self.buf.write().read(0, address);
-            if !(data[0] == 0x22 && data[1] == 0x22 && data[2] == 0xff && data[3] == 0xff) {
+            let data = self.buf.write().read(0, address)?;
+            let magic = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            if magic != MAGIC_NUMBER && magic != LEGACY_MAGIC_NUMBER {
                 index += 1;
                 continue;
             }
-            let num = data[4];
+            let num = data[4] as usize;
             let file_id = (data[5] as u32) << 16 | (data[6] as u32) << 8 | data[7] as u32;
-            self.files.insert(file_id, (index, num as usize));
-            self.sstable_num += 1;
-            if file_id > self.sstable_max_id {
-                self.sstable_max_id = file_id;
-            }
-            let mut blocks = vec![];
-            for i in 0..num {
-                self.block_table.insert(index + i as u32, true);
-                blocks.push(index + i as u32)
+            // tables written before compaction have no base and stand for themselves
+            let mut base_id = (data[8] as u32) << 16 | (data[9] as u32) << 8 | data[10] as u32;
+            if base_id == 0 {
+                base_id = file_id;
             }
-            self.file_table.insert(file_id, blocks);
+            // a damaged count must not carry the scan past the region
+            let num = num.clamp(1, (self.block_id + self.block_num as u32 - index) as usize);
+            let corrupted = match self.check_table(index, num) {
+                Ok(()) => None,
+                Err(WondError::Corrupted(reason)) => Some(reason),
+                Err(err) => return Err(err),
+            };
+            tables.push((file_id, base_id, index, num, corrupted));
             index += num as u32;
         }
-        for i in self.block_id..self.block_id+self.block_num as u32 {
-            if self.block_table.get(&i).is_some() && *self.block_table.get(&i).unwrap() {
-                if i == self.block_id + self.block_num as u32 - 1 {
-                    panic!()
+        for &(file_id, base_id, index, num, corrupted) in tables.iter() {
+            // a compaction torn before its inputs were erased leaves them beside the merged table,
+            // a torn shrink leaves a table beside its copy below it, only a table that reads back
+            // cleanly may stand in for them
+            let obsolete = tables.iter().any(|table| table.2 != index && table.4.is_none() && table.1 <= file_id && file_id <= table.0 && (file_id < table.0 || base_id > table.1 || table.2 < index));
+            if obsolete {
+                for i in 0..num as u32 {
+                    self.buf.write().erase(0, index + i)?;
                 }
                 continue;
             }
-            self.cur_block_id = i;
-            break;
+            if file_id > self.sstable_max_id {
+                self.sstable_max_id = file_id;
+            }
+            if let Some(reason) = corrupted {
+                eprintln!("server-fs: quarantined sstable {} at block {}: {}", file_id, index, reason);
+                for block in index..index + num as u32 {
+                    self.block_table.insert(block, true);
+                }
+                self.quarantine.insert(file_id, (index, num));
+                continue;
+            }
+            self.insert_table(file_id, index, num)?;
         }
+        self.cur_block_id = self.alloc_blocks(1)?;
+        Ok(())
+    }
+
+    fn check_table(&self, block_id: u32, block_num: usize) -> WondResult<()> {
+        file_iter::FileIter::open(block_id, block_num, Arc::clone(&self.buf))?.verify()
     }
 
-    pub fn get(&mut self, key: &Vec<u8>) -> Option<Vec<u8>> {
+    pub fn get(&mut self, key: &Vec<u8>) -> WondResult<Option<Vec<u8>>> {
         for (file_id, entry) in self.files.iter().rev() {
-            let mut file_iter = self.file_iter.get_mut(file_id);
-            if file_iter.is_none() {
-                let mut file_iter = file_iter::FileIter::new(entry.0, entry.1, Arc::clone(&self.buf));
-                if let Some(val) = file_iter.get(key) {
-                    self.file_iter.insert(*file_id, file_iter);
-                    return Some(val);
-                }
+            if !self.file_iter.contains_key(file_id) {
+                let file_iter = file_iter::FileIter::open(entry.0, entry.1, Arc::clone(&self.buf))?;
+                self.file_iter.insert(*file_id, file_iter);
+            }
+            if let Some(val) = self.file_iter.get_mut(file_id).unwrap().get(key)? {
+                return Ok(Some(val));
+            }
+        }
+        Ok(None)
+    }
+
+    // one sorted run per table, newest first
+    pub fn scan(&mut self, start: &[u8], end: Option<&[u8]>) -> WondResult<Vec<file_iter::Run>> {
+        let mut runs = vec![];
+        for (file_id, entry) in self.files.iter().rev() {
+            if !self.file_iter.contains_key(file_id) {
+                let file_iter = file_iter::FileIter::open(entry.0, entry.1, Arc::clone(&self.buf))?;
                 self.file_iter.insert(*file_id, file_iter);
-            } else {
-                if let Some(val) = file_iter.as_mut().unwrap().get(key) {
-                    return Some(val);
-                }
             }
+            runs.push(self.file_iter.get_mut(file_id).unwrap().scan(start, end)?);
         }
-        None
+        Ok(runs)
     }
 
-    pub fn flush(&mut self, entries: &Vec<entry::Entry>) {
+    pub fn flush(&mut self, entries: &Vec<entry::Entry>) -> WondResult<()> {
+        // a region the last flush left full is only reported once it is needed
+        if self.is_used(self.cur_block_id) {
+            self.update_cur_block_id()?;
+        }
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = entries.iter().map(|entry| (entry.key.clone(), entry.value.clone())).collect();
+        let table = SSTableManager::pack(&entries)?;
+        let block_id = self.alloc_blocks(table.blocks.len())?;
         self.sstable_max_id += 1;
-        self.sstable_num += 1;
-        self.files.insert(self.sstable_max_id, (self.cur_block_id, 1));
-        self.file_table.insert(self.sstable_max_id, vec![self.cur_block_id]);
-        self.block_table.insert(self.cur_block_id, true);
-        let mut page_data: [u8; 4096] = [0; 4096];
-        page_data[0] = 0x22;
-        page_data[1] = 0x22;
-        page_data[2] = 0xff;
-        page_data[3] = 0xff;
-        page_data[4] = 1;
-        page_data[5] = (self.sstable_max_id >> 16) as u8;
-        page_data[6] = (self.sstable_max_id >> 8) as u8;
-        page_data[7] = self.sstable_max_id as u8;
-        let mut index = 0;
-        let mut size = 12;
-        for entry in entries.iter() {
-            let mut raw_entry = raw_entry::Entry::new(entry.key.clone(), entry.value.clone());
-            let data = raw_entry.encode_entry();
-            let write_num = min(4096 - size, data.len());
-            page_data[size..size+write_num].copy_from_slice(&data[..write_num]);
-            size += write_num;
-            if write_num != data.len() {
-                self.buf.write().write(0, self.cur_block_id * 128 + index, &page_data);
-                index += 1;
-                size = 0;
-                page_data = [0; 4096];
-                let mut write_num = write_num;
-                let mut remain_num = data.len() - write_num;
-                while remain_num != 0 {
-                    write_num = min(4096, remain_num);
-                    page_data[..write_num].copy_from_slice(&data[data.len()-remain_num..data.len()-remain_num+write_num]);
-                    size += write_num;
-                    remain_num -= write_num;
-                    if remain_num != 0 {
-                        self.buf.write().write(0, self.cur_block_id * 128 + index, &page_data);
-                        index += 1;
-                        size = 0;
-                        page_data = [0; 4096];
-                    }
+        self.write_table(self.sstable_max_id, self.sstable_max_id, block_id, &table)?;
+        self.insert_table(self.sstable_max_id, block_id, table.blocks.len())?;
+        self.compact()?;
+        match self.update_cur_block_id() {
+            Err(WondError::NoSpace) => Ok(()),
+            ret => ret,
+        }
+    }
+
+    // entries must be sorted, the filter and index grow with them so the packing repeats until they fit
+    pub fn pack(entries: &[(Vec<u8>, Vec<u8>)]) -> WondResult<TableData> {
+        let mut filter = bloom::BloomFilter::new(entries.len());
+        for (key, _) in entries.iter() {
+            filter.insert(key);
+        }
+        let mut start_page = 1;
+        loop {
+            let mut blocks: Vec<Vec<u8>> = vec![vec![]];
+            let mut index = vec![];
+            let mut last_page = None;
+            for (key, value) in entries.iter() {
+                let data = raw_entry::Entry::new(key.clone(), value.clone()).encode_entry();
+                if HEADER_SIZE + data.len() + EOF_SIZE > 128 * 4096 {
+                    return Err(WondError::NoSpace);
+                }
+                let base = if blocks.len() == 1 { start_page * 4096 } else { HEADER_SIZE };
+                if base + blocks.last().unwrap().len() + data.len() + EOF_SIZE > 128 * 4096 {
+                    blocks.push(vec![]);
+                }
+                let block = blocks.len() - 1;
+                let base = if block == 0 { start_page * 4096 } else { HEADER_SIZE };
+                let pos = base + blocks[block].len();
+                if last_page != Some((block, pos / 4096)) {
+                    last_page = Some((block, pos / 4096));
+                    index.push(file_iter::IndexEntry {
+                        key: key.clone(),
+                        block: block as u8,
+                        page: (pos / 4096) as u8,
+                        offset: (pos % 4096) as u16,
+                    });
+                }
+                blocks[block].extend_from_slice(&data);
+            }
+            let mut meta = filter.encode();
+            meta.extend_from_slice(&raw_entry::Entry::encode_u32(index.len() as u32));
+            for entry in index.iter() {
+                meta.extend_from_slice(&raw_entry::Entry::encode_u32(entry.key.len() as u32));
+                meta.extend_from_slice(&entry.key);
+                meta.push(entry.block);
+                meta.push(entry.page);
+                meta.push((entry.offset >> 8) as u8);
+                meta.push(entry.offset as u8);
+            }
+            let page_num = (HEADER_SIZE + meta.len()).div_ceil(4096);
+            if page_num >= 128 {
+                return Err(WondError::NoSpace);
+            }
+            if page_num <= start_page {
+                return Ok(TableData {
+                    start_page,
+                    meta,
+                    blocks,
+                });
+            }
+            start_page = page_num;
+        }
+    }
+
+    // every block holds whole entries closed by an EOF entry, only the first carries the header
+    pub fn write_table(&mut self, file_id: u32, base_id: u32, block_id: u32, table: &TableData) -> WondResult<()> {
+        let mut header = vec![0; 4096];
+        for (i, data) in table.blocks.iter().enumerate() {
+            let block_no = block_id + i as u32;
+            // a torn compaction may have left pages here without a header
+            self.buf.write().erase(0, block_no)?;
+            let mut raw = vec![0; HEADER_SIZE];
+            if i == 0 {
+                raw.copy_from_slice(&SSTableManager::encode_header(file_id, base_id, table));
+                raw.extend_from_slice(&table.meta);
+                raw.resize(table.start_page * 4096, 0);
+            }
+            raw.extend_from_slice(data);
+            // the eof entry is the block's footer, its crc covers every byte before it
+            let mut eof_entry = raw_entry::Entry::new(raw_entry::EOF.as_bytes().to_vec(), raw_entry::EOF.as_bytes().to_vec());
+            let mut eof = eof_entry.encode_entry();
+            eof[..4].copy_from_slice(&raw_entry::Entry::encode_u32(crc32(&raw)));
+            raw.extend_from_slice(&eof);
+            for (index, page) in raw.chunks(4096).enumerate() {
+                let mut page_data = [0; 4096];
+                page_data[..page.len()].copy_from_slice(page);
+                if i == 0 && index == 0 {
+                    header = page_data.to_vec();
+                    continue;
                 }
+                self.buf.write().write(0, block_no * 128 + index as u32, &page_data);
+            }
+        }
+        // the header goes down last, so build never finds a table missing its tail
+        let translation_layer = Arc::clone(&self.buf.read().translation_layer);
+        translation_layer.flush()?;
+        let mut page_data = [0; 4096];
+        page_data.copy_from_slice(&header);
+        self.buf.write().write(0, block_id * 128, &page_data);
+        translation_layer.flush()
+    }
+
+    fn encode_header(file_id: u32, base_id: u32, table: &TableData) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[..4].copy_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        header[4] = table.blocks.len() as u8;
+        header[5] = (file_id >> 16) as u8;
+        header[6] = (file_id >> 8) as u8;
+        header[7] = file_id as u8;
+        header[8] = (base_id >> 16) as u8;
+        header[9] = (base_id >> 8) as u8;
+        header[10] = base_id as u8;
+        header[11] = table.start_page as u8;
+        header
+    }
+
+    // size-tiered, merges the oldest run of adjacent tables whose block counts share a power of two
+    pub fn compact(&mut self) -> WondResult<bool> {
+        let mut run = vec![];
+        let mut tier = 0;
+        for (file_id, entry) in self.files.iter() {
+            let cur_tier = usize::BITS - entry.1.leading_zeros();
+            if run.is_empty() || cur_tier != tier {
+                if run.len() >= MERGE_NUM {
+                    break;
+                }
+                run.clear();
+                tier = cur_tier;
+            }
+            run.push(*file_id);
+        }
+        if run.len() < MERGE_NUM {
+            return Ok(false);
+        }
+        self.merge(&run)
+    }
+
+    // file_ids must be adjacent and ascending, the merged table takes the newest id
+    pub fn merge(&mut self, file_ids: &[u32]) -> WondResult<bool> {
+        let base_id = file_ids[0];
+        let file_id = file_ids[file_ids.len() - 1];
+        // tombstones only shadow older tables, once none is left they can go
+        let is_oldest = self.files.keys().next() == Some(&base_id);
+        let mut entries = BTreeMap::new();
+        for id in file_ids.iter() {
+            let (block_id, block_num) = self.files[id];
+            let mut file_iter = file_iter::FileIter::open(block_id, block_num, Arc::clone(&self.buf))?;
+            for entry in file_iter.entries()? {
+                entries.insert(entry.key, entry.value);
             }
         }
-        let mut eof_entry = raw_entry::Entry::new(raw_entry::EOF.as_bytes().to_vec(), raw_entry::EOF.as_bytes().to_vec());
-        let data = eof_entry.encode_entry();
-        page_data[size..size+data.len()].copy_from_slice(&data);
-        self.buf.write().write(0, self.cur_block_id * 128 + index, &page_data);
-        self.update_cur_block_id();
+        let tombstone = entry::TOMBSTONE.as_bytes();
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = entries.into_iter().filter(|entry| !(is_oldest && entry.1 == tombstone)).collect();
+        let table = SSTableManager::pack(&entries)?;
+        // too full to merge into, the next flush tries again
+        let block_id = match self.alloc_blocks(table.blocks.len()) {
+            Ok(block_id) => block_id,
+            Err(WondError::NoSpace) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        self.write_table(file_id, base_id, block_id, &table)?;
+        for id in file_ids.iter() {
+            self.remove_table(*id)?;
+        }
+        self.insert_table(file_id, block_id, table.blocks.len())?;
+        Ok(true)
     }
 
-    pub fn update_cur_block_id(&mut self) {
-        if self.cur_block_id == self.block_id + self.block_num as u32 - 1 {
-            self.cur_block_id = self.block_id;
-        } else {
-            self.cur_block_id += 1;
+    // gives up the blocks past the first block_num, the tables on them are written again below
+    pub fn shrink(&mut self, block_num: usize) -> WondResult<()> {
+        let end = self.block_id + block_num as u32;
+        if self.quarantine.values().any(|entry| entry.0 + entry.1 as u32 > end) {
+            return Err(WondError::Corrupted("damaged sstable sits on blocks the region gives up"));
         }
-        let mut loop_num = 0;
-        while self.block_table.get(&self.cur_block_id).is_some() && *self.block_table.get(&self.cur_block_id).unwrap() {
-            self.cur_block_id += 1;
-            loop_num += 1;
-            if loop_num == self.block_num {
-                panic!();
+        self.block_num = block_num;
+        let moved: Vec<u32> = self.files.iter().filter(|(_, entry)| entry.0 + entry.1 as u32 > end).map(|(file_id, _)| *file_id).collect();
+        for file_id in moved {
+            let (block_id, num) = self.files[&file_id];
+            let header = self.buf.write().read(0, block_id * 128)?;
+            let mut base_id = (header[8] as u32) << 16 | (header[9] as u32) << 8 | header[10] as u32;
+            if base_id == 0 {
+                base_id = file_id;
             }
+            let mut file_iter = file_iter::FileIter::open(block_id, num, Arc::clone(&self.buf))?;
+            let entries: Vec<(Vec<u8>, Vec<u8>)> = file_iter.entries()?.into_iter().map(|entry| (entry.key, entry.value)).collect();
+            let table = SSTableManager::pack(&entries)?;
+            let new_block_id = self.alloc_blocks(table.blocks.len())?;
+            // the copy is down before the table goes, build keeps the copy if a crash leaves both
+            self.write_table(file_id, base_id, new_block_id, &table)?;
+            self.remove_table(file_id)?;
+            self.insert_table(file_id, new_block_id, table.blocks.len())?;
         }
+        Ok(())
     }
 
-    // pub fn merge(&mut self) {
-    //     // 这里有问题
-    //     let mut a = None;
-    //     let mut b = None;
-    //     for entry in self.files.iter() {
-    //         if a.is_none() {
-    //             a = Some(entry.0);
-    //         }
-    //         if b.is_none() {
-    //             b = Some(entry.0);
-    //         }
-    //         break;
-    //     }
-    //     let a_block = self.files.get(&a.unwrap()).unwrap();
-    //     let b_block = self.files.get(&b.unwrap()).unwrap();
-    //     let mut a_data = file_iter::FileIter::new(a_block.0, a_block.1, Arc::clone(&self.buf));
-    //     let mut b_data = file_iter::FileIter::new(b_block.0, b_block.1, Arc::clone(&self.buf));
-    //     let mut page_data: [u8; 4096] = [0; 4096];
-    //     let mut index = 0;
-    //     let mut size = 0;
-    //     let mut a_key: Option<Vec<u8>> = None;
-    //     let mut b_key: Option<Vec<u8>> = None;
-    //     let mut a_value: Option<Vec<u8>> = None;
-    //     let mut b_value: Option<Vec<u8>> = None;
-    //     loop {
-    //         if a_key.is_none() && a_data.has_next() {
-    //             let res = a_data.next().unwrap();
-    //             a_key = Some(res.key);
-    //             a_value = Some(res.value);
-    //         }
-    //         if b_key.is_none() && b_data.has_next() {
-    //             let res = b_data.next().unwrap();
-    //             b_key = Some(res.key);
-    //             b_value = Some(res.value);
-    //         }
-    //         if a_key.is_none() && b_key.is_none() && a_data.has_next() == false && b_data.has_next() == false {
-    //             break;
-    //         }
-    //         let k: Vec<u8>;
-    //         let v: Vec<u8>;
-    //         if !a_key.is_none() && !b_key.is_none() {
-    //             let cmp = a_key.clone().unwrap().cmp(&b_key.clone().unwrap());
-    //             if cmp == Ordering::Equal {
-    //                 k = b_key.unwrap();
-    //                 v = b_value.unwrap();
-    //                 a_key = None;
-    //                 a_value = None;
-    //                 b_key = None;
-    //                 b_value = None;
-    //             } else if cmp == Ordering::Greater {
-    //                 k = b_key.unwrap();
-    //                 v = b_value.unwrap();
+    pub fn update_cur_block_id(&mut self) -> WondResult<()> {
+        self.cur_block_id = self.block_id + (self.cur_block_id + 1 - self.block_id) % self.block_num as u32;
+        self.cur_block_id = self.alloc_blocks(1)?;
+        Ok(())
+    }
 
-    //                 b_key = None;
-    //                 b_value = None;
-    //             } else {
-    //                 k = a_key.unwrap();
-    //                 v = a_value.unwrap();
+    // the first run of num free blocks from cur_block_id on, wrapping around the region
+    pub fn alloc_blocks(&self, num: usize) -> WondResult<u32> {
+        let end = self.block_id + self.block_num as u32;
+        for i in 0..self.block_num as u32 {
+            let start = self.block_id + (self.cur_block_id.max(self.block_id) - self.block_id + i) % self.block_num as u32;
+            if start + num as u32 > end {
+                continue;
+            }
+            if (start..start + num as u32).all(|block| !self.is_used(block)) {
+                return Ok(start);
+            }
+        }
+        Err(WondError::NoSpace)
+    }
 
-    //                 a_key = None;
-    //                 a_value = None;
-    //             }
-    //         } else if !a_key.is_none() {
-    //             k = a_key.unwrap();
-    //             v = a_value.unwrap();
-    //             a_key = None;
-    //             a_value = None;
-    //         } else {
-    //             k = b_key.unwrap();
-    //             v = b_value.unwrap();
-    //             b_key = None;
-    //             b_value = None;
-    //         }
-    //         let mut raw_entry = raw_entry::Entry::new(k, v);
-    //         let data = raw_entry.encode_entry();
-    //         if size + data.len() > 4096 {
-    //             if index == 128 {
-    //                 self.update_cur_block_id();
-    //                 index = 0;
-    //             }
-    //             self.buf.write().write(0, self.cur_block_id * 128 + index as u32, &page_data);
-    //             index += 1;
-    //             size = 0;
-    //             page_data = [0; 4096];
-    //         }
-    //         page_data[size..size+data.len()].copy_from_slice(&data);
-    //         size += data.len();
-    //     }
-    //     let mut eof_entry = raw_entry::Entry::new(raw_entry::EOF.as_bytes().to_vec(), raw_entry::EOF.as_bytes().to_vec());
-    //     let data = eof_entry.encode_entry();
-    //     page_data[size..size+data.len()].copy_from_slice(&data);
-    //     self.buf.write().write(0, self.cur_block_id * 128 + index as u32, &page_data);
-    //     self.update_cur_block_id();
-    // }
+    pub fn is_used(&self, block_id: u32) -> bool {
+        self.block_table.get(&block_id).is_some_and(|used| *used)
+    }
+
+    fn insert_table(&mut self, file_id: u32, block_id: u32, block_num: usize) -> WondResult<()> {
+        let blocks: Vec<u32> = (block_id..block_id + block_num as u32).collect();
+        for block in blocks.iter() {
+            self.block_table.insert(*block, true);
+        }
+        self.files.insert(file_id, (block_id, block_num));
+        self.file_table.insert(file_id, blocks);
+        self.sstable_num += 1;
+        self.file_iter.insert(file_id, file_iter::FileIter::open(block_id, block_num, Arc::clone(&self.buf))?);
+        Ok(())
+    }
+
+    // erasing drops the header too, so build will not bring the table back
+    fn remove_table(&mut self, file_id: u32) -> WondResult<()> {
+        self.files.remove(&file_id);
+        self.file_iter.remove(&file_id);
+        self.sstable_num -= 1;
+        if let Some(blocks) = self.file_table.remove(&file_id) {
+            for block in blocks {
+                self.buf.write().erase(0, block)?;
+                self.block_table.remove(&block);
+            }
+        }
+        Ok(())
+    }
 
     pub fn clear(&mut self) {
         self.cur_block_id = 0;
@@ -265,5 +410,7 @@ impl SSTableManager {
         self.files = BTreeMap::new();
         self.file_table = HashMap::new();
         self.block_table = HashMap::new();
+        self.file_iter = HashMap::new();
+        self.quarantine = BTreeMap::new();
     }
 }
\ No newline at end of file
@@ -0,0 +1,144 @@
+extern crate alloc;
+use spin::RwLock;
+use alloc::sync::Arc;
+use crate::buf;
+use crate::error::{WondError, WondResult};
+use crate::util::crc32::crc32;
+
+pub const MAGIC_NUMBER: u32 = 0x4444ffff;
+pub const WAL_BLOCK_NUM: usize = 4;
+pub const HEADER_SIZE: usize = 16;
+
+// records are packed into pages behind each other and go to the disk in groups. a page is
+// programmed once it is full or at sync, which pads it out, the next group starts on a fresh page
+pub struct WAL {
+    pub block_id: u32,
+    pub block_num: usize,
+    pub offset: u32,
+    pub pending: Vec<u8>,
+    pub buf: Arc<RwLock<buf::BufCache>>,
+}
+
+impl WAL {
+    pub fn new(block_id: u32, block_num: usize, buf: Arc<RwLock<buf::BufCache>>) -> WAL {
+        WAL {
+            block_id,
+            block_num,
+            offset: 0,
+            pending: vec![],
+            buf,
+        }
+    }
+
+    pub fn can_append(&self, key: &[u8], value: &[u8]) -> bool {
+        let size = self.pending.len() + HEADER_SIZE + key.len() + value.len();
+        self.offset + size.div_ceil(4096) as u32 <= self.block_num as u32 * 128
+    }
+
+    // the record only reaches the disk with its group, a put is not durable before the next sync
+    pub fn append(&mut self, key: &[u8], value: &[u8]) -> WondResult<()> {
+        if !self.can_append(key, value) {
+            return Err(WondError::NoSpace);
+        }
+        let mut data = vec![0; HEADER_SIZE];
+        data[0] = (MAGIC_NUMBER >> 24) as u8;
+        data[1] = (MAGIC_NUMBER >> 16) as u8;
+        data[2] = (MAGIC_NUMBER >> 8) as u8;
+        data[3] = MAGIC_NUMBER as u8;
+        data[4..8].copy_from_slice(&(key.len() as u32).to_be_bytes());
+        data[8..12].copy_from_slice(&(value.len() as u32).to_be_bytes());
+        data.extend_from_slice(key);
+        data.extend_from_slice(value);
+        let crc = crc32(&data[HEADER_SIZE..]);
+        data[12..16].copy_from_slice(&crc.to_be_bytes());
+        self.pending.extend_from_slice(&data);
+        while self.pending.len() >= 4096 {
+            let page: Vec<u8> = self.pending.drain(..4096).collect();
+            self.write_page(&page);
+        }
+        Ok(())
+    }
+
+    fn write_page(&mut self, page: &[u8]) {
+        let mut page_data = [0; 4096];
+        page_data[..page.len()].copy_from_slice(page);
+        self.buf.write().write(0, self.block_id * 128 + self.offset, &page_data);
+        self.offset += 1;
+    }
+
+    // pads out the last page of the group and takes the log pages, and nothing else, to the disk
+    pub fn sync(&mut self) -> WondResult<()> {
+        if !self.pending.is_empty() {
+            let page = std::mem::take(&mut self.pending);
+            self.write_page(&page);
+        }
+        let translation_layer = Arc::clone(&self.buf.read().translation_layer);
+        translation_layer.flush_range(self.block_id * 128..(self.block_id + self.block_num as u32) * 128)
+    }
+
+    // stops at the first page that is clean or holds a torn record. pages reach the disk in
+    // address order, so a group missing a page left nothing behind it. a record header that
+    // does not check out in the middle of a page is the padding that ends a group
+    pub fn replay(&mut self) -> WondResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut records = vec![];
+        let end = self.block_num * 128 * 4096;
+        let mut log = vec![];
+        let mut pos = 0;
+        let mut torn = false;
+        while self.load(&mut log, pos + HEADER_SIZE)? {
+            let header = &log[pos..pos + HEADER_SIZE];
+            if u32::from_be_bytes([header[0], header[1], header[2], header[3]]) != MAGIC_NUMBER {
+                if pos % 4096 != 0 {
+                    pos = pos.next_multiple_of(4096);
+                    continue;
+                }
+                self.load(&mut log, pos + 4096)?;
+                torn = log[pos..pos + 4096] != [0; 4096];
+                break;
+            }
+            let key_size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            let value_size = u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize;
+            let crc = u32::from_be_bytes([header[12], header[13], header[14], header[15]]);
+            let size = HEADER_SIZE + key_size + value_size;
+            if size > end - pos || !self.load(&mut log, pos + size)? {
+                torn = true;
+                break;
+            }
+            if crc32(&log[pos + HEADER_SIZE..pos + size]) != crc {
+                torn = true;
+                break;
+            }
+            let key = log[pos + HEADER_SIZE..pos + HEADER_SIZE + key_size].to_vec();
+            let value = log[pos + HEADER_SIZE + key_size..pos + size].to_vec();
+            records.push((key, value));
+            pos += size;
+        }
+        // appends go on from the page behind the last record, a torn one may have left pages
+        // past it, so the next truncate erases it all
+        self.offset = if torn { end / 4096 } else { pos.div_ceil(4096) } as u32;
+        self.pending.clear();
+        Ok(records)
+    }
+
+    // reads pages into the log until it holds size bytes, false if the region ends before
+    fn load(&self, log: &mut Vec<u8>, size: usize) -> WondResult<bool> {
+        if size > self.block_num * 128 * 4096 {
+            return Ok(false);
+        }
+        while log.len() < size {
+            let page = self.buf.write().read(0, self.block_id * 128 + (log.len() / 4096) as u32)?;
+            log.extend_from_slice(&page);
+        }
+        Ok(true)
+    }
+
+    pub fn truncate(&mut self) -> WondResult<()> {
+        let block_num = self.offset.div_ceil(128);
+        for i in 0..block_num {
+            self.buf.write().erase(0, self.block_id + i)?;
+        }
+        self.offset = 0;
+        self.pending.clear();
+        Ok(())
+    }
+}
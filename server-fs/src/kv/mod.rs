@@ -10,55 +10,307 @@ mod tests {
     use super::*;
     extern crate alloc;
     use crate::tl::tl;
+    use crate::compress::compress;
+    use crate::util::time::Timespec;
     use alloc::sync::Arc;
 
     #[test]
     fn test_kv_manager() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let tl = Arc::new(tl);
-        let mut kv = kv::KV::new(Arc::clone(&tl));
-        kv.mount();
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
         let mut metadata = kv::InodeMetadata {
             file_type: 0,
             ino: 0,
             size: 0,
             n_link: 1,
-            last_accessed: 0,
-            last_modified: 0,
-            last_metadata_changed: 0,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
         };
-        let ino = kv.allocate_indoe(&mut metadata);
+        let (total, free) = kv.get_space_stat();
+        assert_eq!(total, free);
+        let ino = kv.allocate_indoe(&mut metadata).unwrap();
+        assert_eq!(kv.get_inode_num(), 1);
         let data = vec![111; 6000];
-        kv.set_inode_data(ino, 0, data.len(), &data);
-        kv.delete_inode(ino);
-        let metadata = kv.get_inode_metadata(ino);
+        kv.set_inode_data(ino, 0, data.len(), &data).unwrap();
+        assert!(kv.get_space_stat().1 < free);
+        kv.delete_inode(ino).unwrap();
+        assert_eq!(kv.get_inode_num(), 0);
+        // the deleted pages are dirty and stay used until gc erases them
+        let dirty = kv.manager.read().gc.get_page_stat().1;
+        assert!(dirty > 0);
+        assert_eq!(kv.get_space_stat(), (total, free - dirty));
+        let metadata = kv.get_inode_metadata(ino).unwrap();
         assert!(metadata.is_none());
         let data = kv.get_inode_data(ino, 0, 0);
         assert_eq!(data.unwrap(), vec![]);
+        assert_eq!(kv.manager.write().get(&String::from("x"), 0, 0), Err(crate::error::WondError::Unsupported("key has no object prefix")));
+    }
+
+    #[test]
+    fn test_kv_wal() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let tl = Arc::new(tl);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        let mut metadata = kv::InodeMetadata {
+            file_type: 0,
+            ino: 0,
+            size: 0,
+            n_link: 1,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+        };
+        let ino = kv.allocate_indoe(&mut metadata).unwrap();
+        kv.set_inode_data(ino, 0, 6000, &vec![111; 6000]).unwrap();
+        tl.flush().unwrap();
+        kv.sync().unwrap();
+        // the put behind the sync is still waiting for its group
+        kv.allocate_indoe(&mut metadata).unwrap();
+        // dropped without a flush, as after a crash, only what reached the disk comes back
+        drop(kv);
+        let kv = kv::KV::new(reopen(&tl));
+        kv.mount().unwrap();
+        assert_eq!(kv.get_inode_num(), 1);
+        assert_eq!(kv.get_inode_metadata(ino).unwrap().unwrap().size, 6000);
+        assert_eq!(kv.get_inode_data(ino, 0, 6000).unwrap(), vec![111; 6000]);
+    }
+
+    // a fresh translation layer over the disk image, the write cache of the old one is lost
+    fn reopen(tl: &Arc<tl::TranslationLayer>) -> Arc<tl::TranslationLayer> {
+        let disk = tl.disk_manager.read().fake_disk.as_ref().unwrap().clone();
+        let mut tl = tl::TranslationLayer::with_disk_manager(crate::driver::disk_manager::DiskManager::with_fake_disk(disk));
+        tl.init().unwrap();
+        Arc::new(tl)
+    }
+
+    #[test]
+    fn test_kv_wal_migration() {
+        use rkyv::ser::{Serializer, serializers::AllocSerializer};
+        use component::super_block::{SuperStat, SuperStatV1};
+        use lsm_tree::{sstable_manager, wal};
+        // an image from before the log, with a table on the last blocks of the kv area
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let stat = SuperStat::default_geometry(component::super_block::DEFAULT_BLOCK_NUM).unwrap();
+        let old = SuperStatV1 {
+            magic_code: stat.magic_code,
+            block_num: stat.block_num,
+            super_block_num: stat.super_block_num,
+            bit_block_num: stat.bit_block_num,
+            pit_block_num: stat.pit_block_num,
+            journal_block_num: stat.journal_block_num,
+            kv_block_num: stat.kv_block_num,
+            main_area_block_num: stat.main_area_block_num,
+            reserved_block_num: stat.reserved_block_num,
+            page_size: stat.page_size,
+            page_num_per_block: stat.page_num_per_block,
+        };
+        let mut serializer = AllocSerializer::<0>::default();
+        serializer.serialize_value(&old).unwrap();
+        let payload = serializer.into_serializer().into_inner().to_vec();
+        let mut page = [0; 4096];
+        page[..4].copy_from_slice(&(payload.len() as u32).to_be_bytes());
+        page[4..4 + payload.len()].copy_from_slice(&payload);
+        tl.disk_manager.write().fake_disk.as_mut().unwrap().data[0] = page;
+        let tl = Arc::new(tl);
+        let buf = Arc::new(spin::RwLock::new(crate::buf::BufCache::new(Arc::clone(&tl))));
+        let end = stat.get_kv_offset() + stat.get_kv_size();
+        let mut tables = sstable_manager::SSTableManager::new(stat.get_kv_offset(), stat.get_kv_size() as usize, buf);
+        let table = sstable_manager::SSTableManager::pack(&[(b"e:inode_num".to_vec(), vec![0, 0, 0, 5])]).unwrap();
+        tables.write_table(1, 1, end - 2, &table).unwrap();
+        // a read-only mount reads the tables where they are and changes nothing
+        let kv = kv::KV::new(reopen(&tl));
+        kv.set_read_only(true);
+        kv.mount().unwrap();
+        assert_eq!(kv.get_inode_num(), 5);
+        assert_eq!(kv.manager.read().super_stat.get_wal_size(), 0);
+        let tl = reopen(&tl);
+        assert_eq!(tl.read(1).unwrap(), [0; 4096]);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        assert_eq!(kv.manager.read().super_stat.get_wal_size(), wal::WAL_BLOCK_NUM as u32);
+        assert_eq!(kv.get_inode_num(), 5);
+        kv.set_extra_value(String::from("migrated"), &vec![1]).unwrap();
+        kv.sync().unwrap();
+        drop(kv);
+        // the new superblock went on the next page, the table moved and the log holds the put
+        let tl = reopen(&tl);
+        assert_eq!(SuperStat::decode(&tl.read(1).unwrap()).unwrap().get_wal_size(), wal::WAL_BLOCK_NUM as u32);
+        assert_eq!(tl.read((end - 2) * 128).unwrap(), [0; 4096]);
+        assert_ne!(tl.read((end - wal::WAL_BLOCK_NUM as u32) * 128).unwrap(), [0; 4096]);
+        let kv = kv::KV::new(tl);
+        kv.mount().unwrap();
+        assert_eq!(kv.get_inode_num(), 5);
+        assert_eq!(kv.get_extra_value(String::from("migrated")).unwrap(), Some(vec![1]));
+    }
+
+    #[test]
+    fn test_kv_wide_ino() {
+        use rkyv::ser::{Serializer, serializers::AllocSerializer};
+        use component::super_block::{SuperStat, SuperStatV2};
+        let metadata = || kv::InodeMetadata {
+            file_type: 0,
+            ino: 0,
+            size: 0,
+            n_link: 1,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+        };
+        let data = vec![111; 6000];
+        // a fresh image keeps 64 bit owners in the PIT
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let tl = Arc::new(tl);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        assert_eq!(kv.get_ino_size(), 8);
+        *kv.max_ino.write() = u32::MAX as u64;
+        let ino = kv.allocate_indoe(&mut metadata()).unwrap();
+        assert_eq!(ino, 1 << 32);
+        kv.set_inode_data(ino, 0, 6000, &data).unwrap();
+        kv.flush().unwrap();
+        tl.flush().unwrap();
+        let kv = kv::KV::new(reopen(&tl));
+        kv.mount().unwrap();
+        assert_eq!(*kv.max_ino.read(), 1 << 32);
+        assert_eq!(kv.get_inode_metadata(ino).unwrap().unwrap().ino, ino);
+        assert_eq!(kv.get_inode_data(ino, 0, 6000).unwrap(), data);
+        assert!(kv.manager.read().pit.table.values().any(|owner| *owner == ino));
+        // an image from before keeps 32 bits of them, its PIT and the numbers it hands out stay narrow
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let stat = SuperStat::default_geometry(component::super_block::DEFAULT_BLOCK_NUM).unwrap();
+        let old = SuperStatV2 {
+            magic_code: stat.magic_code,
+            block_num: stat.block_num,
+            super_block_num: stat.super_block_num,
+            bit_block_num: stat.bit_block_num,
+            pit_block_num: 2,
+            journal_block_num: stat.journal_block_num,
+            kv_block_num: stat.kv_block_num,
+            main_area_block_num: stat.main_area_block_num + stat.pit_block_num - 2,
+            reserved_block_num: stat.reserved_block_num,
+            page_size: stat.page_size,
+            page_num_per_block: stat.page_num_per_block,
+            wal_block_num: stat.wal_block_num,
+        };
+        let mut serializer = AllocSerializer::<0>::default();
+        serializer.serialize_value(&old).unwrap();
+        let mut payload = serializer.into_serializer().into_inner().to_vec();
+        let tag = crate::util::archive::encode(&stat);
+        payload.extend_from_slice(&tag[tag.len() - crate::util::archive::TAG_SIZE..]);
+        *payload.last_mut().unwrap() = 2;
+        let mut page = [0; 4096];
+        page[..4].copy_from_slice(&(payload.len() as u32).to_be_bytes());
+        page[4..4 + payload.len()].copy_from_slice(&payload);
+        tl.disk_manager.write().fake_disk.as_mut().unwrap().data[0] = page;
+        let tl = Arc::new(tl);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        assert_eq!(kv.get_ino_size(), 4);
+        assert_eq!(kv.get_ino_limit(), u32::MAX as u64);
+        let ino = kv.allocate_indoe(&mut metadata()).unwrap();
+        kv.set_inode_data(ino, 0, 6000, &data).unwrap();
+        kv.flush().unwrap();
+        tl.flush().unwrap();
+        // a map entry is a 4 byte address and a 4 byte owner
+        let pit = tl.read(stat.get_pit_offset() * 128).unwrap();
+        assert_eq!(pit[..4], [0x77, 0x77, 0xdd, 0xdd]);
+        assert_eq!(pit[12..16], (ino as u32).to_be_bytes());
+        let kv = kv::KV::new(reopen(&tl));
+        kv.mount().unwrap();
+        assert_eq!(kv.get_inode_data(ino, 0, 6000).unwrap(), data);
+        assert!(kv.manager.read().pit.table.values().any(|owner| *owner == ino));
+        *kv.max_ino.write() = u32::MAX as u64;
+        assert_eq!(kv.allocate_indoe(&mut metadata()), Err(crate::error::WondError::NoSpace));
+    }
+
+    #[test]
+    fn test_kv_layout_from_super_block() {
+        use component::super_block::SuperStat;
+        // a second super block shifts BIT, PIT and journal one block up
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let mut stat = SuperStat::default_geometry(component::super_block::DEFAULT_BLOCK_NUM).unwrap();
+        stat.super_block_num = 2;
+        stat.main_area_block_num -= 1;
+        tl.disk_manager.write().fake_disk.as_mut().unwrap().data[0] = stat.encode();
+        let tl = Arc::new(tl);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        let mut metadata = kv::InodeMetadata {
+            file_type: 0,
+            ino: 0,
+            size: 0,
+            n_link: 1,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+        };
+        let ino = kv.allocate_indoe(&mut metadata).unwrap();
+        kv.set_inode_data(ino, 0, 6000, &vec![111; 6000]).unwrap();
+        kv.flush().unwrap();
+        tl.flush().unwrap();
+        let space = kv.get_space_stat();
+        drop(kv);
+        let tl = reopen(&tl);
+        for address in 128..256 {
+            assert_eq!(tl.read(address).unwrap(), [0; 4096]);
+        }
+        let kv = kv::KV::new(tl);
+        kv.mount().unwrap();
+        assert_eq!(kv.get_space_stat(), space);
+        assert_eq!(kv.get_inode_data(ino, 0, 0).unwrap(), vec![111; 6000]);
     }
 
     #[test]
     fn test_kv_meta_object() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let tl = Arc::new(tl);
-        let mut kv = kv::KV::new(Arc::clone(&tl));
-        kv.mount();
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
         for i in 0..10000 {
             let metadata = kv::InodeMetadata {
                 file_type: 0,
                 ino: i,
                 size: 0,
                 n_link: 1,
-                last_accessed: 0,
-                last_modified: 0,
-                last_metadata_changed: 0,
+                last_accessed: Timespec::default(),
+                last_modified: Timespec::default(),
+                last_metadata_changed: Timespec::default(),
+                created: Timespec::default(),
+                mode: 0o644,
+                uid: 0,
+                gid: 0,
             };
-            kv.set_inode_metadata(i, &metadata);
+            kv.set_inode_metadata(i, &metadata).unwrap();
         }
         for i in 0..10000 {
-            let metadata = kv.get_inode_metadata(i).unwrap();
+            let metadata = kv.get_inode_metadata(i).unwrap().unwrap();
             assert_eq!(metadata.ino, i);
         }
     }
@@ -66,31 +318,35 @@ mod tests {
     #[test]
     fn test_kv_data_object() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let tl = Arc::new(tl);
-        let mut kv = kv::KV::new(Arc::clone(&tl));
-        kv.mount();
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
         let mut metadata = kv::InodeMetadata {
             file_type: 0,
             ino: 0,
             size: 0,
             n_link: 1,
-            last_accessed: 0,
-            last_modified: 0,
-            last_metadata_changed: 0,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
         };
-        let ino = kv.allocate_indoe(&mut metadata);
+        let ino = kv.allocate_indoe(&mut metadata).unwrap();
         let mut off = 0;
         for _ in 0..10 {
             let data = vec![111; 6000];
-            kv.set_inode_data(ino, off, data.len(), &data);
-            off += data.len();
+            kv.set_inode_data(ino, off, data.len(), &data).unwrap();
+            off += data.len() as u64;
         }
         let mut off = 1000;
         for _ in 0..10 {
             let data = vec![222; 2000];
-            kv.set_inode_data(ino, off, data.len(), &data);
-            off += data.len();
+            kv.set_inode_data(ino, off, data.len(), &data).unwrap();
+            off += data.len() as u64;
         }
         let data = kv.get_inode_data(ino, 0, 1000).unwrap();
         assert_eq!(data, vec![111; 1000]);
@@ -98,11 +354,11 @@ mod tests {
         assert_eq!(data, vec![222; 2000 * 10]);
         let data = kv.get_inode_data(ino, 2000 * 10 + 1000, 6000 * 10 - 2000 * 10 - 1000).unwrap();
         assert_eq!(data, vec![111; 6000 * 10 - 2000 * 10 - 1000]);
-        let metadata = kv.get_inode_metadata(ino).unwrap();
+        let metadata = kv.get_inode_metadata(ino).unwrap().unwrap();
         assert_eq!(metadata.size, 60000);
         let data = vec![233; 2000];
-        let metadata = kv.get_inode_metadata(ino).unwrap();
-        kv.set_inode_data(ino, 59000, data.len(), &data);
+        let metadata = kv.get_inode_metadata(ino).unwrap().unwrap();
+        kv.set_inode_data(ino, 59000, data.len(), &data).unwrap();
         assert_eq!(metadata.size, 60000);
         let data = kv.get_inode_data(ino, 59000, 2000).unwrap();
         assert_eq!(data, vec![233; 2000]);
@@ -111,10 +367,10 @@ mod tests {
     #[test]
     fn test_kv_data_object_advanced() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let tl = Arc::new(tl);
-        let mut kv = kv::KV::new(Arc::clone(&tl));
-        kv.mount();
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
         let mut inos = vec![];
         for _ in 0..5 {
             let mut metadata = kv::InodeMetadata {
@@ -122,21 +378,25 @@ mod tests {
                 ino: 0,
                 size: 0,
                 n_link: 1,
-                last_accessed: 0,
-                last_modified: 0,
-                last_metadata_changed: 0,
+                last_accessed: Timespec::default(),
+                last_modified: Timespec::default(),
+                last_metadata_changed: Timespec::default(),
+                created: Timespec::default(),
+                mode: 0o644,
+                uid: 0,
+                gid: 0,
             };
-            let ino = kv.allocate_indoe(&mut metadata);
+            let ino = kv.allocate_indoe(&mut metadata).unwrap();
             inos.push(ino);
             let mut off = 0;
             for _ in 0..4 {
                 let data = vec![111; 5000];
-                kv.set_inode_data(ino, off, data.len(), &data);
-                off += data.len();
+                kv.set_inode_data(ino, off, data.len(), &data).unwrap();
+                off += data.len() as u64;
             }
             let data = vec![222; 2000];
-            kv.set_inode_data(ino, 4000, data.len(), &data);
-            kv.set_inode_data(ino, 19000, data.len(), &data);
+            kv.set_inode_data(ino, 4000, data.len(), &data).unwrap();
+            kv.set_inode_data(ino, 19000, data.len(), &data).unwrap();
         }
         for ino in inos {
             let data = kv.get_inode_data(ino, 0, 4000).unwrap();
@@ -147,62 +407,431 @@ mod tests {
             assert_eq!(data, vec![111; 13000]);
             let data = kv.get_inode_data(ino, 19000, 2000).unwrap();
             assert_eq!(data, vec![222; 2000]);
-            let metadata = kv.get_inode_metadata(ino).unwrap();
+            let metadata = kv.get_inode_metadata(ino).unwrap().unwrap();
             assert_eq!(metadata.size, 21000);
         }
     }
 
+    #[test]
+    fn test_kv_gc_journal() {
+        // data that does not compress, so every extent keeps its pages
+        let mut seed: u32 = 1;
+        let mut random = |len: usize| -> Vec<u8> {
+            (0..len).map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            }).collect()
+        };
+        let data_a = random(3 * 4096);
+        let data_b = random(2 * 4096);
+        let data_c = random(4096);
+        // crash after every step of the group, the last run goes through
+        for steps in 0..=3 {
+            let mut tl = tl::TranslationLayer::new();
+            tl.init().unwrap();
+            let tl = Arc::new(tl);
+            let kv = kv::KV::new(Arc::clone(&tl));
+            kv.mount().unwrap();
+            let mut metadata = kv::InodeMetadata {
+                file_type: 0,
+                ino: 0,
+                size: 0,
+                n_link: 1,
+                last_accessed: Timespec::default(),
+                last_modified: Timespec::default(),
+                last_metadata_changed: Timespec::default(),
+                created: Timespec::default(),
+                mode: 0o644,
+                uid: 0,
+                gid: 0,
+            };
+            let a = kv.allocate_indoe(&mut metadata).unwrap();
+            let b = kv.allocate_indoe(&mut metadata).unwrap();
+            kv.set_inode_data(a, 0, 4096, &data_c).unwrap();
+            kv.set_inode_data(b, 0, 2 * 4096, &data_b).unwrap();
+            kv.set_inode_data(a, 4096, 2 * 4096, &data_a[4096..].to_vec()).unwrap();
+            kv.set_inode_data(a, 0, 4096, &data_a[..4096].to_vec()).unwrap();
+            kv.flush().unwrap();
+            let group = kv.manager.write().gc.new_gc_event(gc::gc_define::GCStrategy::Forward).unwrap();
+            assert_eq!(group.events.len(), 3);
+            kv.manager.write().begin_gc_group(&group).unwrap();
+            for event in group.events.iter().take(steps) {
+                kv.manager.write().run_gc_event(event).unwrap();
+            }
+            if steps == group.events.len() {
+                kv.manager.write().end_gc_group().unwrap();
+            }
+            drop(kv);
+            // a read-only mount leaves the journal for the next writable one and changes nothing
+            let tl = reopen(&tl);
+            let image = tl.disk_manager.read().fake_disk.as_ref().unwrap().data.clone();
+            let kv = kv::KV::new(Arc::clone(&tl));
+            kv.set_read_only(true);
+            kv.mount().unwrap();
+            assert_eq!(kv.get_inode_data(a, 0, 3 * 4096).unwrap(), data_a);
+            assert_eq!(kv.get_inode_data(b, 0, 2 * 4096).unwrap(), data_b);
+            tl.flush().unwrap();
+            assert!(tl.disk_manager.read().fake_disk.as_ref().unwrap().data == image);
+            drop(kv);
+            let kv = kv::KV::new(reopen(&tl));
+            kv.mount().unwrap();
+            assert_eq!(kv.manager.write().read_page(5 * 128, false).unwrap(), [0; 4096]);
+            assert_eq!(kv.get_inode_data(a, 0, 3 * 4096).unwrap(), data_a);
+            assert_eq!(kv.get_inode_data(b, 0, 2 * 4096).unwrap(), data_b);
+            // freed and moved pages are told apart, new data lands elsewhere
+            let c = kv.allocate_indoe(&mut metadata).unwrap();
+            kv.set_inode_data(c, 0, 4096, &data_c).unwrap();
+            assert_eq!(kv.get_inode_data(a, 0, 3 * 4096).unwrap(), data_a);
+            assert_eq!(kv.get_inode_data(b, 0, 2 * 4096).unwrap(), data_b);
+            assert_eq!(kv.get_inode_data(c, 0, 4096).unwrap(), data_c);
+        }
+    }
+
+    #[test]
+    fn test_kv_background_gc() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let tl = Arc::new(tl);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        let mut metadata = kv::InodeMetadata {
+            file_type: 0,
+            ino: 0,
+            size: 0,
+            n_link: 1,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+        };
+        let a = kv.allocate_indoe(&mut metadata).unwrap();
+        let b = kv.allocate_indoe(&mut metadata).unwrap();
+        for i in 0..40u8 {
+            kv.set_inode_data(a, 0, 4096, &vec![i; 4096]).unwrap();
+            kv.set_inode_data(b, i as u64 * 4096, 4096, &vec![i; 4096]).unwrap();
+        }
+        let mut config = gc::gc_define::GCConfig::new();
+        // plenty of room, the pass does not start
+        kv.gc_pass(&config).unwrap();
+        assert_eq!(kv.get_gc_status().passes, 0);
+        config.low_watermark = 100;
+        config.high_watermark = 100;
+        let free = kv.manager.read().get_free_percent();
+        kv.gc_pass(&config).unwrap();
+        let status = kv.get_gc_status();
+        assert_eq!(status.passes, 1);
+        assert!(status.collected_blocks > 0);
+        assert!(status.reclaimed_pages >= 39);
+        assert!(kv.manager.read().get_free_percent() >= free);
+        assert_eq!(kv.manager.read().gc.get_page_stat().1, 0);
+        assert_eq!(kv.get_inode_data(a, 0, 4096).unwrap(), vec![39; 4096]);
+        let data: Vec<u8> = (0..40u8).flat_map(|i| vec![i; 4096]).collect();
+        assert_eq!(kv.get_inode_data(b, 0, 40 * 4096).unwrap(), data);
+        // nothing dirty is left, so the next pass gives up right away
+        kv.gc_pass(&config).unwrap();
+        assert_eq!(kv.get_gc_status().collected_blocks, status.collected_blocks);
+        kv.flush().unwrap();
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        assert_eq!(kv.get_inode_data(b, 0, 40 * 4096).unwrap(), data);
+    }
+
+    #[test]
+    fn test_kv_wear_leveling() {
+        let mut seed: u32 = 1;
+        let mut random = |len: usize| -> Vec<u8> {
+            (0..len).map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            }).collect()
+        };
+        // a small main area on a fake disk, half of it cold data and a hot extent rewritten over and over
+        let mut simulate = |threshold: u32| -> Vec<u32> {
+            let stat = component::super_block::SuperStat::build(86, 40, 16, 24);
+            let mut disk = crate::driver::fake_disk::FakeDisk::new(86 * 128);
+            disk.data[0] = stat.encode();
+            let mut tl = tl::TranslationLayer::with_disk_manager(crate::driver::disk_manager::DiskManager::with_fake_disk(disk));
+            tl.set_geometry(86, stat.get_reserved_offset());
+            tl.init().unwrap();
+            let tl = Arc::new(tl);
+            let kv = kv::KV::new(Arc::clone(&tl));
+            kv.mount().unwrap();
+            kv.manager.write().gc.wear_level_threshold = threshold;
+            let mut metadata = kv::InodeMetadata {
+                file_type: 0,
+                ino: 0,
+                size: 0,
+                n_link: 1,
+                last_accessed: Timespec::default(),
+                last_modified: Timespec::default(),
+                last_metadata_changed: Timespec::default(),
+                created: Timespec::default(),
+                mode: 0o644,
+                uid: 0,
+                gid: 0,
+            };
+            let cold = kv.allocate_indoe(&mut metadata).unwrap();
+            let hot = kv.allocate_indoe(&mut metadata).unwrap();
+            let cold_data = random(8 * 128 * 4096);
+            for i in 0..64 {
+                kv.set_inode_data(cold, i as u64 * 16 * 4096, 16 * 4096, &cold_data[i * 16 * 4096..(i + 1) * 16 * 4096].to_vec()).unwrap();
+            }
+            let mut hot_data = vec![];
+            for _ in 0..400 {
+                hot_data = random(16 * 4096);
+                kv.set_inode_data(hot, 0, 16 * 4096, &hot_data).unwrap();
+            }
+            assert_eq!(kv.get_inode_data(cold, 0, cold_data.len()).unwrap(), cold_data);
+            assert_eq!(kv.get_inode_data(hot, 0, hot_data.len()).unwrap(), hot_data);
+            let main_offset = stat.get_main_offset() as usize;
+            let disk_manager = tl.disk_manager.read();
+            disk_manager.fake_disk.as_ref().unwrap().erase_count[main_offset..main_offset + 16].to_vec()
+        };
+        // without static leveling the cold blocks are never erased
+        let erase_count = simulate(0);
+        assert_eq!(*erase_count.iter().min().unwrap(), 0);
+        assert!(*erase_count.iter().max().unwrap() >= 5);
+        let erase_count = simulate(2);
+        let (min, max) = (*erase_count.iter().min().unwrap(), *erase_count.iter().max().unwrap());
+        assert!(min > 0);
+        assert!(max - min <= 2 + 1);
+    }
+
     #[test]
     fn test_kv_data_object_delete() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let tl = Arc::new(tl);
-        let mut kv = kv::KV::new(Arc::clone(&tl));
-        kv.mount();
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
         let mut metadata = kv::InodeMetadata {
             file_type: 0,
             ino: 0,
             size: 0,
             n_link: 1,
-            last_accessed: 0,
-            last_modified: 0,
-            last_metadata_changed: 0,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
         };
-        let ino = kv.allocate_indoe(&mut metadata);
+        let ino = kv.allocate_indoe(&mut metadata).unwrap();
         let mut off = 0;
         for _ in 0..5 {
             let data = vec![111; 6000];
-            kv.set_inode_data(ino, off, data.len(), &data);
-            off += data.len();
+            kv.set_inode_data(ino, off, data.len(), &data).unwrap();
+            off += data.len() as u64;
         }
-        kv.delete_inode_data(ino, 0, 30000);
+        kv.delete_inode_data(ino, 0, 30000).unwrap();
         let data = kv.get_inode_data(ino, 0, 1000);
-        assert_eq!(data, Some(vec![]));
-        let metadata = kv.get_inode_metadata(ino).unwrap();
+        assert_eq!(data.unwrap(), vec![]);
+        let metadata = kv.get_inode_metadata(ino).unwrap().unwrap();
         assert_eq!(metadata.size, 0);
         let mut off = 0;
         for _ in 0..5 {
             let data = vec![111; 6000];
-            kv.set_inode_data(ino, off, data.len(), &data);
-            off += data.len();
+            kv.set_inode_data(ino, off, data.len(), &data).unwrap();
+            off += data.len() as u64;
         }
-        kv.delete_inode_data(ino, 10000, 20000);
-        let metadata = kv.get_inode_metadata(ino).unwrap();
+        kv.delete_inode_data(ino, 10000, 20000).unwrap();
+        let metadata = kv.get_inode_metadata(ino).unwrap().unwrap();
         assert_eq!(metadata.size, 10000);
         let data = kv.get_inode_data(ino, 0, 0).unwrap();
         assert_eq!(data, vec![111; 10000]);
-        kv.delete_inode_data(ino, 9000, 1000);
+        kv.delete_inode_data(ino, 9000, 1000).unwrap();
         let data = kv.get_inode_data(ino, 0, 0).unwrap();
         assert_eq!(data, vec![111; 9000]);
     }
 
+    #[test]
+    fn test_kv_sparse_data_object() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let tl = Arc::new(tl);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        let mut metadata = kv::InodeMetadata {
+            file_type: 0,
+            ino: 0,
+            size: 0,
+            n_link: 1,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+        };
+        let ino = kv.allocate_indoe(&mut metadata).unwrap();
+        let free = kv.get_space_stat().1;
+        // a write past the end leaves zeros behind it
+        kv.set_inode_data(ino, 10000, 100, &vec![111; 100]).unwrap();
+        assert_eq!(kv.get_inode_metadata(ino).unwrap().unwrap().size, 10100);
+        let data = kv.get_inode_data(ino, 0, 10100).unwrap();
+        assert_eq!(data[..10000], vec![0; 10000][..]);
+        assert_eq!(data[10000..], vec![111; 100][..]);
+        assert_eq!(kv.get_inode_data(ino, 9990, 20).unwrap()[10..], vec![111; 10][..]);
+        assert_eq!(kv.get_inode_data_ranges(ino).unwrap(), vec![(10000, 100)]);
+        // growing takes no pages
+        let used = free - kv.get_space_stat().1;
+        assert_eq!(kv.resize_inode_data(ino, 10 << 30).unwrap(), 10 << 30);
+        assert_eq!(free - kv.get_space_stat().1, used);
+        assert_eq!(kv.get_inode_data(ino, (10 << 30) - 10, 100).unwrap(), vec![0; 10]);
+        // a hole is never read whole, only the window asked for is filled in
+        assert!(kv.get_inode_data(ino, 0, 0).is_err());
+        assert_eq!(kv.resize_inode_data(ino, 10050).unwrap(), 10050);
+        assert_eq!(kv.get_inode_data(ino, 0, 20000).unwrap().len(), 10050);
+        // a write over the middle of a hole splits it
+        kv.set_inode_data(ino, 4096, 4096, &vec![222; 4096]).unwrap();
+        assert_eq!(kv.get_inode_data_ranges(ino).unwrap(), vec![(4096, 4096), (10000, 50)]);
+        kv.punch_inode_data(ino, 6000, 5000).unwrap();
+        assert_eq!(kv.get_inode_metadata(ino).unwrap().unwrap().size, 10050);
+        assert_eq!(kv.get_inode_data_ranges(ino).unwrap(), vec![(4096, 1904)]);
+        let data = kv.get_inode_data(ino, 0, 10050).unwrap();
+        assert_eq!(data[4096..6000], vec![222; 1904][..]);
+        assert_eq!(data[6000..], vec![0; 4050][..]);
+        kv.delete_inode_data(ino, 0, 0).unwrap();
+        assert_eq!(kv.get_space_stat().1 + kv.manager.read().gc.get_page_stat().1, free);
+    }
+
+    #[test]
+    fn test_kv_large_data_object() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let tl = Arc::new(tl);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        let mut metadata = kv::InodeMetadata {
+            file_type: 0,
+            ino: 0,
+            size: 0,
+            n_link: 1,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+        };
+        let ino = kv.allocate_indoe(&mut metadata).unwrap();
+        // well past what 32 bits can address
+        let off = 5u64 << 30;
+        kv.set_inode_data(ino, 100, 100, &vec![1; 100]).unwrap();
+        assert_eq!(kv.set_inode_data(ino, off, 6000, &vec![111; 6000]).unwrap(), off + 6000);
+        kv.flush().unwrap();
+        drop(kv);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        assert_eq!(kv.get_inode_metadata(ino).unwrap().unwrap().size, off + 6000);
+        assert_eq!(kv.get_inode_data(ino, off - 10, 6010).unwrap()[10..], vec![111; 6000][..]);
+        assert_eq!(kv.get_inode_data(ino, 150, 100).unwrap(), [vec![1; 50], vec![0; 50]].concat());
+        assert_eq!(kv.get_inode_data_ranges(ino).unwrap(), vec![(100, 100), (off, 6000)]);
+        assert_eq!(kv.delete_inode_data(ino, 0, off).unwrap(), 6000);
+        assert_eq!(kv.get_inode_data(ino, 0, 0).unwrap(), vec![111; 6000]);
+    }
+
+    #[test]
+    fn test_kv_compression() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let tl = Arc::new(tl);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        let mut metadata = kv::InodeMetadata {
+            file_type: 0,
+            ino: 0,
+            size: 0,
+            n_link: 1,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+        };
+        let ino = kv.allocate_indoe(&mut metadata).unwrap();
+        let (_, free) = kv.get_space_stat();
+        let data = "a line of a log file that repeats itself\n".repeat(1000).into_bytes();
+        kv.set_inode_data(ino, 0, data.len(), &data).unwrap();
+        // forty thousand bytes take ten pages raw, snappy fits them in one
+        assert_eq!(free - kv.get_space_stat().1, 1);
+        assert!(kv.get_compress_stat().saved_bytes() > 9 * 4096);
+        assert_eq!(kv.get_inode_data(ino, 0, 0).unwrap(), data);
+        assert_eq!(kv.get_inode_data(ino, 5000, 100).unwrap(), data[5000..5100].to_vec());
+        // overwriting the middle re-encodes both sides of the old extent
+        kv.set_inode_data(ino, 10000, 5000, &vec![7; 5000]).unwrap();
+        let mut expected = data.clone();
+        expected[10000..15000].copy_from_slice(&[7; 5000]);
+        assert_eq!(kv.get_inode_data(ino, 0, 0).unwrap(), expected);
+        kv.delete_inode_data(ino, 30000, 2000).unwrap();
+        expected.drain(30000..32000);
+        assert_eq!(kv.get_inode_data(ino, 0, 0).unwrap(), expected);
+        kv.set_compress_policy(compress::CompressPolicy::Best);
+        kv.set_inode_data(ino, 0, data.len(), &data).unwrap();
+        assert_eq!(kv.get_inode_data(ino, 0, data.len()).unwrap(), data);
+        kv.set_compress_policy(compress::CompressPolicy::Off);
+        let extents = kv.get_compress_stat().compressed_extents;
+        kv.set_inode_data(ino, 0, data.len(), &data).unwrap();
+        assert_eq!(kv.get_compress_stat().compressed_extents, extents);
+        assert_eq!(kv.get_inode_data(ino, 0, 0).unwrap(), data);
+    }
+
+    #[test]
+    fn test_kv_scan() {
+        let mut tl = tl::TranslationLayer::new();
+        tl.init().unwrap();
+        let tl = Arc::new(tl);
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
+        let mut metadata = kv::InodeMetadata {
+            file_type: 0,
+            ino: 0,
+            size: 0,
+            n_link: 1,
+            last_accessed: Timespec::default(),
+            last_modified: Timespec::default(),
+            last_metadata_changed: Timespec::default(),
+            created: Timespec::default(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+        };
+        let mut inos = vec![];
+        for _ in 0..3 {
+            inos.push(kv.allocate_indoe(&mut metadata).unwrap());
+        }
+        kv.set_inode_data(inos[1], 0, 100, &vec![1; 100]).unwrap();
+        kv.flush().unwrap();
+        kv.delete_inode(inos[0]).unwrap();
+        kv.set_extra_value(String::from("scan"), &vec![1]).unwrap();
+        let keys: Vec<String> = kv.scan("m:").unwrap().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![format!("m:{}", inos[1]), format!("m:{}", inos[2])]);
+        let entries = kv.scan("d:").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, format!("d:{}", inos[1]));
+        assert!(kv.scan("e:").unwrap().contains(&(String::from("e:scan"), vec![1])));
+        assert_eq!(kv.scan_range("m:", Some("m:~")).unwrap().len(), 2);
+    }
+
     #[test]
     fn test_kv_extra_object() {
         let mut tl = tl::TranslationLayer::new();
-        tl.init();
+        tl.init().unwrap();
         let tl = Arc::new(tl);
-        let mut kv = kv::KV::new(Arc::clone(&tl));
-        kv.mount();
+        let kv = kv::KV::new(Arc::clone(&tl));
+        kv.mount().unwrap();
         for i in 0..127 as usize {
             let mut ret = [0; 4];
             ret[0] = (i >> 24) as u8;
@@ -214,7 +843,7 @@ mod tests {
             key.extend_from_slice(&ret);
             let mut value = vec![3, 4];
             value.extend_from_slice(&ret);
-            kv.set_extra_value(String::from_utf8(key).unwrap(), &value);
+            kv.set_extra_value(String::from_utf8(key).unwrap(), &value).unwrap();
         }
         for i in 0..127 as usize {
             let mut ret = [0; 4];
@@ -227,7 +856,7 @@ mod tests {
             key.extend_from_slice(&ret);
             let mut value = vec![3, 4];
             value.extend_from_slice(&ret);
-            assert_eq!(kv.get_extra_value(String::from_utf8(key).unwrap()).unwrap(), value);
+            assert_eq!(kv.get_extra_value(String::from_utf8(key).unwrap()).unwrap().unwrap(), value);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,14 @@
+pub mod fs;
+pub mod tl;
+pub mod kv;
+pub mod buf;
+pub mod css;
+pub mod util;
+pub mod mkfs;
+pub mod track;
+pub mod inode;
+pub mod error;
+pub mod driver;
+pub mod common;
+pub mod compress;
+pub mod write_buf;
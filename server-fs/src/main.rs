@@ -1,20 +1,116 @@
-mod fs;
-mod tl;
-mod kv;
-mod buf;
-mod css;
-mod util;
-mod track;
-mod inode;
-mod driver;
-mod common;
-mod compress;
-mod write_buf;
 use std::env;
-use fuser::MountOption;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::process;
+use std::sync::{Arc, Mutex};
+use server_fs::fs;
+use server_fs::fs::options::MountConfig;
 
 fn main() {
-    let mountpoint = env::args_os().nth(1).unwrap();
-    let fs = fs::filesystem::WondFS::new();
-    fuser::mount2(fs, mountpoint, &[MountOption::AutoUnmount]).unwrap();
-}
\ No newline at end of file
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", fs::options::USAGE);
+        return;
+    }
+    let config = match MountConfig::parse(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("server-fs: {}", err);
+            eprintln!("{}", fs::options::USAGE);
+            process::exit(1);
+        },
+    };
+    let pipe = Arc::new(Mutex::new(None));
+    if !config.foreground {
+        *pipe.lock().unwrap() = Some(daemonize());
+    }
+    let mut fs = match fs::filesystem::WondFS::mount(&config) {
+        Ok(fs) => fs,
+        Err(err) => fail(&pipe, format!("server-fs: {}", err)),
+    };
+    if !config.foreground {
+        let pipe = Arc::clone(&pipe);
+        fs.on_init = Some(Box::new(move || {
+            if let Some(mut pipe) = pipe.lock().unwrap().take() {
+                let _ = pipe.write_all(&[0]);
+                detach_stdio();
+            }
+        }));
+    }
+    if let Err(err) = fuser::mount2(fs, &config.mountpoint, &config.fuse_options()) {
+        fail(&pipe, format!("server-fs: mount {}: {}", config.mountpoint.display(), err));
+    }
+}
+
+// fork before the filesystem spawns its threads, the parent stays until the child reports
+// through the returned pipe and exits with the status of the mount
+fn daemonize() -> File {
+    let mut fds = [0; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            eprintln!("server-fs: pipe failed");
+            process::exit(1);
+        }
+        match libc::fork() {
+            -1 => {
+                eprintln!("server-fs: fork failed");
+                process::exit(1);
+            },
+            0 => {
+                libc::close(fds[0]);
+            },
+            _ => {
+                libc::close(fds[1]);
+                process::exit(wait_mount(File::from_raw_fd(fds[0])));
+            },
+        }
+        libc::setsid();
+        File::from_raw_fd(fds[1])
+    }
+}
+
+// a zero byte means mounted, anything else is the message the child failed with
+fn wait_mount(mut pipe: File) -> i32 {
+    let mut msg = vec![];
+    if let Err(err) = pipe.read_to_end(&mut msg) {
+        eprintln!("server-fs: {}", err);
+        return 1;
+    }
+    match msg.as_slice() {
+        [0] => 0,
+        [] => {
+            eprintln!("server-fs: exited before the filesystem was mounted");
+            1
+        },
+        msg => {
+            eprintln!("{}", String::from_utf8_lossy(msg));
+            1
+        },
+    }
+}
+
+// hands the message to a parent still waiting on the pipe, or prints it
+fn fail(pipe: &Mutex<Option<File>>, msg: String) -> ! {
+    match pipe.lock().unwrap().take() {
+        Some(mut pipe) => {
+            let _ = pipe.write_all(msg.as_bytes());
+        },
+        None => eprintln!("{}", msg),
+    }
+    process::exit(1);
+}
+
+fn detach_stdio() {
+    unsafe {
+        let null = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+        if null >= 0 {
+            libc::dup2(null, 0);
+            libc::dup2(null, 1);
+            libc::dup2(null, 2);
+            if null > 2 {
+                libc::close(null);
+            }
+        }
+    }
+}
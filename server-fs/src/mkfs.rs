@@ -0,0 +1,115 @@
+use std::io;
+use std::path::Path;
+use crate::driver::disk;
+use crate::driver::disk_manager;
+use crate::fs::filesystem::WondFS;
+use crate::error::WondResult;
+use crate::kv::component::super_block;
+use crate::kv::lsm_tree::wal;
+use crate::tl::tl::TranslationLayer;
+
+pub struct MkfsOptions {
+    pub block_num: u32,
+    pub kv_block_num: u32,
+    pub main_area_block_num: u32,
+    pub reserved_block_num: u32,
+}
+
+impl MkfsOptions {
+    pub fn new(block_num: u32) -> io::Result<MkfsOptions> {
+        let stat = super_block::SuperStat::default_geometry(block_num).ok_or_else(|| invalid_input("image too small"))?;
+        Ok(MkfsOptions {
+            block_num,
+            kv_block_num: stat.kv_block_num,
+            main_area_block_num: stat.main_area_block_num,
+            reserved_block_num: stat.reserved_block_num,
+        })
+    }
+
+    pub fn super_stat(&self) -> io::Result<super_block::SuperStat> {
+        let used = [self.kv_block_num, self.main_area_block_num, self.reserved_block_num]
+            .iter()
+            .try_fold(4u32, |sum, num| sum.checked_add(*num));
+        if used.is_none_or(|used| used > self.block_num) {
+            return Err(invalid_input("regions do not fit in the block count"));
+        }
+        let stat = super_block::SuperStat::build(self.block_num, self.kv_block_num, self.main_area_block_num, self.reserved_block_num);
+        if stat.get_reserved_offset() + stat.get_reserved_size() != self.block_num {
+            return Err(invalid_input("regions do not add up to the block count"));
+        }
+        if self.kv_block_num <= wal::WAL_BLOCK_NUM as u32 {
+            return Err(invalid_input("kv area needs more blocks than the write-ahead log takes"));
+        }
+        if self.main_area_block_num == 0 {
+            return Err(invalid_input("main area needs at least one block"));
+        }
+        if self.reserved_block_num < 2 {
+            return Err(invalid_input("reserved area needs at least two blocks"));
+        }
+        if stat.get_pit_size() < super_block::SuperStat::pit_block_num(self.main_area_block_num) {
+            return Err(invalid_input("no room left for the PIT of the main area"));
+        }
+        Ok(stat)
+    }
+}
+
+pub fn mkfs(path: &Path, options: &MkfsOptions) -> io::Result<()> {
+    let stat = options.super_stat()?;
+    let disk = disk::Disk::create(path, options.block_num)?;
+    let tl = TranslationLayer::with_disk_manager(disk_manager::DiskManager::with_disk(disk));
+    let fs = format(tl, &stat)?;
+    fs.flush()?;
+    Ok(())
+}
+
+pub fn format(tl: TranslationLayer, stat: &super_block::SuperStat) -> WondResult<WondFS> {
+    let mut tl = tl;
+    tl.set_geometry(stat.get_block_num(), stat.get_reserved_offset());
+    tl.format()?;
+    tl.write(0, &stat.encode());
+    let fs = WondFS::with_translation_layer(tl)?;
+    // like mke2fs, the root directory belongs to whoever formats the image
+    let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    fs.create_root(uid, gid)?;
+    Ok(fs)
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn basics() {
+        let path = env::temp_dir().join(format!("wondfs-mkfs-{}.img", std::process::id()));
+        mkfs(&path, &MkfsOptions::new(super_block::DEFAULT_BLOCK_NUM).unwrap()).unwrap();
+        let disk = disk::Disk::open(&path).unwrap();
+        let mut tl = TranslationLayer::with_disk_manager(disk_manager::DiskManager::with_disk(disk));
+        tl.init().unwrap();
+        let fs = WondFS::with_translation_layer(tl).unwrap();
+        let root = fs.get_inode(1).unwrap();
+        assert!(root.get_stat().file_type == crate::inode::inode::InodeFileType::Directory);
+        assert_eq!(crate::common::directory::dir_lookup(&root, ".".to_string()).unwrap().unwrap().0, 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn small_image() {
+        for block_num in [0, 1, 4, 5] {
+            let err = MkfsOptions::new(block_num).err().unwrap();
+            assert_eq!(err.to_string(), "image too small");
+        }
+        // past the fixed regions the geometry either fits or is turned down, never panics
+        for block_num in 6..64 {
+            let _ = MkfsOptions::new(block_num).and_then(|options| options.super_stat());
+        }
+        let mut options = MkfsOptions::new(super_block::DEFAULT_BLOCK_NUM).unwrap();
+        options.reserved_block_num = u32::MAX;
+        assert_eq!(options.super_stat().err().unwrap().to_string(), "regions do not fit in the block count");
+    }
+}
@@ -1,3 +1,9 @@
+use crate::error::{WondError, WondResult};
+use crate::util::crc32;
+
+// one SECDED code per chunk, so a single flipped bit in every chunk can be repaired
+const ECC_CHUNK_SIZE: usize = 128;
+
 #[derive(PartialEq, Debug)]
 pub enum CheckType {
     Crc32,
@@ -7,9 +13,9 @@ pub enum CheckType {
 pub struct CheckCenter;
 
 impl CheckCenter {
-    pub fn check(data: &[u8], sa: &Vec<u8>) -> (bool, CheckType, Option<[u8; 4096]>) {
+    pub fn check(data: &[u8], sa: &Vec<u8>) -> WondResult<(bool, CheckType, Option<[u8; 4096]>)> {
         if sa.len() != 128 {
-            panic!("CheckCenter: check sa not valid size");
+            return Err(WondError::Corrupted("signature has not valid size"));
         }
         let check_type;
         if sa[127] == 0x00 {
@@ -26,21 +32,22 @@ impl CheckCenter {
                 let byte_4 = bytes[3] as u32;
                 let signature = byte_1 + byte_2 + byte_3 + byte_4;
                 let ret = CheckCenter::check_crc_32(data, signature);
-                (ret.0, CheckType::Crc32, ret.1)
+                Ok((ret.0, CheckType::Crc32, ret.1))
             },
             CheckType::Ecc => {
-                unimplemented!()
+                let ret = CheckCenter::check_ecc(data, sa);
+                Ok((ret.0, CheckType::Ecc, ret.1))
             },
         }
     }
 
-    pub fn sign(data: &[u8; 4096], address: u32, sign_type: CheckType) -> Vec<u8> {
+    pub fn sign(data: &[u8; 4096], address: u32, sign_type: CheckType) -> WondResult<Vec<u8>> {
         match sign_type {
             CheckType::Crc32 => {
-                CheckCenter::sign_crc_32(data, address)
+                Ok(CheckCenter::sign_crc_32(data, address))
             },
             CheckType::Ecc => {
-                unimplemented!()
+                Ok(CheckCenter::sign_ecc(data, address))
             },
         }
     }
@@ -56,15 +63,40 @@ impl CheckCenter {
         let byte_4 = bytes[3] as u32;
         byte_1 + byte_2 + byte_3 + byte_4
     }
+
+    // the translation layer orders signature pages by this, the newest signature of an address wins
+    pub fn extract_sequence(signature: &[u8]) -> u32 {
+        u32::from_be_bytes([signature[115], signature[116], signature[117], signature[118]])
+    }
+
+    pub fn set_sequence(signature: &mut [u8], sequence: u32) {
+        signature[115..119].copy_from_slice(&sequence.to_be_bytes());
+    }
+
+    // stands in for the signature of a page that could not be recovered, so it keeps failing
+    pub fn sign_lost(address: u32) -> Vec<u8> {
+        let mut sa = vec![0; 128];
+        sa[119] = 0x33;
+        sa[120] = 0x33;
+        sa[121] = 0xaa;
+        sa[122] = 0xaa;
+        sa[123..127].copy_from_slice(&address.to_be_bytes());
+        sa[127] = 0xff;
+        sa
+    }
+
+    pub fn is_lost(signature: &[u8]) -> bool {
+        signature[127] == 0xff
+    }
 }
 
 impl CheckCenter {
     fn check_crc_32(data: &[u8], signature: u32) -> (bool, Option<[u8; 4096]>) {
-        (true, None)
+        (crc32::crc32(data) == signature, None)
     }
 
     fn sign_crc_32(data: &[u8; 4096], address: u32) -> Vec<u8> {
-        let check_sum = 0;
+        let check_sum = crc32::crc32(data);
         let mut sa = vec![];
         for _ in 0..128 {
             sa.push(0);
@@ -92,6 +124,75 @@ impl CheckCenter {
         sa[127] = 0x00;
         sa
     }
+
+    // a repaired page comes back as (false, Some(page)), an unrepairable one as (false, None)
+    fn check_ecc(data: &[u8], sa: &[u8]) -> (bool, Option<[u8; 4096]>) {
+        let mut page = [0; 4096];
+        page.copy_from_slice(data);
+        let mut repaired = false;
+        for (index, chunk) in page.chunks_mut(ECC_CHUNK_SIZE).enumerate() {
+            let code = ((sa[index * 2] as u16) << 8) + sa[index * 2 + 1] as u16;
+            let diff = code ^ CheckCenter::hamming_code(chunk);
+            let syndrome = diff & 0x7ff;
+            let odd = (diff >> 11 ^ syndrome.count_ones() as u16) & 1 == 1;
+            if !odd {
+                if syndrome != 0 {
+                    return (false, None);
+                }
+                continue;
+            }
+            // a syndrome of zero or a power of two points at the code itself, the data is intact
+            if syndrome == 0 || syndrome.is_power_of_two() {
+                continue;
+            }
+            let bit = (syndrome - syndrome.ilog2() as u16 - 2) as usize;
+            if bit >= ECC_CHUNK_SIZE * 8 {
+                return (false, None);
+            }
+            chunk[bit / 8] ^= 1 << (bit % 8);
+            repaired = true;
+        }
+        if repaired {
+            (false, Some(page))
+        } else {
+            (true, None)
+        }
+    }
+
+    fn sign_ecc(data: &[u8; 4096], address: u32) -> Vec<u8> {
+        let mut sa = vec![0; 128];
+        for (index, chunk) in data.chunks(ECC_CHUNK_SIZE).enumerate() {
+            let code = CheckCenter::hamming_code(chunk);
+            sa[index * 2] = (code >> 8) as u8;
+            sa[index * 2 + 1] = code as u8;
+        }
+        sa[119] = 0x33;
+        sa[120] = 0x33;
+        sa[121] = 0xaa;
+        sa[122] = 0xaa;
+        sa[123..127].copy_from_slice(&address.to_be_bytes());
+        sa[127] = 0x01;
+        sa
+    }
+
+    // data bits sit on the hamming positions that are not powers of two, the low 11 bits
+    // xor those positions together and bit 11 is the parity over data and check bits
+    fn hamming_code(chunk: &[u8]) -> u16 {
+        let mut syndrome = 0u16;
+        let mut parity = 0u16;
+        let mut pos = 2u16;
+        for bit in 0..chunk.len() * 8 {
+            pos += 1;
+            if pos.is_power_of_two() {
+                pos += 1;
+            }
+            if chunk[bit / 8] >> (bit % 8) & 1 == 1 {
+                syndrome ^= pos;
+                parity ^= 1;
+            }
+        }
+        syndrome | ((parity ^ syndrome.count_ones() as u16) & 1) << 11
+    }
 }
 
 #[cfg(test)]
@@ -104,11 +205,40 @@ mod test {
         data[234] = 23;
         data[123] = 78;
         data[89] = 12;
-        let sa = CheckCenter::sign(&data, 383, CheckType::Crc32);
-        let ret = CheckCenter::check(&data, &sa);
+        let sa = CheckCenter::sign(&data, 383, CheckType::Crc32).unwrap();
+        let ret = CheckCenter::check(&data, &sa).unwrap();
         assert_eq!(ret.0, true);
         assert_eq!(ret.1, CheckType::Crc32);
         assert_eq!(ret.2, None);
         assert_eq!(CheckCenter::extract_address(&sa), 383);
+        assert!(CheckCenter::check(&data, &vec![0; 12]).is_err());
+        data[0] ^= 0x01;
+        assert_eq!(CheckCenter::check(&data, &sa).unwrap(), (false, CheckType::Crc32, None));
+        let mut sa = CheckCenter::sign_lost(383);
+        CheckCenter::set_sequence(&mut sa, 7);
+        assert!(CheckCenter::is_lost(&sa));
+        assert_eq!(CheckCenter::extract_sequence(&sa), 7);
+        assert_eq!(CheckCenter::extract_address(&sa), 383);
+    }
+
+    #[test]
+    fn ecc() {
+        let mut data = [27; 4096];
+        data[234] = 23;
+        data[4095] = 78;
+        let sa = CheckCenter::sign(&data, 383, CheckType::Ecc).unwrap();
+        assert_eq!(CheckCenter::extract_address(&sa), 383);
+        assert_eq!(CheckCenter::check(&data, &sa).unwrap(), (true, CheckType::Ecc, None));
+        let mut page = data;
+        page[0] ^= 0x01;
+        page[200] ^= 0x80;
+        page[4095] ^= 0x10;
+        assert_eq!(CheckCenter::check(&page, &sa).unwrap(), (false, CheckType::Ecc, Some(data)));
+        let mut sa_flipped = sa.clone();
+        sa_flipped[3] ^= 0x04;
+        assert_eq!(CheckCenter::check(&data, &sa_flipped).unwrap(), (true, CheckType::Ecc, None));
+        let mut page = data;
+        page[130] ^= 0x03;
+        assert_eq!(CheckCenter::check(&page, &sa).unwrap(), (false, CheckType::Ecc, None));
     }
 }
\ No newline at end of file
@@ -1,2 +1,3 @@
 pub mod tl;
-pub mod check_center;
\ No newline at end of file
+pub mod check_center;
+pub mod scrub;
\ No newline at end of file
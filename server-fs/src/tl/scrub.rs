@@ -0,0 +1,118 @@
+use std::thread;
+use std::time::{Duration, SystemTime};
+use crate::error::{WondError, WondResult};
+use super::tl::TranslationLayer;
+
+pub const DEFAULT_SCRUB_RATE: u32 = 4;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScrubStatus {
+    pub running: bool,
+    pub next_block_no: u32,
+    pub passes: u64,
+    pub scanned_blocks: u64,
+    pub repaired_pages: u64,
+    pub lost_pages: u64,
+    pub relocated_blocks: u64,
+    pub last_error: Option<WondError>,
+}
+
+impl TranslationLayer {
+    // walks every block in use at rate blocks a second until stop_scrub, 0 never starts
+    pub fn scrub_loop(&self, rate: u32) {
+        if rate == 0 {
+            return;
+        }
+        self.scrub_status.write().running = true;
+        loop {
+            if !self.scrub_status.read().running {
+                return;
+            }
+            let block_no = self.scrub_status.read().next_block_no;
+            if let Err(err) = self.scrub_block(block_no) {
+                eprintln!("server-fs: scrub block {}: {}", block_no, err);
+                self.scrub_status.write().last_error = Some(err);
+            }
+            let mut status = self.scrub_status.write();
+            if block_no >= self.use_max_block_no {
+                status.next_block_no = 0;
+                status.passes += 1;
+            } else {
+                status.next_block_no = block_no + 1;
+            }
+            drop(status);
+            thread::sleep(Duration::from_micros(1000000 / rate as u64));
+        }
+    }
+
+    pub fn stop_scrub(&self) {
+        self.scrub_status.write().running = false;
+    }
+
+    pub fn get_scrub_status(&self) -> ScrubStatus {
+        self.scrub_status.read().clone()
+    }
+
+    // a block with a repaired or corrupted page moves to a spare like any failing block, an
+    // erase in place would lose every page of it to a crash before the rewrite
+    pub fn scrub_block(&self, block_no: u32) -> WondResult<()> {
+        let _flush = self.flush_lock.lock();
+        let mut data = vec![0; 128 * 4096];
+        let start_time = SystemTime::now();
+        self.disk_manager.read().disk_block_read(self.transfer(block_no), &mut data)?;
+        let duration = SystemTime::now().duration_since(start_time).map_or(0, |duration| duration.as_micros());
+        self.update_read_speed(128 * 4, duration);
+        // with flush_lock held a cached page has not reached the disk yet
+        let should_check: Vec<bool> = (0..128).map(|index| !self.write_cache.read().contains_address(block_no * 128 + index)).collect();
+        let (repaired, lost) = self.check_block(block_no, data, &should_check)?;
+        let mut status = self.scrub_status.write();
+        status.scanned_blocks += 1;
+        status.repaired_pages += repaired;
+        status.lost_pages += lost;
+        if repaired + lost > 0 {
+            status.relocated_blocks += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basics() {
+        let mut tl = TranslationLayer::new();
+        tl.init().unwrap();
+        tl.write(300, &[3; 4096]);
+        tl.write(301, &[4; 4096]);
+        tl.flush().unwrap();
+        // the first block is fine, crc32 pages cannot be repaired so block 2 moves
+        tl.scrub_block(1).unwrap();
+        tl.disk_manager.write().fake_disk.as_mut().unwrap().data[300][7] ^= 0x40;
+        tl.scrub_block(2).unwrap();
+        let status = tl.get_scrub_status();
+        assert_eq!(status.scanned_blocks, 2);
+        assert_eq!(status.lost_pages, 1);
+        assert_eq!(status.relocated_blocks, 1);
+        assert!(tl.map_v_table.read().contains_key(&2));
+        assert_eq!(tl.read(301).unwrap(), [4; 4096]);
+        assert!(tl.read(300).is_err());
+        // ecc pages written after the error are repaired on the spare the block moves to, the
+        // damaged copy stays behind untouched
+        tl.write(400, &[5; 4096]);
+        tl.write(401, &[6; 4096]);
+        tl.flush().unwrap();
+        tl.disk_manager.write().fake_disk.as_mut().unwrap().data[400][1] ^= 0x02;
+        tl.scrub_block(3).unwrap();
+        let status = tl.get_scrub_status();
+        assert_eq!(status.repaired_pages, 1);
+        assert_eq!(status.relocated_blocks, 2);
+        assert!(tl.map_v_table.read().contains_key(&3));
+        assert_ne!(tl.disk_manager.read().fake_disk.as_ref().unwrap().data[400], [5; 4096]);
+        let spare = tl.transfer(3) as usize * 128;
+        assert_eq!(tl.disk_manager.read().fake_disk.as_ref().unwrap().data[spare + 16], [5; 4096]);
+        assert_eq!(tl.read(400).unwrap(), [5; 4096]);
+        assert_eq!(tl.read(401).unwrap(), [6; 4096]);
+    }
+}
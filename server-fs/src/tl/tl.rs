@@ -1,33 +1,25 @@
 extern crate alloc;
-use spin::RwLock;
+use spin::{Mutex, RwLock};
 use alloc::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 use std::time::SystemTime;
 use crate::util::array::array;
 use crate::write_buf;
 use crate::tl::check_center;
+use crate::tl::scrub;
 use crate::driver::disk_manager;
-
-use std::sync::mpsc;
+use crate::error::{WondError, WondResult};
 
 const MAGIC_NUMBER_1: u32 = 0x2222ffff;
 const MAGIC_NUMBER_2: u32 = 0x3333aaaa;
 
-#[derive(PartialEq)]
-pub enum MessageType {
-    Read,
-    Write,
-    Erase,
-    WriteImme,
-    EraseImme,
-}
-
-pub struct Message {
-    method: MessageType,
-    pointer: u32,
-    value: Option<[u8; 4096]>,
-    channel1: Option<mpsc::Sender<[u8; 4096]>>,
-    channel2: Option<mpsc::Sender<Vec<u8>>>,
+#[derive(PartialEq, Debug)]
+pub enum PageState {
+    Valid,
+    Repaired,
+    Corrupted,
+    Lost,
 }
 
 pub enum BlockType {
@@ -51,74 +43,186 @@ pub fn judge_block_type(data: &Vec<u8>) -> BlockType {
 pub struct TranslationLayer {
     pub disk_manager: Arc<RwLock<disk_manager::DiskManager>>,
     pub write_cache: Arc<RwLock<write_buf::WriteCache>>,
+    // held while a batch goes to disk and leaves the cache and while a block is scrubbed, moved
+    // or erased, so none of them sees half of another
+    pub flush_lock: Arc<Mutex<()>>,
     pub used_table: Arc<RwLock<HashMap<u32, bool>>>,
     pub map_v_table: Arc<RwLock<HashMap<u32, u32>>>,
     pub sign_block_map: Arc<RwLock<HashMap<u32, u32>>>,
     pub sign_offset_map: Arc<RwLock<HashMap<u32, u32>>>,
     pub sign_block_no: Arc<RwLock<u32>>,
     pub sign_block_offset: Arc<RwLock<u32>>,
+    pub sign_seq: Arc<RwLock<u32>>,
     pub write_speed: Arc<RwLock<u32>>,
     pub read_speed: Arc<RwLock<u32>>,
     pub err_block_num: Arc<RwLock<u32>>,
     pub last_err_time: Arc<RwLock<SystemTime>>,
+    pub write_error: Arc<RwLock<Option<WondError>>>,
+    pub scrub_status: Arc<RwLock<scrub::ScrubStatus>>,
     pub block_num: u32,
     pub use_max_block_no: u32,
     pub max_block_no: u32,
-    pub table_block_no: u32,
+    pub table_block_no: Arc<RwLock<u32>>,
+    // bumped on every table write, a table left behind on a retired block loses to the newer one
+    pub table_seq: Arc<RwLock<u32>>,
 }
 
 impl TranslationLayer {
     pub fn write_loop(&self) {
         loop {
+            let _flush = self.flush_lock.lock();
             if !self.write_cache.read().need_sync() {
                 return;
             }
-            let data = self.write_cache.write().get_all();
-            // self.write_sign(&data);
+            let data = self.write_cache.read().get_batch();
             let start_time = SystemTime::now();
-            for (address, data) in data.into_iter() {
-                let  block_no = address / 128;
-                let offset = address % 128;
-                let map_block_no = self.transfer(block_no);
-                let map_address = map_block_no * 128 + offset;
-                self.disk_manager.write().disk_write(map_address, &data); 
+            if let Err(err) = self.write_pages(&data) {
+                // the background writer has no caller, the next flush reports it
+                *self.write_error.write() = Some(err);
+                return;
             }
+            self.write_cache.write().release_batch(&data);
             let end_time = SystemTime::now();
             let duration = end_time.duration_since(start_time).ok().unwrap().as_micros();
             self.update_write_speed(32 * 4, duration);
             self.write_cache.write().sync();
         }
     }
+
+    pub fn flush(&self) -> WondResult<()> {
+        self.flush_range(0..u32::MAX)
+    }
+
+    // only the cached pages in the range go to the disk, the rest wait for the writer
+    pub fn flush_range(&self, range: Range<u32>) -> WondResult<()> {
+        loop {
+            let _flush = self.flush_lock.lock();
+            let data = self.write_cache.read().get_batch_in(range.clone());
+            if data.is_empty() {
+                break;
+            }
+            self.write_pages(&data)?;
+            self.write_cache.write().release_batch(&data);
+        }
+        self.write_cache.write().sync();
+        match self.write_error.write().take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn write_pages(&self, data: &Vec<(u32, [u8; 4096])>) -> WondResult<()> {
+        for (index, (address, page)) in data.iter().enumerate() {
+            let ret = self.disk_manager.write().disk_write(self.transfer_address(*address), page);
+            match ret {
+                Err(WondError::BadBlock(_)) => self.rewrite_block(*address / 128, &data[..=index])?,
+                ret => ret?,
+            }
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.write_sign(data)
+    }
+
+    // a page that failed to program takes its block along to a spare, the pages this batch
+    // already put on the block are still cached and left behind, so they are written again
+    fn rewrite_block(&self, block_no: u32, written: &[(u32, [u8; 4096])]) -> WondResult<()> {
+        self.relocate_block_locked(block_no)?;
+        for (address, page) in written.iter().filter(|(address, _)| *address / 128 == block_no) {
+            let ret = self.disk_manager.write().disk_write(self.transfer_address(*address), page);
+            match ret {
+                Err(WondError::BadBlock(_)) => return self.rewrite_block(block_no, written),
+                ret => ret?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for TranslationLayer {
+    fn default() -> Self {
+        TranslationLayer::new()
+    }
 }
 
 impl TranslationLayer {
     pub fn new() -> TranslationLayer {
+        TranslationLayer::with_disk_manager(disk_manager::DiskManager::virtual_disk())
+    }
+
+    pub fn with_disk_manager(disk_manager: disk_manager::DiskManager) -> TranslationLayer {
         TranslationLayer {
-            disk_manager: Arc::new(RwLock::new(disk_manager::DiskManager::new(true))),
+            disk_manager: Arc::new(RwLock::new(disk_manager)),
             write_cache: Arc::new(RwLock::new(write_buf::WriteCache::new())),
+            flush_lock: Arc::new(Mutex::new(())),
             map_v_table: Arc::new(RwLock::new(HashMap::new())),
             used_table: Arc::new(RwLock::new(HashMap::new())),
             sign_block_map: Arc::new(RwLock::new(HashMap::new())),
             sign_offset_map: Arc::new(RwLock::new(HashMap::new())),
             sign_block_no: Arc::new(RwLock::new(1025)),
             sign_block_offset: Arc::new(RwLock::new(0)),
+            sign_seq: Arc::new(RwLock::new(1)),
             block_num: 1224,
             use_max_block_no: 1023,
             max_block_no: 1223,
-            table_block_no: 1024,
+            table_block_no: Arc::new(RwLock::new(1024)),
+            table_seq: Arc::new(RwLock::new(0)),
             write_speed: Arc::new(RwLock::new(0)),
             read_speed: Arc::new(RwLock::new(0)),
             err_block_num: Arc::new(RwLock::new(0)),
             last_err_time: Arc::new(RwLock::new(SystemTime::UNIX_EPOCH)),
+            write_error: Arc::new(RwLock::new(None)),
+            scrub_status: Arc::new(RwLock::new(scrub::ScrubStatus::default())),
         }
     }
 
-    pub fn init(&mut self) {
+    pub fn init(&mut self) -> WondResult<()> {
+        let mut sign_seqs = HashMap::new();
+        let mut table: Option<(u32, u32, Vec<u8>)> = None;
         for block_no in self.use_max_block_no + 1..=self.max_block_no {
             let mut data = vec![0; 4096 * 128];
-            self.disk_manager.read().disk_block_read(block_no, &mut data);
-            self.init_with_block(block_no, &data);
+            self.disk_manager.read().disk_block_read(block_no, &mut data)?;
+            if let BlockType::MappingTable = judge_block_type(&data) {
+                let seq = MapDataRegion::extract_sequence(&data);
+                if table.as_ref().is_none_or(|(cur, _, _)| seq >= *cur) {
+                    table = Some((seq, block_no, data));
+                }
+                continue;
+            }
+            self.init_with_block(block_no, &data, &mut sign_seqs);
+        }
+        if let Some((seq, block_no, data)) = table {
+            self.init_with_block(block_no, &data, &mut sign_seqs);
+            *self.table_seq.write() = seq;
+        }
+        // every entry in the table stands for a block that failed, remapped or retired
+        *self.err_block_num.write() = self.map_v_table.read().len() as u32;
+        Ok(())
+    }
+
+    // a reserved block that will not erase is retired on the spot instead of failing the format
+    pub fn format(&self) -> WondResult<()> {
+        self.map_v_table.write().clear();
+        self.used_table.write().clear();
+        self.sign_block_map.write().clear();
+        self.sign_offset_map.write().clear();
+        *self.sign_block_offset.write() = 0;
+        *self.sign_seq.write() = 1;
+        *self.table_seq.write() = 0;
+        *self.err_block_num.write() = 0;
+        for block_no in self.use_max_block_no + 1..=self.max_block_no {
+            let ret = self.disk_manager.write().disk_erase(block_no);
+            match ret {
+                Err(WondError::BadBlock(_)) => self.retire_block(block_no),
+                ret => ret?,
+            }
+        }
+        if self.used_table.read().contains_key(&*self.sign_block_no.read()) {
+            let sign_block_no = self.take_spare_block()?;
+            self.set_sign_block_no(sign_block_no);
         }
+        self.sync_map_v_table()
     }
     
     pub fn get_disk_speed(&self) -> (u32, u32) {
@@ -138,37 +242,46 @@ impl TranslationLayer {
     }
 
     pub fn set_table_block_no(&mut self, table_block_no: u32) {
-        self.table_block_no = table_block_no;
+        *self.table_block_no.write() = table_block_no;
     }
 
     pub fn set_sign_block_no(&self, sign_block_no: u32) {
         *self.sign_block_no.write() = sign_block_no;
     }
+
+    pub fn set_geometry(&mut self, block_num: u32, reserved_offset: u32) {
+        self.set_block_num(block_num);
+        self.set_use_max_block_no(reserved_offset - 1);
+        self.set_table_block_no(reserved_offset);
+        self.set_sign_block_no(reserved_offset + 1);
+        self.set_max_block_no(block_num - 1);
+    }
 }
 
 impl TranslationLayer {
-    pub fn read(&self, address: u32) -> [u8; 4096] {
+    pub fn read(&self, address: u32) -> WondResult<[u8; 4096]> {
         if self.write_cache.read().contains_address(address) {
             let data = self.write_cache.read().read(address).unwrap();
-            return data;
+            return Ok(data);
         }
-        self.disk_manager.read().disk_read(address)
+        let mut data = self.disk_manager.read().disk_read(self.transfer_address(address))?;
+        self.verify_page(address, &mut data)?;
+        Ok(data)
     }
 
-    pub fn read_advanced(&self, address: u32, buf: &mut [u8]) {
-        if self.write_cache.read().contains_address(address) {
-            let data = self.write_cache.read().read(address).unwrap();
-            buf.copy_from_slice(&data[..buf.len()]);
-            return;
-        }
-        self.disk_manager.read().disk_read_advanced(address, buf);
+    // the signature covers the whole page, so a partial read still pulls in all of it
+    pub fn read_advanced(&self, address: u32, buf: &mut [u8]) -> WondResult<()> {
+        let data = self.read(address)?;
+        buf.copy_from_slice(&data[..buf.len()]);
+        Ok(())
     }
 
     pub fn write(&self, address: u32, data: &[u8; 4096]) {
         self.write_cache.write().write(address, *data);
     }
 
-    pub fn erase(&self, block_no: u32) {
+    pub fn erase(&self, block_no: u32) -> WondResult<()> {
+        let _flush = self.flush_lock.lock();
         let start_index = block_no * 128;
         let end_index = (block_no + 1) * 128;
         for index in start_index..end_index {
@@ -179,12 +292,17 @@ impl TranslationLayer {
             }
         }
         let map_block_no = self.transfer(block_no);
-        self.disk_manager.write().disk_erase(map_block_no);
+        let ret = self.disk_manager.write().disk_erase(map_block_no);
+        match ret {
+            // nothing on the block is wanted any more, a fresh spare takes its place
+            Err(WondError::BadBlock(_)) => self.replace_block(block_no),
+            ret => ret,
+        }
     }
 }
 
 impl TranslationLayer {
-    fn init_with_block(&mut self, block_no: u32, data: &Vec<u8>) {
+    fn init_with_block(&mut self, block_no: u32, data: &Vec<u8>, sign_seqs: &mut HashMap<u32, u32>) {
         let block_type = judge_block_type(&data);
         match block_type {
             BlockType::MappingTable => {
@@ -194,141 +312,307 @@ impl TranslationLayer {
                     self.used_table.write().insert(entry.1, true);
                 }
                 self.used_table.write().insert(block_no, true);
-                self.table_block_no = block_no;
+                *self.table_block_no.write() = block_no;
             },
             BlockType::Signature => {
                 let iter = SignDataRegion::new(&data);
-                let mut len = 0;
-                for (index, entry) in iter.enumerate() {
+                for (index, entry) in iter {
                     let address = check_center::CheckCenter::extract_address(&entry.to_vec());
-                    if self.sign_block_map.read().contains_key(&address) {
-                        *self.sign_block_map.write().get_mut(&address).unwrap() = block_no;
-                        *self.sign_offset_map.write().get_mut(&address).unwrap() = index as u32;
-                    } else {
-                        self.sign_block_map.write().insert(address, block_no);
-                        self.sign_offset_map.write().insert(address, index as u32);
+                    let seq = check_center::CheckCenter::extract_sequence(&entry);
+                    if sign_seqs.get(&address).is_some_and(|cur| *cur > seq) {
+                        continue;
+                    }
+                    sign_seqs.insert(address, seq);
+                    self.sign_block_map.write().insert(address, block_no);
+                    self.sign_offset_map.write().insert(address, index as u32);
+                    // keep appending behind the newest signature page
+                    if seq >= *self.sign_seq.read() {
+                        *self.sign_seq.write() = seq + 1;
+                        *self.sign_block_no.write() = block_no;
+                        *self.sign_block_offset.write() = (index as u32 / 32 + 1) * 32;
                     }
-                    len += 1;
                 }
                 self.used_table.write().insert(block_no, true);
-                *self.sign_block_no.write() = block_no;
-                *self.sign_block_offset.write() = len;
             },
             _ => (),
         }
     }
 
-    fn check_block(&self, block_no: u32, data: &mut Vec<u8>, should_check: &Vec<bool>) -> bool {
-        let mut flag = true;
+    // counts the (repaired, corrupted) pages of a block read into data, either kind moves the
+    // block to a spare with the repaired copies, the caller holds flush_lock
+    pub fn check_block(&self, block_no: u32, mut data: Vec<u8>, should_check: &[bool]) -> WondResult<(u64, u64)> {
+        let mut repaired = 0;
+        let mut corrupted = 0;
         for index in 0..128 {
-            let page = &data[index * 4096..(index + 1) * 4096];
             if !should_check[index] {
                 continue;
             }
             let address = block_no * 128 + index as u32;
-            let signature = self.get_address_sign(address);
-            if signature.is_none() {
-                continue;
+            let page = &mut data[index * 4096..(index + 1) * 4096];
+            match self.check_page(address, page)? {
+                PageState::Valid | PageState::Lost => (),
+                PageState::Repaired => repaired += 1,
+                PageState::Corrupted => corrupted += 1,
             }
-            if page == [0; 4096] {
+        }
+        if repaired + corrupted > 0 {
+            self.relocate_block_with(block_no, data)?;
+        }
+        Ok((repaired, corrupted))
+    }
+
+    pub fn check_page(&self, address: u32, data: &mut [u8]) -> WondResult<PageState> {
+        let signature = match self.get_address_sign(address)? {
+            Some(signature) => signature,
+            None => return Ok(PageState::Valid),
+        };
+        if check_center::CheckCenter::is_lost(&signature) {
+            return Ok(PageState::Lost);
+        }
+        if *data == [0; 4096] {
+            return Ok(PageState::Valid);
+        }
+        let ret = check_center::CheckCenter::check(data, &signature)?;
+        if ret.0 {
+            return Ok(PageState::Valid);
+        }
+        match ret.2 {
+            Some(page) => {
+                data.copy_from_slice(&page);
+                Ok(PageState::Repaired)
+            },
+            None => Ok(PageState::Corrupted),
+        }
+    }
+
+    fn verify_page(&self, address: u32, data: &mut [u8]) -> WondResult<()> {
+        match self.check_page(address, data)? {
+            PageState::Valid | PageState::Repaired => Ok(()),
+            PageState::Lost => Err(WondError::Corrupted("page was lost to a bad block")),
+            PageState::Corrupted => {
+                self.relocate_block(address / 128)?;
+                Err(WondError::Corrupted("page failed its signature check"))
+            },
+        }
+    }
+
+    // moves what is still readable of a failing block onto a spare block and points the map at
+    // it, pages beyond repair get a lost signature so they keep failing instead of reading garbage
+    pub fn relocate_block(&self, block_no: u32) -> WondResult<()> {
+        let _flush = self.flush_lock.lock();
+        self.relocate_block_locked(block_no)
+    }
+
+    pub fn relocate_block_locked(&self, block_no: u32) -> WondResult<()> {
+        let mut data = vec![0; 128 * 4096];
+        self.disk_manager.read().disk_block_read(self.transfer(block_no), &mut data)?;
+        self.relocate_block_with(block_no, data)
+    }
+
+    // the caller holds flush_lock, so a cached page has not reached the block yet and lands on
+    // the new one with its batch, or it belongs to the failing batch that rewrite_block repeats
+    pub fn relocate_block_with(&self, block_no: u32, mut data: Vec<u8>) -> WondResult<()> {
+        let mut lost = vec![];
+        let mut pages = vec![];
+        for index in 0..128 {
+            let address = block_no * 128 + index as u32;
+            if self.write_cache.read().contains_address(address) {
                 continue;
             }
-            let ret = check_center::CheckCenter::check(page, &signature.as_ref().unwrap());
-            if ret.0 == false {
-                if ret.2 == None {
-                    println!("{:?} {}", signature.unwrap(), index);
-                    flag = false;
-                    break;
-                } else {
-                    data[index * 4096..(index + 1) * 4096].copy_from_slice(&ret.2.unwrap());
+            let page = &mut data[index * 4096..(index + 1) * 4096];
+            match self.check_page(address, page)? {
+                PageState::Valid | PageState::Repaired => (),
+                PageState::Corrupted | PageState::Lost => {
+                    lost.push((address, check_center::CheckCenter::sign_lost(address)));
+                    continue;
+                },
+            }
+            if *page != [0; 4096] {
+                pages.push(index);
+            }
+        }
+        'spare: loop {
+            let new_block_no = self.take_spare_block()?;
+            for index in pages.iter() {
+                let mut buf = [0; 4096];
+                buf.copy_from_slice(&data[index * 4096..(index + 1) * 4096]);
+                let ret = self.disk_manager.write().disk_write(new_block_no * 128 + *index as u32, &buf);
+                match ret {
+                    Err(WondError::BadBlock(_)) => {
+                        self.retire_block(new_block_no);
+                        continue 'spare;
+                    },
+                    ret => ret?,
                 }
             }
+            self.map_block(block_no, new_block_no);
+            break;
         }
-        if !flag {
-            let new_block_no = self.find_next_block();
-            self.used_table.write().insert(new_block_no, true);
-            self.map_v_table.write().insert(block_no, new_block_no);
+        self.sync_map_v_table()?;
+        if !lost.is_empty() {
+            self.write_signatures(lost)?;
+        }
+        Ok(())
+    }
+
+    // points a main area block at an erased spare, used when the old copy is not worth keeping
+    fn replace_block(&self, block_no: u32) -> WondResult<()> {
+        let new_block_no = self.take_spare_block()?;
+        self.map_block(block_no, new_block_no);
+        self.sync_map_v_table()
+    }
+
+    fn map_block(&self, block_no: u32, new_block_no: u32) {
+        let old_block_no = self.transfer(block_no);
+        if old_block_no != block_no {
+            self.retire_block(old_block_no);
+        } else {
             *self.err_block_num.write() += 1;
-            self.sync_map_v_table();
-            return false;
+            *self.last_err_time.write() = SystemTime::now();
         }
-        true
+        self.map_v_table.write().insert(block_no, new_block_no);
     }
 
-    fn check_page(&self, address: u32, data: &mut [u8]) -> bool {
-        let signature = self.get_address_sign(address);
-        if signature.is_none() {
-            return true;
+    // a failed reserved block maps onto itself, which keeps it out of the spare pool for good
+    fn retire_block(&self, block_no: u32) {
+        if self.map_v_table.read().get(&block_no) == Some(&block_no) {
+            return;
         }
-        if *data == [0; 4096] {
-            return true;
+        self.map_v_table.write().insert(block_no, block_no);
+        self.used_table.write().insert(block_no, true);
+        *self.err_block_num.write() += 1;
+        *self.last_err_time.write() = SystemTime::now();
+    }
+
+    // hands out an erased spare, spares that fail their erase are retired on the way
+    fn take_spare_block(&self) -> WondResult<u32> {
+        loop {
+            let block_no = self.find_next_block()?;
+            let ret = self.disk_manager.write().disk_erase(block_no);
+            match ret {
+                Ok(()) => {
+                    self.used_table.write().insert(block_no, true);
+                    return Ok(block_no);
+                },
+                Err(WondError::BadBlock(_)) => self.retire_block(block_no),
+                Err(err) => return Err(err),
+            }
         }
-        let ret = check_center::CheckCenter::check(data, &signature.as_ref().unwrap());
-        if ret.0 == false {
-            if ret.2 == None {
-                return false;
-            } else {
-                data.copy_from_slice(&ret.2.unwrap());
+    }
+
+    fn write_sign(&self, data: &Vec<(u32, [u8;4096])>) -> WondResult<()> {
+        for batch in data.chunks(32) {
+            let mut signatures = vec![];
+            for (address, page) in batch.iter() {
+                signatures.push((*address, self.set_address_sign(page, *address)?));
             }
+            self.write_signatures(signatures)?;
         }
-        true
+        Ok(())
     }
 
-    fn write_sign(&self, data: &Vec<(u32, [u8;4096])>) {
-        if data.len() != 32 {
-            panic!("TranslationLayer: write sign no available size");
+    // one page holds 32 signatures, a short batch leaves the rest of its page empty
+    fn write_signatures(&self, mut signatures: Vec<(u32, Vec<u8>)>) -> WondResult<()> {
+        if signatures.len() > 32 {
+            return Err(WondError::Device("more signatures than a page holds"));
         }
         let mut page_data = [0; 4096];
-        if *self.sign_block_offset.read() / 32 == 127 {
-            if !self.used_table.read().contains_key(&self.sign_block_no.read()) {
-                self.used_table.write().insert(*self.sign_block_no.read(), true);
-            }
-            *self.sign_block_no.write() = self.find_next_block();
-            *self.sign_block_offset.write() = 0;
-        }
-        let address = *self.sign_block_no.read() * 128 + *self.sign_block_offset.read() / 32;
-        for (index, data) in data.iter().enumerate() {
-            let signature = self.set_address_sign(&data.1, data.0);
-            let start_index = index * 128;
-            for (index, byte) in signature.iter().enumerate() {
-                page_data[start_index + index] = *byte;
+        // held throughout, the writer thread and a relocating reader may both append
+        let mut sign_block_offset = self.sign_block_offset.write();
+        if *sign_block_offset / 32 == 127 {
+            let sign_block_no = *self.sign_block_no.read();
+            self.used_table.write().insert(sign_block_no, true);
+            let sign_block_no = self.find_next_block()?;
+            *self.sign_block_no.write() = sign_block_no;
+            *sign_block_offset = 0;
+            self.reclaim_sign_blocks()?;
+        }
+        let seq = *self.sign_seq.read();
+        for (index, (_, signature)) in signatures.iter_mut().enumerate() {
+            check_center::CheckCenter::set_sequence(signature, seq);
+            page_data[index * 128..(index + 1) * 128].copy_from_slice(signature);
+        }
+        // signatures already on a failing block stay readable, only new ones move on
+        loop {
+            let sign_block_no = *self.sign_block_no.read();
+            let ret = self.disk_manager.write().disk_write(sign_block_no * 128 + *sign_block_offset / 32, &page_data);
+            match ret {
+                Err(WondError::BadBlock(_)) => {
+                    self.retire_block(sign_block_no);
+                    let sign_block_no = self.take_spare_block()?;
+                    *self.sign_block_no.write() = sign_block_no;
+                    *sign_block_offset = 0;
+                    self.sync_map_v_table()?;
+                },
+                ret => break ret?,
             }
-            if self.sign_block_map.read().contains_key(&data.0) {
-                *self.sign_block_map.write().get_mut(&data.0).unwrap() = *self.sign_block_no.read();
-                *self.sign_offset_map.write().get_mut(&data.0).unwrap() = *self.sign_block_offset.read() + index as u32;
-            } else {
-                self.sign_block_map.write().insert(data.0, *self.sign_block_no.read());
-                self.sign_offset_map.write().insert(data.0,  *self.sign_block_offset.read() + index as u32);
+        }
+        let sign_block_no = *self.sign_block_no.read();
+        for (index, (address, _)) in signatures.iter().enumerate() {
+            self.sign_block_map.write().insert(*address, sign_block_no);
+            self.sign_offset_map.write().insert(*address, *sign_block_offset + index as u32);
+        }
+        *self.sign_seq.write() += 1;
+        *sign_block_offset += 32;
+        Ok(())
+    }
+
+    // a signature block no address points into any more is erased and handed out again
+    fn reclaim_sign_blocks(&self) -> WondResult<()> {
+        let live: HashSet<u32> = self.sign_block_map.read().values().cloned().collect();
+        let mapped: HashSet<u32> = self.map_v_table.read().values().cloned().collect();
+        let sign_block_no = *self.sign_block_no.read();
+        let table_block_no = *self.table_block_no.read();
+        let stale: Vec<u32> = self.used_table.read().keys().cloned().filter(|block_no| {
+            *block_no != table_block_no && *block_no != sign_block_no && !live.contains(block_no) && !mapped.contains(block_no)
+        }).collect();
+        let mut retired = false;
+        for block_no in stale {
+            let ret = self.disk_manager.write().disk_erase(block_no);
+            match ret {
+                Err(WondError::BadBlock(_)) => {
+                    self.retire_block(block_no);
+                    retired = true;
+                },
+                ret => {
+                    ret?;
+                    self.used_table.write().remove(&block_no);
+                },
             }
         }
-        *self.sign_block_offset.write() += 32;
-        self.disk_manager.write().disk_write(address, &page_data);
+        if retired {
+            self.sync_map_v_table()?;
+        }
+        Ok(())
     }
 
-    fn transfer(&self, pla: u32) -> u32 {
-        if self.map_v_table.read().contains_key(&pla) {
-            *self.map_v_table.read().get(&pla).unwrap()
-        } else {
-            pla
-        }
+    pub fn transfer(&self, pla: u32) -> u32 {
+        self.map_v_table.read().get(&pla).copied().unwrap_or(pla)
+    }
+
+    fn transfer_address(&self, address: u32) -> u32 {
+        self.transfer(address / 128) * 128 + address % 128
     }
 
-    fn get_address_sign(&self, address: u32) -> Option<Vec<u8>> {
-        let sign_block_map = self.sign_block_map.read();
-        let sign_address = *sign_block_map.get(&address)?;
-        let sign_offset_map = self.sign_offset_map.read();
-        let offset = *sign_offset_map.get(&address)?;
+    fn get_address_sign(&self, address: u32) -> WondResult<Option<Vec<u8>>> {
+        let sign_address = match self.sign_block_map.read().get(&address) {
+            Some(sign_address) => *sign_address,
+            None => return Ok(None),
+        };
+        let offset = match self.sign_offset_map.read().get(&address) {
+            Some(offset) => *offset,
+            None => return Ok(None),
+        };
         let mut ret = vec![0; 128];
         let mut data = vec![0; 4096];
-        self.disk_manager.read().disk_read_advanced(sign_address*128+offset/32, &mut data);
+        self.disk_manager.read().disk_read_advanced(sign_address*128+offset/32, &mut data)?;
         ret.copy_from_slice(&data[(offset%32*128) as usize..(offset%32*128+128) as usize]);
-        Some(ret)
+        Ok(Some(ret))
     }
 
-    fn set_address_sign(&self, data: &[u8; 4096], address: u32) -> Vec<u8> {
+    fn set_address_sign(&self, data: &[u8; 4096], address: u32) -> WondResult<Vec<u8>> {
         let sign_type = self.choose_sign_type();
-        let sign = check_center::CheckCenter::sign(data, address, sign_type);
-        sign
+        check_center::CheckCenter::sign(data, address, sign_type)
     }
 
     fn choose_sign_type(&self) -> check_center::CheckType {
@@ -344,25 +628,46 @@ impl TranslationLayer {
         check_center::CheckType::Crc32
     }
 
-    fn find_next_block(&self) -> u32 {
+    fn find_next_block(&self) -> WondResult<u32> {
         for block_no in self.use_max_block_no+1..self.max_block_no {
-            if block_no == self.table_block_no || block_no == *self.sign_block_no.read() {
+            if block_no == *self.table_block_no.read() || block_no == *self.sign_block_no.read() {
                 continue;
             }
             if self.used_table.read().contains_key(&block_no) {
                 continue;
             }
-            return block_no;
+            return Ok(block_no);
+        }
+        Err(WondError::NoSpace)
+    }
+
+    // a table block that fails is retired and the table, now listing it, goes to a spare
+    fn sync_map_v_table(&self) -> WondResult<()> {
+        loop {
+            // a half written table on a failed block must not tie with its retry
+            *self.table_seq.write() += 1;
+            let data = self.encode_map_v_table();
+            match self.write_table_block(&data) {
+                Err(WondError::BadBlock(_)) => {
+                    let table_block_no = *self.table_block_no.read();
+                    self.retire_block(table_block_no);
+                    let table_block_no = self.take_spare_block()?;
+                    *self.table_block_no.write() = table_block_no;
+                },
+                ret => return ret,
+            }
         }
-        panic!("TranslationLayer: No available block to map")
     }
 
-    fn sync_map_v_table(&self) {
+    fn encode_map_v_table(&self) -> array::Array1::<u8> {
         let mut data = array::Array1::<u8>::new(128 * 4096, 0);
         data.set(0, 0x22);
         data.set(1, 0x22);
         data.set(2, 0xff);
         data.set(3, 0xff);
+        for (index, byte) in self.table_seq.read().to_be_bytes().iter().enumerate() {
+            data.set(4 + index as u32, *byte);
+        }
         let mut index = 0;
         for (key, value) in self.map_v_table.read().iter() {
             let start_index = 8 + index * 8;
@@ -384,11 +689,12 @@ impl TranslationLayer {
             data.set(start_index + 7, byte_4);
             index += 1;
         }
-        self.write_table_block(&data);
+        data
     }
 
-    pub fn write_table_block(&self, data: &array::Array1::<u8>) {
-        self.disk_manager.write().disk_erase(self.table_block_no);
+    pub fn write_table_block(&self, data: &array::Array1::<u8>) -> WondResult<()> {
+        let table_block_no = *self.table_block_no.read();
+        self.disk_manager.write().disk_erase(table_block_no)?;
         let mut index = 0;
         while index < 128 {
             let start_index = 4096 * index;
@@ -397,14 +703,15 @@ impl TranslationLayer {
             for index in start_index..end_index {
                 page[index - start_index] = data.get(index as u32);
             }
-            self.disk_manager.write().disk_write(self.table_block_no * 128 + index as u32, &page);
+            self.disk_manager.write().disk_write(table_block_no * 128 + index as u32, &page)?;
             index += 1;
         }
+        Ok(())
     }
 
-    fn update_read_speed(&self, size: u32, duration: u128) {
+    pub fn update_read_speed(&self, size: u32, duration: u128) {
         let len = size * 1000000 / 1024;
-        let duration = duration as u32;
+        let duration = (duration as u32).max(1);
         let speed = len / duration;
         let new_speed = 6 * speed / 10 + 4 * *self.read_speed.read() / 10;
         *self.read_speed.write() = new_speed;
@@ -425,8 +732,12 @@ struct MapDataRegion<'a> {
 }
 
 impl MapDataRegion<'_> {
+    fn extract_sequence(data: &[u8]) -> u32 {
+        u32::from_be_bytes([data[4], data[5], data[6], data[7]])
+    }
+
     fn new(data: &Vec<u8>) -> MapDataRegion {
-        if data.len() != 128 {
+        if data.len() != 128 * 4096 {
             panic!("MapDataRegion: new not matched size");
         }
         MapDataRegion {
@@ -468,7 +779,7 @@ struct SignDataRegion<'a> {
 
 impl SignDataRegion<'_> {
     fn new(data: &Vec<u8>) -> SignDataRegion {
-        if data.len() != 128 {
+        if data.len() != 128 * 4096 {
             panic!("SignDataRegion: new not matched size");
         }
         SignDataRegion {
@@ -478,20 +789,141 @@ impl SignDataRegion<'_> {
     }
 }
 
+// short batches leave empty slots behind them, so the walk covers the whole block
 impl Iterator for SignDataRegion<'_> {
-    type Item = [u8; 128];
+    type Item = (usize, [u8; 128]);
     fn next(&mut self) -> Option<Self::Item> {
-        if self.count < 128 * 4096 {
+        while self.count < 128 * 4096 {
             let mut data = [0; 128];
             data.copy_from_slice(&self.data[self.count..self.count + 128]);
+            let index = self.count / 128;
             self.count += 128;
-            if data == [0; 128] {
-                None
-            } else {
-                Some(data)
+            if data != [0; 128] {
+                return Some((index, data));
             }
-        } else {
-            None
         }
+        None
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::fake_disk;
+
+    #[test]
+    fn verify() {
+        let mut tl = TranslationLayer::new();
+        tl.init().unwrap();
+        tl.write(200, &[7; 4096]);
+        tl.write(201, &[8; 4096]);
+        tl.flush().unwrap();
+        assert_eq!(tl.read(200).unwrap(), [7; 4096]);
+        // a crc32 page cannot be repaired, its block moves and the page stays lost
+        tl.disk_manager.write().fake_disk.as_mut().unwrap().data[200][9] ^= 0x01;
+        assert!(tl.read(200).is_err());
+        assert!(tl.map_v_table.read().contains_key(&1));
+        assert_eq!(*tl.err_block_num.read(), 1);
+        assert_eq!(tl.read(201).unwrap(), [8; 4096]);
+        assert!(tl.read(200).is_err());
+        // after an error pages are signed with ecc, a single flipped bit is repaired
+        tl.write(300, &[9; 4096]);
+        tl.flush().unwrap();
+        tl.disk_manager.write().fake_disk.as_mut().unwrap().data[300][100] ^= 0x10;
+        assert_eq!(tl.read(300).unwrap(), [9; 4096]);
+        let mut buf = [0; 16];
+        tl.read_advanced(300, &mut buf).unwrap();
+        assert_eq!(buf, [9; 16]);
+        let disk = tl.disk_manager.read().fake_disk.as_ref().unwrap().clone();
+        let mut tl = TranslationLayer::with_disk_manager(disk_manager::DiskManager::with_fake_disk(disk));
+        tl.init().unwrap();
+        assert_eq!(tl.read(201).unwrap(), [8; 4096]);
+        assert_eq!(tl.read(300).unwrap(), [9; 4096]);
+        assert!(tl.read(200).is_err());
+        tl.write(202, &[1; 4096]);
+        tl.flush().unwrap();
+        assert_eq!(tl.read(202).unwrap(), [1; 4096]);
+    }
+
+    #[test]
+    fn relocate_waits_for_flush() {
+        let mut tl = TranslationLayer::new();
+        tl.init().unwrap();
+        tl.write(200, &[7; 4096]);
+        tl.flush().unwrap();
+        let tl = Arc::new(tl);
+        // a batch halfway on disk holds the lock, the relocation has to wait for all of it
+        let flush = tl.flush_lock.lock();
+        let handle = {
+            let tl = Arc::clone(&tl);
+            std::thread::spawn(move || tl.relocate_block(1))
+        };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!tl.map_v_table.read().contains_key(&1));
+        drop(flush);
+        handle.join().unwrap().unwrap();
+        assert!(tl.map_v_table.read().contains_key(&1));
+        assert_eq!(tl.read(200).unwrap(), [7; 4096]);
+    }
+
+    #[test]
+    fn end_of_life() {
+        let model = fake_disk::FlashModel {
+            erase_limit: 12,
+            bit_flips: 2.0,
+            program_fail_rate: 0.01,
+            erase_fail_rate: 0.01,
+            seed: 42,
+        };
+        let mount = |disk: fake_disk::FakeDisk| -> TranslationLayer {
+            let mut tl = TranslationLayer::with_disk_manager(disk_manager::DiskManager::with_fake_disk(disk));
+            tl.set_geometry(40, 32);
+            tl
+        };
+        let tl = mount(fake_disk::FakeDisk::with_model(40 * 128, model));
+        tl.format().unwrap();
+        let mut survived = HashMap::new();
+        for address in 5 * 128..5 * 128 + 8 {
+            tl.write(address, &[address as u8; 4096]);
+            survived.insert(address, [address as u8; 4096]);
+        }
+        tl.flush().unwrap();
+        // three hot blocks are rewritten until the spares run out, a read either returns what
+        // was written or fails, it never hands back flipped bits, and the cold block outlives them
+        'round: for round in 0..40u32 {
+            for block_no in 1..4 {
+                if let Err(err) = tl.erase(block_no) {
+                    assert_eq!(err, WondError::NoSpace);
+                    survived.retain(|address, _| *address / 128 >= block_no);
+                    break 'round;
+                }
+                survived.retain(|address, _| *address / 128 != block_no);
+                for address in block_no * 128..block_no * 128 + 8 {
+                    tl.write(address, &[(round * 8 + address % 128) as u8; 4096]);
+                }
+            }
+            if let Err(err) = tl.flush() {
+                assert_eq!(err, WondError::NoSpace);
+                survived.retain(|address, _| *address / 128 == 5);
+                break;
+            }
+            for block_no in 1..4 {
+                for address in block_no * 128..block_no * 128 + 8 {
+                    if let Ok(data) = tl.read(address) {
+                        assert_eq!(data, [(round * 8 + address % 128) as u8; 4096]);
+                        survived.insert(address, data);
+                    }
+                }
+            }
+        }
+        let disk = tl.disk_manager.read().fake_disk.as_ref().unwrap().clone();
+        assert!(disk.bad_block_num() > 0);
+        assert!(survived.len() >= 8);
+        let err_block_num = *tl.err_block_num.read();
+        let mut tl = mount(disk);
+        tl.init().unwrap();
+        assert_eq!(*tl.err_block_num.read(), err_block_num);
+        for (address, data) in survived.iter() {
+            assert_eq!(tl.read(*address).unwrap(), *data);
+        }
+    }
+}
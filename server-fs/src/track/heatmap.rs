@@ -2,6 +2,12 @@ pub struct HeatmapGen {
     
 }
 
+impl Default for HeatmapGen {
+    fn default() -> Self {
+        HeatmapGen::new()
+    }
+}
+
 impl HeatmapGen {
     pub fn new() -> HeatmapGen {
         HeatmapGen {
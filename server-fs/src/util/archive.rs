@@ -0,0 +1,113 @@
+use rkyv::ser::{Serializer, serializers::AllocSerializer};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{AlignedVec, Archive, CheckBytes, Deserialize, Infallible, Serialize};
+use crate::error::{WondError, WondResult};
+
+// every persisted structure ends with a tag naming its kind and layout version, so a layout
+// change is noticed on read instead of misread. the tag trails the archive, which keeps the
+// archive where it was in the buffer and its fields aligned
+pub const TAG_SIZE: usize = 4;
+const TAG_MAGIC: [u8; 2] = [0x77, 0x76];
+
+pub const KIND_INODE_METADATA: u8 = 1;
+pub const KIND_INODE_XATTR: u8 = 2;
+pub const KIND_DATA_OBJECT: u8 = 3;
+pub const KIND_SUPER_STAT: u8 = 4;
+pub const KIND_CSS_NODE: u8 = 5;
+
+// bump VERSION with any change to the archived layout and teach the reader the old one, a change
+// older readers would misread but this one still reads as is only moves VERSION
+pub trait Versioned {
+    const KIND: u8;
+    const VERSION: u8;
+    // oldest version that shares the current layout
+    const MIN_VERSION: u8 = Self::VERSION;
+}
+
+pub fn encode<T: Versioned + Serialize<AllocSerializer<0>>>(value: &T) -> Vec<u8> {
+    let mut serializer = AllocSerializer::<0>::default();
+    serializer.serialize_value(value).unwrap();
+    let mut data = serializer.into_serializer().into_inner().to_vec();
+    data.extend_from_slice(&[TAG_MAGIC[0], TAG_MAGIC[1], T::KIND, T::VERSION]);
+    data
+}
+
+// the archive and the version it was written with, none for data from before tags
+pub fn split_tag<T: Versioned>(data: &[u8]) -> (&[u8], Option<u8>) {
+    if data.len() >= TAG_SIZE {
+        let (payload, tag) = data.split_at(data.len() - TAG_SIZE);
+        if tag[..2] == TAG_MAGIC && tag[2] == T::KIND {
+            return (payload, Some(tag[3]));
+        }
+    }
+    (data, None)
+}
+
+// untagged data predates tags and shares the layout of the first version
+pub fn decode<T>(data: &[u8]) -> WondResult<T>
+where
+    T: Archive + Versioned,
+    T::Archived: for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<T, Infallible>,
+{
+    let (payload, version) = split_tag::<T>(data);
+    match version.unwrap_or(1) {
+        version if version > T::VERSION => Err(WondError::Unsupported("archived layout is newer than this build")),
+        version if version < T::MIN_VERSION => Err(WondError::Unsupported("archived layout has no migration")),
+        _ => check(payload),
+    }
+}
+
+// validates the archive before touching it, damaged bytes become an error instead of undefined behavior
+pub fn check<T>(payload: &[u8]) -> WondResult<T>
+where
+    T: Archive,
+    T::Archived: for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<T, Infallible>,
+{
+    // the validator refuses a buffer that does not sit where the archive's alignment wants it
+    let mut aligned = AlignedVec::with_capacity(payload.len());
+    aligned.extend_from_slice(payload);
+    let archived = rkyv::check_archived_root::<T>(&aligned).map_err(|_| WondError::Corrupted("archived value fails validation"))?;
+    Ok(archived.deserialize(&mut Infallible).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+    #[archive(check_bytes)]
+    struct Test {
+        int: u32,
+        list: Vec<u16>,
+    }
+
+    impl Versioned for Test {
+        const KIND: u8 = 0xff;
+        const VERSION: u8 = 2;
+        const MIN_VERSION: u8 = 1;
+    }
+
+    #[test]
+    fn basics() {
+        let value = Test {
+            int: 42,
+            list: vec![1, 2, 3],
+        };
+        let mut data = encode(&value);
+        assert_eq!(split_tag::<Test>(&data).1, Some(2));
+        assert_eq!(decode::<Test>(&data), Ok(Test { int: 42, list: vec![1, 2, 3] }));
+        // untagged data is read as the first layout
+        assert_eq!(decode::<Test>(&data[..data.len() - TAG_SIZE]).unwrap().int, 42);
+        let len = data.len();
+        data[len - 1] = 3;
+        assert_eq!(decode::<Test>(&data), Err(WondError::Unsupported("archived layout is newer than this build")));
+        data[len - 1] = 0;
+        assert_eq!(decode::<Test>(&data), Err(WondError::Unsupported("archived layout has no migration")));
+        data[len - 1] = 1;
+        // a relative pointer sent out of the buffer
+        let list = len - TAG_SIZE - 8;
+        data[list..list + 4].copy_from_slice(&0x7fffu32.to_le_bytes());
+        assert_eq!(decode::<Test>(&data), Err(WondError::Corrupted("archived value fails validation")));
+        assert!(check::<Test>(&[1, 2, 3]).is_err());
+    }
+}
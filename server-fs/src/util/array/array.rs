@@ -16,6 +16,10 @@ impl<T: Copy> Array1<T> {
         self.array.get_len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn init(&mut self, value: T) {
         self.array.init_array(value);
     }
@@ -89,6 +93,10 @@ impl<T: Copy> Array2<T> {
         self.array.get_len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn size(&self) -> [u32; 2] {
         let size = self.array.get_size();
         [size[0], size[1]]
@@ -1,2 +1,5 @@
 pub mod array;
-pub mod lru_cache;
\ No newline at end of file
+pub mod crc32;
+pub mod archive;
+pub mod lru_cache;
+pub mod time;
\ No newline at end of file
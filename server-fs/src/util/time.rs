@@ -0,0 +1,22 @@
+use rkyv::{Archive, Deserialize, Serialize};
+
+// seconds since the epoch and the nanoseconds past them, what inode timestamps are kept as
+#[derive(Archive, Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[archive(check_bytes)]
+pub struct Timespec {
+    pub sec: i64,
+    pub nsec: u32,
+}
+
+impl Timespec {
+    pub fn new(sec: i64, nsec: u32) -> Timespec {
+        Timespec { sec, nsec }
+    }
+}
+
+impl From<u32> for Timespec {
+    // layouts before nanoseconds stored whole seconds
+    fn from(sec: u32) -> Self {
+        Timespec::new(sec as i64, 0)
+    }
+}
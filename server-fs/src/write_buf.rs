@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::ops::Range;
 
 pub struct WriteCache {
     pub table: BTreeMap<u32, [u8; 4096]>,
@@ -6,6 +7,12 @@ pub struct WriteCache {
     pub size: usize,
 }
 
+impl Default for WriteCache {
+    fn default() -> Self {
+        WriteCache::new()
+    }
+}
+
 impl WriteCache {
     pub fn new() -> WriteCache {
         WriteCache {
@@ -36,7 +43,9 @@ impl WriteCache {
 
 impl WriteCache {
     pub fn write(&mut self, address: u32, data: [u8; 4096]) {
-        self.table.insert(address, data);
+        if self.table.insert(address, data).is_none() {
+            self.size += 1;
+        }
         if self.table.len() >= 32 {
             self.sync = true;
         }
@@ -51,22 +60,28 @@ impl WriteCache {
         }
     }
 
-    pub fn get_all(&mut self) -> Vec<(u32, [u8; 4096])> {
-        let mut entries: Vec<(u32, [u8; 4096])> = Vec::new();
-        for entry in self.table.clone() {
-            entries.push((entry.0, entry.1));
-            self.table.remove(&entry.0);
-            self.size -= 1;
-            if entries.len() == 32 {
-                break;
+    // entries stay readable here until release_batch, after they and their signatures hit the disk
+    pub fn get_batch(&self) -> Vec<(u32, [u8; 4096])> {
+        self.table.iter().take(32).map(|entry| (*entry.0, *entry.1)).collect()
+    }
+
+    pub fn get_batch_in(&self, range: Range<u32>) -> Vec<(u32, [u8; 4096])> {
+        self.table.range(range).take(32).map(|entry| (*entry.0, *entry.1)).collect()
+    }
+
+    pub fn release_batch(&mut self, entries: &[(u32, [u8; 4096])]) {
+        for (address, data) in entries.iter() {
+            if self.table.get(address) == Some(data) {
+                self.table.remove(address);
+                self.size -= 1;
             }
         }
-        entries
     }
 
     pub fn recall_write(&mut self, address: u32) {
         if self.table.contains_key(&address) {
             self.table.remove(&address);
+            self.size -= 1;
         }
     }
 }